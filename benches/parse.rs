@@ -0,0 +1,112 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ycf::{cursor::TokenKind, de, parse::Parser, Value};
+
+/// A document of `n` servers, each with dotted keys, strings and a numeric list.
+fn document(n: usize) -> String {
+    let mut out = String::new();
+    for i in 0..n {
+        out.push_str(&format!(
+            "// server {i}\n\
+             servers.s{i}.host = \"10.0.{}.{}\"\n\
+             servers.s{i}.port = {}\n\
+             servers.s{i}.tags = [\"web\" \"edge\" \"tier-{}\"]\n\
+             servers.s{i}.weights = [0.25 1.5 3 {i}.125]\n\
+             servers.s{i}.timeout = 30s\n",
+            i / 256,
+            i % 256,
+            8000 + i,
+            i % 4,
+        ));
+    }
+    out
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+    for n in [10, 1000] {
+        let src = document(n);
+        group.throughput(Throughput::Bytes(src.len() as u64));
+        for pretokenize in [false, true] {
+            let name = if pretokenize {
+                "pretokenized"
+            } else {
+                "streaming"
+            };
+            group.bench_with_input(BenchmarkId::new(name, n), &src, |b, src| {
+                b.iter(|| {
+                    let options = de::Options {
+                        pretokenize,
+                        ..de::Options::default()
+                    };
+                    de::from_str_with_options::<Value>(black_box(src), options).unwrap()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Reads every token while peeking eight tokens ahead of it, like a speculative parse.
+fn lookahead(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookahead");
+    let src = document(100);
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    for pretokenize in [false, true] {
+        let name = if pretokenize {
+            "pretokenized"
+        } else {
+            "streaming"
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut parser = Parser::new(black_box(&src));
+                if pretokenize {
+                    parser.tokenize();
+                }
+                while parser.next_token().token.kind != TokenKind::Eof {
+                    black_box(parser.peek_nth(8));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// A list of floats like telemetry samples, which is dominated by float parsing.
+/// Compare with `--features fast-float`.
+fn floats(c: &mut Criterion) {
+    let mut group = c.benchmark_group("floats");
+    let mut src = String::from("[");
+    for i in 0..10_000u32 {
+        src.push_str(&format!(" {}", f64::from(i) * 1.618_033_988_7 - 4096.0));
+    }
+    src.push_str(" ]");
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("f64", |b| {
+        b.iter(|| de::from_str::<Vec<f64>>(black_box(&src)).unwrap())
+    });
+    group.finish();
+}
+
+/// Parsing a document into a tree and dropping it, compared with an arena with
+/// `--features arena`.
+fn dom(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dom");
+    let src = document(1000);
+    group.throughput(Throughput::Bytes(src.len() as u64));
+    group.bench_function("value", |b| {
+        b.iter(|| black_box(&src).parse::<Value>().unwrap())
+    });
+    #[cfg(feature = "arena")]
+    group.bench_function("arena", |b| {
+        let mut bump = bumpalo::Bump::new();
+        b.iter(|| {
+            ycf::arena::Value::from_str(black_box(&src), &bump).unwrap();
+            bump.reset();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, deserialize, lookahead, floats, dom);
+criterion_main!(benches);
@@ -0,0 +1,270 @@
+//! [`Arbitrary`] values and documents, for structured fuzzing of the parser and the
+//! serializer.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write as _;
+use core::time::Duration;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::value::{Map, Value};
+
+/// How deep generated lists and maps nest.
+const MAX_DEPTH: u32 = 4;
+/// The most elements or entries a generated list or map has.
+const MAX_LEN: usize = 6;
+
+/// Values are generated so that they are read back the same after being written:
+/// floats are finite, an [`I64`](Value::I64) is negative as a positive one is read as
+/// a [`U64`](Value::U64), 128 bit integers do not fit in 64 bits, and keys have no
+/// `"`, `\` or control characters, which quoted keys can not escape.
+impl<'a> Arbitrary<'a> for Value {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        value(u, MAX_DEPTH)
+    }
+}
+
+fn value(u: &mut Unstructured<'_>, depth: u32) -> Result<Value> {
+    let kinds = if depth == 0 { 7 } else { 9 };
+    Ok(match u.choose_index(kinds)? {
+        0 => Value::Null,
+        1 => Value::Bool(u.arbitrary()?),
+        2 if u.ratio(1, 4)? => Value::U128(u.int_in_range(u64::MAX as u128 + 1..=u128::MAX)?),
+        2 => Value::U64(u.arbitrary()?),
+        3 if u.ratio(1, 4)? => Value::I128(u.int_in_range(i128::MIN..=i64::MIN as i128 - 1)?),
+        3 => Value::I64(u.int_in_range(i64::MIN..=-1)?),
+        4 => {
+            let v: f64 = u.arbitrary()?;
+            Value::F64(if v.is_finite() { v } else { 0.0 })
+        }
+        5 => Value::String(u.arbitrary()?),
+        6 => Value::Duration(Duration::new(
+            u.int_in_range(0..=u64::MAX / (24 * 60 * 60))?,
+            u.int_in_range(0..=999_999_999)?,
+        )),
+        7 => {
+            let len = u.int_in_range(0..=MAX_LEN)?;
+            Value::List(
+                (0..len)
+                    .map(|_| value(u, depth - 1))
+                    .collect::<Result<_>>()?,
+            )
+        }
+        _ => Value::Map(map(u, depth - 1)?),
+    })
+}
+
+fn map(u: &mut Unstructured<'_>, depth: u32) -> Result<Map> {
+    let len = u.int_in_range(0..=MAX_LEN)?;
+    let mut map = Map::new();
+    for _ in 0..len {
+        let key: String = u.arbitrary()?;
+        let key: String = key
+            .chars()
+            .filter(|&c| !matches!(c, '"' | '\\') && !c.is_control())
+            .collect();
+        map.insert(key.into(), value(u, depth)?);
+    }
+    Ok(map)
+}
+
+/// The text of a top level document that is valid syntax and reads as a
+/// [`Value`] without errors. It uses every form of literal, dotted keys, comments
+/// and odd but valid whitespace, so it covers more of the parser than the output of
+/// the serializer does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Document(pub String);
+
+impl<'a> Arbitrary<'a> for Document {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut out = String::new();
+        entries(u, &mut out, MAX_DEPTH)?;
+        Ok(Document(out))
+    }
+}
+
+/// Writes the entries of a map. The first segment of every key is different, so
+/// entries never conflict.
+fn entries(u: &mut Unstructured<'_>, out: &mut String, depth: u32) -> Result<()> {
+    let len = u.int_in_range(0..=MAX_LEN)?;
+    for i in 0..len {
+        space(u, out)?;
+        // the index keeps the first segments apart
+        key(u, out, Some(i))?;
+        for _ in 0..u.int_in_range(0..=2)? {
+            out.push('.');
+            key(u, out, None)?;
+        }
+        out.push_str(if u.arbitrary()? { " = " } else { "=" });
+        document_value(u, out, depth)?;
+        // entries have to be apart, or the next key would continue this value
+        out.push(' ');
+    }
+    space(u, out)
+}
+
+fn key(u: &mut Unstructured<'_>, out: &mut String, index: Option<usize>) -> Result<()> {
+    let name = *u.choose(&["a", "key", "_x", "snake_case", "kebab-case", "A1", "über"])?;
+    // identifiers are ascii only without the `unicode` feature
+    let quoted = u.arbitrary()? || !(name.is_ascii() || cfg!(feature = "unicode"));
+    if quoted {
+        out.push('"');
+    }
+    out.push_str(name);
+    if let Some(i) = index {
+        let _ = write!(out, "{i}");
+    }
+    if quoted {
+        out.push('"');
+    }
+    Ok(())
+}
+
+/// Writes whitespace and comments, possibly none.
+fn space(u: &mut Unstructured<'_>, out: &mut String) -> Result<()> {
+    for _ in 0..u.int_in_range(0..=2)? {
+        match u.choose_index(5)? {
+            0 => out.push(' '),
+            1 => out.push('\n'),
+            2 => out.push_str("\t\r\n"),
+            3 => out.push('\u{2028}'),
+            _ => out.push_str("// a comment = [\n"),
+        }
+    }
+    Ok(())
+}
+
+fn document_value(u: &mut Unstructured<'_>, out: &mut String, depth: u32) -> Result<()> {
+    let kinds = if depth == 0 { 6 } else { 8 };
+    match u.choose_index(kinds)? {
+        0 => out.push_str(u.choose(&["null", "true", "false"])?),
+        1 => integer(u, out)?,
+        2 => {
+            let v: f64 = u.arbitrary()?;
+            let v = if v.is_finite() { v } else { 0.5 };
+            // debug formatting always has a `.` or an exponent
+            let _ = write!(out, "{v:?}");
+        }
+        3 => {
+            // units go from largest to smallest, and the first group can have a fraction
+            let mut units: &[&str] = &["d", "h", "m", "s", "ms", "us", "ns"];
+            for i in 0..u.int_in_range(1..=3)? {
+                let amount = u.int_in_range(0..=1_000u32)?;
+                let _ = write!(out, "{amount}");
+                if i == 0 && u.ratio(1, 4)? {
+                    let _ = write!(out, ".{}", u.int_in_range(0..=999u32)?);
+                }
+                let unit = u.choose_index(units.len())?;
+                out.push_str(units[unit]);
+                units = &units[unit + 1..];
+                if units.is_empty() {
+                    break;
+                }
+            }
+        }
+        4 => quoted(u, out, '"')?,
+        5 => quoted(u, out, '\'')?,
+        6 => {
+            out.push('[');
+            for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                space(u, out)?;
+                document_value(u, out, depth - 1)?;
+                out.push(' ');
+            }
+            space(u, out)?;
+            out.push(']');
+        }
+        _ => {
+            out.push('{');
+            entries(u, out, depth - 1)?;
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+/// Writes an integer in any base, with `_` between digits now and then.
+fn integer(u: &mut Unstructured<'_>, out: &mut String) -> Result<()> {
+    let negative: bool = u.arbitrary()?;
+    let magnitude: u64 = if negative {
+        u.int_in_range(0..=1 << 63)?
+    } else {
+        u.arbitrary()?
+    };
+    let (prefix, digits) = match u.choose_index(4)? {
+        0 => ("", format!("{magnitude}")),
+        1 => ("0x", format!("{magnitude:x}")),
+        2 => ("0o", format!("{magnitude:o}")),
+        _ => ("0b", format!("{magnitude:b}")),
+    };
+    if negative {
+        out.push('-');
+    }
+    out.push_str(prefix);
+    let digits: Vec<char> = digits.chars().collect();
+    for (i, &c) in digits.iter().enumerate() {
+        if i > 0 && u.ratio(1, 8)? {
+            out.push('_');
+        }
+        out.push(c);
+    }
+    Ok(())
+}
+
+/// Writes a string or char literal with escapes.
+fn quoted(u: &mut Unstructured<'_>, out: &mut String, quote: char) -> Result<()> {
+    out.push(quote);
+    let len = if quote == '\'' {
+        1
+    } else {
+        u.int_in_range(0..=8)?
+    };
+    for _ in 0..len {
+        let c: char = u.arbitrary()?;
+        match u.choose_index(4)? {
+            0 => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            1 => {
+                let _ = write!(out, "\\x{:02x}", c as u32 % 0x80);
+            }
+            2 => out.push_str(u.choose(&["\\n", "\\r", "\\t", "\\0", "\\\\", "\\\"", "\\'"])?),
+            _ if matches!(c, '"' | '\'' | '\\' | '\n') => out.push('_'),
+            _ => out.push(c),
+        }
+    }
+    out.push(quote);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::from_str;
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn arbitrary_round_trip() {
+        // a xorshift generator, so the inputs are the same every run
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut bytes = vec![0; 4096];
+        for _ in 0..500 {
+            for b in &mut bytes {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                *b = state as u8;
+            }
+            let value = Value::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            let value = Value::Map(Map::from([("v".into(), value)]));
+            let src = crate::to_string(&value).unwrap();
+            assert_eq!(from_str::<Value>(&src).unwrap(), value, "{src}");
+
+            let Document(src) = Document::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            let value = src
+                .parse::<Value>()
+                .unwrap_or_else(|err| panic!("{err}\n{src}"));
+            assert_eq!(from_str::<Value>(&src).unwrap(), value, "{src}");
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! A document model allocated in a [`bumpalo`] arena, for documents that are parsed,
+//! queried and dropped. Strings without escapes and keys borrow from the source,
+//! and everything else is bump allocated, so dropping the arena frees it all at once.
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    boxed::Box,
+    vec::Vec,
+};
+use core::time::Duration;
+
+use bumpalo::{collections::Vec as BumpVec, Bump};
+
+use crate::cursor::{Keyword, TokenKind};
+use crate::error::Error;
+use crate::parse::{
+    starts_key, starts_value, ContextFrame, ExpectedSet, ParseError, ParseErrorKind, Parser,
+    Reference, SpanToken,
+};
+use crate::value::Interner;
+
+/// Like [`crate::Value`], but borrowed from an arena and the source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value<'a> {
+    Null,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    /// An unsigned integer that does not fit in a `u64`.
+    U128(u128),
+    /// A signed integer that does not fit in an `i64`.
+    I128(i128),
+    F64(f64),
+    String(&'a str),
+    Duration(Duration),
+    List(&'a [Value<'a>]),
+    /// The entries sorted by key, without duplicates.
+    Map(&'a [(&'a str, Value<'a>)]),
+}
+
+impl<'a> Value<'a> {
+    /// Parses the next value from `parser` into `bump`. Entries are merged like
+    /// [`crate::Value::parse`] does.
+    pub fn parse(parser: &mut Parser<'a>, bump: &'a Bump) -> Result<Value<'a>, ParseError> {
+        let context = parser.context.len();
+        let res = parse_node(parser, bump)
+            .map_err(|mut err| {
+                parser.add_context(&mut err);
+                err
+            })
+            .and_then(|node| finish(node, bump, &mut Vec::new()));
+        parser.context.truncate(context);
+        res
+    }
+
+    /// Like [`parse`](Self::parse), but reads top level entries up to the end of the
+    /// input into a map.
+    pub fn parse_document(
+        parser: &mut Parser<'a>,
+        bump: &'a Bump,
+    ) -> Result<Value<'a>, ParseError> {
+        let context = parser.context.len();
+        let res = parse_entries(parser, bump, TokenKind::Eof)
+            .map_err(|mut err| {
+                parser.add_context(&mut err);
+                err
+            })
+            .and_then(|entries| finish(Node::Map(entries), bump, &mut Vec::new()));
+        parser.context.truncate(context);
+        res
+    }
+
+    /// Parses a document or a single value, like [`from_str`](crate::from_str).
+    pub fn from_str(src: &'a str, bump: &'a Bump) -> Result<Value<'a>, Error> {
+        let mut parser = Parser::new(src);
+        if parser.starts_top_level() {
+            Ok(Value::parse_document(&mut parser, bump)?)
+        } else {
+            let value = Value::parse(&mut parser, bump)?;
+            parser.end_of_input()?;
+            Ok(value)
+        }
+    }
+
+    /// The value of `key` if this is a map that has it.
+    pub fn get(&self, key: &str) -> Option<&Value<'a>> {
+        match self {
+            Self::Map(entries) => entries
+                .binary_search_by(|&(k, _)| k.cmp(key))
+                .ok()
+                .map(|i| &entries[i].1),
+            _ => None,
+        }
+    }
+}
+
+impl From<Value<'_>> for crate::Value {
+    fn from(value: Value<'_>) -> Self {
+        to_owned(value, &mut Interner::new())
+    }
+}
+
+fn to_owned(value: Value<'_>, interner: &mut Interner) -> crate::Value {
+    match value {
+        Value::Null => crate::Value::Null,
+        Value::Bool(v) => crate::Value::Bool(v),
+        Value::U64(v) => crate::Value::U64(v),
+        Value::I64(v) => crate::Value::I64(v),
+        Value::U128(v) => crate::Value::U128(v),
+        Value::I128(v) => crate::Value::I128(v),
+        Value::F64(v) => crate::Value::F64(v),
+        Value::String(v) => crate::Value::String(v.to_owned()),
+        Value::Duration(v) => crate::Value::Duration(v),
+        Value::List(v) => crate::Value::List(v.iter().map(|&v| to_owned(v, interner)).collect()),
+        Value::Map(v) => crate::Value::Map(
+            v.iter()
+                .map(|&(k, v)| (interner.intern(k), to_owned(v, interner)))
+                .collect(),
+        ),
+    }
+}
+
+/// A value whose maps have not been merged yet.
+enum Node<'a> {
+    Leaf(Value<'a>),
+    List(BumpVec<'a, Node<'a>>),
+    Map(BumpVec<'a, Entry<'a>>),
+}
+
+struct Entry<'a> {
+    key: &'a str,
+    // the key and the first token of the value, for merge conflicts
+    key_span: SpanToken,
+    token: SpanToken,
+    // whether the entry was made by a dotted key, which later entries merge into
+    dotted: bool,
+    node: Node<'a>,
+}
+
+fn parse_node<'a>(parser: &mut Parser<'a>, bump: &'a Bump) -> Result<Node<'a>, ParseError> {
+    let next = parser.peek_token();
+    let value = match next.token.kind {
+        TokenKind::StartSquare => {
+            parser.start_list()?;
+            let mut list = BumpVec::new_in(bump);
+            loop {
+                let next = parser.peek_token();
+                if next.token.kind == TokenKind::EndSquare {
+                    break;
+                }
+                if !starts_value(next.token.kind) {
+                    let expected = ExpectedSet::VALUE | ExpectedSet::LIST_END;
+                    return Err(ParseError::new(next, ParseErrorKind::Expected(expected)));
+                }
+                parser.check_elements(list.len() + 1, next)?;
+                parser.push_context(ContextFrame::Element(list.len()));
+                list.push(parse_node(parser, bump)?);
+                parser.pop_context();
+            }
+            parser.end_list()?;
+            return Ok(Node::List(list));
+        }
+        TokenKind::StartCurly => {
+            parser.start_map()?;
+            let entries = parse_entries(parser, bump, TokenKind::EndCurly)?;
+            parser.end_map()?;
+            return Ok(Node::Map(entries));
+        }
+        TokenKind::Keyword(Keyword::Null) => {
+            parser.parse_null()?;
+            Value::Null
+        }
+        TokenKind::Keyword(_) => Value::Bool(parser.parse_bool()?),
+        // integers that do not fit in 64 bits are kept as 128 bit ones
+        TokenKind::Integer { sign: false, .. } => {
+            let v = parser.parse_u128()?;
+            u64::try_from(v).map_or(Value::U128(v), Value::U64)
+        }
+        TokenKind::Integer { sign: true, .. } => {
+            let v = parser.parse_i128()?;
+            i64::try_from(v).map_or(Value::I128(v), Value::I64)
+        }
+        TokenKind::Float => Value::F64(parser.parse_float()?),
+        TokenKind::Duration => Value::Duration(parser.parse_duration()?),
+        TokenKind::String { .. } => Value::String(parse_str(parser, bump)?),
+        TokenKind::Ident if parser.bare_strings => Value::String(parse_str(parser, bump)?),
+        TokenKind::Char { .. } => {
+            let c = parser.parse_char()?;
+            Value::String(bump.alloc_str(c.encode_utf8(&mut [0; 4])))
+        }
+        _ => return Err(ParseError::new(next, ParseErrorKind::ExpectedValue)),
+    };
+    Ok(Node::Leaf(value))
+}
+
+/// Reads a string, copying it into `bump` only if it had escapes.
+fn parse_str<'a>(parser: &mut Parser<'a>, bump: &'a Bump) -> Result<&'a str, ParseError> {
+    Ok(match parser.parse_str_scratch()? {
+        Reference::Borrowed(s) => s,
+        Reference::Copied(s) => bump.alloc_str(s),
+    })
+}
+
+/// Reads the entries of a map up to, but not including, `closer`.
+fn parse_entries<'a>(
+    parser: &mut Parser<'a>,
+    bump: &'a Bump,
+    closer: TokenKind,
+) -> Result<BumpVec<'a, Entry<'a>>, ParseError> {
+    let mut entries = BumpVec::new_in(bump);
+    loop {
+        let next = parser.peek_token();
+        if next.token.kind == closer {
+            return Ok(entries);
+        }
+        parser.check_elements(entries.len() + 1, next)?;
+        if !starts_key(next.token.kind) {
+            let expected = ExpectedSet::IDENT | ExpectedSet::closing(closer);
+            return Err(ParseError::new(next, ParseErrorKind::Expected(expected)));
+        }
+        let path = parser.parse_path_ref()?;
+        let delimiter = parser.peek_token();
+        parser.map_delimiter()?;
+        let token = parser.peek_token();
+        if parser.value_missing(delimiter.line) {
+            return Err(ParseError::new(token, ParseErrorKind::ExpectedValue));
+        }
+        for &(segment, _) in &path.segments {
+            parser.push_context(ContextFrame::Key(Cow::Borrowed(segment)));
+        }
+        let node = parse_node(parser, bump)?;
+        let len = parser.context.len() - path.segments.len();
+        parser.context.truncate(len);
+
+        // `a.b.c = v` is read as `a = { b = { c = v } }`, marked as dotted
+        let (&(key, key_span), parents) = path.segments.split_last().expect("path is not empty");
+        let mut entry = Entry {
+            key,
+            key_span,
+            token,
+            dotted: false,
+            node,
+        };
+        for &(key, key_span) in parents.iter().rev() {
+            let mut map = BumpVec::with_capacity_in(1, bump);
+            map.push(entry);
+            entry = Entry {
+                key,
+                key_span,
+                token,
+                dotted: true,
+                node: Node::Map(map),
+            };
+        }
+        entries.push(entry);
+    }
+}
+
+/// Merges the maps in `node`, whose path is `path`.
+fn finish<'a>(
+    node: Node<'a>,
+    bump: &'a Bump,
+    path: &mut Vec<&'a str>,
+) -> Result<Value<'a>, ParseError> {
+    match node {
+        Node::Leaf(value) => Ok(value),
+        Node::List(list) => {
+            let mut out = BumpVec::with_capacity_in(list.len(), bump);
+            for node in list {
+                out.push(finish(node, bump, path)?);
+            }
+            Ok(Value::List(out.into_bump_slice()))
+        }
+        Node::Map(mut entries) => {
+            // the sort is stable, so entries with the same key stay in order
+            entries.sort_by(|a, b| a.key.cmp(b.key));
+            let mut merged: BumpVec<Entry> = BumpVec::with_capacity_in(entries.len(), bump);
+            for next in entries {
+                match merged.last_mut() {
+                    Some(prev) if prev.key == next.key => merge(prev, next, path)?,
+                    _ => merged.push(next),
+                }
+            }
+            let mut out = BumpVec::with_capacity_in(merged.len(), bump);
+            for entry in merged {
+                path.push(entry.key);
+                out.push((entry.key, finish(entry.node, bump, path)?));
+                path.pop();
+            }
+            Ok(Value::Map(out.into_bump_slice()))
+        }
+    }
+}
+
+/// Merges `next` into an earlier entry with the same key. A key set again replaces
+/// the earlier value, unless one of them is dotted, in which case both have to be maps.
+fn merge<'a>(prev: &mut Entry<'a>, next: Entry<'a>, path: &[&str]) -> Result<(), ParseError> {
+    if !prev.dotted && !next.dotted {
+        *prev = next;
+        return Ok(());
+    }
+    let (plain, dotted) = match (&mut prev.node, next.node) {
+        (Node::Map(entries), Node::Map(more)) => {
+            entries.extend(more);
+            prev.dotted = true;
+            return Ok(());
+        }
+        _ if prev.dotted => (next.token, prev.key_span),
+        _ => (prev.token, next.key_span),
+    };
+    let key: Vec<&str> = path.iter().copied().chain([prev.key]).collect();
+    let mut err = ParseError::new(
+        plain,
+        ParseErrorKind::MergeConflict {
+            key: key.join("."),
+            other: Box::new(dotted),
+        },
+    );
+    err.context = key
+        .into_iter()
+        .map(|key| ContextFrame::Key(Cow::Owned(key.to_owned())))
+        .collect();
+    Err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arena_value() {
+        let src = "b = [1 \"x\\ty\"] a.c = 'q' a = { d = 2s }";
+        let bump = Bump::new();
+        let value = Value::from_str(src, &bump).unwrap();
+        assert_eq!(
+            value.get("a").and_then(|a| a.get("c")),
+            Some(&Value::String("q"))
+        );
+        assert_eq!(
+            crate::Value::from(value),
+            src.parse::<crate::Value>().unwrap()
+        );
+
+        let value = Value::from_str("a =\n    1", &bump).unwrap();
+        assert!(value.get("a").is_some());
+        assert!(Value::from_str("a =\nb = 1", &bump).is_err());
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use serde_json::json;
+use ycf::cursor::TokenKind;
+use ycf::error::{render_message, ErrorKind};
+use ycf::parse::Parser;
+use ycf::schema::{self, Schema};
+use ycf::SpanToken;
+
+use crate::Input;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The files to check, or stdin if there are none
+    files: Vec<PathBuf>,
+    /// How problems are written
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Fails on warnings as well as errors
+    #[arg(long)]
+    deny_warnings: bool,
+    /// A schema the files have to match
+    #[arg(long)]
+    schema: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Format {
+    /// Errors with a snippet of the source, for people
+    Text,
+    /// A JSON array of problems with 1 based lines and columns, for editors and CI
+    Json,
+}
+
+pub fn run(args: Args) -> Result<ExitCode, String> {
+    let paths = if args.files.is_empty() {
+        vec![None]
+    } else {
+        args.files.iter().map(|path| Some(path.as_path())).collect()
+    };
+
+    let schema = match &args.schema {
+        Some(path) => {
+            let input = Input::read(Some(path))?;
+            Some(
+                input
+                    .src
+                    .parse::<Schema>()
+                    .map_err(|err| input.render(err))?,
+            )
+        }
+        None => None,
+    };
+
+    let mut failed = false;
+    let mut errors = 0;
+    let mut warnings = 0;
+    let mut problems = Vec::new();
+    for path in paths {
+        let input = match Input::read(path) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("{err}");
+                failed = true;
+                continue;
+            }
+        };
+        for problem in check(&input.src, schema.as_ref()) {
+            match problem.severity() {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+            }
+            match args.format {
+                Format::Text => eprintln!("{}\n", problem.render(&input)),
+                Format::Json => problems.push(problem.to_json(&input)),
+            }
+        }
+    }
+    if let Format::Json = args.format {
+        println!("{}", serde_json::Value::Array(problems));
+    }
+
+    Ok(if failed {
+        ExitCode::from(2)
+    } else if errors > 0 || (args.deny_warnings && warnings > 0) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+enum Problem {
+    Error(ycf::Error),
+    Lint(Lint),
+}
+
+/// A warning about a document that is valid, but most likely not what was meant.
+struct Lint {
+    message: String,
+    token: SpanToken,
+    // another place the warning is about, like the first of two duplicate keys
+    note: Option<(&'static str, SpanToken)>,
+}
+
+/// Every syntax error in `src`, or if there are none, the first error in its
+/// structure, like a dotted key into a value that is not a map, along with the lints
+/// and everything that `schema` does not allow.
+fn check(src: &str, schema: Option<&Schema>) -> Vec<Problem> {
+    let top_level = Parser::new(src).starts_top_level();
+    let mut parser = Parser::new(src);
+    let errors = if top_level {
+        parser.check_top_level()
+    } else {
+        parser.check_value()
+    };
+    if !errors.is_empty() {
+        return errors
+            .into_iter()
+            .map(|err| Problem::Error(err.into()))
+            .collect();
+    }
+
+    let mut problems = Vec::new();
+    if let Err(err) = ycf::from_str::<ycf::Value>(src) {
+        problems.push(Problem::Error(err));
+    } else if let Some(schema) = schema {
+        match schema.validate_str(src) {
+            Ok(violations) => problems.extend(violations.into_iter().map(|violation| {
+                match (violation.severity, violation.span) {
+                    (schema::Severity::Warning, Some(token)) => Problem::Lint(Lint {
+                        message: violation.message,
+                        token,
+                        note: None,
+                    }),
+                    _ => Problem::Error(violation.into()),
+                }
+            })),
+            Err(err) => problems.push(Problem::Error(err)),
+        }
+    }
+    let mut linter = Linter {
+        parser: Parser::new(src),
+        lints: Vec::new(),
+    };
+    if top_level {
+        linter.entries(TokenKind::Eof, &[], &mut HashMap::new());
+    } else {
+        linter.value(&[], &mut HashMap::new());
+    }
+    problems.extend(linter.lints.into_iter().map(Problem::Lint));
+    problems
+}
+
+impl Problem {
+    fn severity(&self) -> Severity {
+        match self {
+            Problem::Error(_) => Severity::Error,
+            Problem::Lint(_) => Severity::Warning,
+        }
+    }
+
+    fn render(&self, input: &Input) -> String {
+        match self {
+            Problem::Error(err) => input.render(err.clone()),
+            Problem::Lint(lint) => render_message(
+                &input.src,
+                "warning",
+                &lint.message,
+                lint.token,
+                lint.note,
+                input.path.as_ref().map(|path| path.display()),
+            ),
+        }
+    }
+
+    fn to_json(&self, input: &Input) -> serde_json::Value {
+        let (message, path, span) = match self {
+            Problem::Error(err) => {
+                let message = match err.kind() {
+                    ErrorKind::Parse(err) => err.kind.display(err.token).to_string(),
+                    ErrorKind::Message(msg, _) => msg.clone(),
+                    ErrorKind::Io(err) => err.to_string(),
+                };
+                (message, err.path().map(|path| path.to_string()), err.span())
+            }
+            Problem::Lint(lint) => (lint.message.clone(), None, Some(lint.token)),
+        };
+        json!({
+            "file": input.path.as_ref().map(|path| path.display().to_string()),
+            "severity": match self.severity() {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            "message": message,
+            "path": path,
+            "line": span.map(|span| span.line + 1),
+            "column": span.map(|span| span.col + 1),
+            "endLine": span.map(|span| span.end_line + 1),
+            "endColumn": span.map(|span| span.end_col + 1),
+        })
+    }
+}
+
+/// Walks a document that is known to be valid syntax, collecting lints.
+struct Linter<'a> {
+    parser: Parser<'a>,
+    lints: Vec<Lint>,
+}
+
+/// The keys set in a map and its dotted keys, by their full path from the map, and
+/// the key that first set them.
+type Seen = HashMap<Vec<String>, SpanToken>;
+
+impl Linter<'_> {
+    /// Reads the entries of a map up to and including `closer`.
+    fn entries(&mut self, closer: TokenKind, prefix: &[String], seen: &mut Seen) {
+        loop {
+            let next = self.parser.next_token();
+            if next.token.kind == closer || next.token.kind == TokenKind::Eof {
+                return;
+            }
+
+            let mut path = prefix.to_vec();
+            let mut key = next;
+            loop {
+                let text = self.parser.src(key.token);
+                // quoted keys can not have escapes, so they only need their quotes taken off
+                let text = text
+                    .strip_prefix('"')
+                    .and_then(|text| text.strip_suffix('"'))
+                    .unwrap_or(text);
+                path.push(text.to_owned());
+                if self.parser.peek_kind() != TokenKind::Dot {
+                    break;
+                }
+                self.parser.next_token();
+                key = self.parser.next_token();
+            }
+            // the `=`
+            self.parser.next_token();
+
+            match seen.get(&path) {
+                Some(&first) => self.lints.push(Lint {
+                    message: format!(
+                        "`{}` is set more than once, which most types do not allow",
+                        path.join(".")
+                    ),
+                    token: key,
+                    note: Some(("first set", first)),
+                }),
+                None => {
+                    seen.insert(path.clone(), key);
+                }
+            }
+            self.value(&path, seen);
+        }
+    }
+
+    /// Reads a value, where the keys of a map are under `prefix` in `seen`.
+    fn value(&mut self, prefix: &[String], seen: &mut Seen) {
+        let next = self.parser.next_token();
+        match next.token.kind {
+            TokenKind::StartCurly => self.entries(TokenKind::EndCurly, prefix, seen),
+            TokenKind::StartSquare => {
+                while self.parser.peek_kind() != TokenKind::EndSquare {
+                    // maps in a list are separate from each other and the map around them
+                    self.value(&[], &mut HashMap::new());
+                }
+                self.parser.next_token();
+            }
+            TokenKind::String { escaped: true, .. } | TokenKind::Char { .. } => {
+                let text = self.parser.src(next.token);
+                if let Some(message) = suspicious_escape(text) {
+                    self.lints.push(Lint {
+                        message,
+                        token: next,
+                        note: None,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Finds escapes in a quoted literal that are valid, but were most likely meant as
+/// something else: `\0` before a digit, which is not an octal escape, and escapes
+/// in a Windows path like `"C:\temp"`, where `\t` is a tab rather than a backslash.
+fn suspicious_escape(text: &str) -> Option<String> {
+    let body = &text[1..text.len() - 1];
+    let bytes = body.as_bytes();
+    let is_path =
+        bytes.len() >= 3 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && bytes[2] == b'\\';
+
+    let mut escapes = body.match_indices('\\');
+    while let Some((i, _)) = escapes.next() {
+        let escape = &body[i..i + 2];
+        if escape == "\\\\" {
+            // the other half of `\\` is not an escape of its own
+            escapes.next();
+            continue;
+        }
+        let after = body[i + 2..].chars().next();
+        if escape == "\\0" && after.is_some_and(|c| c.is_ascii_digit()) {
+            return Some(format!(
+                "`{escape}` is a null character followed by a digit, not an octal escape, \
+                 write `\\u{{..}}` for a character by its code"
+            ));
+        }
+        if is_path {
+            return Some(format!(
+                "`{escape}` in what looks like a Windows path is an escape, \
+                 write `\\\\` for a backslash"
+            ));
+        }
+    }
+    None
+}
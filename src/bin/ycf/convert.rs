@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use serde::de::{Deserialize, DeserializeSeed, Deserializer, Error as _};
+
+use crate::Input;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The file to convert, or `-` for stdin
+    file: PathBuf,
+    /// The format of the file, by default from its extension
+    #[arg(long, value_enum)]
+    from: Option<Format>,
+    /// The format to convert to, by default from the extension of the output, or ycf
+    /// for files in other formats
+    #[arg(long, value_enum)]
+    to: Option<Format>,
+    /// The file to write, instead of stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    Ycf,
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "ycf" => Some(Format::Ycf),
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+}
+
+pub fn run(args: Args) -> Result<ExitCode, String> {
+    let from = args
+        .from
+        .or_else(|| Format::from_extension(&args.file))
+        .ok_or_else(|| {
+            format!(
+                "error: the format of `{}` is not known from its extension, give it with `--from`",
+                args.file.display()
+            )
+        })?;
+    let to = match (args.to, &args.output) {
+        (Some(to), _) => to,
+        (None, Some(output)) => Format::from_extension(output).ok_or_else(|| {
+            format!(
+                "error: the format of `{}` is not known from its extension, give it with `--to`",
+                output.display()
+            )
+        })?,
+        (None, None) if from != Format::Ycf => Format::Ycf,
+        (None, None) => return Err("error: give the format to convert to with `--to`".into()),
+    };
+
+    let input = Input::read(Some(&args.file))?;
+    let src = &input.src;
+    let mut out = match from {
+        Format::Ycf => ycf::from_str_seed(src, Output(to)).map_err(|err| input.render(err))?,
+        Format::Json => {
+            let mut de = serde_json::Deserializer::from_str(src);
+            let out = Output(to).deserialize(&mut de);
+            out.and_then(|out| de.end().map(|_| out))
+                .map_err(|err| format!("error: {}: {err}", input.name()))?
+        }
+        Format::Toml => toml::Deserializer::parse(src)
+            .and_then(|de| Output(to).deserialize(de))
+            .map_err(|err| format!("error: {}: {err}", input.name()))?,
+        Format::Yaml => Output(to)
+            .deserialize(serde_yaml_ng::Deserializer::from_str(src))
+            .map_err(|err| format!("error: {}: {err}", input.name()))?,
+    };
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+
+    match &args.output {
+        Some(path) => std::fs::write(path, out)
+            .map_err(|err| format!("error: cannot write `{}`: {err}", path.display()))?,
+        None => print!("{out}"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Transcodes whatever it is given into a document in a format.
+struct Output(Format);
+
+impl<'de> DeserializeSeed<'de> for Output {
+    type Value = String;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<String, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match self.0 {
+            Format::Ycf => {
+                ycf::transcode::from_deserializer(deserializer).map_err(D::Error::custom)
+            }
+            Format::Json => {
+                let mut out = Vec::new();
+                let mut serializer = serde_json::Serializer::pretty(&mut out);
+                serde_transcode::transcode(deserializer, &mut serializer)
+                    .map_err(D::Error::custom)?;
+                Ok(String::from_utf8(out).expect("serde_json should only write utf-8"))
+            }
+            Format::Toml => {
+                // the TOML serializer tries some values more than once, which a
+                // transcoder can not do, so the document is read into a table first
+                let table = toml::Table::deserialize(deserializer)?;
+                toml::to_string_pretty(&table).map_err(D::Error::custom)
+            }
+            Format::Yaml => {
+                let mut out = Vec::new();
+                let mut serializer = serde_yaml_ng::Serializer::new(&mut out);
+                serde_transcode::transcode(deserializer, &mut serializer)
+                    .map_err(D::Error::custom)?;
+                Ok(String::from_utf8(out).expect("serde_yaml_ng should only write utf-8"))
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use ycf::Value;
+
+use crate::Input;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The file to compare from
+    old: PathBuf,
+    /// The file to compare to
+    new: PathBuf,
+}
+
+/// Prints a line for every value that was removed (`-`) or added (`+`), by path, with
+/// a changed value as both. Fails if there are any, like `diff` does.
+pub fn run(args: Args) -> Result<ExitCode, String> {
+    let old = Input::read(Some(&args.old))?.value()?;
+    let new = Input::read(Some(&args.new))?.value()?;
+
+    let mut lines = Vec::new();
+    diff(Some(&old), Some(&new), "", &mut lines)?;
+    for line in &lines {
+        println!("{line}");
+    }
+    Ok(if lines.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn diff(
+    old: Option<&Value>,
+    new: Option<&Value>,
+    path: &str,
+    out: &mut Vec<String>,
+) -> Result<(), String> {
+    match (old, new) {
+        (Some(Value::Map(old)), Some(Value::Map(new))) => {
+            let keys: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+            for key in keys {
+                diff(old.get(key), new.get(key), &join(path, key), out)?;
+            }
+        }
+        (Some(Value::List(old)), Some(Value::List(new))) => {
+            for i in 0..old.len().max(new.len()) {
+                diff(old.get(i), new.get(i), &format!("{path}[{i}]"), out)?;
+            }
+        }
+        (Some(old), Some(new)) if old == new => (),
+        (old, new) => {
+            if let Some(old) = old {
+                leaves(old, path, '-', out)?;
+            }
+            if let Some(new) = new {
+                leaves(new, path, '+', out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a line for every value in `value` that is not a map or list, so a map
+/// that was added shows as its entries.
+fn leaves(value: &Value, path: &str, sign: char, out: &mut Vec<String>) -> Result<(), String> {
+    match value {
+        Value::Map(map) if !map.is_empty() => {
+            for (key, value) in map {
+                leaves(value, &join(path, key), sign, out)?;
+            }
+        }
+        Value::List(list) if !list.is_empty() => {
+            for (i, value) in list.iter().enumerate() {
+                leaves(value, &format!("{path}[{i}]"), sign, out)?;
+            }
+        }
+        _ => out.push(format!("{sign} {path} = {}", crate::to_string(value)?)),
+    }
+    Ok(())
+}
+
+/// Appends `key` to a dotted path, quoting it if it is not a plain identifier.
+fn join(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    let key = if plain {
+        key.to_owned()
+    } else {
+        format!("{key:?}")
+    };
+    if path.is_empty() {
+        key
+    } else {
+        format!("{path}.{key}")
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use ycf::fmt::{format, is_formatted, Config};
+
+use crate::Input;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The files to format in place, or stdin to stdout if there are none
+    files: Vec<PathBuf>,
+    /// Only lists the files that are not formatted, failing if there are any
+    #[arg(long)]
+    check: bool,
+    /// Writes the formatted files to stdout instead of in place
+    #[arg(long, conflicts_with = "check")]
+    stdout: bool,
+}
+
+pub fn run(args: Args) -> Result<ExitCode, String> {
+    let paths = if args.files.is_empty() {
+        vec![None]
+    } else {
+        args.files.iter().map(|path| Some(path.as_path())).collect()
+    };
+
+    let mut failed = false;
+    let mut unformatted = false;
+    for path in paths {
+        let input = match Input::read(path) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("{err}");
+                failed = true;
+                continue;
+            }
+        };
+        let config = Config::default();
+        if args.check && is_formatted(&input.src, &config) {
+            continue;
+        }
+        // this also gives the syntax errors of files that are not formatted
+        let formatted = match format(&input.src, &config) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!("{}", input.render(err));
+                failed = true;
+                continue;
+            }
+        };
+
+        if args.check {
+            println!("{}", input.name());
+            unformatted = true;
+        } else if args.stdout || input.path.is_none() {
+            io::stdout()
+                .write_all(formatted.as_bytes())
+                .map_err(|err| format!("error: cannot write to stdout: {err}"))?;
+        } else if formatted != input.src {
+            let path = input.path.as_ref().unwrap();
+            if let Err(err) = std::fs::write(path, formatted) {
+                eprintln!("error: cannot write `{}`: {err}", path.display());
+                failed = true;
+            }
+        }
+    }
+
+    Ok(if failed {
+        ExitCode::from(2)
+    } else if unformatted {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use ycf::{Path, Value};
+
+use crate::Input;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The file to read, or `-` for stdin
+    file: PathBuf,
+    /// The path of the value, like `server.port`, `servers[0].host` or `hosts."a.com"`
+    path: Path,
+    /// Writes strings without quotes or escapes
+    #[arg(long, conflicts_with = "json")]
+    raw: bool,
+    /// Writes the value as JSON, with durations as `{ secs, nanos }` objects
+    #[arg(long)]
+    json: bool,
+}
+
+pub fn run(args: Args) -> Result<ExitCode, String> {
+    let input = Input::read(Some(&args.file))?;
+    // only the value at the path is read, the rest of the file is skipped over
+    let value = ycf::get::<Value, _>(&input.src, &args.path).map_err(|err| input.render(err))?;
+    let Some(value) = &value else {
+        eprintln!("error: `{}` has no value at `{}`", input.name(), args.path);
+        return Ok(ExitCode::FAILURE);
+    };
+
+    let out = match value {
+        Value::String(s) if args.raw => s.clone(),
+        _ if args.json => serde_json::to_string_pretty(value).map_err(|err| err.to_string())?,
+        _ => crate::to_string(value)?,
+    };
+    println!("{out}");
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,131 @@
+//! The `ycf` command line tool.
+
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use ycf::parse::ColumnEncoding;
+use ycf::Value;
+
+mod check;
+mod convert;
+mod diff;
+mod fmt;
+mod get;
+mod merge;
+
+#[derive(Parser)]
+#[command(version, about = "Tools for ycf config files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Reformats files in the canonical layout
+    Fmt(fmt::Args),
+    /// Reports every syntax error in files, and warnings for likely mistakes
+    Check(check::Args),
+    /// Prints the value at a path in a file
+    Get(get::Args),
+    /// Converts files between ycf, JSON, TOML and YAML
+    Convert(convert::Args),
+    /// Merges files into one, where later files override earlier ones
+    Merge(merge::Args),
+    /// Shows the values that differ between two files, by path
+    Diff(diff::Args),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Fmt(args) => fmt::run(args),
+        Command::Check(args) => check::run(args),
+        Command::Get(args) => get::run(args),
+        Command::Convert(args) => convert::run(args),
+        Command::Merge(args) => merge::run(args),
+        Command::Diff(args) => diff::run(args),
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// A file given on the command line, where `-` or no file at all is stdin.
+pub struct Input {
+    pub path: Option<PathBuf>,
+    pub src: String,
+}
+
+impl Input {
+    /// Reads the input as bytes and decodes them like [`ycf::from_slice`] does, so
+    /// that a UTF-8 byte order mark is skipped and other encodings are reported.
+    pub fn read(path: Option<&Path>) -> Result<Self, String> {
+        let (path, bytes) = match path {
+            Some(path) if path != Path::new("-") => {
+                let bytes = std::fs::read(path)
+                    .map_err(|err| format!("error: cannot read `{}`: {err}", path.display()))?;
+                (Some(path.to_owned()), bytes)
+            }
+            _ => {
+                let mut bytes = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(|err| format!("error: cannot read stdin: {err}"))?;
+                (None, bytes)
+            }
+        };
+        match ycf::parse::decode(&bytes, ColumnEncoding::default()) {
+            Ok(src) => Ok(Self {
+                path,
+                src: src.to_owned(),
+            }),
+            Err(err) => {
+                let rest = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&bytes);
+                let input = Self {
+                    path,
+                    src: String::from_utf8_lossy(rest).into_owned(),
+                };
+                Err(input.render(err.into()))
+            }
+        }
+    }
+
+    /// The name of the input for messages.
+    pub fn name(&self) -> String {
+        match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "<stdin>".to_owned(),
+        }
+    }
+
+    /// Reads the input as a [`Value`].
+    pub fn value(&self) -> Result<Value, String> {
+        self.src.parse().map_err(|err| self.render(err))
+    }
+
+    /// Renders an error in this input with a snippet of the source.
+    pub fn render(&self, err: ycf::Error) -> String {
+        let err = match &self.path {
+            Some(path) => err.with_file(path),
+            None => err,
+        };
+        err.render(&self.src)
+    }
+}
+
+/// Writes `value` in ycf syntax, with braces around maps as they are not at the top level.
+pub fn to_string(value: &Value) -> Result<String, String> {
+    let mut out = Vec::new();
+    value
+        .serialize(&mut ycf::Serializer::new(&mut out))
+        .map_err(|err| format!("error: {err}"))?;
+    Ok(String::from_utf8(out).expect("serializer should only write utf-8"))
+}
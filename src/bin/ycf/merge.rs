@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::Input;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The files to merge, where each one overrides the ones before it
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+    /// The file to write, instead of stdout
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+    /// Sets a value after merging, like `server.port=9090`
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    overrides: Vec<String>,
+}
+
+pub fn run(args: Args) -> Result<ExitCode, String> {
+    let mut merged: Option<ycf::Value> = None;
+    for path in &args.files {
+        let value = Input::read(Some(path))?.value()?;
+        match &mut merged {
+            Some(merged) => merged.merge(value),
+            None => merged = Some(value),
+        }
+    }
+    let mut merged = merged.expect("clap requires at least one file");
+    ycf::overrides::apply_all(&mut merged, &args.overrides)
+        .map_err(|err| format!("error: {err}"))?;
+
+    let out = ycf::to_string(&merged).map_err(|err| format!("error: {err}"))?;
+    match &args.output {
+        Some(path) => std::fs::write(path, out)
+            .map_err(|err| format!("error: cannot write `{}`: {err}", path.display()))?,
+        None => print!("{out}"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,111 @@
+//! A [`config`] format for ycf, so ycf files can be sources of a `config::Config`.
+
+use std::error::Error as StdError;
+
+use config::{FileStoredFormat, Format, Map, ValueKind};
+
+use crate::error::Error;
+use crate::value::Value;
+
+/// The ycf [`Format`], used like the formats that come with `config`:
+///
+/// ```text
+/// Config::builder()
+///     .add_source(File::new("app", Ycf))
+///     .add_source(File::from_str(src, Ycf))
+///     .build()?
+/// ```
+///
+/// `config` has no durations, so they are given as `{ secs, nanos }` tables, which
+/// deserialize into a `Duration`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ycf;
+
+impl Format for Ycf {
+    fn parse(
+        &self,
+        uri: Option<&String>,
+        text: &str,
+    ) -> Result<Map<String, config::Value>, Box<dyn StdError + Send + Sync>> {
+        match text.parse()? {
+            Value::Map(map) => Ok(map
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), convert(uri, v)))
+                .collect()),
+            _ => Err(Error::custom("expected a map at the top level").into()),
+        }
+    }
+}
+
+impl FileStoredFormat for Ycf {
+    fn file_extensions(&self) -> &'static [&'static str] {
+        &["ycf"]
+    }
+}
+
+fn convert(uri: Option<&String>, value: Value) -> config::Value {
+    let kind = match value {
+        Value::Null => ValueKind::Nil,
+        Value::Bool(v) => ValueKind::Boolean(v),
+        Value::U64(v) => ValueKind::U64(v),
+        Value::I64(v) => ValueKind::I64(v),
+        Value::U128(v) => ValueKind::U128(v),
+        Value::I128(v) => ValueKind::I128(v),
+        Value::F64(v) => ValueKind::Float(v),
+        Value::String(v) => ValueKind::String(v),
+        Value::Duration(v) => ValueKind::Table(Map::from_iter([
+            ("secs".to_owned(), config::Value::new(uri, v.as_secs())),
+            (
+                "nanos".to_owned(),
+                config::Value::new(uri, v.subsec_nanos()),
+            ),
+        ])),
+        Value::List(v) => ValueKind::Array(v.into_iter().map(|v| convert(uri, v)).collect()),
+        Value::Map(v) => ValueKind::Table(
+            v.into_iter()
+                .map(|(k, v)| (k.to_string(), convert(uri, v)))
+                .collect(),
+        ),
+    };
+    config::Value::new(uri, kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use config::{Config, File};
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[test]
+    fn config_format() {
+        #[derive(Debug, Deserialize)]
+        struct Server {
+            host: String,
+            timeout: Duration,
+            ports: Vec<u16>,
+        }
+
+        let server: Server = Config::builder()
+            .add_source(File::from_str(
+                "server = { host = 'a' timeout = 1500ms ports = [1 2] }",
+                Ycf,
+            ))
+            .add_source(File::from_str("server.host = 'b'", Ycf))
+            .build()
+            .unwrap()
+            .get("server")
+            .unwrap();
+        assert_eq!(server.host, "b");
+        assert_eq!(server.timeout, Duration::from_millis(1500));
+        assert_eq!(server.ports, [1, 2]);
+
+        let err = Config::builder()
+            .add_source(File::from_str("[1 2]", Ycf))
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("top level"), "{err}");
+    }
+}
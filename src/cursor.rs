@@ -1,34 +1,86 @@
-use std::str::Chars;
+/// Whether `c` can start an identifier.
+#[inline]
+pub fn is_ident_start(c: char) -> bool {
+    #[cfg(feature = "unicode")]
+    {
+        unicode_ident::is_xid_start(c) || c == '_'
+    }
+    #[cfg(not(feature = "unicode"))]
+    {
+        c.is_ascii_alphabetic() || c == '_'
+    }
+}
 
-use unicode_ident::{is_xid_continue, is_xid_start};
+/// Whether `c` can continue an identifier.
+#[inline]
+pub fn is_ident_continue(c: char) -> bool {
+    #[cfg(feature = "unicode")]
+    {
+        unicode_ident::is_xid_continue(c) || c == '_' || c == '-'
+    }
+    #[cfg(not(feature = "unicode"))]
+    {
+        c.is_ascii_alphanumeric() || c == '_' || c == '-'
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Cursor<'a> {
-    chars: Chars<'a>,
     src: &'a str,
+    // byte offset of the next unread character, always on a char boundary
+    pos: usize,
+    // whether `#` starts a line comment, like `//`
+    hash_comments: bool,
 }
 
 impl<'a> Cursor<'a> {
     pub fn new(src: &'a str) -> Self {
         Self {
-            chars: src.chars(),
             src,
+            pos: 0,
+            hash_comments: false,
         }
     }
 
     #[inline]
-    fn eat(&mut self) -> Option<char> {
-        self.chars.next()
+    pub fn hash_comments(&self) -> bool {
+        self.hash_comments
     }
 
     #[inline]
-    fn peek(&self) -> Option<char> {
-        self.chars.clone().next()
+    pub fn set_hash_comments(&mut self, enabled: bool) {
+        self.hash_comments = enabled;
+    }
+
+    /// Eats the next byte. This must only be used when the next byte is ascii.
+    #[inline]
+    fn eat(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        debug_assert!(b.is_ascii());
+        self.pos += 1;
+        Some(b)
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    #[inline]
+    fn eat_char(&mut self) -> Option<char> {
+        let c = self.peek_char()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    #[inline]
+    fn peek_char(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
     }
 
     #[inline]
     pub fn is_eof(&self) -> bool {
-        self.peek().is_none()
+        self.pos >= self.src.len()
     }
 
     #[inline]
@@ -38,7 +90,7 @@ impl<'a> Cursor<'a> {
 
     #[inline]
     pub fn offset(&self) -> usize {
-        self.chars.as_str().as_ptr() as usize - self.src.as_ptr() as usize
+        self.pos
     }
 
     #[inline]
@@ -46,12 +98,53 @@ impl<'a> Cursor<'a> {
         &self.src[token.start..token.end]
     }
 
+    /// Eats ascii bytes while `pred` holds. `pred` must reject every non-ascii byte.
+    #[inline]
+    fn eat_while(&mut self, mut pred: impl FnMut(u8) -> bool) {
+        let bytes = self.src.as_bytes();
+        while self.pos < bytes.len() && pred(bytes[self.pos]) {
+            self.pos += 1;
+        }
+    }
+
+    /// Eats characters while `pred` holds, decoding utf-8 only for non-ascii bytes.
     #[inline]
-    fn eat_while(&mut self, mut pred: impl FnMut(char) -> bool) {
-        while self.peek().map(&mut pred).unwrap_or(false) {
-            self.eat();
+    fn eat_while_char(&mut self, mut pred: impl FnMut(char) -> bool) {
+        while let Some(b) = self.peek() {
+            if b.is_ascii() {
+                if !pred(b as char) {
+                    break;
+                }
+                self.pos += 1;
+            } else {
+                match self.peek_char() {
+                    Some(c) if pred(c) => self.pos += c.len_utf8(),
+                    _ => break,
+                }
+            }
         }
     }
+
+    /// Eats every byte up to, but not including, the next `needle` or the end of input.
+    #[inline]
+    fn eat_until(&mut self, needle: u8) {
+        let rest = &self.src.as_bytes()[self.pos..];
+        self.pos += memchr::memchr(needle, rest).unwrap_or(rest.len());
+    }
+
+    /// Eats a run of ascii whitespace, skipping indentation eight spaces at a time.
+    #[inline]
+    fn eat_ascii_whitespace(&mut self) {
+        const SPACES: u64 = u64::from_ne_bytes([b' '; 8]);
+        let bytes = self.src.as_bytes();
+        while let Some(chunk) = bytes.get(self.pos..self.pos + 8) {
+            if u64::from_ne_bytes(chunk.try_into().unwrap()) != SPACES {
+                break;
+            }
+            self.pos += 8;
+        }
+        self.eat_while(|b| matches!(b, b'\t' | b'\n' | 0x0B | 0x0C | b'\r' | b' '));
+    }
 }
 
 macro_rules! patterns {
@@ -70,10 +163,7 @@ macro_rules! patterns {
         '\u{2029}'   // paragraph separator
     };
     (dec_digits) => {
-        '0'..='9' | '_'
-    };
-    (hex_digits) => {
-        patterns!(dec_digits) | 'a'..='f' | 'A'..='F'
+        b'0'..=b'9' | b'_'
     };
 }
 
@@ -88,72 +178,120 @@ impl<'a> Cursor<'a> {
     }
 
     fn consume_token(&mut self) -> Option<TokenKind> {
-        match self.eat()? {
-            '/' => match self.peek() {
-                Some('/') => {
-                    // eat everything until the end of the line
-                    self.eat_while(|c| c != '\n');
-                    // eat the newline
-                    self.eat();
-                    Some(TokenKind::Comment)
-                }
-                _ => Some(TokenKind::Unknown),
+        let first = self.peek()?;
+        if !first.is_ascii() {
+            // only whitespace and identifiers can start with a non-ascii character
+            let c = self.eat_char()?;
+            return Some(match c {
+                patterns!(whitespace) => self.consume_whitespace(),
+                c if is_ident_start(c) => self.consume_ident(self.pos - c.len_utf8()),
+                _ => TokenKind::Unknown,
+            });
+        }
+        self.pos += 1;
+        Some(match first {
+            b'/' => match self.peek() {
+                Some(b'/') => self.consume_comment(),
+                _ => TokenKind::Unknown,
             },
-            patterns!(whitespace) => {
-                // eat all of the whitespace
-                self.eat_while(|c| matches!(c, patterns!(whitespace)));
-                Some(TokenKind::Whitespace)
+            b'#' if self.hash_comments => self.consume_comment(),
+            b'\t' | b'\n' | 0x0B | 0x0C | b'\r' | b' ' => self.consume_whitespace(),
+            b'[' => TokenKind::StartSquare,
+            b']' => TokenKind::EndSquare,
+            b'{' => TokenKind::StartCurly,
+            b'}' => TokenKind::EndCurly,
+            b'.' => TokenKind::Dot,
+            b'=' => TokenKind::Equal,
+            b @ (b'0'..=b'9' | b'-') => self.consume_number(b),
+            b'"' => {
+                let (terminated, escaped) = self.consume_quoted(b'"');
+                TokenKind::String {
+                    terminated,
+                    escaped,
+                }
             }
-            c if is_xid_start(c) || c == '_' => {
-                let start = self.offset() - c.len_utf8();
-                // eat the rest of the ident
-                self.eat_while(|c| is_xid_continue(c) || c == '_' || c == '-');
-                let end = self.offset();
-                Some(match &self.src[start..end] {
-                    "true" => TokenKind::Keyword(Keyword::True),
-                    "false" => TokenKind::Keyword(Keyword::False),
-                    "null" => TokenKind::Keyword(Keyword::Null),
-                    _ => TokenKind::Ident,
-                })
+            b'\'' => TokenKind::Char {
+                terminated: self.consume_quoted(b'\'').0,
+            },
+            b if is_ident_start(b as char) => self.consume_ident(self.pos - 1),
+            _ => TokenKind::Unknown,
+        })
+    }
+
+    fn consume_whitespace(&mut self) -> TokenKind {
+        // eat all of the whitespace, only decoding characters for the non-ascii kinds
+        loop {
+            self.eat_ascii_whitespace();
+            match self.peek() {
+                Some(b) if !b.is_ascii() => match self.peek_char() {
+                    Some(c @ patterns!(whitespace)) => self.pos += c.len_utf8(),
+                    _ => break,
+                },
+                _ => break,
             }
-            '[' => Some(TokenKind::StartSquare),
-            ']' => Some(TokenKind::EndSquare),
-            '{' => Some(TokenKind::StartCurly),
-            '}' => Some(TokenKind::EndCurly),
-            '.' => Some(TokenKind::Dot),
-            '=' => Some(TokenKind::Equal),
-            c @ ('0'..='9' | '-') => Some(self.consume_number(c)),
-            '"' => {
-                let mut terminated = false;
-                while let Some(v) = self.eat() {
-                    match v {
-                        '"' => {
-                            terminated = true;
-                            break;
-                        }
-                        '\\' if matches!(self.peek(), Some('"' | '\\')) => {
-                            self.eat();
-                        }
-                        '\n' => {
-                            break;
-                        }
-                        _ => (),
+        }
+        TokenKind::Whitespace
+    }
+
+    fn consume_ident(&mut self, start: usize) -> TokenKind {
+        // eat the rest of the ident
+        self.eat_while_char(is_ident_continue);
+        match &self.src[start..self.pos] {
+            "true" => TokenKind::Keyword(Keyword::True),
+            "false" => TokenKind::Keyword(Keyword::False),
+            "null" => TokenKind::Keyword(Keyword::Null),
+            _ => TokenKind::Ident,
+        }
+    }
+
+    fn consume_comment(&mut self) -> TokenKind {
+        // eat everything until the end of the line
+        self.eat_until(b'\n');
+        // eat the newline
+        self.eat();
+        TokenKind::Comment
+    }
+
+    /// Eats the rest of a quoted literal, returning whether the closing quote was found
+    /// and whether there were any escapes.
+    fn consume_quoted(&mut self, quote: u8) -> (bool, bool) {
+        // utf-8 continuation bytes are never ascii, so scanning bytes is enough, and
+        // only the quote, backslashes and newlines can end or change the literal
+        let bytes = self.src.as_bytes();
+        let mut escaped = false;
+        while let Some(i) = memchr::memchr3(quote, b'\\', b'\n', &bytes[self.pos..]) {
+            let b = bytes[self.pos + i];
+            self.pos += i + 1;
+            match b {
+                b if b == quote => return (true, escaped),
+                b'\\' => {
+                    escaped = true;
+                    if matches!(self.peek(), Some(b) if b == quote || b == b'\\') {
+                        self.pos += 1;
                     }
                 }
-                Some(TokenKind::String { terminated })
+                _ => {
+                    // the newline is left to the whitespace after, so lines are counted
+                    self.pos -= 1;
+                    return (false, escaped);
+                }
             }
-            _ => Some(TokenKind::Unknown),
         }
+        self.pos = bytes.len();
+        (false, escaped)
     }
 
-    fn consume_number(&mut self, mut first: char) -> TokenKind {
-        let hex_digits = |c| matches!(c, patterns!(hex_digits));
-        let dec_digits = |c| matches!(c, patterns!(dec_digits));
+    fn consume_number(&mut self, mut first: u8) -> TokenKind {
+        let radix_digits = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+        let dec_digits = |b| matches!(b, patterns!(dec_digits));
 
-        let sign = if first == '-' {
-            first = match self.eat() {
-                Some(v) => v,
-                None => {
+        let sign = if first == b'-' {
+            first = match self.peek() {
+                Some(v @ b'0'..=b'9') => {
+                    self.eat();
+                    v
+                }
+                _ => {
                     return TokenKind::Integer {
                         sign: true,
                         base: Base::Dec,
@@ -165,30 +303,33 @@ impl<'a> Cursor<'a> {
             false
         };
 
-        if first == '0' {
+        if first == b'0' {
             match self.peek() {
-                Some('x') => {
+                Some(b'x') => {
                     self.eat();
-                    // eat hex number
-                    self.eat_while(hex_digits);
+                    // eat hex number, digits that are invalid for the base
+                    // are left for the parser to report
+                    self.eat_while(radix_digits);
                     return TokenKind::Integer {
                         sign,
                         base: Base::Hex,
                     };
                 }
-                Some('o') => {
+                Some(b'o') => {
                     self.eat();
-                    // eat oct number
-                    self.eat_while(dec_digits);
+                    // eat oct number, digits that are invalid for the base
+                    // are left for the parser to report
+                    self.eat_while(radix_digits);
                     return TokenKind::Integer {
                         sign,
                         base: Base::Oct,
                     };
                 }
-                Some('b') => {
+                Some(b'b') => {
                     self.eat();
-                    // eat bin number
-                    self.eat_while(dec_digits);
+                    // eat bin number, digits that are invalid for the base
+                    // are left for the parser to report
+                    self.eat_while(radix_digits);
                     return TokenKind::Integer {
                         sign,
                         base: Base::Bin,
@@ -204,33 +345,59 @@ impl<'a> Cursor<'a> {
         }
 
         match self.peek() {
-            Some('.') => {
+            Some(b'.') => {
                 self.eat();
-                if matches!(self.peek(), Some('0'..='9')) {
+                if matches!(self.peek(), Some(b'0'..=b'9')) {
                     self.eat_while(dec_digits);
-                    if matches!(self.peek(), Some('e' | 'E')) {
-                        if matches!(self.peek(), Some('+' | '-')) {
+                    match self.peek() {
+                        Some(b'e' | b'E') => {
                             self.eat();
+                            if matches!(self.peek(), Some(b'+' | b'-')) {
+                                self.eat();
+                            }
+                            self.eat_while(dec_digits);
                         }
-                        self.eat_while(dec_digits);
+                        // a fractional duration, like `1.5s`
+                        Some(b'a'..=b'z' | b'A'..=b'Z') => return self.consume_duration(),
+                        _ => (),
                     }
                 }
                 TokenKind::Float
             }
-            Some('e' | 'E') => {
+            Some(b'e' | b'E') => {
                 self.eat();
-                if matches!(self.peek(), Some('+' | '-')) {
+                if matches!(self.peek(), Some(b'+' | b'-')) {
                     self.eat();
                 }
                 self.eat_while(dec_digits);
                 TokenKind::Float
             }
+            Some(b'a'..=b'z' | b'A'..=b'Z') => self.consume_duration(),
             _ => TokenKind::Integer {
                 sign,
                 base: Base::Dec,
             },
         }
     }
+
+    /// Eats the rest of a duration from its first unit.
+    fn consume_duration(&mut self) -> TokenKind {
+        let dec_digits = |b| matches!(b, patterns!(dec_digits));
+
+        // a duration is a run of `<digits><unit>` groups, like `2h30m` or `1.5s`
+        loop {
+            self.eat_while(|b| b.is_ascii_alphabetic());
+            if !matches!(self.peek(), Some(b'0'..=b'9')) {
+                break;
+            }
+            self.eat_while(dec_digits);
+            if self.peek() == Some(b'.') {
+                self.eat();
+                self.eat_while(dec_digits);
+            }
+        }
+        TokenKind::Duration
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -254,7 +421,10 @@ pub enum TokenKind {
     Equal,
     Integer { sign: bool, base: Base },
     Float,
-    String { terminated: bool },
+    Duration,
+    // `escaped` is whether the string has any backslash escapes
+    String { terminated: bool, escaped: bool },
+    Char { terminated: bool },
     Unknown,
     Eof,
 }
@@ -298,6 +468,16 @@ impl Base {
         }
     }
 
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Dec => "decimal",
+            Self::Hex => "hexadecimal",
+            Self::Oct => "octal",
+            Self::Bin => "binary",
+        }
+    }
+
     #[inline]
     pub fn radix(&self) -> u32 {
         match self {
@@ -307,4 +487,4 @@ impl Base {
             Self::Bin => 2,
         }
     }
-}
\ No newline at end of file
+}
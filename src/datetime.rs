@@ -0,0 +1,256 @@
+//! Datetimes as RFC 3339 strings like `"2024-05-01T12:00:00Z"`, for fields with
+//! `#[serde(with = "ycf::datetime::chrono")]` or `#[serde(with = "ycf::datetime::time")]`.
+//! Each module is behind a feature of the same name, and has an `option` module
+//! for optional fields.
+//!
+//! A datetime without an offset is an error that says so, rather than being read
+//! in some time zone that may not be the one that was meant.
+
+use alloc::{format, string::String};
+use core::fmt;
+
+use serde::de::{self, Visitor};
+
+/// Whether `s` is a datetime without an offset, like `2024-05-01T12:00:00`.
+fn is_local_datetime(s: &str) -> bool {
+    let b = s.as_bytes();
+    let digits = |range: core::ops::Range<usize>| b[range].iter().all(u8::is_ascii_digit);
+    b.len() >= 19
+        && digits(0..4)
+        && b[4] == b'-'
+        && digits(5..7)
+        && b[7] == b'-'
+        && digits(8..10)
+        && matches!(b[10], b'T' | b't' | b' ')
+        && digits(11..13)
+        && b[13] == b':'
+        && digits(14..16)
+        && b[16] == b':'
+        && digits(17..19)
+        && match &b[19..] {
+            [] => true,
+            [b'.', frac @ ..] => !frac.is_empty() && frac.iter().all(u8::is_ascii_digit),
+            _ => false,
+        }
+}
+
+/// The error for a string that is not a valid datetime.
+fn invalid<E: de::Error>(s: &str, err: impl fmt::Display) -> E {
+    if is_local_datetime(s) {
+        E::custom(format!(
+            "datetime `{s}` has no offset, add `Z` for UTC or one like `+02:00`"
+        ))
+    } else {
+        E::custom(format!(
+            "invalid datetime `{s}`: {err}, expected one like `2024-05-01T12:00:00Z`"
+        ))
+    }
+}
+
+/// Reads a string with `parse`.
+struct StrVisitor<F>(F);
+
+impl<'de, T, F> Visitor<'de> for StrVisitor<F>
+where
+    F: FnOnce(&str) -> Result<T, String>,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an RFC 3339 datetime string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        (self.0)(v).map_err(|err| invalid(v, err))
+    }
+}
+
+#[cfg(feature = "chrono")]
+pub mod chrono {
+    use alloc::string::ToString;
+
+    use chrono::{DateTime, FixedOffset, SecondsFormat, TimeZone};
+    use serde::{Deserializer, Serializer};
+
+    use super::StrVisitor;
+
+    pub fn serialize<S, Tz>(datetime: &DateTime<Tz>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        Tz: TimeZone,
+    {
+        serializer.serialize_str(&datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    /// Reads the datetime in the time zone of `Tz`, like `Utc` or `FixedOffset`.
+    pub fn deserialize<'de, D, Tz>(deserializer: D) -> Result<DateTime<Tz>, D::Error>
+    where
+        D: Deserializer<'de>,
+        Tz: TimeZone,
+        DateTime<Tz>: From<DateTime<FixedOffset>>,
+    {
+        deserializer.deserialize_str(StrVisitor(|s: &str| {
+            DateTime::parse_from_rfc3339(s)
+                .map(DateTime::from)
+                .map_err(|err| err.to_string())
+        }))
+    }
+
+    pub mod option {
+        use chrono::{DateTime, FixedOffset, TimeZone};
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S, Tz>(
+            datetime: &Option<DateTime<Tz>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            Tz: TimeZone,
+        {
+            match datetime {
+                Some(datetime) => super::serialize(datetime, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D, Tz>(deserializer: D) -> Result<Option<DateTime<Tz>>, D::Error>
+        where
+            D: Deserializer<'de>,
+            Tz: TimeZone,
+            DateTime<Tz>: From<DateTime<FixedOffset>>,
+        {
+            struct Datetime<Tz: TimeZone>(DateTime<Tz>);
+
+            impl<'de, Tz> Deserialize<'de> for Datetime<Tz>
+            where
+                Tz: TimeZone,
+                DateTime<Tz>: From<DateTime<FixedOffset>>,
+            {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    super::deserialize(deserializer).map(Datetime)
+                }
+            }
+
+            Ok(Option::<Datetime<Tz>>::deserialize(deserializer)?.map(|v| v.0))
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+pub mod time {
+    use alloc::string::ToString;
+
+    use serde::{ser, Deserializer, Serializer};
+    use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+    use super::StrVisitor;
+
+    pub fn serialize<S>(datetime: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = datetime.format(&Rfc3339).map_err(ser::Error::custom)?;
+        serializer.serialize_str(&s)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(StrVisitor(|s: &str| {
+            OffsetDateTime::parse(s, &Rfc3339).map_err(|err| err.to_string())
+        }))
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use time::OffsetDateTime;
+
+        pub fn serialize<S>(
+            datetime: &Option<OffsetDateTime>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match datetime {
+                Some(datetime) => super::serialize(datetime, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            struct Datetime(OffsetDateTime);
+
+            impl<'de> Deserialize<'de> for Datetime {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    super::deserialize(deserializer).map(Datetime)
+                }
+            }
+
+            Ok(Option::<Datetime>::deserialize(deserializer)?.map(|v| v.0))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "chrono", feature = "time", feature = "std"))]
+mod tests {
+    use ::chrono::{DateTime, FixedOffset, Utc};
+    use ::time::OffsetDateTime;
+    use serde::{Deserialize, Serialize};
+
+    use crate::de::from_str;
+
+    #[test]
+    fn datetimes() {
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Event {
+            #[serde(with = "crate::datetime::chrono")]
+            start: DateTime<Utc>,
+            #[serde(with = "crate::datetime::chrono")]
+            local: DateTime<FixedOffset>,
+            #[serde(with = "crate::datetime::time")]
+            end: OffsetDateTime,
+            #[serde(with = "crate::datetime::chrono::option", default)]
+            cancelled: Option<DateTime<Utc>>,
+            #[serde(with = "crate::datetime::time::option", default)]
+            moved: Option<OffsetDateTime>,
+        }
+
+        let src = "start = \"2024-05-01T12:00:00+02:00\"\n\
+                   local = \"2024-05-01T12:00:00.5+02:00\"\n\
+                   end = \"2024-05-01T13:30:00Z\"\n\
+                   cancelled = null\n\
+                   moved = \"2024-05-02T00:00:00Z\"";
+        let event: Event = from_str(src).unwrap();
+        assert_eq!(event.start.to_rfc3339(), "2024-05-01T10:00:00+00:00");
+        assert_eq!(event.local.offset().local_minus_utc(), 2 * 60 * 60);
+        assert_eq!(
+            event.end.unix_timestamp(),
+            event.start.timestamp() + 3 * 60 * 60 + 30 * 60
+        );
+        assert_eq!(event.cancelled, None);
+        assert!(event.moved.is_some());
+
+        let written = crate::to_string(&event).unwrap();
+        assert!(
+            written.contains("start = \"2024-05-01T10:00:00Z\""),
+            "{written}"
+        );
+        assert_eq!(from_str::<Event>(&written).unwrap(), event);
+
+        let src = "start = \"2024-05-01T12:00:00\"";
+        let err = from_str::<Event>(src).unwrap_err();
+        assert!(err.to_string().contains("has no offset"), "{err}");
+        let src = "start = \"yesterday\"";
+        let err = from_str::<Event>(src).unwrap_err();
+        assert!(
+            err.to_string().contains("invalid datetime `yesterday`"),
+            "{err}"
+        );
+    }
+}
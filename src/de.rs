@@ -1,44 +1,548 @@
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{marker::PhantomData, mem, str::FromStr, time::Duration};
+use serde::de::value::BorrowedStrDeserializer;
 use serde::de::{
     self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
     Visitor,
 };
 use serde::forward_to_deserialize_any;
+use smallvec::{smallvec, SmallVec};
+#[cfg(feature = "std")]
+use std::path::PathBuf;
 
 use crate::cursor::{Keyword, TokenKind};
-use crate::error::{Error, Result};
-use crate::parse::{ParseError, ParseErrorKind, Parser};
+use crate::error::{closest, Error, Result};
+use crate::parse::{
+    decode, starts_key, starts_value, BytesEncoding, Checkpoint, ColumnEncoding, ContextFrame, Env,
+    ExpectedSet, Limits, MapPathRef, ParseError, ParseErrorKind, Parser, Reference, SpanToken,
+    DEFAULT_MAX_DEPTH,
+};
+use crate::path::{AsPath, Path, Segment};
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    // whether `#` starts a line comment, in addition to `//`
+    pub hash_comments: bool,
+    // the maximum number of nested maps and lists, `None` for no limit.
+    // this also bounds the recursion of the deserializer, which dotted keys nest as well
+    pub max_depth: Option<u32>,
+    // what to do when a key appears more than once in the same map
+    pub duplicate_keys: DuplicateKeys,
+    pub limits: Limits,
+    // what columns in error positions count
+    pub column_encoding: ColumnEncoding,
+    // whether an identifier in value position is read as a string, like `level = debug`
+    pub bare_strings: bool,
+    // how strings given for bytes are decoded
+    pub bytes_encoding: BytesEncoding,
+    // whether a quoted number like `"8080"` is accepted where a number is expected
+    pub numeric_strings: bool,
+    // whether a float without a fractional part like `3.0` is accepted for an integer
+    pub integral_floats: bool,
+    // where `${NAME}` and `${NAME:-default}` in strings are substituted from, like
+    // `Env::process()` for environment variables. with `numeric_strings` they can be
+    // used for numbers too, like `port = "${PORT:-8080}"`
+    pub env: Option<Env>,
+    // how keys are rewritten before they are matched against fields
+    pub key_case: KeyCase,
+    // whether enum variants match ignoring ASCII case, like `level = "DEBUG"` for `Debug`
+    pub case_insensitive_variants: bool,
+    // whether `from_str` stops after a single value, leaving whatever follows it unread,
+    // for values embedded in a larger input
+    pub allow_trailing: bool,
+    // whether `null` is read as an empty list or map, like `tags = null`
+    pub null_as_empty: bool,
+    // whether a struct field set to `null` is read as if it was left out, so it takes
+    // its default with `#[serde(default)]`, like `timeout = null` in an overlay
+    pub null_as_default: bool,
+    // whether a key that is not a field of the struct it is in is an error pointing at
+    // the key, unlike `#[serde(deny_unknown_fields)]` which has no position
+    pub deny_unknown_fields: bool,
+    // the file the input was read from, which errors show before their position
+    #[cfg(feature = "std")]
+    pub file: Option<PathBuf>,
+    // whether the whole input is lexed up front, see `Parser::tokenize`. this uses more
+    // memory and is slower on typical documents (`cargo bench`), so it is only worth it
+    // for callers that read far ahead with `Parser::peek_nth` or checkpoints
+    pub pretokenize: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            hash_comments: false,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            duplicate_keys: DuplicateKeys::default(),
+            limits: Limits::default(),
+            column_encoding: ColumnEncoding::default(),
+            bare_strings: false,
+            bytes_encoding: BytesEncoding::default(),
+            numeric_strings: false,
+            integral_floats: false,
+            env: None,
+            key_case: KeyCase::default(),
+            case_insensitive_variants: false,
+            allow_trailing: false,
+            null_as_empty: false,
+            null_as_default: false,
+            deny_unknown_fields: false,
+            #[cfg(feature = "std")]
+            file: None,
+            pretokenize: false,
+        }
+    }
+}
+
+/// A key in the document that the type being deserialized did not use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnusedKey {
+    /// The path to the key, like `server.tls.port`.
+    pub path: Path,
+    /// The key itself, or its first segment for a dotted key.
+    pub key: SpanToken,
+}
+
+/// How entries with the same key (or dotted path) in one map are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Every entry is passed on, so the type being deserialized decides.
+    #[default]
+    Allow,
+    /// A repeated key is an error pointing at both entries.
+    Error,
+    /// The first entry is used and the rest are skipped.
+    FirstWins,
+    /// The last entry is used and the rest are skipped.
+    LastWins,
+}
+
+/// How keys are rewritten before they are matched against fields. Keys that are
+/// the same once rewritten count as the same key, so duplicates are still found.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyCase {
+    /// Keys are used as written.
+    #[default]
+    Preserve,
+    /// `-` is read as `_`, so `max-connections` matches a `max_connections` field.
+    Snake,
+    /// `_` is read as `-`, for types with `#[serde(rename_all = "kebab-case")]`.
+    Kebab,
+}
+
+impl KeyCase {
+    pub fn apply(self, key: &str) -> Cow<'_, str> {
+        let (from, to) = match self {
+            KeyCase::Preserve => return Cow::Borrowed(key),
+            KeyCase::Snake => ('-', "_"),
+            KeyCase::Kebab => ('_', "-"),
+        };
+        if key.contains(from) {
+            Cow::Owned(key.replace(from, to))
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+}
 
+/// Deserializes values from YCF text, reading the entries of maps as they are
+/// asked for. A dotted key that adds to a braced map read before it, like `a.y = 2`
+/// after `a = { x = 1 }`, is then a [`DottedAfterMap`](ParseErrorKind::DottedAfterMap)
+/// error. [`from_str`] and the functions like it read such documents again with
+/// every map read ahead, which merges them.
 pub struct Deserializer<'de> {
     parser: Parser<'de>,
+    duplicate_keys: DuplicateKeys,
+    key_case: KeyCase,
+    case_insensitive_variants: bool,
+    allow_trailing: bool,
+    null_as_empty: bool,
+    null_as_default: bool,
+    deny_unknown_fields: bool,
+    #[cfg(feature = "std")]
+    file: Option<PathBuf>,
+    // the keys skipped by the type being deserialized, if those are being recorded
+    unused: Option<Vec<UnusedKey>>,
+    // the first token of the value of the key read last, and that key
+    last_value: Option<(SpanToken, SpanToken)>,
+    // the number of seqs, maps and enums being deserialized
+    depth: u32,
+    max_depth: Option<u32>,
+    // where the maps and lists that were skipped over while reading ahead end, by the
+    // offset of their opening token, so that they are only read through once
+    skipped: BTreeMap<usize, Checkpoint<'de>>,
+    // whether every map is read through before its entries are given out, rather than
+    // only once a dotted key shows up in it. Only then can a dotted key be merged with
+    // a braced map before it, and the last of repeated keys be the one used
+    read_ahead: bool,
+    // whether a dotted key came after the braced map it adds to was read already
+    merged_late: bool,
 }
 
 impl<'de> Deserializer<'de> {
     #[allow(clippy::should_implement_trait)]
     pub fn from_str(input: &'de str) -> Self {
+        Self::with_options(input, Options::default())
+    }
+
+    pub fn with_options(input: &'de str, options: Options) -> Self {
+        let mut parser = Parser::new(input);
+        parser.cursor.set_hash_comments(options.hash_comments);
+        parser.max_depth = options.max_depth;
+        parser.limits = options.limits;
+        parser.column_encoding = options.column_encoding;
+        parser.bare_strings = options.bare_strings;
+        parser.bytes_encoding = options.bytes_encoding;
+        parser.numeric_strings = options.numeric_strings;
+        parser.integral_floats = options.integral_floats;
+        parser.env = options.env;
+        if options.pretokenize {
+            parser.tokenize();
+        }
         Self {
-            parser: Parser::new(input),
+            parser,
+            duplicate_keys: options.duplicate_keys,
+            key_case: options.key_case,
+            case_insensitive_variants: options.case_insensitive_variants,
+            allow_trailing: options.allow_trailing,
+            null_as_empty: options.null_as_empty,
+            null_as_default: options.null_as_default,
+            deny_unknown_fields: options.deny_unknown_fields,
+            #[cfg(feature = "std")]
+            file: options.file,
+            unused: None,
+            last_value: None,
+            depth: 0,
+            max_depth: options.max_depth,
+            skipped: BTreeMap::new(),
+            read_ahead: options.duplicate_keys == DuplicateKeys::LastWins,
+            merged_late: false,
+        }
+    }
+
+    /// Sets the file the input was read from, which errors then show before their
+    /// position, like `config/app.ycf:12:7`.
+    #[cfg(feature = "std")]
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// Attaches the file the input was read from to `err`, if it is known.
+    #[cfg(feature = "std")]
+    fn in_file(&self, err: Error) -> Error {
+        match &self.file {
+            Some(file) if err.file().is_none() => err.with_file(file),
+            _ => err,
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn in_file(&self, err: Error) -> Error {
+        err
+    }
+
+    /// Removes the limit on nesting, for documents that are trusted to be deep.
+    /// Without it, the stack can overflow on those instead.
+    pub fn disable_recursion_limit(&mut self) {
+        self.max_depth = None;
+        self.parser.max_depth = None;
+    }
+
+    /// Runs `f` one level deeper, erroring at `token` instead if that is too deep.
+    fn nested<T>(&mut self, token: SpanToken, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if let Some(max) = self.max_depth {
+            if self.depth >= max {
+                return Err(Error::parse(ParseError::new(
+                    token,
+                    ParseErrorKind::TooDeep { max },
+                )));
+            }
+        }
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res.map_err(|err| self.in_file(err))
+    }
+
+    /// Starts recording the keys that the type being deserialized skips over,
+    /// which are most likely misspelled or no longer used.
+    pub fn capture_unused(&mut self) {
+        self.unused.get_or_insert_with(Vec::new);
+    }
+
+    /// Takes the keys recorded since [`capture_unused`](Self::capture_unused).
+    pub fn take_unused(&mut self) -> Vec<UnusedKey> {
+        self.unused.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    /// Records the key at the innermost context as unused, if recording.
+    fn mark_unused(&mut self, key: SpanToken) {
+        if let Some(unused) = &mut self.unused {
+            unused.push(UnusedKey {
+                path: Path::from(&self.parser.context[..]),
+                key,
+            });
+        }
+    }
+
+    /// Checks that nothing but whitespace and comments follows the value that was
+    /// deserialized, erroring at the first token that does.
+    pub fn end(&mut self) -> Result<()> {
+        self.parser
+            .end_of_input()
+            .map_err(|err| self.in_file(Error::parse(err)))
+    }
+
+    /// The variant `name` is when ignoring case, if that is enabled and `name` is
+    /// not already one of `variants`. Otherwise the visitor gets `name` as is.
+    fn variant_name(&self, name: &str, variants: &'static [&'static str]) -> Option<&'static str> {
+        if !self.case_insensitive_variants || variants.contains(&name) {
+            return None;
+        }
+        variants
+            .iter()
+            .copied()
+            .find(|v| v.eq_ignore_ascii_case(name))
+    }
+
+    /// Deserializes a map written in braces, `{ key = value }`, which is a struct
+    /// with `fields` if those are given.
+    fn deserialize_braced_map<V>(
+        &mut self,
+        fields: Option<&'static [&'static str]>,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let token = self.parser.peek_token();
+        self.nested(token, |de| {
+            de.parser.start_map()?;
+            let mut access = MapAccessor::new(de, TokenKind::EndCurly, fields)?;
+            let out = visitor.visit_map(&mut access)?;
+            access.finish()?;
+            de.parser.end_map()?;
+            Ok(out)
+        })
+    }
+
+    /// Moves the parser to a position that was read ahead to or back from, keeping
+    /// the error context.
+    fn jump(&mut self, to: Checkpoint<'de>) {
+        let context = mem::take(&mut self.parser.context);
+        self.parser.restore(to);
+        self.parser.context = context;
+    }
+
+    /// Skips the value at the parser, going straight past it if it was read through
+    /// before. When `record` is set, where the maps and lists that are values of keys
+    /// in it end is remembered, so that reading ahead through the maps it is in reads
+    /// it only once.
+    fn skip_value(&mut self, record: bool) -> Result<()> {
+        let start = self.parser.peek_token();
+        if let Some(end) = self.skipped.get(&start.token.start) {
+            self.parser.skip_to(end);
+            return Ok(());
+        }
+        if !record {
+            return Ok(self.parser.skip_value()?);
+        }
+        let skipped = &mut self.skipped;
+        self.parser.skip_value_with(|open, end| {
+            skipped.insert(open.token.start, end);
+        })?;
+        Ok(())
+    }
+
+    /// Reads the key of the `count`th entry of a map body and the `=` after it,
+    /// returning the key and the line of the `=`, or `None` at the `closer` of the map.
+    fn read_key(
+        &mut self,
+        closer: TokenKind,
+        count: usize,
+    ) -> Result<Option<(MapPathRef<'de>, u32)>> {
+        if !self.key_start(closer, count)? {
+            return Ok(None);
+        }
+        let path = self.parser.parse_path_ref()?;
+        let delimiter = self.parser.peek_token();
+        self.parser.map_delimiter()?;
+        Ok(Some((path, delimiter.line)))
+    }
+
+    /// Checks that the next token starts the key of the `count`th entry of a map body,
+    /// or returns `false` at the `closer` of the map.
+    fn key_start(&mut self, closer: TokenKind, count: usize) -> Result<bool> {
+        let next = self.parser.peek_token();
+        if next.token.kind == closer {
+            return Ok(false);
+        }
+        self.parser.check_elements(count, next)?;
+        if !starts_key(next.token.kind) {
+            return Err(Error::parse(ParseError::new(
+                next,
+                ParseErrorKind::Expected(ExpectedSet::IDENT | ExpectedSet::closing(closer)),
+            )));
+        }
+        Ok(true)
+    }
+
+    /// The first token of the value of the key `path`, which has to start on `line`
+    /// or after it. Tokens that can not start any value are syntax errors here, rather
+    /// than whatever type is asked for not being found.
+    fn value_start(&mut self, path: &[(&'de str, SpanToken)], line: u32) -> Result<SpanToken> {
+        let value = self.parser.peek_token();
+        let kind = match value.token.kind {
+            TokenKind::Unknown => ParseErrorKind::UnknownToken,
+            _ if self.parser.value_missing(line) => ParseErrorKind::ExpectedValue,
+            kind if !starts_value(kind) => ParseErrorKind::ExpectedValue,
+            _ => return Ok(value),
+        };
+        let mut context = self.parser.context.clone();
+        context.extend(
+            path.iter()
+                .map(|&(segment, _)| ContextFrame::Key(Cow::Borrowed(segment))),
+        );
+        let mut err = Error::parse(ParseError::new(value, kind));
+        err.add_context(&context);
+        Err(err)
+    }
+
+    /// Reads ahead through the entries of a map body up to `closer`, recording where
+    /// each of their values is, and returns the position of the closer. The parser
+    /// is left there as well.
+    fn scan_entries(
+        &mut self,
+        closer: TokenKind,
+        out: &mut Vec<Source<'de>>,
+    ) -> Result<Checkpoint<'de>> {
+        for count in 1.. {
+            let Some((path, line)) = self.read_key(closer, count)? else {
+                break;
+            };
+            let value = self.value_start(&path.segments, line)?;
+            // errors in the value are in the context of its key, like when reading it
+            let frames = self.parser.context.len();
+            for &(segment, _) in &path.segments {
+                self.parser
+                    .push_context(ContextFrame::Key(Cow::Borrowed(segment)));
+            }
+            out.push(Source {
+                path,
+                segment: 0,
+                value,
+                at: self.parser.checkpoint(),
+            });
+            let res = self.skip_value(true);
+            self.parser.context.truncate(frames);
+            res?;
+        }
+        Ok(self.parser.checkpoint())
+    }
+
+    /// Reads a map again from its `start`, up to the first key that is `folded` after
+    /// case folding, which has to be in it.
+    fn find_key(
+        &mut self,
+        closer: TokenKind,
+        start: Checkpoint<'de>,
+        folded: &str,
+    ) -> Result<SpanToken> {
+        self.jump(start);
+        for count in 1.. {
+            let Some((path, line)) = self.read_key(closer, count)? else {
+                break;
+            };
+            let (key, token) = path.segments[0];
+            if self.key_case.apply(key) == folded {
+                return Ok(token);
+            }
+            self.value_start(&path.segments, line)?;
+            self.skip_value(false)?;
+        }
+        unreachable!("a key that was read is in the map")
+    }
+
+    /// Checks that every key is one of `fields`, if unknown fields are denied.
+    fn check_fields(
+        &self,
+        fields: &'static [&'static str],
+        keys: impl IntoIterator<Item = (&'de str, SpanToken)>,
+    ) -> Result<()> {
+        if !self.deny_unknown_fields {
+            return Ok(());
+        }
+        for (key, token) in keys {
+            if !fields.contains(&&*self.key_case.apply(key)) {
+                return Err(Error::parse(ParseError::new(
+                    token,
+                    ParseErrorKind::UnknownField(key.to_string(), closest(key, fields)),
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with `frame` pushed onto the parser's context, which is attached
+    /// to any parse error that `f` returns.
+    fn in_context<T>(
+        &mut self,
+        frame: ContextFrame<'de>,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.parser.push_context(frame);
+        let mut res = f(self);
+        if let Err(err) = &mut res {
+            err.add_context(&self.parser.context);
         }
+        self.parser.pop_context();
+        res.map_err(|err| self.in_file(err))
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        match self.parser.peek_token().token.kind {
+        match self.parser.peek_kind() {
             TokenKind::Keyword(Keyword::Null) => self.deserialize_unit(visitor),
             TokenKind::Keyword(Keyword::True | Keyword::False) => self.deserialize_bool(visitor),
-            TokenKind::Integer { sign: false, .. } => self.deserialize_u64(visitor),
-            TokenKind::Integer { sign: true, .. } => self.deserialize_i64(visitor),
+            // integers that do not fit in 64 bits are given as 128 bit ones
+            TokenKind::Integer { sign: false, .. } => {
+                let v = self.parser.parse_u128()?;
+                match u64::try_from(v) {
+                    Ok(v) => visitor.visit_u64(v),
+                    Err(_) => visitor.visit_u128(v),
+                }
+            }
+            TokenKind::Integer { sign: true, .. } => {
+                let v = self.parser.parse_i128()?;
+                match i64::try_from(v) {
+                    Ok(v) => visitor.visit_i64(v),
+                    Err(_) => visitor.visit_i128(v),
+                }
+            }
             TokenKind::Float => self.deserialize_f64(visitor),
+            TokenKind::Duration => {
+                visitor.visit_map(DurationDeserializer::new(self.parser.parse_duration()?))
+            }
             TokenKind::String { .. } => self.deserialize_string(visitor),
+            TokenKind::Ident if self.parser.bare_strings => self.deserialize_string(visitor),
+            TokenKind::Char { .. } => self.deserialize_char(visitor),
             TokenKind::StartSquare => self.deserialize_seq(visitor),
             TokenKind::StartCurly => self.deserialize_map(visitor),
             _ => Err(Error::parse(
-                self.parser.error(ParseErrorKind::UnknownToken),
+                self.parser.error(ParseErrorKind::ExpectedValue),
             )),
         }
     }
@@ -78,6 +582,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_i64(self.parser.parse_int()?)
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i128(self.parser.parse_i128()?)
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
@@ -106,6 +617,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_u64(self.parser.parse_uint()?)
     }
 
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u128(self.parser.parse_u128()?)
+    }
+
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
@@ -124,35 +642,60 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_str(visitor)
+        if let Some(r) = self.parser.try_parse_char() {
+            return visitor.visit_char(r?);
+        }
+        // single character strings are accepted as well
+        let next = self.parser.peek_token();
+        let s = self.parser.parse_str_scratch().map_err(|mut e| {
+            if e.kind == ParseErrorKind::ExpectedString {
+                e.kind = ParseErrorKind::ExpectedChar;
+            }
+            e
+        })?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::parse(ParseError::new(
+                next,
+                ParseErrorKind::InvalidChar,
+            ))),
+        }
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_str(&self.parser.parse_string()?)
+        match self.parser.parse_str_scratch()? {
+            Reference::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Reference::Copied(s) => visitor.visit_str(s),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_string(self.parser.parse_string()?)
+        self.deserialize_str(visitor)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.deserialize_byte_buf(visitor)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        // either a string in the `bytes_encoding` or a list of integers
+        match self.parser.peek_kind() {
+            TokenKind::String { .. } => visitor.visit_byte_buf(self.parser.parse_bytes()?),
+            _ => self.deserialize_seq(visitor),
+        }
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
@@ -181,10 +724,18 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if name == VALUE_NAME {
+            // `Value` asks for its durations to be marked so it can tell them apart
+            // from maps, everything else sees the plain `{ secs, nanos }` struct
+            if let Some(r) = self.parser.try_parse_duration() {
+                return visitor.visit_map(DurationValueMap { duration: Some(r?) });
+            }
+            return self.deserialize_any(visitor);
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -192,10 +743,16 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.parser.start_list()?;
-        let out = visitor.visit_seq(&mut *self)?;
-        self.parser.end_list()?;
-        Ok(out)
+        if self.null_as_empty && self.parser.try_parse_null().is_some() {
+            return visitor.visit_seq(de::value::SeqDeserializer::new(core::iter::empty::<()>()));
+        }
+        let token = self.parser.peek_token();
+        self.nested(token, |de| {
+            de.parser.start_list()?;
+            let out = visitor.visit_seq(SeqAccessor { de, index: 0 })?;
+            de.parser.end_list()?;
+            Ok(out)
+        })
     }
 
     fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
@@ -221,41 +778,78 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.parser.start_map()?;
-        let out = visitor.visit_map(&mut *self)?;
-        self.parser.end_map()?;
-        Ok(out)
+        if self.null_as_empty && self.parser.try_parse_null().is_some() {
+            return visitor.visit_map(de::value::MapDeserializer::new(
+                core::iter::empty::<((), ())>(),
+            ));
+        }
+        self.deserialize_braced_map(None, visitor)
     }
-
     fn deserialize_struct<V>(
         self,
-        _name: &'static str,
-        _fields: &'static [&'static str],
+        name: &'static str,
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_map(visitor)
+        if let Some(r) = self.parser.try_parse_duration() {
+            // `std::time::Duration` is a struct of `secs` and `nanos`
+            return visitor.visit_map(DurationDeserializer::new(r?));
+        }
+        if name == "Duration"
+            && fields == ["secs", "nanos"]
+            && self.parser.peek_kind() != TokenKind::StartCurly
+        {
+            // otherwise `5` would be "expected a map", which is true but not helpful
+            let token = self.parser.peek_token();
+            return Err(Error::parse(ParseError::new(
+                token,
+                ParseErrorKind::ExpectedDuration,
+            )));
+        }
+        self.deserialize_braced_map(Some(fields), visitor)
     }
 
     fn deserialize_enum<V>(
         self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if let Some(r) = self.parser.try_parse_string() {
-            visitor.visit_enum(r?.into_deserializer())
-        } else if self.parser.try_start_map().is_some() {
-            let v = visitor.visit_enum(&mut *self)?;
-            self.parser.end_map()?;
-            Ok(v)
+        let next = self.parser.peek_token();
+        if next.token.kind == TokenKind::Ident {
+            // a unit variant written without quotes, like `mode = fast`
+            self.parser.next_token();
+            let name = self.parser.src(next.token);
+            let name = self.variant_name(name, variants).unwrap_or(name);
+            visitor.visit_enum(BorrowedStrDeserializer::new(name))
+        } else if let Some(r) = self.parser.try_parse_str() {
+            match r? {
+                Cow::Borrowed(s) => visitor.visit_enum(BorrowedStrDeserializer::new(
+                    self.variant_name(s, variants).unwrap_or(s),
+                )),
+                Cow::Owned(s) => match self.variant_name(&s, variants) {
+                    Some(name) => visitor.visit_enum(BorrowedStrDeserializer::new(name)),
+                    None => visitor.visit_enum(s.into_deserializer()),
+                },
+            }
+        } else if next.token.kind == TokenKind::StartCurly {
+            self.nested(next, |de| {
+                de.parser.start_map()?;
+                let v = visitor.visit_enum(EnumAccessor { de, variants })?;
+                de.parser.end_map()?;
+                Ok(v)
+            })
         } else {
-            Err(Error::custom("expected an enum"))
+            Err(Error::parse(ParseError::new(
+                next,
+                ParseErrorKind::ExpectedEnum,
+            )))
         }
     }
 
@@ -270,156 +864,904 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        if let Some((value, key)) = self.last_value {
+            if value == self.parser.peek_token() {
+                self.mark_unused(key);
+            }
+        }
+        self.skip_value(false)?;
+        visitor.visit_unit()
     }
 }
 
-impl<'de, 'a> SeqAccess<'de> for &'a mut Deserializer<'de> {
+struct SeqAccessor<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    index: usize,
+}
+
+impl<'de> SeqAccess<'de> for SeqAccessor<'_, 'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: DeserializeSeed<'de>,
     {
-        if self.parser.peek_end_list() {
-            return Ok(None);
+        let next = self.de.parser.peek_token();
+        if next.token.kind == TokenKind::EndSquare {
+            Ok(None)
+        } else if starts_value(next.token.kind) {
+            let index = self.index;
+            self.index += 1;
+            self.de.parser.check_elements(self.index, next)?;
+            self.de
+                .in_context(ContextFrame::Element(index), |de| seed.deserialize(de))
+                .map(Some)
+        } else {
+            Err(Error::parse(ParseError::new(
+                next,
+                ParseErrorKind::Expected(ExpectedSet::VALUE | ExpectedSet::LIST_END),
+            )))
         }
-
-        seed.deserialize(&mut **self).map(Some)
     }
 }
 
-impl<'de, 'a> MapAccess<'de> for &'a mut Deserializer<'de> {
-    type Error = Error;
+/// One of the entries that make up the value of a key, which is the segment
+/// at `segment` of its path.
+#[derive(Clone)]
+struct Source<'de> {
+    path: MapPathRef<'de>,
+    segment: usize,
+    // the first token of the value after the path, and the position at it
+    value: SpanToken,
+    at: Checkpoint<'de>,
+}
 
-    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
-    where
-        K: DeserializeSeed<'de>,
-    {
-        if self.parser.peek_end_map() {
-            return Ok(None);
+impl<'de> Source<'de> {
+    fn key(&self) -> (&'de str, SpanToken) {
+        self.path.segments[self.segment]
+    }
+
+    /// Whether the key is followed by more of the path, rather than the value.
+    fn dotted(&self) -> bool {
+        self.segment + 1 < self.path.segments.len()
+    }
+
+    fn next_segment(&self) -> Self {
+        Self {
+            segment: self.segment + 1,
+            ..self.clone()
         }
+    }
 
-        seed.deserialize(&mut KeyDeserializer { de: self })
-            .map(Some)
+    /// Whether the value is `null`, which `null_as_default` leaves out.
+    fn null(&self) -> bool {
+        !self.dotted() && self.value.token.kind == TokenKind::Keyword(Keyword::Null)
     }
+}
 
-    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
-    where
-        V: DeserializeSeed<'de>,
-    {
-        if self.parser.peek_token().token.kind == TokenKind::Dot {
-            self.parser.next_token();
-            seed.deserialize(&mut PathMapDeserializer {
-                de: self,
-                done: false,
-            })
+// a key has more than one source only when it is repeated, which is rare
+type Sources<'de> = SmallVec<[Source<'de>; 1]>;
+
+type Entry<'de> = (&'de str, Sources<'de>);
+
+/// Groups entries by key, so that `a.b = 1` and `a.c = 2` become a single `a`,
+/// and applies the duplicate key policy to keys that are assigned more than once.
+fn group_entries<'de>(
+    entries: Vec<Source<'de>>,
+    policy: DuplicateKeys,
+    case: KeyCase,
+    prefix: &[&str],
+) -> Result<Vec<Entry<'de>>> {
+    let mut groups: Vec<Entry<'de>> = Vec::with_capacity(entries.len());
+    // the group of every entry, in the order they are written
+    let mut order = Vec::with_capacity(entries.len());
+    let mut index: BTreeMap<Cow<'de, str>, usize> = BTreeMap::new();
+    for source in entries {
+        let key = source.key().0;
+        match index.get(&case.apply(key)) {
+            Some(&i) => {
+                groups[i].1.push(source);
+                order.push(i);
+            }
+            None => {
+                index.insert(case.apply(key), groups.len());
+                order.push(groups.len());
+                groups.push((key, smallvec![source]));
+            }
+        }
+    }
+
+    let mut out: Vec<SmallVec<[Entry<'de>; 1]>> = Vec::with_capacity(groups.len());
+    for (key, mut sources) in groups {
+        if sources.len() == 1 {
+            out.push(smallvec![(key, sources)]);
+            continue;
+        }
+
+        let values = sources.iter().filter(|s| !s.dotted()).count();
+        if values > 1 {
+            let mut assigned = sources.iter().filter(|s| !s.dotted());
+            match policy {
+                DuplicateKeys::Allow => (),
+                DuplicateKeys::Error => {
+                    let first = assigned.next().expect("there are two values").key().1;
+                    let second = assigned.next().expect("there are two values").key().1;
+                    return Err(Error::parse(ParseError::new(
+                        second,
+                        ParseErrorKind::DuplicateKey {
+                            key: join_path(prefix, key),
+                            first: Box::new(first),
+                        },
+                    )));
+                }
+                DuplicateKeys::FirstWins => {
+                    let mut seen = 0;
+                    sources.retain(|s| {
+                        seen += !s.dotted() as usize;
+                        s.dotted() || seen == 1
+                    });
+                }
+                DuplicateKeys::LastWins => {
+                    let mut seen = 0;
+                    sources.retain(|s| {
+                        seen += !s.dotted() as usize;
+                        s.dotted() || seen == values
+                    });
+                }
+            }
+        }
+
+        if sources.iter().any(Source::dotted) {
+            out.push(smallvec![(key, sources)]);
         } else {
-            self.parser.map_delimiter()?;
-            seed.deserialize(&mut **self)
+            // allowed duplicates are passed on one by one
+            out.push(sources.into_iter().map(|s| (key, smallvec![s])).collect());
         }
     }
-}
 
-struct KeyDeserializer<'a, 'de> {
-    de: &'a mut Deserializer<'de>,
+    // a group goes where its key is first written, and allowed duplicates each go
+    // where they are written, like when the entries are read as they come
+    let mut out: Vec<_> = out.into_iter().map(IntoIterator::into_iter).collect();
+    Ok(order.into_iter().filter_map(|i| out[i].next()).collect())
 }
 
-impl<'a, 'de> de::Deserializer<'de> for &'a mut KeyDeserializer<'a, 'de> {
-    type Error = Error;
-
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        let next = self.de.parser.peek_token();
-        match next.token.kind {
-            TokenKind::Ident => {
-                self.de.parser.next_token();
-                visitor.visit_borrowed_str(self.de.parser.src(next.token))
+/// The entries of every source of a key, where sources with a dotted key give the
+/// rest of their path and the others have to be maps.
+fn merged_entries<'de>(
+    de: &mut Deserializer<'de>,
+    sources: &[Source<'de>],
+    prefix: &[&str],
+) -> Result<Vec<Source<'de>>> {
+    let mut entries = Vec::new();
+    for source in sources {
+        if source.dotted() {
+            entries.push(source.next_segment());
+            continue;
+        }
+        de.jump(source.at.clone());
+        match de.parser.try_start_map() {
+            Some(r) => r?,
+            None => {
+                let other = sources
+                    .iter()
+                    .find(|s| s.dotted())
+                    .expect("merged sources include a dotted key");
+                return Err(Error::parse(ParseError::new(
+                    source.value,
+                    ParseErrorKind::MergeConflict {
+                        key: prefix.join("."),
+                        other: Box::new(other.key().1),
+                    },
+                )));
             }
-            _ => Err(Error::parse(ParseError::new(
-                next,
-                ParseErrorKind::ExpectedIdent,
-            ))),
         }
+        de.scan_entries(TokenKind::EndCurly, &mut entries)?;
     }
+    Ok(entries)
+}
 
-    forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+/// Whether any of `entries` has the same key as another, so they need grouping.
+fn repeated(entries: &[Source<'_>], case: KeyCase) -> bool {
+    // most maps are small enough that comparing every pair is quicker than sorting
+    if entries.len() <= 16 {
+        return entries.iter().enumerate().any(|(i, a)| {
+            let key = case.apply(a.key().0);
+            entries[i + 1..]
+                .iter()
+                .any(|b| case.apply(b.key().0) == key)
+        });
     }
+    let mut keys: Vec<_> = entries.iter().map(|s| case.apply(s.key().0)).collect();
+    keys.sort_unstable();
+    keys.windows(2).any(|pair| pair[0] == pair[1])
 }
 
-struct PathMapDeserializer<'a, 'de> {
+fn join_path(prefix: &[&str], key: &str) -> String {
+    let mut out = String::new();
+    for segment in prefix {
+        out.push_str(segment);
+        out.push('.');
+    }
+    out.push_str(key);
+    out
+}
+
+/// The entries of a map. Unless the deserializer reads maps ahead, they are read
+/// as they are asked for, until a dotted key shows up. Since that can add to any
+/// key after it, the whole map is then read ahead, and the entries that were not
+/// given out yet are grouped by key. The parser jumps to the sources of entries
+/// that were read ahead, so whoever creates this has to [`finish`](Self::finish)
+/// it after.
+struct MapAccessor<'a, 'de> {
     de: &'a mut Deserializer<'de>,
-    done: bool,
+    // the keys leading from the map where merging started to this one
+    prefix: Vec<&'de str>,
+    entries: Entries<'de>,
+    // whether entries set to `null` are left out, for a struct with `null_as_default`
+    skip_nulls: bool,
+    value: Option<Pending<'de>>,
+    // where the map ends, if it was read ahead
+    end: Option<Checkpoint<'de>>,
 }
 
-impl<'a, 'de> de::Deserializer<'de> for &'a mut PathMapDeserializer<'a, 'de> {
-    type Error = Error;
+/// An entry whose key was given out, but not its value yet.
+#[allow(clippy::large_enum_variant)]
+enum Pending<'de> {
+    /// An entry of a streamed map, whose value is next at the parser.
+    Streamed {
+        key: &'de str,
+        token: SpanToken,
+        value: SpanToken,
+    },
+    Read(Entry<'de>),
+}
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        visitor.visit_map(self)
+impl<'de> Pending<'de> {
+    fn key(&self) -> (&'de str, SpanToken) {
+        match self {
+            Self::Streamed { key, token, .. } => (key, *token),
+            Self::Read((key, sources)) => (key, sources[0].key().1),
+        }
     }
+}
 
-    fn deserialize_enum<V>(
-        self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
-        visitor: V,
-    ) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        visitor.visit_enum(&mut *self.de)
+#[allow(clippy::large_enum_variant)]
+enum Entries<'de> {
+    /// Entries read from the parser as they are asked for.
+    Streamed(Streamed<'de>),
+    /// Entries as they are written, when no key is repeated.
+    InOrder(vec::IntoIter<Source<'de>>),
+    /// Entries grouped by key, when keys are repeated or merged from several maps.
+    Grouped(vec::IntoIter<Entry<'de>>),
+}
+
+/// A map whose entries are read as they are asked for.
+struct Streamed<'de> {
+    closer: TokenKind,
+    // the fields of the struct the map is, if it is one
+    fields: Option<&'static [&'static str]>,
+    // the number of entries read so far
+    count: usize,
+    // where the map starts, which it is read again from for where the keys that were
+    // read are. Only duplicate keys and dotted keys need those, which are rare
+    start: Checkpoint<'de>,
+    read: ReadKeys<'de>,
+}
+
+/// The keys of a map that were read, with case folding applied.
+#[derive(Default)]
+struct ReadKeys<'de> {
+    keys: SmallVec<[Cow<'de, str>; 4]>,
+    // every key, once there are too many to look through
+    index: BTreeSet<Cow<'de, str>>,
+}
+
+impl<'de> ReadKeys<'de> {
+    /// Most maps are small enough that looking through every key is quicker than
+    /// keeping an index.
+    const UNINDEXED: usize = 16;
+
+    fn contains(&self, key: &str) -> bool {
+        if self.keys.len() <= Self::UNINDEXED {
+            self.keys.iter().any(|read| read == key)
+        } else {
+            self.index.contains(key)
+        }
     }
 
-    forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct identifier ignored_any
+    fn insert(&mut self, key: Cow<'de, str>) {
+        if self.keys.len() == Self::UNINDEXED {
+            self.index = self.keys.iter().cloned().collect();
+        }
+        if self.keys.len() >= Self::UNINDEXED {
+            self.index.insert(key.clone());
+        }
+        self.keys.push(key);
     }
 }
 
-impl<'a, 'de> MapAccess<'de> for &'a mut PathMapDeserializer<'a, 'de> {
-    type Error = Error;
-
-    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
-    where
-        K: DeserializeSeed<'de>,
-    {
-        if self.done {
-            Ok(None)
+impl<'a, 'de> MapAccessor<'a, 'de> {
+    /// Starts on the entries of the map body at the parser, which runs up to `closer`
+    /// and is a struct with `fields` if those are given.
+    fn new(
+        de: &'a mut Deserializer<'de>,
+        closer: TokenKind,
+        fields: Option<&'static [&'static str]>,
+    ) -> Result<Self> {
+        if !de.read_ahead {
+            return Ok(Self {
+                entries: Entries::Streamed(Streamed {
+                    closer,
+                    fields,
+                    count: 0,
+                    start: de.parser.checkpoint(),
+                    read: ReadKeys::default(),
+                }),
+                skip_nulls: fields.is_some() && de.null_as_default,
+                de,
+                prefix: Vec::new(),
+                value: None,
+                end: None,
+            });
+        }
+        let mut sources = Vec::new();
+        let end = de.scan_entries(closer, &mut sources)?;
+        let entries = if repeated(&sources, de.key_case) {
+            let entries = group_entries(sources, de.duplicate_keys, de.key_case, &[])?;
+            Entries::Grouped(entries.into_iter())
         } else {
-            seed.deserialize(&mut KeyDeserializer { de: self.de })
-                .map(Some)
+            Entries::InOrder(sources.into_iter())
+        };
+        let mut access = Self::with_entries(de, Vec::new(), entries, fields)?;
+        access.end = Some(end);
+        Ok(access)
+    }
+
+    /// Merges every source of a key into one map, which is a struct with `fields`
+    /// if those are given.
+    fn merged(
+        de: &'a mut Deserializer<'de>,
+        sources: Sources<'de>,
+        prefix: Vec<&'de str>,
+        fields: Option<&'static [&'static str]>,
+    ) -> Result<Self> {
+        let entries = merged_entries(de, &sources, &prefix)?;
+        let entries = group_entries(entries, de.duplicate_keys, de.key_case, &prefix)?;
+        Self::with_entries(de, prefix, Entries::Grouped(entries.into_iter()), fields)
+    }
+
+    /// Checks the keys of a struct with `fields` and sets the accessor up.
+    fn with_entries(
+        de: &'a mut Deserializer<'de>,
+        prefix: Vec<&'de str>,
+        entries: Entries<'de>,
+        fields: Option<&'static [&'static str]>,
+    ) -> Result<Self> {
+        if let Some(fields) = fields {
+            check_entries(de, fields, &entries)?;
         }
+        Ok(Self {
+            skip_nulls: fields.is_some() && de.null_as_default,
+            de,
+            prefix,
+            entries,
+            value: None,
+            end: None,
+        })
+    }
+
+    /// Moves on to the next entry that is not left out, if there is one.
+    fn next_entry(&mut self) -> Result<bool> {
+        let skip_nulls = self.skip_nulls;
+        let entry = match &mut self.entries {
+            Entries::Streamed(_) => return self.next_streamed(),
+            Entries::InOrder(sources) => sources
+                .find(|source| !(skip_nulls && source.null()))
+                .map(|source| (source.key().0, smallvec![source])),
+            Entries::Grouped(entries) => entries.find(|(_, sources)| {
+                !(skip_nulls && matches!(&sources[..], [source] if source.null()))
+            }),
+        };
+        self.value = entry.map(Pending::Read);
+        Ok(self.value.is_some())
+    }
+
+    /// Reads the next entry from the parser, which is left at its value.
+    fn next_streamed(&mut self) -> Result<bool> {
+        let Entries::Streamed(map) = &mut self.entries else {
+            unreachable!("only a streamed map is read from the parser");
+        };
+        let de = &mut *self.de;
+        loop {
+            map.count += 1;
+            if !de.key_start(map.closer, map.count)? {
+                return Ok(false);
+            }
+            // most keys are not dotted, so they are read without building a path
+            let token = de.parser.next_checked()?;
+            if de.parser.peek_no_skip().token.kind == TokenKind::Dot {
+                return self.read_rest();
+            }
+            let key = de.parser.key_segment(token)?;
+            let delimiter = de.parser.peek_token();
+            de.parser.map_delimiter()?;
+            let value = de.value_start(&[(key, token)], delimiter.line)?;
+            let folded = de.key_case.apply(key);
+            if !map.read.contains(&folded) {
+                map.read.insert(folded);
+            } else {
+                match de.duplicate_keys {
+                    DuplicateKeys::Error => {
+                        let first = de.find_key(map.closer, map.start.clone(), &folded)?;
+                        return Err(Error::parse(ParseError::new(
+                            token,
+                            ParseErrorKind::DuplicateKey {
+                                key: join_path(&self.prefix, key),
+                                first: Box::new(first),
+                            },
+                        )));
+                    }
+                    DuplicateKeys::FirstWins => {
+                        de.in_context(ContextFrame::Key(Cow::Borrowed(key)), |de| {
+                            de.skip_value(false)
+                        })?;
+                        continue;
+                    }
+                    // allowed duplicates are passed on as they come, and for the last
+                    // one to win, maps are read ahead
+                    DuplicateKeys::Allow | DuplicateKeys::LastWins => (),
+                }
+            }
+            if let Some(fields) = map.fields {
+                de.check_fields(fields, [(key, token)])?;
+            }
+            if self.skip_nulls && value.token.kind == TokenKind::Keyword(Keyword::Null) {
+                de.parser.next_token();
+                continue;
+            }
+            self.value = Some(Pending::Streamed { key, token, value });
+            return Ok(true);
+        }
+    }
+
+    /// Reads ahead through a streamed map once a dotted key shows up in it, and goes
+    /// on with the entries that were not read yet, grouped by key.
+    fn read_rest(&mut self) -> Result<bool> {
+        let Entries::Streamed(map) = &mut self.entries else {
+            unreachable!("only a streamed map is read from the parser");
+        };
+        let de = &mut *self.de;
+        // only the text of the keys that were read is kept, so the map is read again
+        // from its start for where they are
+        de.jump(map.start.clone());
+        let mut sources = Vec::new();
+        let end = de.scan_entries(map.closer, &mut sources)?;
+        let mut rest = sources.split_off(map.count - 1);
+
+        // keys that were read already can be repeated, but not merged with
+        for read in &map.read.keys {
+            let first = sources
+                .iter()
+                .find(|s| de.key_case.apply(s.key().0) == *read)
+                .expect("a key that was read is in the map");
+            let (token, value) = (first.key().1, first.value);
+            let mut repeats = rest
+                .iter()
+                .filter(|s| de.key_case.apply(s.key().0) == *read)
+                .peekable();
+            let Some(repeat) = repeats.peek() else {
+                continue;
+            };
+            let key = join_path(&self.prefix, repeat.key().0);
+            if de.duplicate_keys == DuplicateKeys::Error {
+                if let Some(second) = repeats.clone().find(|s| !s.dotted()) {
+                    return Err(Error::parse(ParseError::new(
+                        second.key().1,
+                        ParseErrorKind::DuplicateKey {
+                            key,
+                            first: Box::new(token),
+                        },
+                    )));
+                }
+            }
+            let Some(dotted) = repeats.find(|s| s.dotted()) else {
+                continue;
+            };
+            if value.token.kind != TokenKind::StartCurly {
+                return Err(Error::parse(ParseError::new(
+                    value,
+                    ParseErrorKind::MergeConflict {
+                        key,
+                        other: Box::new(dotted.key().1),
+                    },
+                )));
+            }
+            let dotted = dotted.key().1;
+            sources.append(&mut rest);
+            return Err(dotted_after_map(de, sources, key, dotted, value));
+        }
+        if de.duplicate_keys == DuplicateKeys::FirstWins {
+            let read = &map.read;
+            rest.retain(|s| s.dotted() || !read.contains(&de.key_case.apply(s.key().0)));
+        }
+
+        let entries = group_entries(rest, de.duplicate_keys, de.key_case, &self.prefix)?;
+        let entries = Entries::Grouped(entries.into_iter());
+        if let Some(fields) = map.fields {
+            check_entries(de, fields, &entries)?;
+        }
+        self.entries = entries;
+        self.end = Some(end);
+        self.next_entry()
+    }
+
+    /// Reads through the entries that were not asked for, and moves the parser to
+    /// the end of the map.
+    fn finish(mut self) -> Result<()> {
+        while self.next_entry()? {
+            if let Some(Pending::Streamed { key, .. }) = self.value.take() {
+                self.de
+                    .in_context(ContextFrame::Key(Cow::Borrowed(key)), |de| {
+                        de.skip_value(false)
+                    })?;
+            }
+        }
+        if let Some(end) = self.end {
+            self.de.jump(end);
+        }
+        Ok(())
+    }
+}
+
+/// Checks that every key of `entries` is one of `fields`, if unknown fields are
+/// denied.
+fn check_entries<'de>(
+    de: &Deserializer<'de>,
+    fields: &'static [&'static str],
+    entries: &Entries<'de>,
+) -> Result<()> {
+    match entries {
+        Entries::Streamed(_) => Ok(()),
+        Entries::InOrder(sources) => {
+            de.check_fields(fields, sources.as_slice().iter().map(Source::key))
+        }
+        Entries::Grouped(entries) => {
+            let keys = entries
+                .as_slice()
+                .iter()
+                .map(|(key, sources)| (*key, sources[0].key().1));
+            de.check_fields(fields, keys)
+        }
+    }
+}
+
+/// The error for the dotted key at `dotted` that adds to the braced map at `map`,
+/// which was read already. If merging the `entries` of the map would fail anyway,
+/// like when the braced map sets the dotted key to a number, that error is given
+/// instead.
+fn dotted_after_map<'de>(
+    de: &mut Deserializer<'de>,
+    entries: Vec<Source<'de>>,
+    key: String,
+    dotted: SpanToken,
+    map: SpanToken,
+) -> Error {
+    de.merged_late = true;
+    match check_merges(de, entries, &mut Vec::new()) {
+        Ok(()) => Error::parse(ParseError::new(
+            dotted,
+            ParseErrorKind::DottedAfterMap {
+                key,
+                map: Box::new(map),
+            },
+        )),
+        Err(err) => err,
+    }
+}
+
+/// Checks that the entries of a map can be grouped and merged, without reading any
+/// of their values.
+fn check_merges<'de>(
+    de: &mut Deserializer<'de>,
+    entries: Vec<Source<'de>>,
+    prefix: &mut Vec<&'de str>,
+) -> Result<()> {
+    if de.max_depth.is_some_and(|max| prefix.len() as u32 >= max) {
+        return Ok(());
+    }
+    for (key, sources) in group_entries(entries, de.duplicate_keys, de.key_case, prefix)? {
+        if sources.iter().any(Source::dotted) {
+            prefix.push(key);
+            let entries = merged_entries(de, &sources, prefix)?;
+            check_merges(de, entries, prefix)?;
+            prefix.pop();
+        }
+    }
+    Ok(())
+}
+
+impl<'de> MapAccess<'de> for MapAccessor<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if !self.next_entry()? {
+            return Ok(None);
+        }
+        let (key, token) = self.value.as_ref().expect("there is an entry").key();
+        let key = MapKeyDeserializer {
+            key: self.de.key_case.apply(key),
+            token,
+        };
+        seed.deserialize(key).map(Some)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
     where
         V: DeserializeSeed<'de>,
     {
-        self.done = true;
-        if self.de.parser.peek_token().token.kind == TokenKind::Dot {
-            self.de.parser.next_token();
-            seed.deserialize(&mut PathMapDeserializer {
-                de: self.de,
-                done: false,
+        let (key, mut sources) = match self.value.take().expect("value requested before key") {
+            Pending::Streamed { key, token, value } => {
+                return self
+                    .de
+                    .in_context(ContextFrame::Key(Cow::Borrowed(key)), |de| {
+                        if de.unused.is_some() {
+                            de.last_value = Some((value, token));
+                        }
+                        seed.deserialize(de)
+                    });
+            }
+            Pending::Read(entry) => entry,
+        };
+        let prefix = &self.prefix;
+        self.de
+            .in_context(ContextFrame::Key(Cow::Borrowed(key)), |de| {
+                if sources.len() == 1 && !sources[0].dotted() {
+                    let source = sources.pop().expect("there is one source");
+                    let key = source.key().1;
+                    de.jump(source.at);
+                    if de.unused.is_some() {
+                        de.last_value = Some((source.value, key));
+                    }
+                    seed.deserialize(de)
+                } else {
+                    let mut prefix = prefix.clone();
+                    prefix.push(key);
+                    seed.deserialize(MergedDeserializer {
+                        de,
+                        sources,
+                        prefix,
+                    })
+                }
             })
+    }
+}
+
+/// A key of a map, which is parsed from its text when something other than a string
+/// is asked for, like the `u16` keys of a `HashMap<u16, Backend>`.
+struct MapKeyDeserializer<'de> {
+    key: Cow<'de, str>,
+    token: SpanToken,
+}
+
+impl MapKeyDeserializer<'_> {
+    fn parse<T: FromStr>(&self, kind: ParseErrorKind) -> Result<T> {
+        self.key
+            .parse()
+            .map_err(|_| Error::parse(ParseError::new(self.token, kind)))
+    }
+}
+
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident($kind:expr),)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                visitor.$visit(self.parse($kind)?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for MapKeyDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.key {
+            Cow::Borrowed(key) => visitor.visit_borrowed_str(key),
+            Cow::Owned(key) => visitor.visit_string(key),
+        }
+    }
+
+    deserialize_parsed_key! {
+        deserialize_bool => visit_bool(ParseErrorKind::ExpectedBool),
+        deserialize_i8 => visit_i8(ParseErrorKind::InvalidInteger),
+        deserialize_i16 => visit_i16(ParseErrorKind::InvalidInteger),
+        deserialize_i32 => visit_i32(ParseErrorKind::InvalidInteger),
+        deserialize_i64 => visit_i64(ParseErrorKind::InvalidInteger),
+        deserialize_i128 => visit_i128(ParseErrorKind::InvalidInteger),
+        deserialize_u8 => visit_u8(ParseErrorKind::InvalidInteger),
+        deserialize_u16 => visit_u16(ParseErrorKind::InvalidInteger),
+        deserialize_u32 => visit_u32(ParseErrorKind::InvalidInteger),
+        deserialize_u64 => visit_u64(ParseErrorKind::InvalidInteger),
+        deserialize_u128 => visit_u128(ParseErrorKind::InvalidInteger),
+        deserialize_f32 => visit_f32(ParseErrorKind::InvalidFloat),
+        deserialize_f64 => visit_f64(ParseErrorKind::InvalidFloat),
+        deserialize_char => visit_char(ParseErrorKind::InvalidChar),
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.key {
+            Cow::Borrowed(key) => visitor.visit_enum(BorrowedStrDeserializer::new(key)),
+            Cow::Owned(key) => visitor.visit_enum(key.into_deserializer()),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// The value of a key with dotted paths, which is a map of every entry under it.
+struct MergedDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    sources: Sources<'de>,
+    prefix: Vec<&'de str>,
+}
+
+impl<'de> de::Deserializer<'de> for MergedDeserializer<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let token = self.sources[0].key().1;
+        let (sources, prefix) = (self.sources, self.prefix);
+        self.de.nested(token, |de| {
+            visitor.visit_map(MapAccessor::merged(de, sources, prefix, None)?)
+        })
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let token = self.sources[0].key().1;
+        let (sources, prefix) = (self.sources, self.prefix);
+        self.de.nested(token, |de| {
+            visitor.visit_map(MapAccessor::merged(de, sources, prefix, Some(fields))?)
+        })
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // the sources were checked when they were scanned, so they are left unread
+        self.de.mark_unused(self.sources[0].key().1);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.sources[..] {
+            // `key.Variant = value`
+            [source] => {
+                let mut prefix = self.prefix;
+                let variant = source.next_segment();
+                let name = variant.key().0;
+                prefix.push(name);
+                self.de
+                    .in_context(ContextFrame::Key(Cow::Borrowed(name)), |de| {
+                        visitor.visit_enum(PathVariantAccess {
+                            de,
+                            variant,
+                            variants,
+                            prefix,
+                        })
+                    })
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map identifier
+    }
+}
+
+/// An enum spelled as a dotted path, with the variant at the segment of `variant`.
+struct PathVariantAccess<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    variant: Source<'de>,
+    variants: &'static [&'static str],
+    prefix: Vec<&'de str>,
+}
+
+/// What follows the variant of a [`PathVariantAccess`].
+#[allow(clippy::large_enum_variant)]
+enum VariantPayload<'a, 'de> {
+    /// `Variant = value`, with the parser at the value
+    Value(&'a mut Deserializer<'de>),
+    /// `Variant.rest = value`
+    Path(MergedDeserializer<'a, 'de>),
+}
+
+impl<'a, 'de> PathVariantAccess<'a, 'de> {
+    fn payload(self) -> Result<VariantPayload<'a, 'de>> {
+        if self.variant.dotted() {
+            Ok(VariantPayload::Path(MergedDeserializer {
+                de: self.de,
+                sources: smallvec![self.variant],
+                prefix: self.prefix,
+            }))
         } else {
-            self.de.parser.map_delimiter()?;
-            seed.deserialize(&mut *self.de)
+            self.de.jump(self.variant.at);
+            Ok(VariantPayload::Value(self.de))
         }
     }
 }
 
-impl<'de, 'a> EnumAccess<'de> for &'a mut Deserializer<'de> {
+impl<'a, 'de> EnumAccess<'de> for PathVariantAccess<'a, 'de> {
     type Error = Error;
 
     type Variant = Self;
@@ -428,33 +1770,33 @@ impl<'de, 'a> EnumAccess<'de> for &'a mut Deserializer<'de> {
     where
         V: DeserializeSeed<'de>,
     {
-        seed.deserialize(&mut KeyDeserializer { de: self })
+        let name = self.variant.key().0;
+        let name = self.de.variant_name(name, self.variants).unwrap_or(name);
+        seed.deserialize(BorrowedStrDeserializer::new(name))
             .map(|v| (v, self))
     }
 }
 
-impl<'de, 'a> VariantAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de> VariantAccess<'de> for PathVariantAccess<'_, 'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        self.parser.map_delimiter()?;
-        self.parser.parse_null()?;
-        Ok(())
+        match self.payload()? {
+            VariantPayload::Value(de) => Ok(de.parser.parse_null()?),
+            VariantPayload::Path(_) => Err(de::Error::invalid_type(
+                de::Unexpected::Map,
+                &"unit variant",
+            )),
+        }
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
     where
         T: DeserializeSeed<'de>,
     {
-        if self.parser.peek_token().token.kind == TokenKind::Dot {
-            self.parser.next_token();
-            seed.deserialize(&mut PathMapDeserializer {
-                de: self,
-                done: false,
-            })
-        } else {
-            self.parser.map_delimiter()?;
-            seed.deserialize(self)
+        match self.payload()? {
+            VariantPayload::Value(de) => seed.deserialize(de),
+            VariantPayload::Path(de) => seed.deserialize(de),
         }
     }
 
@@ -462,44 +1804,65 @@ impl<'de, 'a> VariantAccess<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.parser.map_delimiter()?;
-        de::Deserializer::deserialize_seq(self, visitor)
+        match self.payload()? {
+            VariantPayload::Value(de) => de::Deserializer::deserialize_seq(de, visitor),
+            VariantPayload::Path(de) => de::Deserializer::deserialize_seq(de, visitor),
+        }
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if self.parser.peek_token().token.kind == TokenKind::Dot {
-            self.parser.next_token();
-            de::Deserializer::deserialize_map(
-                &mut PathMapDeserializer {
-                    de: self,
-                    done: false,
-                },
-                visitor,
-            )
-        } else {
-            self.parser.map_delimiter()?;
-            de::Deserializer::deserialize_map(self, visitor)
+        match self.payload()? {
+            VariantPayload::Value(de) => de.deserialize_braced_map(Some(fields), visitor),
+            VariantPayload::Path(de) => {
+                de::Deserializer::deserialize_struct(de, "", fields, visitor)
+            }
         }
     }
 }
 
-pub struct TopDeserializer<'de> {
-    de: Deserializer<'de>,
+struct KeyDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
 }
 
-impl<'de> TopDeserializer<'de> {
-    #[allow(clippy::should_implement_trait)]
-    pub fn from_str(input: &'de str) -> Self {
-        Self {
-            de: Deserializer::from_str(input),
+impl<'a, 'de> de::Deserializer<'de> for &'a mut KeyDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let next = self.de.parser.peek_token();
+        match next.token.kind {
+            TokenKind::Ident => {
+                self.de.parser.next_token();
+                match self.de.key_case.apply(self.de.parser.src(next.token)) {
+                    Cow::Borrowed(key) => visitor.visit_borrowed_str(key),
+                    Cow::Owned(key) => visitor.visit_string(key),
+                }
+            }
+            _ => Err(Error::parse(ParseError::new(
+                next,
+                ParseErrorKind::ExpectedIdent,
+            ))),
         }
     }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct PathMapDeserializer<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    done: bool,
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut TopDeserializer<'de> {
+impl<'a, 'de> de::Deserializer<'de> for &'a mut PathMapDeserializer<'a, 'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
@@ -509,24 +1872,39 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut TopDeserializer<'de> {
         visitor.visit_map(self)
     }
 
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_enum(EnumAccessor {
+            de: self.de,
+            variants,
+        })
+    }
+
     forward_to_deserialize_any! {
         bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
         bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+        tuple_struct map struct identifier ignored_any
     }
 }
 
-impl<'de, 'a> MapAccess<'de> for &'a mut TopDeserializer<'de> {
+impl<'a, 'de> MapAccess<'de> for &'a mut PathMapDeserializer<'a, 'de> {
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where
         K: DeserializeSeed<'de>,
     {
-        if self.de.parser.peek_eof() {
+        if self.done {
             Ok(None)
         } else {
-            seed.deserialize(&mut KeyDeserializer { de: &mut self.de })
+            seed.deserialize(&mut KeyDeserializer { de: self.de })
                 .map(Some)
         }
     }
@@ -535,16 +1913,1985 @@ impl<'de, 'a> MapAccess<'de> for &'a mut TopDeserializer<'de> {
     where
         V: DeserializeSeed<'de>,
     {
-        // println!("{:?}", self.de.parser.peek_token().token.kind);
-        if self.de.parser.peek_token().token.kind == TokenKind::Dot {
+        self.done = true;
+        if self.de.parser.peek_kind() == TokenKind::Dot {
             self.de.parser.next_token();
             seed.deserialize(&mut PathMapDeserializer {
-                de: &mut self.de,
+                de: self.de,
                 done: false,
             })
         } else {
             self.de.parser.map_delimiter()?;
-            seed.deserialize(&mut self.de)
+            seed.deserialize(&mut *self.de)
         }
     }
 }
+
+/// An enum written as a map of the variant, `{ Variant = value }`.
+struct EnumAccessor<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    variants: &'static [&'static str],
+}
+
+impl<'a, 'de> EnumAccess<'de> for EnumAccessor<'a, 'de> {
+    type Error = Error;
+
+    type Variant = &'a mut Deserializer<'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let next = self.de.parser.peek_token();
+        if next.token.kind != TokenKind::Ident {
+            return Err(Error::parse(ParseError::new(
+                next,
+                ParseErrorKind::ExpectedIdent,
+            )));
+        }
+        self.de.parser.next_token();
+        let name = self.de.parser.src(next.token);
+        let name = self.de.variant_name(name, self.variants).unwrap_or(name);
+        seed.deserialize(BorrowedStrDeserializer::new(name))
+            .map(|v| (v, self.de))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        self.parser.map_delimiter()?;
+        self.parser.parse_null()?;
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.parser.peek_kind() == TokenKind::Dot {
+            self.parser.next_token();
+            seed.deserialize(&mut PathMapDeserializer {
+                de: self,
+                done: false,
+            })
+        } else {
+            self.parser.map_delimiter()?;
+            seed.deserialize(self)
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.parser.map_delimiter()?;
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.parser.peek_kind() == TokenKind::Dot {
+            self.parser.next_token();
+            de::Deserializer::deserialize_map(
+                &mut PathMapDeserializer {
+                    de: self,
+                    done: false,
+                },
+                visitor,
+            )
+        } else {
+            self.parser.map_delimiter()?;
+            self.deserialize_braced_map(Some(fields), visitor)
+        }
+    }
+}
+
+/// The name [`Value`](crate::Value) deserializes itself as a newtype struct with.
+/// Duration literals are then given as a single entry map with [`DURATION_KEY`],
+/// while `deserialize_any` gives them as a `{ secs, nanos }` map, which is what
+/// buffered content like `#[serde(flatten)]` needs to deserialize a `Duration` later.
+pub(crate) const VALUE_NAME: &str = "$__ycf_private_value";
+
+/// The key of the single entry map produced for a duration literal when
+/// deserializing a [`Value`](crate::Value), so it can tell it apart from an ordinary map.
+pub(crate) const DURATION_KEY: &str = "$__ycf_private_duration";
+
+pub(crate) struct DurationValueMap {
+    pub(crate) duration: Option<Duration>,
+}
+
+impl<'de> MapAccess<'de> for DurationValueMap {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        if self.duration.is_some() {
+            seed.deserialize(DURATION_KEY.into_deserializer()).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let duration = self.duration.take().expect("value requested before key");
+        seed.deserialize(DurationDeserializer::new(duration))
+    }
+}
+
+/// Presents a duration as the `{ secs, nanos }` struct that serde uses for `Duration`.
+pub(crate) struct DurationDeserializer {
+    duration: Duration,
+    field: u8,
+}
+
+impl DurationDeserializer {
+    pub(crate) fn new(duration: Duration) -> Self {
+        Self { duration, field: 0 }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for DurationDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for DurationDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let key = match self.field {
+            0 => "secs",
+            1 => "nanos",
+            _ => return Ok(None),
+        };
+        seed.deserialize(key.into_deserializer()).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        self.field += 1;
+        match self.field {
+            1 => seed.deserialize(self.duration.as_secs().into_deserializer()),
+            _ => seed.deserialize(self.duration.subsec_nanos().into_deserializer()),
+        }
+    }
+}
+
+pub struct TopDeserializer<'de> {
+    de: Deserializer<'de>,
+}
+
+impl<'de> TopDeserializer<'de> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'de str) -> Self {
+        Self::with_options(input, Options::default())
+    }
+
+    pub fn with_options(input: &'de str, options: Options) -> Self {
+        Self {
+            de: Deserializer::with_options(input, options),
+        }
+    }
+
+    /// See [`Deserializer::with_path`].
+    #[cfg(feature = "std")]
+    pub fn with_path(self, path: impl Into<PathBuf>) -> Self {
+        Self {
+            de: self.de.with_path(path),
+        }
+    }
+
+    /// See [`Deserializer::disable_recursion_limit`].
+    pub fn disable_recursion_limit(&mut self) {
+        self.de.disable_recursion_limit();
+    }
+
+    /// See [`Deserializer::capture_unused`].
+    pub fn capture_unused(&mut self) {
+        self.de.capture_unused();
+    }
+
+    /// See [`Deserializer::take_unused`].
+    pub fn take_unused(&mut self) -> Vec<UnusedKey> {
+        self.de.take_unused()
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut TopDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let res = MapAccessor::new(&mut self.de, TokenKind::Eof, None).and_then(|mut access| {
+            let out = visitor.visit_map(&mut access)?;
+            access.finish()?;
+            Ok(out)
+        });
+        res.map_err(|err| self.de.in_file(err))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let res =
+            MapAccessor::new(&mut self.de, TokenKind::Eof, Some(fields)).and_then(|mut access| {
+                let out = visitor.visit_map(&mut access)?;
+                access.finish()?;
+                Ok(out)
+            });
+        res.map_err(|err| self.de.in_file(err))
+    }
+
+    // entries are always there, even if there are none of them
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// Deserializes a whole document, which is either top level entries like `a = 1`,
+/// or a single value such as a braced map or a list.
+pub fn from_str<'de, T>(input: &'de str) -> Result<T>
+where
+    T: de::Deserialize<'de>,
+{
+    from_str_with_options(input, Options::default())
+}
+
+pub fn from_str_with_options<'de, T>(input: &'de str, options: Options) -> Result<T>
+where
+    T: de::Deserialize<'de>,
+{
+    deserialize_retrying(input, options, false).map(|(value, _)| value)
+}
+
+/// Like [`from_str`], but deserializes with a stateful `seed`.
+pub fn from_str_seed<'de, S>(input: &'de str, seed: S) -> Result<S::Value>
+where
+    S: DeserializeSeed<'de>,
+{
+    from_str_seed_with_options(input, seed, Options::default())
+}
+
+/// Like [`from_str`], but also returns the keys the type did not use, so those
+/// can be warned about instead of being silently ignored.
+pub fn from_str_unused<'de, T>(input: &'de str) -> Result<(T, Vec<UnusedKey>)>
+where
+    T: de::Deserialize<'de>,
+{
+    from_str_unused_with_options(input, Options::default())
+}
+
+pub fn from_str_unused_with_options<'de, T>(
+    input: &'de str,
+    options: Options,
+) -> Result<(T, Vec<UnusedKey>)>
+where
+    T: de::Deserialize<'de>,
+{
+    let (value, mut de) = deserialize_retrying(input, options, true)?;
+    Ok((value, de.take_unused()))
+}
+
+pub fn from_str_seed_with_options<'de, S>(
+    input: &'de str,
+    seed: S,
+    options: Options,
+) -> Result<S::Value>
+where
+    S: DeserializeSeed<'de>,
+{
+    // the seed can not be given a second try, so every map is read ahead
+    let mut de = Deserializer::with_options(input, options);
+    de.read_ahead = true;
+    deserialize_document(de, seed).0
+}
+
+/// Deserializes a document from bytes, which have to be UTF-8. A UTF-8 byte order
+/// mark at the start is skipped, and positions in errors are counted after it.
+pub fn from_slice<'de, T>(input: &'de [u8]) -> Result<T>
+where
+    T: de::Deserialize<'de>,
+{
+    from_slice_with_options(input, Options::default())
+}
+
+pub fn from_slice_with_options<'de, T>(input: &'de [u8], options: Options) -> Result<T>
+where
+    T: de::Deserialize<'de>,
+{
+    let input = match decode(input, options.column_encoding) {
+        Ok(input) => input,
+        // the deserializer adds the file in the options to the error, if there is one
+        Err(err) => return Err(Deserializer::with_options("", options).in_file(Error::parse(err))),
+    };
+    from_str_with_options(input, options)
+}
+
+/// Reads the file at `path` and deserializes a `T` from it like [`from_slice`].
+/// Errors show the path before their position, like `config/app.ycf:12:7`,
+/// including errors reading it.
+///
+/// ```no_run
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Config {
+///     port: u16,
+/// }
+///
+/// let config: Config = ycf::from_file("config/app.ycf")?;
+/// # Ok::<(), ycf::Error>(())
+/// ```
+#[cfg(feature = "std")]
+pub fn from_file<T>(path: impl AsRef<std::path::Path>) -> Result<T>
+where
+    T: de::DeserializeOwned,
+{
+    from_file_with_options(path, Options::default())
+}
+
+/// Like [`from_file`], where the file in `options` is replaced with `path`.
+#[cfg(feature = "std")]
+pub fn from_file_with_options<T>(path: impl AsRef<std::path::Path>, options: Options) -> Result<T>
+where
+    T: de::DeserializeOwned,
+{
+    let path = path.as_ref();
+    let src = std::fs::read(path).map_err(|err| Error::io(err).with_file(path))?;
+    let options = Options {
+        file: Some(path.to_owned()),
+        ..options
+    };
+    from_slice_with_options(&src, options)
+}
+
+/// Deserializes only the value at `path` in a document, like `server.tls`, or
+/// returns `None` if it is not set. The rest of the document is skipped over token
+/// by token without being deserialized, so tools that need one section of a large
+/// file do not pay for all of it.
+///
+/// Keys set with dotted paths are merged like when deserializing the whole
+/// document. Where one is set more than once, the last is used.
+///
+/// ```
+/// # use serde::Deserialize;
+/// #[derive(Deserialize)]
+/// struct Tls {
+///     cert: String,
+///     port: u16,
+/// }
+///
+/// let src = r#"
+///     name = "app"
+///     server = { host = "localhost" tls.port = 443 }
+///     server.tls.cert = "cert.pem"
+/// "#;
+/// let tls: Tls = ycf::get(src, "server.tls")?.unwrap();
+/// assert_eq!((tls.cert.as_str(), tls.port), ("cert.pem", 443));
+/// assert_eq!(ycf::get::<u16, _>(src, "server.port")?, None);
+/// # Ok::<(), ycf::Error>(())
+/// ```
+pub fn get<'de, T, P>(input: &'de str, path: &P) -> Result<Option<T>>
+where
+    T: de::Deserialize<'de>,
+    P: AsPath + ?Sized,
+{
+    get_with_options(input, path, Options::default())
+}
+
+pub fn get_with_options<'de, T, P>(input: &'de str, path: &P, options: Options) -> Result<Option<T>>
+where
+    T: de::Deserialize<'de>,
+    P: AsPath + ?Sized,
+{
+    let path = path
+        .as_path()
+        .ok_or_else(|| Error::custom("invalid path"))?;
+    let mut de = Deserializer::with_options(input, options);
+    de.read_ahead = true;
+    if path.is_empty() {
+        return deserialize_document(de, PhantomData).0.map(Some);
+    }
+    let res = get_seed(&mut de, &path, PhantomData);
+    res.map_err(|mut err| {
+        err.add_context(&de.parser.context);
+        de.in_file(err)
+    })
+}
+
+/// Where [`get_seed`] is in a document.
+#[allow(clippy::large_enum_variant)]
+enum Place<'de> {
+    // the entries of a map, before they are grouped by key
+    Entries(Vec<Source<'de>>),
+    // the sources of a key
+    Key(Sources<'de>),
+    // the parser is at a value
+    Value,
+}
+
+/// Finds the value at `path`, which is not empty, and deserializes it with `seed`.
+fn get_seed<'de, S>(de: &mut Deserializer<'de>, path: &Path, seed: S) -> Result<Option<S::Value>>
+where
+    S: DeserializeSeed<'de>,
+{
+    let mut place = if de.parser.starts_top_level() {
+        let mut entries = Vec::new();
+        de.scan_entries(TokenKind::Eof, &mut entries)?;
+        Place::Entries(entries)
+    } else {
+        Place::Value
+    };
+    // the keys so far, for errors about merging
+    let mut prefix = Vec::new();
+    for segment in path {
+        if let Place::Key(sources) = place {
+            place = if single_value(de, &sources) {
+                Place::Value
+            } else {
+                Place::Entries(merged_entries(de, &sources, &prefix)?)
+            };
+        }
+        place = match (place, segment) {
+            (Place::Value, Segment::Key(key)) => {
+                match de.parser.try_start_map() {
+                    Some(r) => r?,
+                    None => return Ok(None),
+                }
+                let mut entries = Vec::new();
+                de.scan_entries(TokenKind::EndCurly, &mut entries)?;
+                let Some((key, sources)) = find_key(de, entries, key, &prefix)? else {
+                    return Ok(None);
+                };
+                de.parser
+                    .push_context(ContextFrame::Key(Cow::Borrowed(key)));
+                prefix.push(key);
+                Place::Key(sources)
+            }
+            (Place::Entries(entries), Segment::Key(key)) => {
+                let Some((key, sources)) = find_key(de, entries, key, &prefix)? else {
+                    return Ok(None);
+                };
+                de.parser
+                    .push_context(ContextFrame::Key(Cow::Borrowed(key)));
+                prefix.push(key);
+                Place::Key(sources)
+            }
+            (Place::Value, &Segment::Index(index)) => {
+                match de.parser.try_start_list() {
+                    Some(r) => r?,
+                    None => return Ok(None),
+                }
+                for _ in 0..index {
+                    if de.parser.peek_end_list() {
+                        return Ok(None);
+                    }
+                    de.skip_value(false)?;
+                }
+                if de.parser.peek_end_list() {
+                    return Ok(None);
+                }
+                de.parser.push_context(ContextFrame::Element(index));
+                Place::Value
+            }
+            (Place::Entries(_), Segment::Index(_)) => return Ok(None),
+            (Place::Key(_), _) => unreachable!("the sources of a key were opened above"),
+        };
+    }
+
+    match place {
+        Place::Value => (),
+        Place::Key(sources) => {
+            if !single_value(de, &sources) {
+                return seed
+                    .deserialize(MergedDeserializer {
+                        de,
+                        sources,
+                        prefix,
+                    })
+                    .map(Some);
+            }
+        }
+        Place::Entries(_) => unreachable!("every segment ends at a key or an element"),
+    }
+    seed.deserialize(&mut *de).map(Some)
+}
+
+/// Moves the parser to the value of a key if it has only one source that is not
+/// dotted, returning whether it did.
+fn single_value<'de>(de: &mut Deserializer<'de>, sources: &[Source<'de>]) -> bool {
+    match sources {
+        [source] if !source.dotted() => {
+            de.jump(source.at.clone());
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The last group of `entries` with the key `key`, with the key as it is written.
+fn find_key<'de>(
+    de: &Deserializer<'de>,
+    entries: Vec<Source<'de>>,
+    key: &str,
+    prefix: &[&str],
+) -> Result<Option<Entry<'de>>> {
+    let key = de.key_case.apply(key);
+    let groups = group_entries(entries, de.duplicate_keys, de.key_case, prefix)?;
+    Ok(groups
+        .into_iter()
+        .rev()
+        .find(|(written, _)| de.key_case.apply(written) == key))
+}
+
+/// Deserializes top level entries or a single value, whichever the input is,
+/// giving back the deserializer after.
+fn deserialize_document<'de, S>(
+    mut de: Deserializer<'de>,
+    seed: S,
+) -> (Result<S::Value>, Deserializer<'de>)
+where
+    S: DeserializeSeed<'de>,
+{
+    if de.parser.starts_top_level() {
+        // the entries run up to the end of the input
+        let mut top = TopDeserializer { de };
+        let res = seed.deserialize(&mut top);
+        let res = res.map_err(|err| top.de.in_file(err));
+        (res, top.de)
+    } else {
+        let res = seed.deserialize(&mut de).and_then(|value| {
+            if !de.allow_trailing {
+                de.end()?;
+            }
+            Ok(value)
+        });
+        (res.map_err(|err| de.in_file(err)), de)
+    }
+}
+
+/// Deserializes a whole document with maps streamed, and reads it again with every
+/// map read ahead if a dotted key turned out to add to a braced map before it.
+fn deserialize_retrying<'de, T>(
+    input: &'de str,
+    options: Options,
+    unused: bool,
+) -> Result<(T, Deserializer<'de>)>
+where
+    T: de::Deserialize<'de>,
+{
+    let mut de = Deserializer::with_options(input, options.clone());
+    if unused {
+        de.capture_unused();
+    }
+    let (res, de) = deserialize_document(de, PhantomData);
+    if !de.merged_late {
+        return res.map(|value| (value, de));
+    }
+    let mut de = Deserializer::with_options(input, options);
+    de.read_ahead = true;
+    if unused {
+        de.capture_unused();
+    }
+    let (res, de) = deserialize_document(de, PhantomData);
+    res.map(|value| (value, de))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{error::ErrorKind, Value};
+
+    fn from_str<'de, T: Deserialize<'de>>(src: &'de str) -> Result<T> {
+        T::deserialize(&mut TopDeserializer::from_str(src))
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Server {
+        host: String,
+        port: u16,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        name: String,
+        #[serde(flatten)]
+        server: Server,
+        #[serde(flatten)]
+        rest: BTreeMap<String, Value>,
+    }
+
+    #[test]
+    fn flatten() {
+        let config: Config = from_str(
+            r#"
+            name = "app"
+            host = "localhost"
+            port = 8080
+            debug = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.name, "app");
+        assert_eq!(
+            config.server,
+            Server {
+                host: "localhost".into(),
+                port: 8080,
+            }
+        );
+        assert_eq!(
+            config.rest,
+            BTreeMap::from([("debug".into(), Value::Bool(true))])
+        );
+    }
+
+    #[test]
+    fn flatten_dotted_keys() {
+        let config: Config = from_str(
+            r#"
+            name = "app"
+            host = "localhost"
+            log.level = "info"
+            port = 8080
+            log.file = "out.log"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.port, 8080);
+        let log = BTreeMap::from([
+            ("level".into(), Value::String("info".into())),
+            ("file".into(), Value::String("out.log".into())),
+        ]);
+        assert_eq!(
+            config.rest,
+            BTreeMap::from([("log".into(), Value::Map(log))])
+        );
+    }
+
+    #[test]
+    fn flatten_nested() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Outer {
+            inner: Config,
+        }
+
+        let outer: Outer = from_str(
+            r#"
+            inner.name = "app"
+            inner = { host = "localhost" }
+            inner.port = 8080
+            "#,
+        )
+        .unwrap();
+        assert_eq!(outer.inner.name, "app");
+        assert_eq!(outer.inner.server.host, "localhost");
+        assert_eq!(outer.inner.server.port, 8080);
+        assert!(outer.inner.rest.is_empty());
+    }
+
+    #[test]
+    fn flatten_duration() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Timeouts {
+            read: Duration,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            #[serde(flatten)]
+            timeouts: Timeouts,
+        }
+
+        let config: Config = from_str("read = 1m30s").unwrap();
+        assert_eq!(config.timeouts.read, Duration::from_secs(90));
+    }
+
+    #[test]
+    fn value_duration() {
+        let value: Value = from_str("a.b = 5s").unwrap();
+        let b = BTreeMap::from([("b".into(), Value::Duration(Duration::from_secs(5)))]);
+        assert_eq!(
+            value,
+            Value::Map(BTreeMap::from([("a".into(), Value::Map(b))]))
+        );
+    }
+
+    #[test]
+    fn durations() {
+        let duration = |src: &str| {
+            from_str::<BTreeMap<String, Duration>>(&format!("d = {src}")).map(|map| map["d"])
+        };
+        assert_eq!(duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(duration("2h30m").unwrap(), Duration::from_secs(9000));
+        assert_eq!(
+            duration("1d1h1m1s1ms1us1ns").unwrap(),
+            Duration::new(90061, 1_001_001)
+        );
+        assert_eq!(duration("1_000s").unwrap(), Duration::from_secs(1000));
+        assert_eq!(duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(duration("0.25h").unwrap(), Duration::from_secs(900));
+        assert_eq!(duration("1h0.5m").unwrap(), Duration::from_secs(3630));
+        // fractions of a nanosecond are dropped
+        assert_eq!(duration("1.9ns").unwrap(), Duration::from_nanos(1));
+        assert_eq!(
+            duration("0.333333333333333333333333s").unwrap(),
+            Duration::from_nanos(333_333_333)
+        );
+
+        let kind = |src| match duration(src).unwrap_err().kind() {
+            ErrorKind::Parse(err) => (err.kind.clone(), err.token.col),
+            other => panic!("expected a parse error, found {other:?}"),
+        };
+        use ParseErrorKind::*;
+        assert_eq!(kind("1m1h"), (UnorderedDuration, 4));
+        assert_eq!(kind("1s500ms1s"), (UnorderedDuration, 4));
+        assert_eq!(kind("1m1m"), (UnorderedDuration, 4));
+        assert_eq!(kind("1x"), (InvalidDuration, 4));
+        assert_eq!(kind("1h2.m"), (InvalidDuration, 4));
+        assert_eq!(kind("213503982334602d"), (InvalidDuration, 4));
+        // something that is not a duration at all says so, instead of asking for a map
+        assert_eq!(kind("5"), (ExpectedDuration, 4));
+        assert_eq!(kind("\"5s\""), (ExpectedDuration, 4));
+        assert_eq!(
+            duration("1m1h").unwrap_err().to_string(),
+            "Duration units have to go from largest to smallest, each at most once at 1:5\n    \
+             while parsing the value for key `d`"
+        );
+        // the `{ secs, nanos }` form still works
+        assert_eq!(
+            duration("{ secs = 1 nanos = 5 }").unwrap(),
+            Duration::new(1, 5)
+        );
+    }
+
+    #[test]
+    fn nested_maps() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Node {
+            id: u32,
+            next: Option<Box<Node>>,
+        }
+
+        let src = (0..40).rev().fold("id = 40".to_owned(), |inner, i| {
+            format!("id = {i} skip = {{ a = [{{}} {{ b = 1 }}] }} next = {{ {inner} }}")
+        });
+        let mut node: Node = from_str(&src).unwrap();
+        let mut depth = 0;
+        while let Some(next) = node.next {
+            assert_eq!(node.id, depth);
+            node = *next;
+            depth += 1;
+        }
+        assert_eq!((node.id, depth), (40, 40));
+
+        // dotted keys are merged with the braced map under the same key, wherever it is
+        let value: BTreeMap<String, Value> =
+            from_str("a.b = { x = 1 } c = 2 a = { b.y = 2 d = [3] } a.b.z = 4").unwrap();
+        let expected: BTreeMap<String, Value> =
+            from_str("a = { b = { x = 1 y = 2 z = 4 } d = [3] } c = 2").unwrap();
+        assert_eq!(value, expected);
+        let err = from_str::<Value>("a = { b = 1 } a.b.c = 2").unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::Parse(ParseError {
+                kind: ParseErrorKind::MergeConflict { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn dotted_after_map() {
+        // maps are streamed, so a braced map is read before a dotted key adding to it
+        let src = "a = { x = 1 }\nb = 2\na.y = 3";
+        let err = from_str::<Value>(src).unwrap_err();
+        match err.kind() {
+            ErrorKind::Parse(ParseError {
+                kind: ParseErrorKind::DottedAfterMap { key, map },
+                token,
+                ..
+            }) => {
+                assert_eq!(key, "a");
+                assert_eq!((token.line, token.col), (2, 0));
+                assert_eq!((map.line, map.col), (0, 4));
+            }
+            other => panic!("expected a dotted key after its map, found {other:?}"),
+        }
+        // which the document functions read again with every map read ahead
+        let value: Value = crate::from_str(src).unwrap();
+        assert_eq!(value, crate::from_str("a = { x = 1 y = 3 } b = 2").unwrap());
+        let value: Value = crate::from_str(&format!("n = {{ {src} }}")).unwrap();
+        assert_eq!(
+            value,
+            crate::from_str("n.a = { x = 1 y = 3 } n.b = 2").unwrap()
+        );
+
+        let err = crate::from_str::<Value>("a = { b = 1 }\na.b.c = 2").unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            ErrorKind::Parse(ParseError {
+                kind: ParseErrorKind::MergeConflict { .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn max_depth() {
+        #[derive(Debug, Deserialize)]
+        struct Node {
+            #[allow(unused)]
+            a: Option<Box<Node>>,
+        }
+
+        fn too_deep(src: &str) -> (ParseErrorKind, u32, u32, Option<String>) {
+            let err = from_str::<Node>(src).unwrap_err();
+            let path = err.path().map(|path| path.to_string());
+            match err.kind() {
+                ErrorKind::Parse(err) => (err.kind.clone(), err.token.line, err.token.col, path),
+                other => panic!("expected a parse error, found {other:?}"),
+            }
+        }
+
+        let max = DEFAULT_MAX_DEPTH as usize;
+        let braced = |depth| "a = { ".repeat(depth) + &" }".repeat(depth);
+        assert!(from_str::<Node>(&braced(max - 1)).is_ok());
+        let (kind, line, col, path) = too_deep(&braced(max + 1));
+        assert_eq!(kind, ParseErrorKind::TooDeep { max: max as u32 });
+        assert_eq!((line, col), (0, 6 * max as u32 + 4));
+        assert_eq!(path, Some(vec!["a"; max + 1].join(".")));
+
+        // dotted keys are not nested in the source, but still recurse in the deserializer
+        let dotted = |depth| vec!["a"; depth].join(".") + " = {}";
+        assert!(from_str::<Node>(&dotted(max - 1)).is_ok());
+        let (kind, line, col, path) = too_deep(&dotted(max + 1));
+        assert_eq!(kind, ParseErrorKind::TooDeep { max: max as u32 });
+        assert_eq!(line, 0);
+        assert_eq!(col, 2 * max as u32 + 4);
+        assert_eq!(path, Some(vec!["a"; max + 1].join(".")));
+
+        let src = dotted(max + 1);
+        let mut de = TopDeserializer::from_str(&src);
+        de.disable_recursion_limit();
+        assert!(Node::deserialize(&mut de).is_ok());
+    }
+
+    #[test]
+    fn enum_representations() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum External {
+            Unit,
+            Newtype(u32),
+            Struct { x: u32 },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "type")]
+        enum Internal {
+            Unit,
+            Newtype(Server),
+            Struct { path: String },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            Unit,
+            Newtype(u32),
+            Tuple(u8, u8),
+            Struct { x: String },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(untagged)]
+        enum Untagged {
+            Int(u32),
+            String(String),
+            Struct { k: Duration },
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            external: Vec<External>,
+            internal: Vec<Internal>,
+            adjacent: Vec<Adjacent>,
+            untagged: Vec<Untagged>,
+            dotted: Internal,
+        }
+
+        let config: Config = from_str(
+            r#"
+            external = ["Unit" { Newtype = 1 } { Struct = { x = 2 } }]
+            internal = [
+                { type = "Unit" }
+                { type = "Newtype" host = "localhost" port = 80 }
+                { path = "data.db" type = "Struct" }
+            ]
+            adjacent = [
+                { t = "Unit" }
+                { t = "Newtype" c = 1 }
+                { c = [2 3] t = "Tuple" }
+                { t = "Struct" c = { x = "y" } }
+            ]
+            untagged = [1 "two" { k = 3s }]
+            dotted.type = "Struct"
+            dotted.path = "data.db"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                external: vec![
+                    External::Unit,
+                    External::Newtype(1),
+                    External::Struct { x: 2 },
+                ],
+                internal: vec![
+                    Internal::Unit,
+                    Internal::Newtype(Server {
+                        host: "localhost".into(),
+                        port: 80,
+                    }),
+                    Internal::Struct {
+                        path: "data.db".into(),
+                    },
+                ],
+                adjacent: vec![
+                    Adjacent::Unit,
+                    Adjacent::Newtype(1),
+                    Adjacent::Tuple(2, 3),
+                    Adjacent::Struct { x: "y".into() },
+                ],
+                untagged: vec![
+                    Untagged::Int(1),
+                    Untagged::String("two".into()),
+                    Untagged::Struct {
+                        k: Duration::from_secs(3),
+                    },
+                ],
+                dotted: Internal::Struct {
+                    path: "data.db".into(),
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn unit_variant_ident() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Level {
+            Debug,
+            Info,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Config {
+            level: Level,
+            levels: Vec<Level>,
+        }
+
+        let config: Config = from_str("level = Info\nlevels = [Debug Info]").unwrap();
+        assert_eq!(
+            config,
+            Config {
+                level: Level::Info,
+                levels: vec![Level::Debug, Level::Info],
+            }
+        );
+        let config: Config = from_str("level =\n    Debug\nlevels = []").unwrap();
+        assert_eq!(config.level, Level::Debug);
+        let err = from_str::<Config>("level =\nlevels = []").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected a value, found an identifier at 2:1\n    while parsing the value for key `level`"
+        );
+
+        let err = from_str::<Config>("levels = []\nlevel = 1").unwrap_err();
+        assert_eq!(err.path().unwrap().to_string(), "level");
+        assert_eq!(
+            err.to_string().lines().next(),
+            Some("Expected an enum variant, found an unsigned Integer at 2:9")
+        );
+    }
+
+    #[test]
+    fn deny_duplicate_keys() {
+        let options = Options {
+            duplicate_keys: DuplicateKeys::Error,
+            ..Options::default()
+        };
+        let duplicate = |src| {
+            let res = BTreeMap::<String, Value>::deserialize(&mut TopDeserializer::with_options(
+                src,
+                options.clone(),
+            ));
+            match res.unwrap_err().kind() {
+                ErrorKind::Parse(ParseError {
+                    kind: ParseErrorKind::DuplicateKey { key, first },
+                    ..
+                }) => (key.clone(), first.line, first.col),
+                other => panic!("expected a duplicate key error, found {other:?}"),
+            }
+        };
+
+        assert_eq!(duplicate("a = 1\na = 2"), ("a".into(), 0, 0));
+        assert_eq!(duplicate("a.b = 1\na.b = 2"), ("a.b".into(), 0, 2));
+        assert_eq!(duplicate("a.b = 1\na = { b = 2 }"), ("a.b".into(), 0, 2));
+        assert_eq!(duplicate("x = { y = 1 y = 2 }"), ("y".into(), 0, 6));
+        assert!(from_str::<BTreeMap<String, Value>>("a = 1\na = 2").is_ok());
+    }
+
+    #[test]
+    fn duplicate_key_message() {
+        let options = Options {
+            duplicate_keys: DuplicateKeys::Error,
+            ..Options::default()
+        };
+        let src = "a = 1\nb = 2\na = 3";
+        let err = from_str_with_options::<Value>(src, options).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Duplicate key 'a' at 3:1, first defined at 1:1"
+        );
+        assert_eq!(
+            err.render(src),
+            "error: Duplicate key 'a'\n \
+             --> 3:1\n  \
+             |\n\
+             3 | a = 3\n  \
+             | ^\n\
+             note: first defined here\n \
+             --> 1:1\n  \
+             |\n\
+             1 | a = 1\n  \
+             | ^"
+        );
+    }
+
+    #[test]
+    fn first_wins_duplicate_keys() {
+        let options = Options {
+            duplicate_keys: DuplicateKeys::FirstWins,
+            ..Options::default()
+        };
+        let first =
+            |src| from_str_with_options::<BTreeMap<String, Value>>(src, options.clone()).unwrap();
+
+        assert_eq!(first("a = 1 b = 2 a = 3"), from_str("a = 1 b = 2").unwrap());
+        assert_eq!(
+            first("s = { x = 1 x = 2 } s = { x = 3 }"),
+            from_str("s.x = 1").unwrap()
+        );
+        // dotted keys are merged into the first value, not replaced by it
+        assert_eq!(
+            first("a = { x = 1 } a.y = 2 a = { x = 3 }"),
+            from_str("a = { x = 1 y = 2 }").unwrap()
+        );
+        let server: Server =
+            from_str_with_options("host = \"a\" port = 1 port = 2", options).unwrap();
+        assert_eq!(server.port, 1);
+    }
+
+    #[test]
+    fn last_wins_duplicate_keys() {
+        let options = Options {
+            duplicate_keys: DuplicateKeys::LastWins,
+            ..Options::default()
+        };
+        let last = |src| {
+            BTreeMap::<String, Value>::deserialize(&mut TopDeserializer::with_options(
+                src,
+                options.clone(),
+            ))
+            .unwrap()
+        };
+
+        assert_eq!(last("a = 1 b = 2 a = 3"), from_str("a = 3 b = 2").unwrap());
+        assert_eq!(
+            last("s = { x = 1 x = 2 } s = { x = 3 }"),
+            from_str("s.x = 3").unwrap()
+        );
+        assert_eq!(
+            last("a = { x = 1 } a.y = 2 a = { x = 3 }"),
+            from_str("a = { x = 3 y = 2 }").unwrap()
+        );
+        let server: Server =
+            from_str_with_options("host = \"a\" port = 1 port = 2", options).unwrap();
+        assert_eq!(server.port, 2);
+    }
+
+    #[test]
+    fn error_path() {
+        #[derive(Debug, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Tls {
+            #[allow(unused)]
+            port: u16,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[allow(unused)]
+            servers: Vec<BTreeMap<String, Tls>>,
+        }
+
+        let path = |src| {
+            from_str::<Config>(src)
+                .unwrap_err()
+                .path()
+                .map(|path| path.to_string())
+        };
+        assert_eq!(
+            path("servers = [{} { tls.port = true }]").as_deref(),
+            Some("servers[1].tls.port")
+        );
+        assert_eq!(
+            path("servers = [{ tls = { port = 1 host = \"a\" } }]").as_deref(),
+            Some("servers[0].tls")
+        );
+        assert_eq!(path("servers = 1").as_deref(), Some("servers"));
+        assert_eq!(path("x = 1"), None);
+    }
+
+    #[test]
+    fn syntax_error_path() {
+        #[derive(Debug, Deserialize)]
+        struct Tls {
+            #[allow(unused)]
+            port: Vec<u16>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Server {
+            #[allow(unused)]
+            tls: Tls,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[allow(unused)]
+            server: Server,
+        }
+
+        // the same context as checking the syntax alone gives
+        let check = |src| {
+            let errors = crate::parse::Parser::new(src).check_top_level();
+            errors[0].context.clone()
+        };
+        let src = "server = { tls = { port = [1 2 } } }";
+        let err = from_str::<Config>(src).unwrap_err();
+        assert_eq!(
+            err.path().map(|path| path.to_string()).as_deref(),
+            Some("server.tls.port")
+        );
+        match err.kind() {
+            ErrorKind::Parse(err) => {
+                assert_eq!(err.token.col, 31);
+                assert_eq!(err.context, check(src));
+            }
+            other => panic!("expected a parse error, found {other:?}"),
+        }
+
+        // in values that are skipped as well
+        let src = "server.tls.port = []\nold = { a = [1 { b.c = }] }";
+        let err = from_str::<Config>(src).unwrap_err();
+        assert_eq!(
+            err.path().map(|path| path.to_string()).as_deref(),
+            Some("old.a[1].b.c")
+        );
+        match err.kind() {
+            ErrorKind::Parse(err) => assert_eq!(err.context, check(src)),
+            other => panic!("expected a parse error, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn integral_floats() {
+        let options = Options {
+            integral_floats: true,
+            ..Options::default()
+        };
+        let count = |src| {
+            let mut de = TopDeserializer::with_options(src, options.clone());
+            BTreeMap::<String, u8>::deserialize(&mut de).map(|map| map["count"])
+        };
+
+        assert_eq!(count("count = 3.0").unwrap(), 3);
+        assert_eq!(count("count = 2e2").unwrap(), 200);
+        let kind = |src| match count(src).unwrap_err().kind() {
+            ErrorKind::Parse(err) => (err.kind.clone(), err.token.line, err.token.col),
+            other => panic!("expected a parse error, found {other:?}"),
+        };
+        assert_eq!(
+            kind("\ncount = 3.5"),
+            (ParseErrorKind::FractionalInteger, 1, 8)
+        );
+        assert_eq!(kind("count = 3e3"), (ParseErrorKind::InvalidInteger, 0, 8));
+        assert!(from_str::<BTreeMap<String, u8>>("count = 3.0").is_err());
+    }
+
+    #[test]
+    fn integer_floats() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Point {
+            x: f64,
+            y: f32,
+        }
+
+        let point = |src| from_str::<Point>(src);
+        assert_eq!(point("x = 3 y = -2").unwrap(), Point { x: 3.0, y: -2.0 });
+        assert_eq!(
+            point("x = 0xff y = 0b101").unwrap(),
+            Point { x: 255.0, y: 5.0 }
+        );
+        assert_eq!(
+            point("x = 0o17 y = 1_000").unwrap(),
+            Point { x: 15.0, y: 1000.0 }
+        );
+        assert_eq!(
+            point("x = 9007199254740992 y = 16777216").unwrap(),
+            Point {
+                x: 9007199254740992.0,
+                y: 16777216.0
+            }
+        );
+        // any power of two fits, even past 64 bits
+        assert_eq!(
+            point("x = 0x8000_0000_0000_0000_0000 y = 0").unwrap(),
+            Point {
+                x: 2f64.powi(79),
+                y: 0.0
+            }
+        );
+
+        let kind = |src| match point(src).unwrap_err().kind() {
+            ErrorKind::Parse(err) => (err.kind.clone(), err.token.line, err.token.col),
+            other => panic!("expected a parse error, found {other:?}"),
+        };
+        assert_eq!(
+            kind("x = 9007199254740993 y = 0"),
+            (ParseErrorKind::InexactFloat, 0, 4)
+        );
+        assert_eq!(
+            kind("x = 0 y = 16777217"),
+            (ParseErrorKind::InexactFloat, 0, 10)
+        );
+        // `u128` literals are converted too
+        assert_eq!(
+            point("x = 0x8000_0000_0000_0000_0000_0000_0000_0000 y = 0").unwrap(),
+            Point {
+                x: 2f64.powi(127),
+                y: 0.0
+            }
+        );
+        assert_eq!(
+            kind("x = 340282366920938463463374607431768211455 y = 0"),
+            (ParseErrorKind::InexactFloat, 0, 4)
+        );
+        assert_eq!(
+            kind("x = 1000000000000000000000000000000000000000 y = 0"),
+            (ParseErrorKind::InvalidInteger, 0, 4)
+        );
+    }
+
+    #[test]
+    fn byte_strings() {
+        // like `serde_bytes::ByteBuf`, which asks for bytes instead of a sequence
+        #[derive(Debug, PartialEq)]
+        struct ByteBuf(Vec<u8>);
+
+        impl<'de> Deserialize<'de> for ByteBuf {
+            fn deserialize<D>(deserializer: D) -> core::result::Result<Self, D::Error>
+            where
+                D: de::Deserializer<'de>,
+            {
+                struct BytesVisitor;
+
+                impl<'de> Visitor<'de> for BytesVisitor {
+                    type Value = ByteBuf;
+
+                    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                        f.write_str("bytes")
+                    }
+
+                    fn visit_byte_buf<E>(self, v: Vec<u8>) -> core::result::Result<ByteBuf, E> {
+                        Ok(ByteBuf(v))
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> core::result::Result<ByteBuf, A::Error>
+                    where
+                        A: SeqAccess<'de>,
+                    {
+                        let mut bytes = Vec::new();
+                        while let Some(byte) = seq.next_element()? {
+                            bytes.push(byte);
+                        }
+                        Ok(ByteBuf(bytes))
+                    }
+                }
+
+                deserializer.deserialize_byte_buf(BytesVisitor)
+            }
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Key {
+            key: ByteBuf,
+            list: Vec<u8>,
+        }
+
+        let key = |src, bytes_encoding| {
+            let options = Options {
+                bytes_encoding,
+                ..Options::default()
+            };
+            from_str_with_options::<Key>(src, options)
+        };
+        let expected = Key {
+            key: ByteBuf(b"ycf!".to_vec()),
+            list: vec![1, 2],
+        };
+        let hex = BytesEncoding::Hex;
+        let base64 = BytesEncoding::Base64;
+        assert_eq!(
+            key("key = \"79636621\" list = [1 2]", hex).unwrap(),
+            expected
+        );
+        assert_eq!(
+            key("key = \"eWNmIQ==\" list = [1 2]", base64).unwrap(),
+            expected
+        );
+        assert_eq!(
+            key("key = \"eWNmIQ\" list = [1 2]", base64).unwrap(),
+            expected
+        );
+        // a list of integers still works, with any encoding
+        assert_eq!(
+            key("key = [0x79 0x63 0x66 0x21] list = [1 2]", base64).unwrap(),
+            expected
+        );
+        assert_eq!(
+            from_str::<Key>("key = \"\" list = []").unwrap(),
+            Key {
+                key: ByteBuf(Vec::new()),
+                list: Vec::new(),
+            }
+        );
+
+        let kind = |src, encoding| match key(src, encoding).unwrap_err().kind() {
+            ErrorKind::Parse(err) => (err.kind.clone(), err.token.line, err.token.col),
+            other => panic!("expected a parse error, found {other:?}"),
+        };
+        assert_eq!(
+            kind("key = \"7963662\" list = []", hex),
+            (ParseErrorKind::InvalidBytes(hex), 0, 6)
+        );
+        assert_eq!(
+            kind("list = []\nkey = \"7963-621\"", base64),
+            (ParseErrorKind::InvalidBytes(base64), 1, 6)
+        );
+        // a plain `Vec<u8>` is a sequence, so it never takes a string
+        assert!(key("key = \"\" list = \"0102\"", hex).is_err());
+    }
+
+    #[test]
+    fn env_variables() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Config {
+            dir: String,
+            port: u16,
+            name: String,
+        }
+
+        let options = Options {
+            env: Some(Env::new(|name| match name {
+                "HOME" => Some("/home/me".into()),
+                "EMPTY" => Some(String::new()),
+                _ => None,
+            })),
+            numeric_strings: true,
+            ..Options::default()
+        };
+        let config: Config = from_str_with_options(
+            r#"dir = "${HOME}/data $${HOME}" port = "${PORT:-8080}" name = "\x24{EMPTY:-x}${EMPTY}""#,
+            options.clone(),
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                dir: "/home/me/data ${HOME}".into(),
+                port: 8080,
+                name: "x".into(),
+            }
+        );
+
+        let err = from_str_with_options::<Config>("\ndir = \"${NOPE}\"", options).unwrap_err();
+        match err.kind() {
+            ErrorKind::Parse(err) => {
+                assert_eq!(err.kind, ParseErrorKind::UnsetVariable("NOPE".into()));
+                assert_eq!(err.token.line, 1);
+            }
+            other => panic!("expected a parse error, found {other:?}"),
+        }
+        // without an environment strings are left as they are
+        let dir = from_str::<BTreeMap<String, String>>("dir = \"${HOME}\"").unwrap();
+        assert_eq!(dir["dir"], "${HOME}");
+    }
+
+    #[test]
+    fn key_case() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Pool {
+            max_connections: u32,
+            idle_timeout: Option<u32>,
+        }
+
+        let options = Options {
+            key_case: KeyCase::Snake,
+            duplicate_keys: DuplicateKeys::Error,
+            ..Options::default()
+        };
+        let pool =
+            |src| Pool::deserialize(&mut TopDeserializer::with_options(src, options.clone()));
+
+        assert_eq!(
+            pool("max-connections = 4 idle_timeout = 2").unwrap(),
+            Pool {
+                max_connections: 4,
+                idle_timeout: Some(2),
+            }
+        );
+        assert!(pool("max-connections = 4 max_connections = 5").is_err());
+        assert!(from_str::<Pool>("max-connections = 4").is_err());
+        assert_eq!(KeyCase::Kebab.apply("idle_timeout"), "idle-timeout");
+    }
+
+    #[test]
+    fn case_insensitive_variants() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        enum Level {
+            Debug,
+            Warn(u8),
+            Error { code: u8 },
+        }
+
+        let options = Options {
+            case_insensitive_variants: true,
+            ..Options::default()
+        };
+        let level = |src| {
+            let mut de = TopDeserializer::with_options(src, options.clone());
+            BTreeMap::<String, Level>::deserialize(&mut de).map(|mut map| map.remove("level"))
+        };
+
+        assert_eq!(level("level = \"DEBUG\"").unwrap(), Some(Level::Debug));
+        assert_eq!(level("level = debug").unwrap(), Some(Level::Debug));
+        assert_eq!(level("level = { warn = 2 }").unwrap(), Some(Level::Warn(2)));
+        assert_eq!(
+            level("level.ERROR.code = 1").unwrap(),
+            Some(Level::Error { code: 1 })
+        );
+        assert!(level("level = \"trace\"").is_err());
+        assert!(from_str::<BTreeMap<String, Level>>("level = \"DEBUG\"").is_err());
+    }
+
+    #[test]
+    fn trailing_content() {
+        let err = super::from_str::<Vec<u8>>("[1 2]\n[3]").unwrap_err();
+        match err.kind() {
+            ErrorKind::Parse(err) => assert_eq!((err.token.line, err.token.col), (1, 0)),
+            other => panic!("expected a parse error, found {other:?}"),
+        }
+
+        let options = Options {
+            allow_trailing: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            from_str_with_options::<Vec<u8>>("[1 2] [3]", options).unwrap(),
+            [1, 2]
+        );
+
+        let mut de = Deserializer::from_str("{ a = 1 } // done");
+        BTreeMap::<String, u8>::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+    }
+
+    #[test]
+    fn unused_keys() {
+        #[derive(Debug, Deserialize)]
+        struct Tls {
+            #[allow(unused)]
+            port: u16,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[allow(unused)]
+            tls: Tls,
+            #[allow(unused)]
+            backends: Vec<Tls>,
+        }
+
+        let (_, unused) = from_str_unused::<Config>(
+            "tls.port = 1\ntls.prot = 2\nbackends = [{ port = 1 weight = 2 }]\nold.a = 1\nold.b = 2",
+        )
+        .unwrap();
+        let unused: Vec<_> = unused
+            .iter()
+            .map(|u| (u.path.to_string(), u.key.line, u.key.col))
+            .collect();
+        assert_eq!(
+            unused,
+            [
+                ("tls.prot", 1, 4),
+                ("backends[0].weight", 2, 23),
+                ("old", 3, 0)
+            ]
+            .map(|(path, line, col)| (path.to_string(), line, col))
+        );
+    }
+
+    #[test]
+    fn stateful_seed() {
+        // strings as their index in a table that is shared between documents
+        struct Intern<'a>(&'a mut Vec<String>);
+
+        impl<'de> DeserializeSeed<'de> for Intern<'_> {
+            type Value = BTreeMap<String, usize>;
+
+            fn deserialize<D>(self, deserializer: D) -> core::result::Result<Self::Value, D::Error>
+            where
+                D: de::Deserializer<'de>,
+            {
+                let map = BTreeMap::<String, String>::deserialize(deserializer)?;
+                let table = self.0;
+                Ok(map
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let index = table.iter().position(|v| *v == value).unwrap_or_else(|| {
+                            table.push(value);
+                            table.len() - 1
+                        });
+                        (key, index)
+                    })
+                    .collect())
+            }
+        }
+
+        let mut table = Vec::new();
+        let a = crate::from_str_seed("x = \"a\" y = \"b\"", Intern(&mut table)).unwrap();
+        let b = crate::from_str_seed("{ z = \"b\" w = \"c\" }", Intern(&mut table)).unwrap();
+        assert_eq!(a, BTreeMap::from([("x".into(), 0), ("y".into(), 1)]));
+        assert_eq!(b, BTreeMap::from([("w".into(), 2), ("z".into(), 1)]));
+        assert_eq!(table, ["a", "b", "c"]);
+
+        let err = crate::from_str_seed("x = 1", Intern(&mut table)).unwrap_err();
+        assert_eq!(err.path().map(|path| path.to_string()), Some("x".into()));
+    }
+
+    #[test]
+    fn null_as_empty() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Config {
+            tags: Vec<String>,
+            labels: BTreeMap<String, String>,
+        }
+
+        let options = Options {
+            null_as_empty: true,
+            ..Options::default()
+        };
+        let config =
+            |src| Config::deserialize(&mut TopDeserializer::with_options(src, options.clone()));
+
+        assert_eq!(
+            config("tags = null labels = null").unwrap(),
+            Config {
+                tags: Vec::new(),
+                labels: BTreeMap::new(),
+            }
+        );
+        assert!(from_str::<Config>("tags = null labels = null").is_err());
+    }
+
+    #[test]
+    fn null_as_default() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Server {
+            #[serde(default = "default_port")]
+            port: u16,
+            host: Option<String>,
+        }
+
+        fn default_port() -> u16 {
+            80
+        }
+
+        let options = Options {
+            null_as_default: true,
+            ..Options::default()
+        };
+        let server = |src| {
+            let mut de = TopDeserializer::with_options(src, options.clone());
+            BTreeMap::<String, Server>::deserialize(&mut de).map(|mut map| map.remove("server"))
+        };
+        let expected = Some(Server {
+            port: 80,
+            host: None,
+        });
+
+        assert_eq!(
+            server("server = { port = null host = null }").unwrap(),
+            expected
+        );
+        assert_eq!(server("server.port = null").unwrap(), expected);
+        assert!(server("server = null").is_err());
+        assert!(from_str::<BTreeMap<String, Server>>("server.port = null").is_err());
+    }
+
+    #[test]
+    fn typed_map_keys() {
+        #[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+        #[serde(rename_all = "lowercase")]
+        enum Proto {
+            Tcp,
+            Udp,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            ports: BTreeMap<u16, String>,
+            protocols: BTreeMap<Proto, bool>,
+        }
+
+        let config: Config =
+            from_str("ports = { \"80\" = \"http\" \"443\" = \"https\" }\nprotocols.udp = false")
+                .unwrap();
+        assert_eq!(
+            config.ports,
+            BTreeMap::from([(80, "http".into()), (443, "https".into())])
+        );
+        assert_eq!(config.protocols, BTreeMap::from([(Proto::Udp, false)]));
+
+        let err = from_str::<Config>("ports.\"http\" = \"x\"").unwrap_err();
+        match err.kind() {
+            ErrorKind::Parse(err) => {
+                assert_eq!(err.kind, ParseErrorKind::InvalidInteger);
+                assert_eq!((err.token.line, err.token.col), (0, 6));
+            }
+            other => panic!("expected a parse error, found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn variant_suggestion() {
+        #[derive(Debug, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Compression {
+            Gzip,
+            Zstd,
+        }
+
+        let message = |src| {
+            from_str::<BTreeMap<String, Compression>>(src)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(message("codec = \"gzpi\"").contains("did you mean `gzip`?"));
+        assert!(message("codec = ZSTD").contains("did you mean `zstd`?"));
+        assert!(!message("codec = \"brotli\"").contains("did you mean"));
+    }
+
+    #[test]
+    fn deny_unknown_fields() {
+        #[derive(Debug, Deserialize)]
+        struct Tls {
+            #[allow(unused)]
+            port: u16,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[allow(unused)]
+            tls: Tls,
+        }
+
+        let options = Options {
+            deny_unknown_fields: true,
+            ..Options::default()
+        };
+        let unknown = |src| {
+            let res = Config::deserialize(&mut TopDeserializer::with_options(src, options.clone()));
+            match res.unwrap_err().kind() {
+                ErrorKind::Parse(err) => (err.kind.clone(), err.token.line, err.token.col),
+                other => panic!("expected a parse error, found {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            unknown("tls = { port = 1\nprot = 2 }"),
+            (
+                ParseErrorKind::UnknownField("prot".into(), Some("port")),
+                1,
+                0
+            )
+        );
+        assert_eq!(
+            unknown("tls.port = 1\ntls.host = 2"),
+            (ParseErrorKind::UnknownField("host".into(), None), 1, 4)
+        );
+        assert_eq!(
+            unknown("tsl.port = 1"),
+            (
+                ParseErrorKind::UnknownField("tsl".into(), Some("tls")),
+                0,
+                0
+            )
+        );
+        assert!(from_str::<Config>("tls = { port = 1 prot = 2 }").is_ok());
+    }
+
+    #[test]
+    fn escaped_strings() {
+        let map: BTreeMap<String, (String, char)> =
+            from_str(r#"a = ["x\ty" '\n'] b = ["\"q\"" 'z'] c = ["plain" '\\']"#).unwrap();
+        assert_eq!(map["a"], ("x\ty".to_owned(), '\n'));
+        assert_eq!(map["b"], ("\"q\"".to_owned(), 'z'));
+        assert_eq!(map["c"], ("plain".to_owned(), '\\'));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn top_level_values() {
+        use serde::Serialize;
+
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        struct Item {
+            name: String,
+        }
+
+        #[derive(Debug, Deserialize, Serialize, PartialEq)]
+        struct Wrapper(BTreeMap<String, u32>);
+
+        let items = vec![
+            Item {
+                name: "a".to_string(),
+            },
+            Item {
+                name: "b".to_string(),
+            },
+        ];
+        let src = crate::to_string(&items).unwrap();
+        assert_eq!(
+            src,
+            "[\n    {\n        name = \"a\"\n    }\n    {\n        name = \"b\"\n    }\n]\n"
+        );
+        assert_eq!(crate::from_str::<Vec<Item>>(&src).unwrap(), items);
+
+        assert_eq!(crate::to_string(&5).unwrap(), "5\n");
+        assert_eq!(crate::from_str::<u32>("5").unwrap(), 5);
+        assert_eq!(crate::to_string("x").unwrap(), "\"x\"\n");
+        assert_eq!(crate::from_str::<String>(r#""x""#).unwrap(), "x");
+        assert_eq!(crate::to_string(&None::<u32>).unwrap(), "null\n");
+        assert_eq!(crate::from_str::<Option<u32>>("null").unwrap(), None);
+
+        // maps are still written without braces
+        let wrapper = Wrapper(BTreeMap::from([("a".to_string(), 1)]));
+        assert_eq!(crate::to_string(&wrapper).unwrap(), "a = 1\n");
+        assert_eq!(crate::from_str::<Wrapper>("a = 1").unwrap(), wrapper);
+        assert_eq!(
+            crate::from_str::<Option<Wrapper>>("a = 1").unwrap(),
+            Some(wrapper)
+        );
+
+        let value = Value::List(vec![Value::U64(1), Value::String("x".to_string())]);
+        assert_eq!(
+            crate::to_string(&value).unwrap().parse::<Value>().unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn from_slice() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Config<'a> {
+            name: &'a str,
+        }
+
+        let expected = Config { name: "é" };
+        assert_eq!(
+            crate::from_slice::<Config>(r#"name = "é""#.as_bytes()).unwrap(),
+            expected
+        );
+        let bom = b"\xef\xbb\xbfname = \"\xc3\xa9\"";
+        assert_eq!(crate::from_slice::<Config>(bom).unwrap(), expected);
+
+        let err =
+            crate::from_slice::<Config>(b"\xef\xbb\xbf\nname = \"\xc3\xa9\xff\"").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid UTF-8 at 2:10");
+        assert_eq!(err.span().unwrap().range(), 11..12);
+
+        for (src, encoding) in [
+            (&b"\xff\xfen\0"[..], "UTF-16LE"),
+            (b"\xfe\xff\0n", "UTF-16BE"),
+        ] {
+            let err = crate::from_slice::<Config>(src).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "The document is encoded as {encoding}, but only UTF-8 is supported at 1:1"
+                )
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn from_file() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Config {
+            port: u16,
+        }
+
+        let dir = std::env::temp_dir().join(format!("ycf-from-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.ycf");
+        std::fs::write(&path, "port = 80").unwrap();
+        assert_eq!(
+            crate::from_file::<Config>(&path).unwrap(),
+            Config { port: 80 }
+        );
+
+        std::fs::write(&path, "\nport = \"x\"").unwrap();
+        let err = crate::from_file::<Config>(&path).unwrap_err();
+        assert_eq!(err.file(), Some(&*path));
+        assert_eq!((err.line(), err.column()), (Some(1), Some(7)));
+        assert!(
+            err.to_string().contains(&format!("{}:2:8", path.display())),
+            "{err}"
+        );
+
+        let missing = dir.join("missing.ycf");
+        let err = crate::from_file::<Config>(&missing).unwrap_err();
+        assert_eq!(err.classify(), crate::error::Category::Io);
+        assert_eq!(err.file(), Some(&*missing));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn get() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Server {
+            port: u16,
+        }
+
+        let src = r#"
+            name = "app"
+            servers = [{ host = "a" port = 1 } { host = "b" port = 2 }]
+            server = { port = 80 }
+            server.host = "h"
+            db = { replica.host = "r" }
+            db.replica.port = 5432
+            level = 1
+            level = 2
+        "#;
+        assert_eq!(crate::get(src, "name").unwrap(), Some("app"));
+        assert_eq!(crate::get(src, "servers[1].host").unwrap(), Some("b"));
+        assert_eq!(
+            crate::get(src, "server").unwrap(),
+            Some(Server { port: 80 })
+        );
+        assert_eq!(crate::get(src, "db.replica.port").unwrap(), Some(5432));
+        assert_eq!(crate::get(src, "level").unwrap(), Some(2));
+        let replica: BTreeMap<String, Value> = crate::get(src, "db.replica").unwrap().unwrap();
+        assert_eq!(replica.len(), 2);
+        for missing in [
+            "nope",
+            "servers[2]",
+            "name.x",
+            "server[0]",
+            "servers[0].tls",
+        ] {
+            assert_eq!(
+                crate::get::<Value, _>(src, missing).unwrap(),
+                None,
+                "{missing}"
+            );
+        }
+        assert_eq!(crate::get("[[1 2] [3]]", "[0][1]").unwrap(), Some(2));
+
+        // errors are the same as when deserializing the whole document
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            server: Server,
+        }
+        let src = "name = 1\nserver.port = \"x\"";
+        let err = crate::get::<Server, _>(src, "server").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            from_str::<Config>(src).unwrap_err().to_string()
+        );
+        assert_eq!(err.path().unwrap().to_string(), "server.port");
+        assert!(crate::get::<u16, _>("a = { b = 1", "a.b").is_err());
+    }
+
+    // the rest of the bindings need a JavaScript host
+}
@@ -0,0 +1,217 @@
+//! Integrations with diagnostic reporting crates, each behind a feature of the same name.
+
+#[cfg(feature = "miette")]
+mod miette {
+    use std::fmt::Display;
+
+    use miette::{Diagnostic, LabeledSpan, SourceSpan};
+
+    use crate::error::{Error, ErrorKind};
+    use crate::parse::{ParseError, ParseErrorKind, SpanToken};
+
+    fn span(token: SpanToken) -> SourceSpan {
+        (token.token.start, token.token.end - token.token.start).into()
+    }
+
+    /// Errors do not keep the source they came from, so it has to be attached to
+    /// the report, like `Report::new(err).with_source_code(src)`.
+    impl Diagnostic for ParseError {
+        fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+            match &self.kind {
+                ParseErrorKind::UnknownField(_, Some(closest)) => {
+                    Some(Box::new(format!("did you mean `{closest}`?")))
+                }
+                _ => None,
+            }
+        }
+
+        fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+            let primary = LabeledSpan::new_primary_with_span(None, span(self.token));
+            let secondary = self
+                .kind
+                .note()
+                .map(|(note, token)| LabeledSpan::at(span(token), format!("{note} here")));
+            Some(Box::new(std::iter::once(primary).chain(secondary)))
+        }
+    }
+
+    impl Diagnostic for Error {
+        fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+            match self.kind() {
+                ErrorKind::Parse(err) => err.help(),
+                _ => None,
+            }
+        }
+
+        fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+            match self.kind() {
+                ErrorKind::Parse(err) => err.labels(),
+                _ => None,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use serde::Deserialize;
+
+        use super::*;
+        use crate::de::{from_str_with_options, DuplicateKeys, Options};
+        use crate::Value;
+
+        #[test]
+        fn miette_labels() {
+            let options = Options {
+                duplicate_keys: DuplicateKeys::Error,
+                ..Options::default()
+            };
+            let err = from_str_with_options::<Value>("a = 1\nb = 2\na = 3", options).unwrap_err();
+            let labels: Vec<_> = err.labels().unwrap().collect();
+            assert_eq!(
+                labels,
+                [
+                    LabeledSpan::new_primary_with_span(None, (12, 1)),
+                    LabeledSpan::new(Some("first defined here".into()), 0, 1),
+                ]
+            );
+            assert!(err.help().is_none());
+
+            #[derive(Debug, Deserialize)]
+            struct Server {
+                #[allow(unused)]
+                port: u16,
+            }
+
+            let options = Options {
+                deny_unknown_fields: true,
+                ..Options::default()
+            };
+            let err = from_str_with_options::<Server>("port = 1\nprot = 2", options).unwrap_err();
+            let labels: Vec<_> = err.labels().unwrap().collect();
+            assert_eq!(labels, [LabeledSpan::new_primary_with_span(None, (9, 4))]);
+            assert_eq!(err.help().unwrap().to_string(), "did you mean `port`?");
+
+            // errors from the data have nothing to point at
+            let err = crate::from_str::<Server>("port = 1\nport = 2").unwrap_err();
+            assert!(err.labels().is_none());
+        }
+    }
+}
+
+#[cfg(feature = "codespan-reporting")]
+mod codespan_reporting {
+    use std::ops::Range;
+
+    use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+    use crate::error::{Error, ErrorKind};
+    use crate::parse::{self, ParseError, ParseErrorKind, SpanToken};
+
+    fn range(token: SpanToken) -> Range<usize> {
+        token.token.start..token.token.end
+    }
+
+    impl ParseError {
+        /// Converts the error to a diagnostic for the file `file_id`, labeling the
+        /// token it occurred at and any other token it refers to.
+        pub fn to_diagnostic<FileId: Copy>(&self, file_id: FileId) -> Diagnostic<FileId> {
+            let mut labels = vec![Label::primary(file_id, range(self.token))];
+            let mut notes = Vec::new();
+            if let Some((note, token)) = self.kind.note() {
+                labels.push(
+                    Label::secondary(file_id, range(token)).with_message(format!("{note} here")),
+                );
+            }
+            if let ParseErrorKind::UnknownField(_, Some(closest)) = &self.kind {
+                notes.push(format!("help: did you mean `{closest}`?"));
+            }
+            if !self.context.is_empty() {
+                notes.push(format!("in `{}`", parse::context_path(&self.context)));
+            }
+            Diagnostic::error()
+                .with_message(self.kind.display(self.token))
+                .with_labels(labels)
+                .with_notes(notes)
+        }
+    }
+
+    impl Error {
+        /// Converts the error to a diagnostic for the file `file_id`. Only errors
+        /// from parsing have labels, the rest just have their message.
+        pub fn to_diagnostic<FileId: Copy>(&self, file_id: FileId) -> Diagnostic<FileId> {
+            match self.kind() {
+                ErrorKind::Parse(err) => err.to_diagnostic(file_id),
+                ErrorKind::Message(msg, _) => {
+                    let diagnostic = Diagnostic::error().with_message(msg);
+                    match self.path() {
+                        Some(path) => diagnostic.with_note(format!("in `{path}`")),
+                        None => diagnostic,
+                    }
+                }
+                ErrorKind::Io(err) => Diagnostic::error().with_message(err),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use codespan_reporting::diagnostic::{LabelStyle, Severity};
+        use serde::Deserialize;
+
+        use crate::de::{from_str_with_options, DuplicateKeys, Options};
+        use crate::Value;
+
+        #[test]
+        fn codespan_diagnostic() {
+            let options = Options {
+                duplicate_keys: DuplicateKeys::Error,
+                ..Options::default()
+            };
+            let src = "a = 1\nb = { c = 2\nc = 3 }";
+            let err = from_str_with_options::<Value>(src, options).unwrap_err();
+            let diagnostic = err.to_diagnostic(7);
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.message, "Duplicate key 'c'");
+            let labels: Vec<_> = diagnostic
+                .labels
+                .iter()
+                .map(|label| {
+                    (
+                        label.style,
+                        label.file_id,
+                        label.range.clone(),
+                        &*label.message,
+                    )
+                })
+                .collect();
+            assert_eq!(
+                labels,
+                [
+                    (LabelStyle::Primary, 7, 18..19, ""),
+                    (LabelStyle::Secondary, 7, 12..13, "first defined here"),
+                ]
+            );
+            assert_eq!(diagnostic.notes, ["in `b`"]);
+            assert_eq!(&src[18..19], "c");
+
+            // errors from the data only have their message and path
+            #[derive(Debug, Deserialize)]
+            struct Inner {
+                #[allow(unused)]
+                b: u8,
+            }
+
+            #[derive(Debug, Deserialize)]
+            struct Outer {
+                #[allow(unused)]
+                a: Inner,
+            }
+
+            let err = crate::from_str::<Outer>("a = { c = 1 }").unwrap_err();
+            let diagnostic = err.to_diagnostic(());
+            assert_eq!(diagnostic.message, "missing field `b`");
+            assert!(diagnostic.labels.is_empty());
+            assert_eq!(diagnostic.notes, ["in `a`"]);
+        }
+    }
+}
@@ -1,35 +1,229 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{
+    fmt::{self, Display, Write as _},
+    mem, result,
+};
+#[cfg(feature = "std")]
 use std::{
-    fmt::{self, Display},
-    result, io,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use serde::{de, ser};
 
-use crate::parse::ParseError;
+use crate::cursor::TokenKind;
+use crate::line_index::LineIndex;
+use crate::parse::{self, ContextFrame, ParseError, ParseErrorKind, SpanToken};
 
 pub type Result<T> = result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Error {
-    kind: Box<ErrorKind>,
+    inner: Box<Inner>,
+}
+
+#[derive(Clone, Debug)]
+struct Inner {
+    kind: ErrorKind,
+    // the file the document was read from
+    #[cfg(feature = "std")]
+    file: Option<PathBuf>,
 }
 
 impl Error {
-    pub fn custom(msg: impl Display) -> Self {
+    fn new(kind: ErrorKind) -> Self {
         Self {
-            kind: ErrorKind::Message(msg.to_string()).into(),
+            inner: Box::new(Inner {
+                kind,
+                #[cfg(feature = "std")]
+                file: None,
+            }),
         }
     }
 
+    pub fn custom(msg: impl Display) -> Self {
+        Self::new(ErrorKind::Message(msg.to_string(), Vec::new()))
+    }
+
     pub fn parse(err: ParseError) -> Self {
-        Self {
-            kind: ErrorKind::Parse(err).into(),
-        }
+        Self::new(ErrorKind::Parse(err))
     }
 
+    #[cfg(feature = "std")]
     pub fn io(err: io::Error) -> Self {
-        Self {
-            kind: ErrorKind::Io(err).into()
+        Self::new(ErrorKind::Io(Arc::new(err)))
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.inner.kind
+    }
+
+    /// The file the document was read from, if the error came from one.
+    #[cfg(feature = "std")]
+    pub fn file(&self) -> Option<&Path> {
+        self.inner.file.as_deref()
+    }
+
+    /// Sets the file the document was read from, which is shown before the position.
+    /// An error that already has a file keeps it.
+    #[cfg(feature = "std")]
+    pub fn with_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.inner.file.get_or_insert_with(|| file.into());
+        self
+    }
+
+    /// Like [`with_file`](Self::with_file) for a document named `name`, which does
+    /// nothing without std, where errors have no file.
+    pub(crate) fn in_document(self, name: &str) -> Self {
+        #[cfg(feature = "std")]
+        return self.with_file(name);
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = name;
+            self
+        }
+    }
+
+    /// The file to show before positions, which there never is without std.
+    fn shown_file(&self) -> Option<impl Display + '_> {
+        #[cfg(feature = "std")]
+        return self.file().map(Path::display);
+        #[cfg(not(feature = "std"))]
+        None::<&str>
+    }
+
+    /// What sort of error this is, for handling errors without matching on every kind.
+    pub fn classify(&self) -> Category {
+        match &self.inner.kind {
+            ErrorKind::Message(..) => Category::Data,
+            ErrorKind::Parse(err) => match err.kind {
+                ParseErrorKind::Include(_) => Category::Io,
+                ParseErrorKind::TooDeep { .. } | ParseErrorKind::LimitExceeded(_) => {
+                    Category::Limit
+                }
+                ParseErrorKind::ExpectedNull
+                | ParseErrorKind::ExpectedBool
+                | ParseErrorKind::ExpectedInteger { .. }
+                | ParseErrorKind::ExpectedFloat
+                | ParseErrorKind::ExpectedDuration
+                | ParseErrorKind::ExpectedString
+                | ParseErrorKind::ExpectedChar
+                | ParseErrorKind::ExpectedEnum
+                | ParseErrorKind::InvalidInteger
+                | ParseErrorKind::InvalidFloat
+                | ParseErrorKind::InexactFloat
+                | ParseErrorKind::FractionalInteger
+                | ParseErrorKind::InvalidDuration
+                | ParseErrorKind::UnorderedDuration
+                | ParseErrorKind::InvalidBytes(_)
+                | ParseErrorKind::InvalidChar
+                | ParseErrorKind::DuplicateKey { .. }
+                | ParseErrorKind::UnknownField(..)
+                | ParseErrorKind::MergeConflict { .. }
+                | ParseErrorKind::DottedAfterMap { .. }
+                | ParseErrorKind::UnsetVariable(_)
+                | ParseErrorKind::Schema(_) => Category::Data,
+                _ if err.token.token.kind == TokenKind::Eof => Category::Eof,
+                _ => Category::Syntax,
+            },
+            #[cfg(feature = "std")]
+            ErrorKind::Io(_) => Category::Io,
+        }
+    }
+
+    /// The span of the token the error occurred at, if it came from a document.
+    pub fn span(&self) -> Option<SpanToken> {
+        match &self.inner.kind {
+            ErrorKind::Parse(err) => Some(err.token),
+            _ => None,
+        }
+    }
+
+    /// The 0 indexed line the error occurred at, if it came from a document. The
+    /// message shows it 1 indexed, like editors do.
+    pub fn line(&self) -> Option<u32> {
+        self.span().map(|span| span.line)
+    }
+
+    /// The 0 indexed column the error occurred at, if it came from a document.
+    /// What a column counts is set by the parser's [`ColumnEncoding`](parse::ColumnEncoding).
+    pub fn column(&self) -> Option<u32> {
+        self.span().map(|span| span.col)
+    }
+
+    /// What was being deserialized when the error occurred, outermost first.
+    /// This is empty for errors that did not come from a document.
+    pub fn context(&self) -> &[ContextFrame<'static>] {
+        match &self.inner.kind {
+            ErrorKind::Message(_, context) => context,
+            ErrorKind::Parse(err) => &err.context,
+            #[cfg(feature = "std")]
+            ErrorKind::Io(_) => &[],
+        }
+    }
+
+    /// The path of the value the error occurred in, like `servers[2].tls.port`,
+    /// or `None` if it was not inside any key.
+    pub fn path(&self) -> Option<crate::Path> {
+        let context = self.context();
+        (!context.is_empty()).then(|| crate::Path::from(context))
+    }
+
+    /// Renders the error for showing to a user, with the line of `src` it occurred
+    /// at and the token underlined:
+    ///
+    /// ```text
+    /// error: Invalid integer
+    ///  --> 2:5
+    ///   |
+    /// 2 | b = 300
+    ///   |     ^^^
+    ///     while parsing the value for key `b`
+    /// ```
+    ///
+    /// `src` has to be the document the error came from. Lines and columns start at 1
+    /// like in the [`Display`] impl, and columns are taken to count chars.
+    pub fn render(&self, src: &str) -> String {
+        let mut out = String::new();
+        match &self.inner.kind {
+            ErrorKind::Message(msg, _) => {
+                let _ = write!(out, "error: {msg}");
+            }
+            ErrorKind::Parse(err) => {
+                let _ = write!(out, "error: {}", err.kind.display(err.token));
+                write_snippet(&mut out, src, err.token, self.shown_file());
+            }
+            #[cfg(feature = "std")]
+            ErrorKind::Io(err) => {
+                let _ = write!(out, "error: {err}");
+            }
+        }
+        let _ = write!(out, "{}", Context(self.context()));
+        if let ErrorKind::Parse(err) = &self.inner.kind {
+            if let Some(note) = err.kind.note() {
+                write_note(&mut out, src, note, self.shown_file());
+            }
+        }
+        out
+    }
+
+    /// Attaches `context` to the error, unless it already has one from further inside.
+    pub(crate) fn add_context(&mut self, context: &[ContextFrame<'_>]) {
+        match &mut self.inner.kind {
+            ErrorKind::Message(_, frames)
+            | ErrorKind::Parse(ParseError {
+                context: frames, ..
+            }) if frames.is_empty() => {
+                *frames = context.iter().map(ContextFrame::to_static).collect();
+            }
+            _ => (),
         }
     }
 }
@@ -40,6 +234,7 @@ impl From<ParseError> for Error {
     }
 }
 
+#[cfg(feature = "std")]
 impl From<io::Error> for Error {
     fn from(value: io::Error) -> Self {
         Self::io(value)
@@ -51,9 +246,7 @@ impl ser::Error for Error {
     where
         T: Display,
     {
-        Error {
-            kind: ErrorKind::Message(msg.to_string()).into(),
-        }
+        Error::custom(msg)
     }
 }
 
@@ -62,33 +255,298 @@ impl de::Error for Error {
     where
         T: Display,
     {
-        Error {
-            kind: ErrorKind::Message(msg.to_string()).into(),
+        Error::custom(msg)
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        let mut msg = format!("unknown variant `{variant}`, expected {}", OneOf(expected));
+        if let Some(closest) = closest(variant, expected) {
+            msg.push_str(&format!(", did you mean `{closest}`?"));
+        }
+        Error::custom(msg)
+    }
+}
+
+/// Writes the position of `span` and the line of `src` it is on, with the span underlined.
+fn write_snippet(out: &mut String, src: &str, span: SpanToken, file: Option<impl Display>) {
+    let Some(range) = LineIndex::new(src).line_range(span.line) else {
+        return;
+    };
+    let text = &src[range];
+    let text = text.strip_suffix('\r').unwrap_or(text);
+    let line = (span.line + 1).to_string();
+    let gutter = " ".repeat(line.len());
+
+    // the underline runs to the end of the token, or of the line if the token does not
+    let start = span.col as usize;
+    let end = if span.end_line == span.line {
+        span.end_col as usize
+    } else {
+        text.chars().count()
+    };
+    // tabs are kept so the underline lines up however wide they are shown
+    let mut underline: String = text
+        .chars()
+        .take(start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    underline.extend(core::iter::repeat_n('^', end.saturating_sub(start).max(1)));
+
+    let _ = write!(out, "\n{gutter}--> ");
+    if let Some(file) = file {
+        let _ = write!(out, "{file}:");
+    }
+    let _ = write!(
+        out,
+        "{}:{}\n{gutter} |\n{}\n{gutter} | {underline}",
+        span.line + 1,
+        span.col + 1,
+        format!("{line} | {text}").trim_end(),
+    );
+}
+
+/// Renders `message` about `span` in `src` with a snippet of the source, the way
+/// [`Error::render`] shows errors, for other problems in a document like lints.
+/// `level` says what the message is, like `warning`, and `note` points at another
+/// token the message is about.
+pub fn render_message(
+    src: &str,
+    level: &str,
+    message: impl Display,
+    span: SpanToken,
+    note: Option<(&str, SpanToken)>,
+    file: Option<impl Display>,
+) -> String {
+    let mut out = format!("{level}: {message}");
+    write_snippet(&mut out, src, span, file.as_ref());
+    if let Some(note) = note {
+        write_note(&mut out, src, note, file);
+    }
+    out
+}
+
+/// Writes a note pointing at another token than the one a message is about.
+fn write_note(out: &mut String, src: &str, note: (&str, SpanToken), file: Option<impl Display>) {
+    let _ = write!(out, "\nnote: {} here", note.0);
+    write_snippet(out, src, note.1, file);
+}
+
+/// Writes the lines saying what was being deserialized.
+struct Context<'a>(&'a [ContextFrame<'static>]);
+
+impl Display for Context<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        parse::write_context(f, self.0)
+    }
+}
+
+/// Lists names the way serde does in its own errors.
+struct OneOf(&'static [&'static str]);
+
+impl Display for OneOf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            [] => write!(f, "nothing"),
+            [a] => write!(f, "`{a}`"),
+            [a, b] => write!(f, "`{a}` or `{b}`"),
+            names => {
+                write!(f, "one of ")?;
+                for (i, name) in names.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "`{name}`")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The candidate closest to `name` by edit distance, if any is close enough that
+/// `name` is likely a typo of it.
+pub(crate) fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    // a third of the name can be wrong, so short names need to be nearly right
+    let max = (name.chars().count() / 3).max(1);
+    let name = name.to_lowercase();
+    candidates
+        .iter()
+        .map(|&candidate| (edit_distance(&name, &candidate.to_lowercase()), candidate))
+        .filter(|&(distance, _)| distance <= max)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The edit distance between `a` and `b` in chars, where swapping two neighbours
+/// counts as one edit like in `gzpi`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // the rows of the distance table for the two previous chars of `a` and this one
+    let mut before: Vec<usize> = Vec::new();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            row[j] = (prev[j - 1] + cost).min(prev[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
         }
+        before = mem::replace(&mut prev, row);
     }
+    prev[b.len()]
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.kind)
+        match (&self.inner.kind, self.shown_file()) {
+            (ErrorKind::Parse(err), file) => err.write_in(f, file),
+            (ErrorKind::Message(msg, context), Some(file)) => {
+                write!(f, "{msg} in {file}")?;
+                parse::write_context(f, context)
+            }
+            #[cfg(feature = "std")]
+            (ErrorKind::Io(err), Some(file)) => write!(f, "{file}: {err}"),
+            (kind, None) => write!(f, "{kind}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match &self.inner.kind {
+            ErrorKind::Message(..) => None,
+            ErrorKind::Parse(err) => Some(err),
+            #[cfg(feature = "std")]
+            ErrorKind::Io(err) => Some(&**err),
+        }
     }
 }
 
-impl std::error::Error for Error {}
+/// A broad class of [`Error`], from [`Error::classify`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Category {
+    /// Reading or writing failed.
+    Io,
+    /// The document is not valid syntax.
+    Syntax,
+    /// The document ended in the middle of something, so more input could make it valid.
+    Eof,
+    /// The document is valid syntax, but does not fit the type it is read as.
+    Data,
+    /// The document went over a limit, like the maximum depth.
+    Limit,
+}
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ErrorKind {
-    Message(String),
+    /// A custom error, usually from a `Deserialize` impl, along with what was
+    /// being deserialized when it occurred, outermost first.
+    Message(String, Vec<ContextFrame<'static>>),
     Parse(ParseError),
-    Io(io::Error),
+    /// An error from reading or writing, shared so that errors can be cloned.
+    #[cfg(feature = "std")]
+    Io(Arc<io::Error>),
 }
 
 impl Display for ErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Message(v) => write!(f, "{v}"),
+            Self::Message(v, context) => {
+                write!(f, "{v}")?;
+                parse::write_context(f, context)
+            }
             Self::Parse(v) => write!(f, "{v}"),
+            #[cfg(feature = "std")]
             Self::Io(v) => write!(f, "{v}"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+
+    use super::*;
+    use crate::de::from_str;
+
+    #[test]
+    fn error_classify() {
+        use crate::error::Category;
+
+        let err = from_str::<BTreeMap<String, u8>>("a = 1\nb = 300").unwrap_err();
+        assert_eq!(err.classify(), Category::Data);
+        assert_eq!((err.line(), err.column()), (Some(1), Some(4)));
+
+        let err = from_str::<BTreeMap<String, Vec<u8>>>("a = [1").unwrap_err();
+        assert_eq!(err.classify(), Category::Eof);
+        let err = from_str::<BTreeMap<String, u8>>("a = ]").unwrap_err();
+        assert_eq!(err.classify(), Category::Syntax);
+
+        let err = from_str::<BTreeMap<String, u8>>("a = \"x\"").unwrap_err();
+        assert_eq!(err.classify(), Category::Data);
+        let err = Error::custom("oops");
+        assert_eq!((err.classify(), err.span()), (Category::Data, None));
+    }
+
+    #[test]
+    fn render_error() {
+        let src = "a = {}\nb = { c = 300 }";
+        let err = from_str::<BTreeMap<String, BTreeMap<String, u8>>>(src).unwrap_err();
+        assert_eq!(
+            err.render(src),
+            "error: Invalid integer\n \
+             --> 2:11\n  \
+             |\n\
+             2 | b = { c = 300 }\n  \
+             |           ^^^\n    \
+             while parsing the value for key `b.c`"
+        );
+        assert_eq!(Error::custom("oops").render(src), "error: oops");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn error_file() {
+        use serde::de::Deserialize;
+
+        use crate::de::{from_str_with_options, Deserializer, Options};
+
+        let options = Options {
+            file: Some("config/app.ycf".into()),
+            ..Options::default()
+        };
+        let err =
+            from_str_with_options::<BTreeMap<String, u8>>("a = 1\nb = -1", options).unwrap_err();
+        assert_eq!(err.file(), Some(std::path::Path::new("config/app.ycf")));
+        assert!(err.to_string().contains(" at config/app.ycf:2:5"));
+
+        let mut de = Deserializer::from_str("[1 true]").with_path("list.ycf");
+        let err = Vec::<u8>::deserialize(&mut de).unwrap_err();
+        assert!(err.to_string().contains(" at list.ycf:1:4"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn clone_error() {
+        let err = from_str::<BTreeMap<String, u8>>("a = true").unwrap_err();
+        assert_eq!(err.clone().to_string(), err.to_string());
+
+        let err = Error::io(std::io::Error::other("disk on fire")).with_file("a.ycf");
+        assert_eq!(err.clone().to_string(), "a.ycf: disk on fire");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn error_source() {
+        use std::error::Error as _;
+
+        let err = from_str::<BTreeMap<String, u8>>("a = true").unwrap_err();
+        assert!(err.source().unwrap().is::<ParseError>());
+        let err = Error::io(std::io::Error::other("disk on fire"));
+        assert!(err.source().unwrap().is::<std::io::Error>());
+        assert!(Error::custom("oops").source().is_none());
+    }
+}
@@ -0,0 +1,64 @@
+//! A [`figment`] provider for ycf, so ycf files can be merged with other sources.
+
+use figment::providers::Format;
+use serde::de::DeserializeOwned;
+
+use crate::error::Error;
+
+/// The ycf [`Format`]. `Ycf::file(path)` and `Ycf::string(src)` make the providers,
+/// like the formats that come with figment:
+///
+/// ```text
+/// Figment::new()
+///     .merge(Ycf::file("app.ycf"))
+///     .merge(Env::prefixed("APP_"))
+///     .extract::<Config>()?
+/// ```
+///
+/// Durations are given as `{ secs, nanos }` maps, so they extract into a `Duration`.
+pub struct Ycf;
+
+impl Format for Ycf {
+    type Error = Error;
+
+    const NAME: &'static str = "YCF";
+
+    fn from_str<T: DeserializeOwned>(string: &str) -> Result<T, Error> {
+        crate::from_str(string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use figment::Figment;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[test]
+    fn figment_provider() {
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            name: String,
+            timeout: Duration,
+            ports: Vec<u16>,
+        }
+
+        let config: Config = Figment::new()
+            .merge(Ycf::string("name = 'a' timeout = 3s ports = [1 2]"))
+            .merge(Ycf::string("name = 'b'"))
+            .extract()
+            .unwrap();
+        assert_eq!(config.name, "b");
+        assert_eq!(config.timeout, Duration::from_secs(3));
+        assert_eq!(config.ports, [1, 2]);
+
+        let err = Figment::new()
+            .merge(Ycf::string("name = "))
+            .extract::<Config>()
+            .unwrap_err();
+        assert!(err.to_string().contains("Expected a value"), "{err}");
+    }
+}
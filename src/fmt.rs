@@ -1,3 +1,1106 @@
+//! A formatter that rewrites documents in one layout, like the serializer writes
+//! them, while keeping their comments, the order of their entries and the way every
+//! key and literal is spelled, unless numbers are normalized.
+//!
+//! By default every entry and list element goes on a line of its own, indented by
+//! how deeply it is nested, with a space on each side of `=`. [`Config`] can put
+//! small maps and lists on one line instead, and line up the `=` of entries. Blank
+//! lines between entries are kept, but runs of them become one.
+
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::ops::{ControlFlow, Range};
+
+use crate::cursor::{Base, TokenKind};
+use crate::error::Result;
+use crate::parse::{Parser, SpanToken, TriviaToken, TriviaTokens};
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub indent: Indent,
+    // the widest a line may get by writing a map or list on it, rather than over
+    // several lines. tabs count as 4 columns
+    pub max_width: usize,
+    // the most entries a map can have to be written on one line, like `{ a = 1 b = 2 }`,
+    // when it has no comments and fits. 0 for never
+    pub inline_map_entries: usize,
+    // whether `=` has a space on each side
+    pub space_around_equals: bool,
+    pub list_layout: ListLayout,
+    // whether `#` starts a line comment, like in `de::Options`
+    pub hash_comments: bool,
+    // the marker that comments are rewritten to start with, or `None` to keep the one
+    // each is written with
+    pub comment_style: Option<CommentStyle>,
+    // whether numbers are rewritten in one spelling without changing their values:
+    // lowercase hex digits, no leading zeros, digits grouped in threes (or fours for
+    // hex and binary) if they were grouped with `_` at all, no trailing zeros after
+    // the point, and a lowercase exponent without a `+` or leading zeros
+    pub normalize_numbers: bool,
+    // whether the `=` of entries on consecutive lines of a map are lined up, where a
+    // blank line starts a new run of them
+    pub align_equals: bool,
+    // the widest a key can be to be lined up with the others. wider keys keep one
+    // space before their `=`, so a single long key does not push the others far out
+    pub max_align_width: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            indent: Indent::Spaces(4),
+            max_width: 100,
+            inline_map_entries: 0,
+            space_around_equals: true,
+            list_layout: ListLayout::default(),
+            hash_comments: false,
+            comment_style: None,
+            normalize_numbers: false,
+            align_equals: false,
+            max_align_width: 30,
+        }
+    }
+}
+
+/// What each level of nesting is indented with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Indent {
+    Spaces(usize),
+    Tabs,
+}
+
+/// How lists are laid out. Lists with comments are always written with one element
+/// on each line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ListLayout {
+    /// Every element on a line of its own.
+    #[default]
+    Expanded,
+    /// On one line like `[1 2 3]` if it fits, and expanded otherwise.
+    Inline,
+    /// On one line if it fits, and otherwise with as many elements on each line as
+    /// fit, if they all fit on one line by themselves.
+    Fill,
+}
+
+/// The marker that line comments start with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// `// comment`.
+    Slashes,
+    /// `# comment`, which documents are only read with if `hash_comments` is set.
+    Hash,
+}
+
+/// How many columns a tab counts as.
+const TAB_WIDTH: usize = 4;
+
+/// Where the formatter writes its output.
 pub trait FormattingSink {
-    
-}
\ No newline at end of file
+    /// Writes `s`, or breaks to stop formatting early.
+    fn write_str(&mut self, s: &str) -> ControlFlow<()>;
+}
+
+impl FormattingSink for String {
+    fn write_str(&mut self, s: &str) -> ControlFlow<()> {
+        self.push_str(s);
+        ControlFlow::Continue(())
+    }
+}
+
+/// Formats the document `src`. Documents with syntax errors are not formatted, and
+/// give the first of their errors instead.
+pub fn format(src: &str, config: &Config) -> Result<String> {
+    let mut out = String::with_capacity(src.len());
+    format_into(src, config, &mut out)?;
+    Ok(out)
+}
+
+/// Like [`format`], but writes the document to `sink`, stopping if it breaks.
+pub fn format_into<S>(src: &str, config: &Config, sink: &mut S) -> Result<()>
+where
+    S: FormattingSink + ?Sized,
+{
+    let document = read(src, config)?;
+    let mut printer = Printer {
+        config,
+        sink,
+        col: 0,
+    };
+    let _ = printer.block(&document, 0);
+    Ok(())
+}
+
+/// Whether `src` is already formatted with `config`. This compares the output with
+/// `src` as it is written and stops at the first difference, without allocating
+/// the output. Documents with syntax errors are not formatted.
+pub fn is_formatted(src: &str, config: &Config) -> bool {
+    let mut sink = Compare { rest: Some(src) };
+    format_into(src, config, &mut sink).is_ok() && sink.rest == Some("")
+}
+
+/// A sink that checks that the output is the same as a string.
+struct Compare<'a> {
+    // the part of the string that has not been written yet, or `None` once the
+    // output differs
+    rest: Option<&'a str>,
+}
+
+impl FormattingSink for Compare<'_> {
+    fn write_str(&mut self, s: &str) -> ControlFlow<()> {
+        match self.rest.and_then(|rest| rest.strip_prefix(s)) {
+            Some(rest) => {
+                self.rest = Some(rest);
+                ControlFlow::Continue(())
+            }
+            None => {
+                self.rest = None;
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+/// A change to a document, replacing the bytes in `range` with `text`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+/// Formats only the part of `src` that is in the byte `range`, for formatting a
+/// selection in an editor. This formats the smallest run of entries or list elements
+/// in one map or list that covers the range, along with the comments on the lines
+/// before them, and leaves the rest of the document as it is. An empty range, like a
+/// cursor, formats the entry it is in or next to.
+///
+/// This gives `None` if there are no entries in the range, like when it only has
+/// whitespace or comments at the end of a map. Line ranges can be turned into byte
+/// ranges with [`LineIndex::line_range`](crate::LineIndex::line_range).
+pub fn format_range(src: &str, range: Range<usize>, config: &Config) -> Result<Option<Edit>> {
+    let document = read(src, config)?;
+    let Some((items, depth)) = enclosing(src, &document, &range, 0) else {
+        return Ok(None);
+    };
+
+    let mut text = String::new();
+    let mut printer = Printer {
+        config,
+        sink: &mut text,
+        col: 0,
+    };
+    let _ = printer.lines(items, &[], depth);
+    // the edit starts where the first item does and ends with its last line, rather
+    // than covering the indentation before and the newline after
+    let indent = match config.indent {
+        Indent::Spaces(width) => depth * width,
+        Indent::Tabs => depth,
+    };
+    text.truncate(text.len() - 1);
+    text.drain(..indent);
+    Ok(Some(Edit {
+        range: items[0].span.start..items[items.len() - 1].span.end,
+        text,
+    }))
+}
+
+/// The smallest run of items in `block` or the blocks nested in it that covers
+/// `range` and has the lines it is on to itself, and how deeply it is nested.
+fn enclosing<'b, 'a>(
+    src: &str,
+    block: &'b Block<'a>,
+    range: &Range<usize>,
+    depth: usize,
+) -> Option<(&'b [Item<'a>], usize)> {
+    let items = &block.items;
+    let touches = |item: &Item<'_>| {
+        if range.is_empty() {
+            item.span.start <= range.start && range.start <= item.span.end
+        } else {
+            item.span.start < range.end && range.start < item.span.end
+        }
+    };
+    let mut first = items.iter().position(touches)?;
+    let mut last = first
+        + items[first..]
+            .iter()
+            .take_while(|item| touches(item))
+            .count()
+        - 1;
+    // items on the same line as the run are formatted with it, as they are laid out
+    // together
+    let same_line = |from: usize, to: usize| !src[from..to].contains('\n');
+    while first > 0 && same_line(items[first - 1].span.end, items[first].span.start) {
+        first -= 1;
+    }
+    while last + 1 < items.len() && same_line(items[last].span.end, items[last + 1].span.start) {
+        last += 1;
+    }
+    // items on the same line as the braces around them can only be formatted along
+    // with the map or list
+    if depth > 0
+        && (same_line(block.inner.start, items[first].span.start)
+            || same_line(items[last].span.end, block.inner.end))
+    {
+        return None;
+    }
+
+    let run = &items[first..=last];
+    if let [item] = run {
+        if let Node::Map(block) | Node::List(block) = &item.value {
+            if block.inner.start <= range.start && range.end <= block.inner.end {
+                if let Some(nested) = enclosing(src, block, range, depth + 1) {
+                    return Some(nested);
+                }
+            }
+        }
+    }
+    Some((run, depth))
+}
+
+/// Checks the syntax of `src` and reads it into the items of the document.
+fn read<'a>(src: &'a str, config: &Config) -> Result<Block<'a>> {
+    // the syntax is checked first, so reading can assume it is valid and can recurse,
+    // as the check limits the depth
+    let new_parser = || {
+        let mut parser = Parser::new(src);
+        parser.cursor.set_hash_comments(config.hash_comments);
+        parser
+    };
+    let top_level = new_parser().starts_top_level();
+    let mut parser = new_parser();
+    let errors = if top_level {
+        parser.check_top_level()
+    } else {
+        parser.check_value()
+    };
+    if let Some(err) = errors.into_iter().next() {
+        return Err(err.into());
+    }
+    if !top_level {
+        parser.end_of_input()?;
+    }
+
+    let mut reader = Reader {
+        tokens: new_parser().into_tokens().with_trivia(),
+        line_start: true,
+        end: 0,
+        normalize_numbers: config.normalize_numbers,
+    };
+    Ok(reader.block(TokenKind::Eof, top_level, 0).0)
+}
+
+/// A comment on a line of its own.
+struct Comment<'a> {
+    text: &'a str,
+    // whether there is a blank line before it
+    blank_before: bool,
+}
+
+/// An entry of a map or an element of a list.
+struct Item<'a> {
+    // the comments on the lines before the item
+    comments: Vec<Comment<'a>>,
+    // whether there is a blank line before the item, after its comments
+    blank_before: bool,
+    // the segments of the key as they are written, or `None` for a list element
+    key: Option<Vec<&'a str>>,
+    value: Node<'a>,
+    // the comment at the end of the item's last line
+    trailing: Option<&'a str>,
+    // the bytes from the first of its comments to the end of its last line
+    span: Range<usize>,
+}
+
+enum Node<'a> {
+    // any literal or identifier, as it is written unless it is a normalized number
+    Scalar(Cow<'a, str>),
+    Map(Block<'a>),
+    List(Block<'a>),
+}
+
+/// The inside of a map or list, or the whole document.
+struct Block<'a> {
+    // the comment after the opening brace
+    open: Option<&'a str>,
+    items: Vec<Item<'a>>,
+    // the comments after the last item
+    end: Vec<Comment<'a>>,
+    // the bytes between the braces
+    inner: Range<usize>,
+}
+
+struct Reader<'a> {
+    tokens: TriviaTokens<'a>,
+    // whether the last token read ended its line with a comment
+    line_start: bool,
+    // the end of the last token or comment read
+    end: usize,
+    normalize_numbers: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn next(&mut self) -> TriviaToken {
+        let token = self
+            .tokens
+            .next()
+            .expect("the document should end with the end of file token");
+        self.end = token.token.token.end;
+        token
+    }
+
+    fn text(&self, token: SpanToken) -> &'a str {
+        self.tokens.src(token.token)
+    }
+
+    /// Reads the items of a block up to `closer`, the token that ends it, returning
+    /// the block and the comment after the closer. The block starts at `start`.
+    fn block(
+        &mut self,
+        closer: TokenKind,
+        keys: bool,
+        start: usize,
+    ) -> (Block<'a>, Option<&'a str>) {
+        let mut block = Block {
+            open: None,
+            items: Vec::new(),
+            end: Vec::new(),
+            inner: start..start,
+        };
+        loop {
+            let token = self.next();
+            if token.token.token.kind == closer {
+                block.inner.end = token.token.token.start;
+                self.comments(&token.leading, &mut block.end);
+                let trailing = self.trailing(&token.trailing);
+                return (block, trailing);
+            }
+            block.items.push(self.item(token, keys));
+        }
+    }
+
+    /// Reads an item starting at `first`. Comments in the middle of the item, like one
+    /// after the `=`, are moved to the lines before it.
+    fn item(&mut self, first: TriviaToken, keys: bool) -> Item<'a> {
+        let mut comments = Vec::new();
+        let blank_before = self.comments(&first.leading, &mut comments);
+        let start = first
+            .leading
+            .iter()
+            .find(|trivia| trivia.token.kind == TokenKind::Comment)
+            .unwrap_or(&first.token)
+            .token
+            .start;
+
+        let (key, value) = if keys {
+            let mut segments = Vec::from([self.text(first.token)]);
+            self.hoist(&first.trailing, &mut comments);
+            loop {
+                // a `.` before another segment, or the `=`
+                let next = self.inner(&mut comments);
+                self.hoist(&next.trailing, &mut comments);
+                if next.token.token.kind != TokenKind::Dot {
+                    break;
+                }
+                let segment = self.inner(&mut comments);
+                segments.push(self.text(segment.token));
+                self.hoist(&segment.trailing, &mut comments);
+            }
+            (Some(segments), self.inner(&mut comments))
+        } else {
+            (None, first)
+        };
+
+        let (value, trailing) = self.value(value);
+        Item {
+            comments,
+            blank_before,
+            key,
+            value,
+            trailing,
+            span: start..self.end,
+        }
+    }
+
+    fn value(&mut self, token: TriviaToken) -> (Node<'a>, Option<&'a str>) {
+        match token.token.token.kind {
+            TokenKind::StartCurly => {
+                let open = self.trailing(&token.trailing);
+                let start = token.token.token.end;
+                let (mut block, trailing) = self.block(TokenKind::EndCurly, true, start);
+                block.open = open;
+                (Node::Map(block), trailing)
+            }
+            TokenKind::StartSquare => {
+                let open = self.trailing(&token.trailing);
+                let start = token.token.token.end;
+                let (mut block, trailing) = self.block(TokenKind::EndSquare, false, start);
+                block.open = open;
+                (Node::List(block), trailing)
+            }
+            kind => {
+                let text = self.text(token.token);
+                let normalized = match kind {
+                    TokenKind::Integer { sign, base } if self.normalize_numbers => {
+                        normalize_integer(text, sign, base)
+                    }
+                    TokenKind::Float if self.normalize_numbers => normalize_float(text),
+                    _ => None,
+                };
+                let text = normalized.map_or(Cow::Borrowed(text), Cow::Owned);
+                (Node::Scalar(text), self.trailing(&token.trailing))
+            }
+        }
+    }
+
+    /// Reads a token in the middle of an item, adding the comments before it to `out`.
+    fn inner(&mut self, out: &mut Vec<Comment<'a>>) -> TriviaToken {
+        let token = self.next();
+        self.comments(&token.leading, out);
+        token
+    }
+
+    /// Adds the comments in the trivia before a token to `out`, returning whether
+    /// there is a blank line between the last of them and the token.
+    fn comments(&mut self, leading: &[SpanToken], out: &mut Vec<Comment<'a>>) -> bool {
+        let mut breaks = self.line_start as usize;
+        for &trivia in leading {
+            let text = self.text(trivia);
+            if trivia.token.kind == TokenKind::Comment {
+                out.push(Comment {
+                    text: text.trim_end(),
+                    blank_before: breaks >= 2,
+                });
+                // comments end with their newline
+                breaks = 1;
+            } else {
+                breaks += text.matches('\n').count();
+            }
+        }
+        breaks >= 2
+    }
+
+    /// The comment in the trivia after a token, up to the end of its line.
+    fn trailing(&mut self, trailing: &[SpanToken]) -> Option<&'a str> {
+        let comment = trailing
+            .iter()
+            .find(|trivia| trivia.token.kind == TokenKind::Comment);
+        self.line_start = comment.is_some();
+        comment.map(|&comment| {
+            let text = self.text(comment).trim_end();
+            self.end = comment.token.start + text.len();
+            text
+        })
+    }
+
+    /// Adds the comment after a token in the middle of an item to `out`.
+    fn hoist(&mut self, trailing: &[SpanToken], out: &mut Vec<Comment<'a>>) {
+        if let Some(text) = self.trailing(trailing) {
+            out.push(Comment {
+                text,
+                blank_before: false,
+            });
+        }
+    }
+}
+
+impl Block<'_> {
+    fn has_comments(&self) -> bool {
+        self.open.is_some()
+            || !self.end.is_empty()
+            || self
+                .items
+                .iter()
+                .any(|item| !item.comments.is_empty() || item.trailing.is_some())
+    }
+}
+
+struct Printer<'c, S: ?Sized> {
+    config: &'c Config,
+    sink: &'c mut S,
+    // the width of the current line so far
+    col: usize,
+}
+
+impl<S: FormattingSink + ?Sized> Printer<'_, S> {
+    fn write(&mut self, s: &str) -> ControlFlow<()> {
+        match s.rfind('\n') {
+            Some(i) => self.col = width(&s[i + 1..]),
+            None => self.col += width(s),
+        }
+        self.sink.write_str(s)
+    }
+
+    fn indent(&mut self, depth: usize) -> ControlFlow<()> {
+        const SPACES: &str = "                ";
+        const TABS: &str = "\t\t\t\t\t\t\t\t";
+        let (chars, mut n) = match self.config.indent {
+            Indent::Spaces(width) => (SPACES, depth * width),
+            Indent::Tabs => (TABS, depth),
+        };
+        while n > 0 {
+            let chunk = n.min(chars.len());
+            self.write(&chars[..chunk])?;
+            n -= chunk;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn equals(&self) -> &'static str {
+        if self.config.space_around_equals {
+            " = "
+        } else {
+            "="
+        }
+    }
+
+    /// Writes the lines of a block, each indented to `depth`.
+    fn block(&mut self, block: &Block<'_>, depth: usize) -> ControlFlow<()> {
+        self.lines(&block.items, &block.end, depth)
+    }
+
+    /// Writes `items` and then the comments in `end`, each line indented to `depth`.
+    fn lines(&mut self, items: &[Item<'_>], end: &[Comment<'_>], depth: usize) -> ControlFlow<()> {
+        // blank lines go between lines, never at the start of a block
+        let mut first = true;
+        // the width keys are padded to in the current run of aligned entries
+        let mut align = None;
+        for (i, item) in items.iter().enumerate() {
+            self.comments(&item.comments, depth, &mut first)?;
+            if item.blank_before && !first {
+                self.write("\n")?;
+            }
+            first = false;
+
+            self.indent(depth)?;
+            if let Some(key) = &item.key {
+                if item.blank_before || align.is_none() {
+                    align = self.align_width(&items[i..]);
+                }
+                self.key(key)?;
+                let width = key_width(key);
+                if let Some(align) = align.filter(|&align| width <= align) {
+                    for _ in width..align {
+                        self.write(" ")?;
+                    }
+                }
+                self.write(self.equals())?;
+            }
+            self.value(&item.value, depth)?;
+            if let Some(comment) = item.trailing {
+                self.write(" ")?;
+                self.comment(comment)?;
+            }
+            self.write("\n")?;
+        }
+        self.comments(end, depth, &mut first)
+    }
+
+    /// The width to pad the keys of the run of entries at the start of `items` to, if
+    /// they are aligned.
+    fn align_width(&self, items: &[Item<'_>]) -> Option<usize> {
+        if !self.config.align_equals {
+            return None;
+        }
+        items
+            .iter()
+            .enumerate()
+            .take_while(|(i, item)| *i == 0 || !item.blank_before)
+            .filter_map(|(_, item)| item.key.as_deref().map(key_width))
+            .filter(|&width| width <= self.config.max_align_width)
+            .max()
+    }
+
+    fn comments(
+        &mut self,
+        comments: &[Comment<'_>],
+        depth: usize,
+        first: &mut bool,
+    ) -> ControlFlow<()> {
+        for comment in comments {
+            if comment.blank_before && !*first {
+                self.write("\n")?;
+            }
+            *first = false;
+            self.indent(depth)?;
+            self.comment(comment.text)?;
+            self.write("\n")?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Writes a comment, with its marker rewritten if the config has a style.
+    fn comment(&mut self, text: &str) -> ControlFlow<()> {
+        let Some(style) = self.config.comment_style else {
+            return self.write(text);
+        };
+        let body = text
+            .strip_prefix("//")
+            .or_else(|| text.strip_prefix('#'))
+            .unwrap_or(text);
+        self.write(match style {
+            CommentStyle::Slashes => "//",
+            CommentStyle::Hash => "#",
+        })?;
+        self.write(body)
+    }
+
+    fn key(&mut self, key: &[&str]) -> ControlFlow<()> {
+        for (i, segment) in key.iter().enumerate() {
+            if i > 0 {
+                self.write(".")?;
+            }
+            self.write(segment)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn value(&mut self, node: &Node<'_>, depth: usize) -> ControlFlow<()> {
+        let (block, open, close) = match node {
+            Node::Scalar(text) => return self.write(text),
+            Node::Map(block) => (block, "{", "}"),
+            Node::List(block) => (block, "[", "]"),
+        };
+        if self
+            .inline_width(node)
+            .is_some_and(|width| self.col + width <= self.config.max_width)
+        {
+            return self.inline(node);
+        }
+
+        self.write(open)?;
+        if let Some(comment) = block.open {
+            self.write(" ")?;
+            self.comment(comment)?;
+        }
+        self.write("\n")?;
+        if matches!(node, Node::List(_)) && self.config.list_layout == ListLayout::Fill {
+            if let Some(widths) = self.fill_widths(block) {
+                self.fill(block, &widths, depth + 1)?;
+                self.indent(depth)?;
+                return self.write(close);
+            }
+        }
+        self.block(block, depth + 1)?;
+        self.indent(depth)?;
+        self.write(close)
+    }
+
+    /// The width of `node` on one line, or `None` if it goes over several lines
+    /// whatever the width.
+    fn inline_width(&self, node: &Node<'_>) -> Option<usize> {
+        let (block, is_map) = match node {
+            Node::Scalar(text) => return Some(width(text)),
+            Node::Map(block) => (block, true),
+            Node::List(block) => (block, false),
+        };
+        if block.has_comments() {
+            return None;
+        }
+        if block.items.is_empty() {
+            return Some(2);
+        }
+        let allowed = if is_map {
+            block.items.len() <= self.config.inline_map_entries
+        } else {
+            self.config.list_layout != ListLayout::Expanded
+        };
+        if !allowed {
+            return None;
+        }
+
+        // the brackets, and the spaces between the items and inside the braces of maps
+        let mut total = 2 + (block.items.len() - 1) + if is_map { 2 } else { 0 };
+        for item in &block.items {
+            if let Some(key) = &item.key {
+                total += key_width(key) + self.equals().len();
+            }
+            total += self.inline_width(&item.value)?;
+        }
+        Some(total)
+    }
+
+    /// Writes `node` on one line, which [`inline_width`](Self::inline_width) allows.
+    fn inline(&mut self, node: &Node<'_>) -> ControlFlow<()> {
+        let (block, is_map) = match node {
+            Node::Scalar(text) => return self.write(text),
+            Node::Map(block) => (block, true),
+            Node::List(block) => (block, false),
+        };
+        self.write(if is_map { "{" } else { "[" })?;
+        for (i, item) in block.items.iter().enumerate() {
+            if i > 0 || is_map {
+                self.write(" ")?;
+            }
+            if let Some(key) = &item.key {
+                self.key(key)?;
+                self.write(self.equals())?;
+            }
+            self.inline(&item.value)?;
+        }
+        if is_map && !block.items.is_empty() {
+            self.write(" ")?;
+        }
+        self.write(if is_map { "}" } else { "]" })
+    }
+
+    /// The widths of the elements of a list, if it can be filled.
+    fn fill_widths(&self, block: &Block<'_>) -> Option<Vec<usize>> {
+        if block.has_comments() {
+            return None;
+        }
+        block
+            .items
+            .iter()
+            .map(|item| self.inline_width(&item.value))
+            .collect()
+    }
+
+    /// Writes the elements of a list with as many on each line as fit.
+    fn fill(&mut self, block: &Block<'_>, widths: &[usize], depth: usize) -> ControlFlow<()> {
+        self.indent(depth)?;
+        let start = self.col;
+        for (i, (item, &width)) in block.items.iter().zip(widths).enumerate() {
+            if i > 0 {
+                if self.col + 1 + width > self.config.max_width && self.col > start {
+                    self.write("\n")?;
+                    self.indent(depth)?;
+                } else {
+                    self.write(" ")?;
+                }
+            }
+            self.inline(&item.value)?;
+        }
+        self.write("\n")
+    }
+}
+
+/// The spelling of an integer token for [`Config::normalize_numbers`], or `None` if
+/// it is already spelled that way or is not a valid integer.
+fn normalize_integer(text: &str, sign: bool, base: Base) -> Option<String> {
+    let (prefix, digits) = text.split_at(sign as usize + base.digit_offset());
+    let valid = |b: u8| b == b'_' || (b as char).is_digit(base.radix());
+    if !digits.bytes().all(valid) || !digits.bytes().any(|b| b != b'_') {
+        return None;
+    }
+
+    let bare: String = digits
+        .chars()
+        .filter(|&c| c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let bare = match bare.trim_start_matches('0') {
+        "" => "0",
+        bare => bare,
+    };
+    let mut out = String::from(prefix);
+    if digits.contains('_') {
+        let group = match base {
+            Base::Dec | Base::Oct => 3,
+            Base::Hex | Base::Bin => 4,
+        };
+        for (i, c) in bare.chars().enumerate() {
+            if i > 0 && (bare.len() - i) % group == 0 {
+                out.push('_');
+            }
+            out.push(c);
+        }
+    } else {
+        out.push_str(bare);
+    }
+    (out != text).then_some(out)
+}
+
+/// The spelling of a float token for [`Config::normalize_numbers`], or `None` if it
+/// is already spelled that way or is not a valid float.
+fn normalize_float(text: &str) -> Option<String> {
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(i) => (&text[..i], Some(&text[i + 1..])),
+        None => (text, None),
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(whole) || fraction.is_some_and(|f| !f.is_empty() && !is_digits(f)) {
+        return None;
+    }
+
+    let mut out = String::from(sign);
+    out.push_str(match whole.trim_start_matches('0') {
+        "" => "0",
+        whole => whole,
+    });
+    if let Some(fraction) = fraction {
+        out.push('.');
+        out.push_str(match fraction.trim_end_matches('0') {
+            "" => "0",
+            fraction => fraction,
+        });
+    }
+    if let Some(exponent) = exponent {
+        let (sign, digits) = match exponent.as_bytes().first() {
+            Some(b'-') => ("-", &exponent[1..]),
+            Some(b'+') => ("", &exponent[1..]),
+            _ => ("", exponent),
+        };
+        if !is_digits(digits) {
+            return None;
+        }
+        out.push('e');
+        out.push_str(sign);
+        out.push_str(match digits.trim_start_matches('0') {
+            "" => "0",
+            digits => digits,
+        });
+    }
+    (out != text).then_some(out)
+}
+
+/// The number of columns a dotted key takes up.
+fn key_width(key: &[&str]) -> usize {
+    key.iter().map(|segment| width(segment)).sum::<usize>() + key.len() - 1
+}
+
+/// The number of columns `s` takes up.
+fn width(s: &str) -> usize {
+    s.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(src: &str) -> String {
+        format(src, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn layout() {
+        assert_eq!(
+            fmt("a=1 b.c.d={x=[1 2 []] y={}}\"q k\"=  'c'"),
+            "a = 1\n\
+             b.c.d = {\n    x = [\n        1\n        2\n        []\n    ]\n    y = {}\n}\n\
+             \"q k\" = 'c'\n"
+        );
+        assert_eq!(
+            fmt("[1 {a=0x_ff}]"),
+            "[\n    1\n    {\n        a = 0x_ff\n    }\n]\n"
+        );
+        assert_eq!(fmt(""), "");
+        assert_eq!(fmt("\n\n"), "");
+    }
+
+    #[test]
+    fn comments() {
+        let src = "\
+// head
+
+a = 1 // one
+
+
+// about b
+b = { // open
+  // first
+  x = 1
+
+  y = [ 2 // two
+  ] // after
+  // end of b
+}
+c = // moved
+  3
+// the end
+";
+        let expected = "\
+// head
+
+a = 1 // one
+
+// about b
+b = { // open
+    // first
+    x = 1
+
+    y = [
+        2 // two
+    ] // after
+    // end of b
+}
+// moved
+c = 3
+// the end
+";
+        assert_eq!(fmt(src), expected);
+        assert_eq!(fmt(expected), expected);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let err = format("a = [1 2\nb = 3", &Config::default()).unwrap_err();
+        assert!(err.span().is_some());
+        assert!(format("[1] 2", &Config::default()).is_err());
+    }
+
+    #[test]
+    fn check() {
+        let config = Config::default();
+        assert!(is_formatted("a = 1\nb = [\n    2\n]\n", &config));
+        assert!(is_formatted("", &config));
+        assert!(!is_formatted("a = 1\nb = [\n    2\n]", &config));
+        assert!(!is_formatted("a = 1\nb = [\n    2\n]\n\n", &config));
+        assert!(!is_formatted("a=1\n", &config));
+        assert!(!is_formatted("a = [\n", &config));
+    }
+
+    #[test]
+    fn config() {
+        let src = "a = {x=1 y=[1 2 3]} b = [[1 2] [3 4]] c = [1 // one\n2]";
+        let config = Config {
+            indent: Indent::Tabs,
+            inline_map_entries: 2,
+            space_around_equals: false,
+            list_layout: ListLayout::Inline,
+            ..Config::default()
+        };
+        assert_eq!(
+            format(src, &config).unwrap(),
+            "a={ x=1 y=[1 2 3] }\nb=[[1 2] [3 4]]\nc=[\n\t1 // one\n\t2\n]\n"
+        );
+
+        let config = Config {
+            indent: Indent::Spaces(2),
+            max_width: 12,
+            list_layout: ListLayout::Fill,
+            ..Config::default()
+        };
+        assert_eq!(
+            format("a = [1 22 333 4444 55555] b = [1 2]", &config).unwrap(),
+            "a = [\n  1 22 333\n  4444 55555\n]\nb = [1 2]\n"
+        );
+        // an element wider than a line goes on a line of its own
+        assert_eq!(
+            format("a = [1 \"very long string\" 2]", &config).unwrap(),
+            "a = [\n  1\n  \"very long string\"\n  2\n]\n"
+        );
+
+        let config = Config {
+            hash_comments: true,
+            ..Config::default()
+        };
+        assert_eq!(format("# x\na=1", &config).unwrap(), "# x\na = 1\n");
+    }
+
+    #[test]
+    fn comment_style() {
+        let src = "# head\na = { // open\n  b = 1 # one\n}\n// end\n";
+        let config = Config {
+            hash_comments: true,
+            comment_style: Some(CommentStyle::Slashes),
+            ..Config::default()
+        };
+        let slashes = "// head\na = { // open\n    b = 1 // one\n}\n// end\n";
+        assert_eq!(format(src, &config).unwrap(), slashes);
+        assert!(is_formatted(slashes, &config));
+        assert!(!is_formatted(src, &config));
+
+        let config = Config {
+            comment_style: Some(CommentStyle::Hash),
+            ..Config::default()
+        };
+        let hash = "# head\na = { # open\n    b = 1 # one\n}\n# end\n";
+        assert_eq!(format(slashes, &config).unwrap(), hash);
+        // comments that are kept as written
+        assert_eq!(format(slashes, &Config::default()).unwrap(), slashes);
+    }
+
+    #[test]
+    fn align() {
+        let config = Config {
+            align_equals: true,
+            max_align_width: 10,
+            ..Config::default()
+        };
+        let src = "a = 1\nbb.c = 2\n// about d\nd = { x = 1 yyy = 2 }\n\n\
+                   long_key_name = 3\nee = 4\nvery_very_long_key = 5\n";
+        assert_eq!(
+            format(src, &config).unwrap(),
+            "a    = 1\nbb.c = 2\n// about d\nd    = {\n    x   = 1\n    yyy = 2\n}\n\n\
+             long_key_name = 3\nee = 4\nvery_very_long_key = 5\n"
+        );
+    }
+
+    #[test]
+    fn range() {
+        let config = Config::default();
+        let edit = |src: &str, range: Range<usize>| {
+            let edit = format_range(src, range, &config).unwrap()?;
+            let mut out = String::from(src);
+            out.replace_range(edit.range, &edit.text);
+            Some(out)
+        };
+
+        let src = "a=1\nb = {\n  x=[1 2]  // x\n  y =2\n}\nc=3 d  =4\n";
+        // the elements of `x` are on the line of its brackets, so all of `x` is formatted
+        let at = src.find('2').unwrap();
+        assert_eq!(
+            edit(src, at..at + 1).unwrap(),
+            "a=1\nb = {\n  x = [\n        1\n        2\n    ] // x\n  y =2\n}\nc=3 d  =4\n"
+        );
+        // both entries of `b`
+        let start = src.find("x=").unwrap();
+        let end = src.find("=2").unwrap();
+        assert_eq!(
+            edit(src, start..end).unwrap(),
+            "a=1\nb = {\n  x = [\n        1\n        2\n    ] // x\n    y = 2\n}\nc=3 d  =4\n"
+        );
+        // a cursor at the end of `c`, which shares its line with `d`
+        let at = src.find("3").unwrap() + 1;
+        assert_eq!(
+            edit(src, at..at).unwrap(),
+            "a=1\nb = {\n  x=[1 2]  // x\n  y =2\n}\nc = 3\nd = 4\n"
+        );
+        assert_eq!(
+            edit(src, 0..2).unwrap(),
+            "a = 1\nb = {\n  x=[1 2]  // x\n  y =2\n}\nc=3 d  =4\n"
+        );
+        assert_eq!(
+            edit(src, 0..src.len()).unwrap(),
+            format(src, &config).unwrap()
+        );
+
+        let src = "// about a\na=1\n\n  \n// end\n";
+        assert_eq!(
+            edit(src, 0..1).unwrap(),
+            "// about a\na = 1\n\n  \n// end\n"
+        );
+        assert_eq!(edit(src, 14..16), None);
+    }
+
+    #[test]
+    fn numbers() {
+        let config = Config {
+            normalize_numbers: true,
+            list_layout: ListLayout::Inline,
+            ..Config::default()
+        };
+        let src = "a = [007 -0 1_0000_00 0xDead_BEEF 0x_0F -0b1_0 0o17 1s]\n\
+                   b = [1.50 1. -00.0 1E+05 2.5e-007 1e0 6.02e23]\n";
+        assert_eq!(
+            format(src, &config).unwrap(),
+            "a = [7 -0 1_000_000 0xdead_beef 0xf -0b10 0o17 1s]\n\
+             b = [1.5 1.0 -0.0 1e5 2.5e-7 1e0 6.02e23]\n"
+        );
+        let config = Config {
+            normalize_numbers: false,
+            ..config
+        };
+        assert_eq!(format(src, &config).unwrap(), src);
+    }
+}
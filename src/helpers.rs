@@ -0,0 +1,176 @@
+//! Adapters for fields with `#[serde(with = "...")]`, for types that are written as
+//! strings in config files.
+//!
+//! [`from_str`] reads anything with a [`FromStr`] impl from a string and writes it
+//! with its [`Display`] impl, like `uuid::Uuid`, `url::Url` and `std::net::SocketAddr`,
+//! without those crates needing their `serde` features. [`home_path`] reads paths
+//! with a leading `~` for the home directory.
+
+use alloc::format;
+use core::{fmt::Display, marker::PhantomData, str::FromStr};
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::ser::Serializer;
+
+/// The name of `T` without its module path, for error messages.
+fn short_name<T>() -> &'static str {
+    let name = core::any::type_name::<T>();
+    // the path ends at the first generic argument, if there are any
+    let path = name.split('<').next().unwrap_or(name);
+    &name[path.rfind("::").map_or(0, |i| i + 2)..]
+}
+
+pub mod from_str {
+    use super::*;
+
+    pub fn serialize<S, T>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Display,
+    {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: Display,
+    {
+        deserializer.deserialize_str(FromStrVisitor(PhantomData))
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use super::*;
+
+        pub fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            T: Display,
+        {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+        where
+            D: Deserializer<'de>,
+            T: FromStr,
+            T::Err: Display,
+        {
+            struct Parsed<T>(T);
+
+            impl<'de, T> Deserialize<'de> for Parsed<T>
+            where
+                T: FromStr,
+                T::Err: Display,
+            {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    super::deserialize(deserializer).map(Parsed)
+                }
+            }
+
+            Ok(Option::<Parsed<T>>::deserialize(deserializer)?.map(|v| v.0))
+        }
+    }
+}
+
+struct FromStrVisitor<T>(PhantomData<T>);
+
+impl<T> Visitor<'_> for FromStrVisitor<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "a string holding a `{}`", short_name::<T>())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        v.parse()
+            .map_err(|err| E::custom(format!("invalid `{}` `{v}`: {err}", short_name::<T>())))
+    }
+}
+
+/// Paths where a leading `~` is the home directory, like `~/.cache/app`. They are
+/// written back as they are, without turning the home directory into `~` again.
+#[cfg(feature = "std")]
+pub mod home_path {
+    use std::path::{Path, PathBuf};
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(path: &Path, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(path.to_str().ok_or_else(|| {
+            serde::ser::Error::custom(format!("path `{}` is not valid UTF-8", path.display()))
+        })?)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let path = PathBuf::deserialize(deserializer)?;
+        expand(path).map_err(de::Error::custom)
+    }
+
+    fn expand(path: PathBuf) -> Result<PathBuf, &'static str> {
+        let Ok(rest) = path.strip_prefix("~") else {
+            // `~user` and paths without a `~` are left alone
+            return Ok(path);
+        };
+        let home = std::env::home_dir().ok_or("cannot expand `~` without a home directory")?;
+        Ok(home.join(rest))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::de::from_str;
+
+    #[test]
+    fn helpers() {
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        struct Server {
+            #[serde(with = "super::from_str")]
+            listen: SocketAddr,
+            #[serde(with = "super::from_str::option", default)]
+            proxy: Option<IpAddr>,
+            #[serde(with = "super::home_path")]
+            cache: PathBuf,
+            #[serde(with = "super::home_path")]
+            logs: PathBuf,
+        }
+
+        let src =
+            "listen = \"127.0.0.1:8080\" proxy = \"::1\" cache = \"~/cache\" logs = \"/var/log\"";
+        let server: Server = from_str(src).unwrap();
+        assert_eq!(server.listen, SocketAddr::from(([127, 0, 0, 1], 8080)));
+        assert_eq!(server.proxy, Some(IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1])));
+        assert_eq!(server.cache, std::env::home_dir().unwrap().join("cache"));
+        assert_eq!(server.logs, PathBuf::from("/var/log"));
+        let written = crate::to_string(&server).unwrap();
+        assert_eq!(from_str::<Server>(&written).unwrap(), server);
+
+        let err = from_str::<Server>("listen = \"localhost\"").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid `SocketAddr` `localhost`: invalid socket address syntax"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Includes, which split a document across several files. An entry like
+//! `include "common.ycf"` in a map is replaced with the entries of the document it
+//! refers to, as if they were written in its place: entries before it are merged
+//! with the included ones like [`Value::merge`], and entries after it override them.
+//!
+//! Paths are turned into documents by a [`Resolver`], which is [`FileResolver`] for
+//! reading them from the file system next to the document that includes them.
+//!
+//! ```text
+//! include "defaults.ycf"
+//! server.port = 8080
+//! ```
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::parse::{ParseError, ParseErrorKind, Parser, SpanToken};
+use crate::value::Value;
+
+/// How deeply includes can be nested, which keeps a resolver that gives a new name
+/// every time from going on forever.
+const MAX_DEPTH: usize = 32;
+
+/// A document that an include refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Source {
+    // the name of the document, which includes in it are resolved from, and which
+    // errors in it have as their file
+    pub name: String,
+    pub src: String,
+}
+
+/// Finds the documents that includes refer to.
+pub trait Resolver {
+    /// Finds the document that `path` refers to in an include in the document named
+    /// `from`, or says why it can not.
+    fn resolve(&mut self, from: &str, path: &str) -> core::result::Result<Source, String>;
+}
+
+/// Reads includes from files, with paths relative to the directory of the file that
+/// includes them.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileResolver;
+
+#[cfg(feature = "std")]
+impl Resolver for FileResolver {
+    fn resolve(&mut self, from: &str, path: &str) -> core::result::Result<Source, String> {
+        let dir = Path::new(from).parent().unwrap_or(Path::new(""));
+        let path = dir.join(path);
+        let src = std::fs::read_to_string(&path)
+            .map_err(|err| format!("cannot read `{}`: {err}", path.display()))?;
+        Ok(Source {
+            name: path.to_string_lossy().into_owned(),
+            src,
+        })
+    }
+}
+
+/// Reads the document `src`, named `name`, into a [`Value`] with its includes found
+/// by `resolver`.
+///
+/// An error in an included document has the name of that document as its
+/// [`file`](Error::file), so it can be shown with the right source.
+pub fn from_str(name: &str, src: &str, resolver: &mut dyn Resolver) -> Result<Value> {
+    Loader {
+        resolver,
+        stack: Vec::new(),
+    }
+    .load(name, src)
+}
+
+/// Reads the file at `path` into a [`Value`] with its includes read by a
+/// [`FileResolver`].
+#[cfg(feature = "std")]
+pub fn from_file(path: impl AsRef<Path>) -> Result<Value> {
+    let path = path.as_ref();
+    let src = std::fs::read_to_string(path).map_err(|err| Error::io(err).with_file(path))?;
+    from_str(&path.to_string_lossy(), &src, &mut FileResolver)
+}
+
+struct Loader<'r> {
+    resolver: &'r mut dyn Resolver,
+    // the names of the documents being read, the outermost first
+    stack: Vec<String>,
+}
+
+impl Loader<'_> {
+    fn load(&mut self, name: &str, src: &str) -> Result<Value> {
+        let mut parser = Parser::new(src);
+        parser.max_depth = None;
+        self.stack.push(name.to_string());
+        // an error in an included document, which is returned as it is
+        let mut nested = None;
+        let res = Value::parse_including(&mut parser, &mut |path: &str, token: SpanToken| {
+            let error = |reason| ParseError::new(token, ParseErrorKind::Include(reason));
+            if self.stack.len() > MAX_DEPTH {
+                return Err(error("includes are nested too deeply".to_string()));
+            }
+            let source = self.resolver.resolve(name, path).map_err(error)?;
+            if self.stack.contains(&source.name) {
+                return Err(error(format!("`{}` would include itself", source.name)));
+            }
+            match self.load(&source.name, &source.src) {
+                Ok(value @ Value::Map(_)) => Ok(value),
+                Ok(_) => Err(error(format!("`{}` is not a map", source.name))),
+                Err(err) => {
+                    nested = Some(err);
+                    Err(error(String::new()))
+                }
+            }
+        });
+        self.stack.pop();
+        res.map_err(|err| nested.unwrap_or_else(|| Error::from(err).in_document(name)))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::error::Category;
+
+    #[test]
+    fn includes() {
+        struct Files(&'static [(&'static str, &'static str)]);
+        impl Resolver for Files {
+            fn resolve(&mut self, _: &str, path: &str) -> std::result::Result<Source, String> {
+                let (name, src) = self
+                    .0
+                    .iter()
+                    .find(|(name, _)| *name == path)
+                    .ok_or("no file")?;
+                Ok(Source {
+                    name: name.to_string(),
+                    src: src.to_string(),
+                })
+            }
+        }
+
+        let mut files = Files(&[
+            ("base", "server = { host = 'a' port = 1 } include \"tags\""),
+            ("tags", "tags = [1]"),
+            ("bad", "a = [1"),
+            ("loop", "include \"loop\""),
+        ]);
+        let value = from_str(
+            "main",
+            "server.tls = true include \"base\" server.port = 2 nested = { include \"tags\" }",
+            &mut files,
+        )
+        .unwrap();
+        let expected: Value =
+            "server = { host = 'a' port = 2 tls = true } tags = [1] nested.tags = [1]"
+                .parse()
+                .unwrap();
+        assert_eq!(value, expected);
+        // `include` is still an ordinary key
+        let value = from_str("main", "include = 1", &mut files).unwrap();
+        assert_eq!(value.get("include"), Some(&Value::U64(1)));
+
+        let err = from_str("main", "a = 1\ninclude \"bad\"", &mut files).unwrap_err();
+        assert_eq!(err.file(), Some(std::path::Path::new("bad")));
+        assert_eq!(err.line(), Some(0));
+        // the error is at the include that could not be read
+        for (src, file) in [
+            ("include \"missing\"", "main"),
+            ("include \"loop\"", "loop"),
+        ] {
+            let err = from_str("main", src, &mut files).unwrap_err();
+            assert_eq!(err.classify(), Category::Io, "{err}");
+            assert_eq!(err.file(), Some(std::path::Path::new(file)));
+        }
+    }
+}
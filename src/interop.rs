@@ -0,0 +1,233 @@
+//! Conversions between [`Value`](crate::Value) and the value types of other crates,
+//! each behind a feature.
+
+#[cfg(feature = "json")]
+mod json {
+    use serde_json::{Number, Value as Json};
+
+    use crate::value::{Interner, Value};
+
+    /// `null` is [`Value::Null`]. Numbers are [`Value::U64`] if they fit, then
+    /// [`Value::I64`], and [`Value::F64`] otherwise.
+    impl From<Json> for Value {
+        fn from(json: Json) -> Self {
+            from_json(json, &mut Interner::new())
+        }
+    }
+
+    fn from_json(json: Json, interner: &mut Interner) -> Value {
+        match json {
+            Json::Null => Value::Null,
+            Json::Bool(v) => Value::Bool(v),
+            Json::Number(v) => match (v.as_u64(), v.as_i64()) {
+                (Some(v), _) => Value::U64(v),
+                (_, Some(v)) => Value::I64(v),
+                // without arbitrary precision every number fits in one of the three
+                _ => Value::F64(v.as_f64().unwrap_or(f64::NAN)),
+            },
+            Json::String(v) => Value::String(v),
+            Json::Array(v) => Value::List(v.into_iter().map(|v| from_json(v, interner)).collect()),
+            Json::Object(v) => Value::Map(
+                v.into_iter()
+                    .map(|(k, v)| (interner.intern(&k), from_json(v, interner)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Floats that are not finite become `null`, as JSON has no way to write them.
+    /// Integers that do not fit in 64 bits become the nearest float, unless serde_json
+    /// has arbitrary precision.
+    /// Durations become `{ secs, nanos }` objects, the form serde gives them in.
+    impl From<Value> for Json {
+        fn from(value: Value) -> Self {
+            match value {
+                Value::Null => Json::Null,
+                Value::Bool(v) => Json::Bool(v),
+                Value::U64(v) => Json::Number(v.into()),
+                Value::I64(v) => Json::Number(v.into()),
+                Value::U128(v) => {
+                    Json::Number(Number::from_u128(v).unwrap_or_else(|| float_number(v as f64)))
+                }
+                Value::I128(v) => {
+                    Json::Number(Number::from_i128(v).unwrap_or_else(|| float_number(v as f64)))
+                }
+                Value::F64(v) => Number::from_f64(v).map_or(Json::Null, Json::Number),
+                Value::String(v) => Json::String(v),
+                Value::Duration(v) => serde_json::json!({
+                    "secs": v.as_secs(),
+                    "nanos": v.subsec_nanos(),
+                }),
+                Value::List(v) => Json::Array(v.into_iter().map(Json::from).collect()),
+                Value::Map(v) => Json::Object(
+                    v.into_iter()
+                        .map(|(k, v)| (k.to_string(), v.into()))
+                        .collect(),
+                ),
+            }
+        }
+    }
+
+    fn float_number(v: f64) -> Number {
+        Number::from_f64(v).expect("integers should be finite floats")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::Duration;
+
+        use super::*;
+
+        #[test]
+        fn json_value() {
+            let json = serde_json::json!({ "a": [1, -1, 0.5, null, "x"], "b": { "c": true } });
+            let value = Value::from(json.clone());
+            assert_eq!(value, "a = [1 -1 0.5 null 'x'] b.c = true".parse().unwrap());
+            assert_eq!(serde_json::Value::from(value), json);
+            assert_eq!(
+                serde_json::Value::from(Value::List(vec![
+                    Value::F64(f64::NAN),
+                    Value::Duration(Duration::from_millis(1500)),
+                ])),
+                serde_json::json!([null, { "secs": 1, "nanos": 500_000_000 }])
+            );
+            assert_eq!(
+                serde_json::Value::from(Value::List(vec![
+                    Value::U128(1 << 64),
+                    Value::I128(-(1 << 64)),
+                ])),
+                serde_json::json!([18446744073709551616.0, -18446744073709551616.0])
+            );
+        }
+    }
+}
+
+#[cfg(feature = "toml")]
+mod toml {
+    use toml::{Table, Value as Toml};
+
+    use crate::error::{Error, Result};
+    use crate::value::{Interner, Value};
+
+    /// Datetimes become strings in RFC 3339 form, which they can be parsed back from
+    /// with `str::parse::<toml::value::Datetime>`, as ycf has no datetimes of its own.
+    impl From<Toml> for Value {
+        fn from(toml: Toml) -> Self {
+            from_toml(toml, &mut Interner::new())
+        }
+    }
+
+    impl From<Table> for Value {
+        fn from(table: Table) -> Self {
+            from_table(table, &mut Interner::new())
+        }
+    }
+
+    fn from_toml(toml: Toml, interner: &mut Interner) -> Value {
+        match toml {
+            Toml::String(v) => Value::String(v),
+            Toml::Integer(v) => match u64::try_from(v) {
+                Ok(v) => Value::U64(v),
+                Err(_) => Value::I64(v),
+            },
+            Toml::Float(v) => Value::F64(v),
+            Toml::Boolean(v) => Value::Bool(v),
+            Toml::Datetime(v) => Value::String(v.to_string()),
+            Toml::Array(v) => Value::List(v.into_iter().map(|v| from_toml(v, interner)).collect()),
+            Toml::Table(v) => from_table(v, interner),
+        }
+    }
+
+    fn from_table(table: Table, interner: &mut Interner) -> Value {
+        Value::Map(
+            table
+                .into_iter()
+                .map(|(k, v)| (interner.intern(&k), from_toml(v, interner)))
+                .collect(),
+        )
+    }
+
+    /// TOML has no null, so entries set to null are left out of tables, but null in a
+    /// list is an error, as is an integer outside of `i64`. Durations become
+    /// `{ secs, nanos }` tables, the form serde gives them in.
+    ///
+    /// `toml::Value::try_from` is toml's own method that serializes any value, so
+    /// convert with `value.try_into()` instead.
+    impl TryFrom<Value> for Toml {
+        type Error = Error;
+
+        fn try_from(value: Value) -> Result<Self> {
+            Ok(match value {
+                Value::Null => return Err(Error::custom("TOML has no null")),
+                Value::Bool(v) => Toml::Boolean(v),
+                Value::U64(v) => Toml::Integer(
+                    i64::try_from(v)
+                        .map_err(|_| Error::custom(format!("{v} is too large for TOML")))?,
+                ),
+                Value::I64(v) => Toml::Integer(v),
+                Value::U128(v) => return Err(Error::custom(format!("{v} is too large for TOML"))),
+                Value::I128(v) => return Err(Error::custom(format!("{v} is too small for TOML"))),
+                Value::F64(v) => Toml::Float(v),
+                Value::String(v) => Toml::String(v),
+                Value::Duration(v) => Toml::Table(Table::from_iter([
+                    ("secs".to_owned(), Toml::Integer(v.as_secs() as i64)),
+                    ("nanos".to_owned(), Toml::Integer(v.subsec_nanos().into())),
+                ])),
+                Value::List(v) => {
+                    Toml::Array(v.into_iter().map(Value::try_into).collect::<Result<_>>()?)
+                }
+                Value::Map(_) => Toml::Table(value.try_into()?),
+            })
+        }
+    }
+
+    /// Like the conversion to a [`toml::Value`], for values that are maps.
+    impl TryFrom<Value> for Table {
+        type Error = Error;
+
+        fn try_from(value: Value) -> Result<Self> {
+            let Value::Map(map) = value else {
+                return Err(Error::custom("a TOML document must be a table"));
+            };
+            map.into_iter()
+                .filter(|(_, v)| *v != Value::Null)
+                .map(|(k, v)| Ok((k.to_string(), v.try_into()?)))
+                .collect()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn toml_value() {
+            let table: Table = "a = [1, -1, 0.5]\nwhen = 1979-05-27T07:32:00Z\n[b]\nc = true"
+                .parse()
+                .unwrap();
+            let value = Value::from(table.clone());
+            assert_eq!(
+                value,
+                "a = [1 -1 0.5] when = \"1979-05-27T07:32:00Z\" b.c = true"
+                    .parse()
+                    .unwrap()
+            );
+            let Value::Map(mut map) = value else { panic!() };
+            let when = map.remove("when").unwrap();
+            let back: Table = Value::Map(map).try_into().unwrap();
+            assert_eq!(back["a"], table["a"]);
+            assert_eq!(back["b"], table["b"]);
+            let Value::String(when) = when else { panic!() };
+            assert_eq!(Toml::Datetime(when.parse().unwrap()), table["when"]);
+
+            let value: Value = "a = null b = 1".parse().unwrap();
+            let table: Table = value.try_into().unwrap();
+            assert_eq!(table, Table::from_iter([("b".to_owned(), 1.into())]));
+            assert!(Toml::try_from(Value::List(vec![Value::Null])).is_err());
+            assert!(Table::try_from(Value::U64(u64::MAX)).is_err());
+            assert!(Toml::try_from(Value::U64(u64::MAX)).is_err());
+            assert!(Toml::try_from(Value::U128(u128::MAX)).is_err());
+            assert!(Toml::try_from(Value::I128(i128::MIN)).is_err());
+        }
+    }
+}
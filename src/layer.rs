@@ -0,0 +1,276 @@
+//! Layered configuration, where documents and values are merged in order, like
+//! built in defaults, then a system wide file, then the user's file, then overrides
+//! from the command line. Later layers override earlier ones key by key, like
+//! [`Value::merge`], and the result records which layer every value came from.
+//!
+//! ```
+//! # use ycf::layer::Layers;
+//! let merged = Layers::new()
+//!     .with_str("defaults", r#"port = 80 host = "localhost""#)?
+//!     .with_str("user", "port = 8080")?
+//!     .merge();
+//! assert_eq!(merged.origin("port"), Some("user"));
+//! assert_eq!(merged.origin("host"), Some("defaults"));
+//! # Ok::<(), ycf::Error>(())
+//! ```
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::{io, path::Path};
+
+use serde::de::DeserializeOwned;
+
+use crate::error::{Error, Result};
+use crate::path::AsPath;
+use crate::value::{Map, Value};
+
+/// The layers of a configuration, the first of which is overridden by every other.
+#[derive(Clone, Debug, Default)]
+pub struct Layers {
+    // the name of every layer and its value
+    layers: Vec<(String, Value)>,
+}
+
+impl Layers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the document `src` as a layer named `name`, which errors in it have as
+    /// their file.
+    pub fn with_str(self, name: impl Into<String>, src: &str) -> Result<Self> {
+        let name = name.into();
+        let value = src.parse().map_err(|err: Error| err.in_document(&name))?;
+        Ok(self.with_value(name, value))
+    }
+
+    /// Adds the file at `path` as a layer named by its path.
+    #[cfg(feature = "std")]
+    pub fn with_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let src = std::fs::read_to_string(path).map_err(|err| Error::io(err).with_file(path))?;
+        self.with_str(path.to_string_lossy(), &src)
+    }
+
+    /// Like [`with_file`](Self::with_file), but a file that does not exist is left
+    /// out, for files that do not have to be there, like a system wide config.
+    #[cfg(feature = "std")]
+    pub fn with_optional_file(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(src) => self.with_str(path.to_string_lossy(), &src),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(self),
+            Err(err) => Err(Error::io(err).with_file(path)),
+        }
+    }
+
+    /// Adds `value` as a layer named `name`, like overrides from the command line.
+    pub fn with_value(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.layers.push((name.into(), value));
+        self
+    }
+
+    /// Merges the layers in order.
+    pub fn merge(self) -> Merged {
+        let mut merged = Merged {
+            value: Value::Map(Map::new()),
+            names: Vec::with_capacity(self.layers.len()),
+            origins: BTreeMap::new(),
+        };
+        for (layer, (name, value)) in self.layers.into_iter().enumerate() {
+            merged.names.push(name);
+            merge(
+                &mut merged.value,
+                value,
+                layer,
+                &mut crate::Path::new(),
+                &mut merged.origins,
+            );
+        }
+        merged
+    }
+
+    /// Merges the layers and deserializes a `T` from the result.
+    pub fn deserialize<T: DeserializeOwned>(self) -> Result<T> {
+        self.merge().value.deserialize_into()
+    }
+}
+
+/// The value of merged [`Layers`], with the layer every value in it came from.
+#[derive(Clone, Debug)]
+pub struct Merged {
+    pub value: Value,
+    names: Vec<String>,
+    // the layer that set every value that is not a map, or is an empty map, by path
+    origins: BTreeMap<crate::Path, usize>,
+}
+
+impl Merged {
+    /// The name of the layer that set the value at `path`. Maps are merged from every
+    /// layer, so only values that are not maps have one, along with empty maps.
+    pub fn origin<P: AsPath + ?Sized>(&self, path: &P) -> Option<&str> {
+        self.origins
+            .get(&*path.as_path()?)
+            .map(|&layer| self.names[layer].as_str())
+    }
+
+    /// Every path that has an [`origin`](Self::origin), in order, with the name of
+    /// the layer it came from.
+    pub fn origins(&self) -> impl Iterator<Item = (&crate::Path, &str)> {
+        self.origins
+            .iter()
+            .map(|(path, &layer)| (path, self.names[layer].as_str()))
+    }
+
+    /// Deserializes a `T` from the merged value.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        self.value.clone().deserialize_into()
+    }
+}
+
+/// Merges `other` from `layer` into `target`, which is at `path`.
+fn merge(
+    target: &mut Value,
+    other: Value,
+    layer: usize,
+    path: &mut crate::Path,
+    origins: &mut BTreeMap<crate::Path, usize>,
+) {
+    match (target, other) {
+        (Value::Map(map), Value::Map(other)) => {
+            for (key, value) in other {
+                path.push_key(&*key);
+                match map.get_mut(&key) {
+                    Some(existing) => merge(existing, value, layer, path, origins),
+                    None => {
+                        record(&value, layer, path, origins);
+                        map.insert(key, value);
+                    }
+                }
+                path.pop();
+            }
+        }
+        (target, other) => {
+            // whatever was set in the old value is gone
+            origins.retain(|key, _| !key.starts_with(path));
+            record(&other, layer, path, origins);
+            *target = other;
+        }
+    }
+}
+
+/// Records that the values in `value`, which is at `path`, came from `layer`.
+fn record(
+    value: &Value,
+    layer: usize,
+    path: &mut crate::Path,
+    origins: &mut BTreeMap<crate::Path, usize>,
+) {
+    match value {
+        Value::Map(map) if !map.is_empty() => {
+            for (key, value) in map {
+                path.push_key(&**key);
+                record(value, layer, path, origins);
+                path.pop();
+            }
+        }
+        _ => {
+            origins.insert(path.clone(), layer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn layers() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        enum Level {
+            Info,
+            Debug,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct App {
+            server: Server,
+            level: Level,
+            timeout: Duration,
+            limits: BTreeMap<u16, Option<u32>>,
+            tags: Vec<String>,
+        }
+
+        let overrides: Value = "server.port = 9000 limits = { \"1\" = null }"
+            .parse()
+            .unwrap();
+        let layers = Layers::new()
+            .with_str(
+                "defaults",
+                "server = { host = \"localhost\" port = 80 } level = \"Info\" timeout = 5s tags = []",
+            )
+            .unwrap()
+            .with_str(
+                "user",
+                "level = \"Debug\" tags = [\"a\"] limits = { \"1\" = 2 }",
+            )
+            .unwrap()
+            .with_value("overrides", overrides);
+        let merged = layers.clone().merge();
+        let origins: Vec<_> = merged
+            .origins()
+            .map(|(path, layer)| format!("{path} = {layer}"))
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                "level = user",
+                "limits.\"1\" = overrides",
+                "server.host = defaults",
+                "server.port = overrides",
+                "tags = user",
+                "timeout = defaults",
+            ]
+        );
+        assert_eq!(merged.origin(r#"limits."1""#), Some("overrides"));
+        assert_eq!(merged.origin("server"), None);
+        let app: App = layers.deserialize().unwrap();
+        assert_eq!(
+            app,
+            App {
+                server: Server {
+                    host: "localhost".into(),
+                    port: 9000,
+                },
+                level: Level::Debug,
+                timeout: Duration::from_secs(5),
+                limits: BTreeMap::from([(1, None)]),
+                tags: vec!["a".into()],
+            }
+        );
+
+        // a value that replaces a map takes the place of everything in it
+        let merged = Layers::new()
+            .with_str("a", "server = { host = \"a\" port = 1 }")
+            .unwrap()
+            .with_str("b", "server = false")
+            .unwrap()
+            .merge();
+        let origins: Vec<_> = merged.origins().collect();
+        assert_eq!(origins, [(&"server".parse().unwrap(), "b")]);
+
+        let err = Layers::new().with_str("user", "a = [1").unwrap_err();
+        #[cfg(feature = "std")]
+        assert_eq!(err.file(), Some(std::path::Path::new("user")));
+        assert_eq!(err.line(), Some(0));
+    }
+}
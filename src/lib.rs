@@ -1,11 +1,50 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod cursor;
 pub mod parse;
 
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+#[cfg(feature = "arena")]
+pub mod arena;
+#[cfg(feature = "config")]
+pub mod config;
+
+#[cfg(any(feature = "chrono", feature = "time"))]
+pub mod datetime;
 pub mod de;
+mod diagnostic;
 pub mod error;
+#[cfg(feature = "figment")]
+pub mod figment;
 pub mod fmt;
+pub mod helpers;
+pub mod include;
+mod interop;
+pub mod layer;
+pub mod line_index;
+pub mod overrides;
+pub mod path;
+pub mod schema;
+pub mod secret;
+#[cfg(feature = "std")]
 pub mod ser;
+#[cfg(feature = "transcode")]
+pub mod transcode;
+pub mod value;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use de::Deserializer;
+#[cfg(feature = "std")]
+pub use de::from_file;
+pub use de::{from_slice, from_str, from_str_seed, get, Deserializer};
 pub use error::{Error, Result};
-// pub use ser::Serializer;
+pub use line_index::LineIndex;
+pub use parse::{lex, SpanToken};
+pub use path::Path;
+pub use secret::Secret;
+pub use value::{Interner, Value};
+#[cfg(feature = "std")]
+pub use ser::{to_string, to_writer, Serializer};
@@ -0,0 +1,143 @@
+use alloc::vec::Vec;
+
+use crate::parse::ColumnEncoding;
+
+/// Converts between byte offsets in a source and the 0 indexed line and column
+/// positions used by [`SpanToken`](crate::SpanToken).
+///
+/// Finding the line is a binary search over the line starts. Columns in any
+/// encoding but [`ColumnEncoding::Bytes`] also walk the text of that one line.
+#[derive(Clone, Debug)]
+pub struct LineIndex<'a> {
+    src: &'a str,
+    // byte offset of the start of every line, the first is always 0
+    starts: Vec<usize>,
+    // what columns count, this should match the parser that produced the positions
+    pub encoding: ColumnEncoding,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let starts = core::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            src,
+            starts,
+            encoding: ColumnEncoding::default(),
+        }
+    }
+
+    #[inline]
+    pub fn src(&self) -> &'a str {
+        self.src
+    }
+
+    /// The number of lines in the source. A trailing newline starts an empty last line.
+    #[inline]
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The byte range of `line`, without its newline.
+    pub fn line_range(&self, line: u32) -> Option<core::ops::Range<usize>> {
+        let line = line as usize;
+        let start = *self.starts.get(line)?;
+        let end = match self.starts.get(line + 1) {
+            Some(next) => next - 1,
+            None => self.src.len(),
+        };
+        Some(start..end)
+    }
+
+    /// The line and column of the byte `offset`, or `None` if it is past the end
+    /// of the source or not on a char boundary.
+    pub fn position(&self, offset: usize) -> Option<(u32, u32)> {
+        if !self.src.is_char_boundary(offset) {
+            return None;
+        }
+        // the last line that starts at or before the offset
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let col = self.encoding.width(&self.src[self.starts[line]..offset]);
+        Some((line as u32, col))
+    }
+
+    /// The byte offset of `line` and `col`, or `None` if the position is not in the
+    /// source or is inside a character.
+    pub fn offset(&self, line: u32, col: u32) -> Option<usize> {
+        let range = self.line_range(line)?;
+        let text = &self.src[range.clone()];
+        let offset = match self.encoding {
+            ColumnEncoding::Bytes => Some(col as usize).filter(|&col| text.is_char_boundary(col)),
+            ColumnEncoding::Chars => text
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(Some(text.len()))
+                .nth(col as usize),
+            ColumnEncoding::Utf16 => {
+                let mut units = 0;
+                let mut found = None;
+                for (i, c) in text.char_indices().chain(Some((text.len(), '\0'))) {
+                    if units == col as usize {
+                        found = Some(i);
+                        break;
+                    } else if units > col as usize {
+                        break;
+                    }
+                    units += c.len_utf16();
+                }
+                found
+            }
+        }?;
+        Some(range.start + offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let src = "a = 1\nb = \"é😀\" x\n";
+        let mut index = LineIndex::new(src);
+        assert_eq!(index.line_count(), 3);
+        assert_eq!(index.line_range(0), Some(0..5));
+        assert_eq!(index.line_range(1), Some(6..20));
+        assert_eq!(index.line_range(2), Some(21..21));
+        assert_eq!(index.line_range(3), None);
+
+        let x = src.find('x').unwrap();
+        for (encoding, col) in [
+            (ColumnEncoding::Bytes, 13),
+            (ColumnEncoding::Chars, 9),
+            (ColumnEncoding::Utf16, 10),
+        ] {
+            index.encoding = encoding;
+            assert_eq!(index.position(x), Some((1, col)), "{encoding:?}");
+            assert_eq!(index.offset(1, col), Some(x), "{encoding:?}");
+            // the lexer counts columns the same way
+            let mut p = crate::parse::Parser::new(src);
+            p.column_encoding = encoding;
+            let token = p.into_tokens().find(|t| t.token.start == x).unwrap();
+            assert_eq!((token.line, token.col), (1, col), "{encoding:?}");
+        }
+
+        index.encoding = ColumnEncoding::Chars;
+        assert_eq!(index.position(0), Some((0, 0)));
+        assert_eq!(index.position(5), Some((0, 5)));
+        assert_eq!(index.position(6), Some((1, 0)));
+        assert_eq!(index.position(src.len()), Some((2, 0)));
+        assert_eq!(index.position(src.len() + 1), None);
+        // inside `é`
+        assert_eq!(index.position(12), None);
+        assert_eq!(index.offset(0, 6), None);
+        assert_eq!(index.offset(3, 0), None);
+
+        index.encoding = ColumnEncoding::Bytes;
+        assert_eq!(index.offset(1, 6), None);
+        index.encoding = ColumnEncoding::Utf16;
+        // between the two halves of `😀`
+        assert_eq!(index.offset(1, 7), None);
+    }
+}
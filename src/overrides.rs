@@ -0,0 +1,164 @@
+//! Overrides like `server.port=9090`, for applications that take `--set` flags on
+//! the command line.
+//!
+//! An override is a [`Path`], a `=` and a value written like in a document. A value
+//! that is a single identifier, like `debug`, is read as a string, the same way an
+//! enum variant is, so that it does not have to be quoted in a shell.
+//!
+//! ```
+//! # use ycf::Value;
+//! let mut config: Value = "server.port = 80".parse()?;
+//! ycf::overrides::apply(&mut config, "server.port=9090")?;
+//! ycf::overrides::apply(&mut config, "log.level=debug")?;
+//! assert_eq!(config.get_path("server.port"), Some(&Value::U64(9090)));
+//! assert_eq!(config.get_path("log.level"), Some(&Value::String("debug".into())));
+//! # Ok::<(), ycf::Error>(())
+//! ```
+
+use alloc::string::ToString;
+
+use crate::cursor::{is_ident_continue, is_ident_start};
+use crate::error::{Error, Result};
+use crate::parse::Parser;
+use crate::path::{Path, Segment};
+use crate::value::{Key, Map, Value};
+
+/// Sets the value of `over` at its path in `value`, making maps for the keys along
+/// the path that are not set. Indices along it have to be in lists that are.
+pub fn apply(value: &mut Value, over: &str) -> Result<()> {
+    let (path, new) = parse(over)?;
+    set(value, &path, new)
+}
+
+/// Applies every override in `overrides` in order, stopping at the first error.
+pub fn apply_all<S: AsRef<str>>(
+    value: &mut Value,
+    overrides: impl IntoIterator<Item = S>,
+) -> Result<()> {
+    overrides
+        .into_iter()
+        .try_for_each(|over| apply(value, over.as_ref()))
+}
+
+/// Reads the path and the value of an override.
+pub fn parse(over: &str) -> Result<(Path, Value)> {
+    let error = |reason: &dyn core::fmt::Display| {
+        Error::custom(format_args!("invalid override `{over}`: {reason}"))
+    };
+    let (path, src) = split(over).ok_or_else(|| error(&"expected a `=` after the path"))?;
+    let path: Path = path.trim().parse()?;
+    if path.is_empty() {
+        return Err(error(&"expected a path before the `=`"));
+    }
+    let src = src.trim();
+    let mut chars = src.chars();
+    let ident = chars.next().is_some_and(is_ident_start)
+        && chars.all(is_ident_continue)
+        && !matches!(src, "true" | "false" | "null");
+    if ident {
+        return Ok((path, Value::String(src.to_string())));
+    }
+    let mut parser = Parser::new(src);
+    let value = Value::parse(&mut parser)
+        .and_then(|value| parser.end_of_input().map(|()| value))
+        .map_err(|err| error(&err))?;
+    Ok((path, value))
+}
+
+/// Splits `over` at its first `=` that is not in a quoted key.
+fn split(over: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in over.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '=' if !quoted => return Some((&over[..i], &over[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Sets `path` in `value` to `new`.
+fn set(value: &mut Value, path: &Path, new: Value) -> Result<()> {
+    let mut target = value;
+    for (depth, segment) in path.iter().enumerate() {
+        let at = || path.segments()[..depth].iter().cloned().collect::<Path>();
+        target = match (target, segment) {
+            (Value::Map(map), Segment::Key(key)) => map
+                .entry(Key::from(key.as_str()))
+                .or_insert_with(|| Value::Map(Map::new())),
+            (Value::List(list), Segment::Index(index)) => {
+                let len = list.len();
+                list.get_mut(*index).ok_or_else(|| {
+                    Error::custom(format_args!(
+                        "cannot set `{path}`: index {index} is out of range for `{}`, which has {len} elements",
+                        at(),
+                    ))
+                })?
+            }
+            (_, segment) => {
+                let kind = match segment {
+                    Segment::Key(_) => "map",
+                    Segment::Index(_) => "list",
+                };
+                return Err(match depth {
+                    0 => Error::custom(format_args!(
+                        "cannot set `{path}`: the value is not a {kind}"
+                    )),
+                    _ => Error::custom(format_args!(
+                        "cannot set `{path}`: `{}` is not a {kind}",
+                        at(),
+                    )),
+                });
+            }
+        };
+    }
+    *target = new;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let mut value: Value = r#"server.port = 80 hosts = ["a" "b"] name = "x""#.parse().unwrap();
+        apply(&mut value, "server.port=9090").unwrap();
+        apply(&mut value, "server.tls = { enabled = true }").unwrap();
+        apply(&mut value, "log.level=debug").unwrap();
+        apply(&mut value, "hosts[1]=\"c\"").unwrap();
+        apply(&mut value, "\"a=b\".c=[1 2]").unwrap();
+        let expected: Value = r#"
+            server = { port = 9090 tls.enabled = true }
+            hosts = ["a" "c"]
+            name = "x"
+            log.level = "debug"
+            "a=b".c = [1 2]
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(value, expected);
+
+        let err = |over| apply(&mut value.clone(), over).unwrap_err().to_string();
+        assert_eq!(
+            err("port"),
+            "invalid override `port`: expected a `=` after the path"
+        );
+        assert_eq!(
+            err("=1"),
+            "invalid override `=1`: expected a path before the `=`"
+        );
+        assert!(err("port=1 2").starts_with("invalid override `port=1 2`: "));
+        assert_eq!(err("name.x=1"), "cannot set `name.x`: `name` is not a map");
+        assert_eq!(
+            err("hosts[2]=1"),
+            "cannot set `hosts[2]`: index 2 is out of range for `hosts`, which has 2 elements"
+        );
+        assert_eq!(err("[0]=1"), "cannot set `[0]`: the value is not a list");
+    }
+}
@@ -1,6 +1,25 @@
-use std::{fmt, mem, str::FromStr};
+use alloc::{
+    borrow::{Cow, ToOwned},
+    boxed::Box,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    fmt,
+    iter::FusedIterator,
+    mem,
+    ops::{Deref, Range},
+    str::FromStr,
+    time::Duration,
+};
 
-use crate::cursor::{Cursor, Keyword, Token, TokenKind};
+use smallvec::SmallVec;
+
+use crate::cursor::{Base, Cursor, Keyword, Token, TokenKind};
+use crate::line_index::LineIndex;
+use crate::path::Path;
 
 #[derive(Clone, Debug)]
 pub struct Parser<'a> {
@@ -11,8 +30,258 @@ pub struct Parser<'a> {
     // whether to skip whitespace and comments
     pub skip: bool,
     pub lookahead: Option<SpanToken>,
+    // the number of maps and lists currently open
+    pub depth: u32,
+    // the maximum depth before erroring, `None` for no limit
+    pub max_depth: Option<u32>,
+    // what is being parsed, outermost first, for error messages
+    pub context: Vec<ContextFrame<'a>>,
+    pub limits: Limits,
+    // the number of tokens read from the cursor so far
+    pub tokens: u64,
+    // what `col` counts
+    pub column_encoding: ColumnEncoding,
+    // when `Some`, every comment read from the source is pushed here in order
+    pub comments: Option<Vec<SpanToken>>,
+    // whether an identifier in value position is read as a string, like `level = debug`
+    pub bare_strings: bool,
+    // how strings given for bytes are decoded
+    pub bytes_encoding: BytesEncoding,
+    // whether a quoted number like `"8080"` is accepted where a number is expected
+    pub numeric_strings: bool,
+    // whether a float without a fractional part like `3.0` is accepted for an integer
+    pub integral_floats: bool,
+    // where `${NAME}` in strings is substituted from, `None` to leave strings as they are
+    pub env: Option<Env>,
+    // reused for unescaping strings that are only needed for a moment
+    scratch: String,
+    // tokens read ahead of time by `tokenize`, and the index of the next one
+    tokenized: Arc<[SpanToken]>,
+    tokenized_pos: usize,
+}
+
+/// How [`Parser::parse_bytes`] decodes a string into bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BytesEncoding {
+    /// Two hex digits per byte, in either case.
+    #[default]
+    Hex,
+    /// The standard base64 alphabet, with or without padding.
+    Base64,
+}
+
+impl BytesEncoding {
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Hex => "hex",
+            Self::Base64 => "base64",
+        }
+    }
+
+    /// Decodes `src`, returning `None` if it is not valid in this encoding.
+    pub fn decode(&self, src: &str) -> Option<Vec<u8>> {
+        match self {
+            Self::Hex => decode_hex(src),
+            Self::Base64 => decode_base64(src),
+        }
+    }
+}
+
+/// The variables that `${NAME}` and `${NAME:-default}` in strings are substituted
+/// with, see [`Parser::env`]. The default is used when the variable is not set or is
+/// empty, and `$${` is a `${` that is not substituted.
+#[derive(Clone)]
+pub struct Env(Arc<Lookup>);
+
+type Lookup = dyn Fn(&str) -> Option<String> + Send + Sync;
+
+impl Env {
+    /// Variables looked up with `get`.
+    pub fn new(get: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(get))
+    }
+
+    /// The environment variables of the process.
+    #[cfg(feature = "std")]
+    pub fn process() -> Self {
+        Self::new(|name| std::env::var(name).ok())
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        (self.0)(name)
+    }
+
+    /// Substitutes the variables in `src`, returning `None` if there are none, or the
+    /// name of a variable that is not set and has no default.
+    pub fn substitute(&self, src: &str) -> Result<Option<String>, String> {
+        if !src.contains("${") {
+            return Ok(None);
+        }
+        let mut out = String::with_capacity(src.len());
+        let mut rest = src;
+        while let Some(i) = rest.find("${") {
+            if rest[..i].ends_with('$') {
+                out.push_str(&rest[..i - 1]);
+                out.push_str("${");
+                rest = &rest[i + 2..];
+                continue;
+            }
+            // a `${` that is never closed is left as it is
+            let Some(end) = rest[i..].find('}') else {
+                break;
+            };
+            out.push_str(&rest[..i]);
+            let var = &rest[i + 2..i + end];
+            let (name, default) = match var.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (var, None),
+            };
+            match (self.get(name), default) {
+                (Some(value), Some(default)) if value.is_empty() => out.push_str(default),
+                (Some(value), _) => out.push_str(&value),
+                (None, Some(default)) => out.push_str(default),
+                (None, None) => return Err(name.to_owned()),
+            }
+            rest = &rest[i + end + 1..];
+        }
+        out.push_str(rest);
+        Ok(Some(out))
+    }
+}
+
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Env(..)")
+    }
+}
+
+/// A floating point type for [`Parser::parse_float`].
+pub trait Float: FromStr {
+    /// Converts `v`, or returns `None` if it can not be represented exactly.
+    fn from_i128_exact(v: i128) -> Option<Self>;
+
+    /// Like [`from_i128_exact`](Self::from_i128_exact), for integers past `i128::MAX`.
+    fn from_u128_exact(v: u128) -> Option<Self>;
+
+    /// Parses the source of a float token, with the `fast-float` feature if enabled.
+    fn parse_src(src: &str) -> Option<Self>;
+}
+
+macro_rules! impl_float {
+    ($($ty:ty),*) => {$(
+        impl Float for $ty {
+            #[inline]
+            fn from_i128_exact(v: i128) -> Option<Self> {
+                let float = v as $ty;
+                // the cast back saturates, so `i128::MAX` would round trip through
+                // 2^127 even though neither type can represent it
+                (float as i128 == v && v != i128::MAX).then_some(float)
+            }
+
+            #[inline]
+            fn from_u128_exact(v: u128) -> Option<Self> {
+                let float = v as $ty;
+                (float as u128 == v && v != u128::MAX).then_some(float)
+            }
+
+            #[inline]
+            fn parse_src(src: &str) -> Option<Self> {
+                #[cfg(feature = "fast-float")]
+                {
+                    fast_float2::parse(src).ok()
+                }
+                #[cfg(not(feature = "fast-float"))]
+                {
+                    src.parse().ok()
+                }
+            }
+        }
+    )*};
+}
+
+impl_float!(f32, f64);
+
+/// The unit that columns are counted in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColumnEncoding {
+    /// Bytes of UTF-8.
+    Bytes,
+    /// Unicode scalar values, which is what people usually mean by characters.
+    #[default]
+    Chars,
+    /// UTF-16 code units, which is what most editors and LSP clients use.
+    Utf16,
+}
+
+impl ColumnEncoding {
+    /// The width of `s` in this encoding.
+    #[inline]
+    pub fn width(self, s: &str) -> u32 {
+        if s.is_ascii() {
+            return s.len() as u32;
+        }
+        match self {
+            Self::Bytes => s.len() as u32,
+            Self::Chars => s.chars().count() as u32,
+            Self::Utf16 => s.encode_utf16().count() as u32,
+        }
+    }
+}
+
+/// Limits on the work done for a source, for parsing untrusted input.
+/// By default nothing is limited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    // the maximum length of the source in bytes
+    pub max_input_len: Option<usize>,
+    // the maximum number of tokens, including whitespace and comments
+    pub max_tokens: Option<u64>,
+    // the maximum length in bytes of a string or char literal, including the quotes
+    pub max_string_len: Option<usize>,
+    // the maximum number of entries in a map or elements in a list
+    pub max_elements: Option<usize>,
+}
+
+/// A string read by [`Parser::parse_str_scratch`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reference<'a, 's> {
+    /// Borrowed from the source, because it has no escapes.
+    Borrowed(&'a str),
+    /// Unescaped into the parser's scratch buffer, valid until the next string is read.
+    Copied(&'s str),
+}
+
+impl Deref for Reference<'_, '_> {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        match *self {
+            Self::Borrowed(s) => s,
+            Self::Copied(s) => s,
+        }
+    }
+}
+
+/// A saved position of a [`Parser`], made by [`Parser::checkpoint`].
+#[derive(Clone, Debug)]
+pub struct Checkpoint<'a> {
+    cursor: Cursor<'a>,
+    tokenized_pos: usize,
+    line: u32,
+    col: u32,
+    lookahead: Option<SpanToken>,
+    depth: u32,
+    tokens: u64,
+    // lengths of the context and the captured comments
+    context: usize,
+    comments: usize,
 }
 
+/// The default [`Parser::max_depth`].
+pub const DEFAULT_MAX_DEPTH: u32 = 128;
+
 impl<'a> Parser<'a> {
     pub fn new(src: &'a str) -> Self {
         Self {
@@ -21,6 +290,21 @@ impl<'a> Parser<'a> {
             col: 0,
             skip: true,
             lookahead: None,
+            depth: 0,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            context: Vec::new(),
+            limits: Limits::default(),
+            tokens: 0,
+            column_encoding: ColumnEncoding::default(),
+            comments: None,
+            bare_strings: false,
+            bytes_encoding: BytesEncoding::default(),
+            numeric_strings: false,
+            integral_floats: false,
+            env: None,
+            scratch: String::new(),
+            tokenized: Arc::new([]),
+            tokenized_pos: 0,
         }
     }
 
@@ -32,33 +316,183 @@ impl<'a> Parser<'a> {
             return token;
         }
 
+        let span = match self.tokenized.get(self.tokenized_pos) {
+            Some(&span) => {
+                self.tokenized_pos += 1;
+                self.line = span.end_line;
+                self.col = span.end_col;
+                span
+            }
+            None => self.read_span(),
+        };
+        self.tokens += 1;
+        if let (TokenKind::Comment, Some(comments)) = (span.token.kind, &mut self.comments) {
+            comments.push(span);
+        }
+
+        if self.skip(span.token.kind) {
+            return self.next_token();
+        }
+
+        span
+    }
+
+    /// Reads the next token from the cursor, advancing the line and column.
+    fn read_span(&mut self) -> SpanToken {
         let line = self.line;
         let col = self.col;
         let token = self.cursor.read_token();
-        let src = self.cursor.token_src(token);
         // advance line/col count
-        // handle \n
-        if matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment) {
-            // find the number of newlines and the length of the final line
-            let (line, last) = src
-                .split('\n')
-                .enumerate()
-                .last()
-                .expect("split should not return None");
-            self.line += line as u32;
-            if line >= 1 {
-                self.col = 0;
-            }
-            self.col += last.len() as u32;
+        if is_ascii_token(token.kind) {
+            self.col += (token.end - token.start) as u32;
+        } else if matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment) {
+            let src = self.cursor.token_src(token);
+            // a newline starts the column over at the start of the last line
+            match src.bytes().rposition(|b| b == b'\n') {
+                Some(last) => {
+                    self.line += src.bytes().filter(|&b| b == b'\n').count() as u32;
+                    self.col = self.column_encoding.width(&src[last + 1..]);
+                }
+                None => self.col += self.column_encoding.width(src),
+            }
         } else {
-            self.col += src.len() as u32;
+            self.col += self.column_encoding.width(self.cursor.token_src(token));
         }
 
-        if self.skip(token.kind) {
-            return self.next_token();
+        SpanToken {
+            token,
+            line,
+            col,
+            end_line: self.line,
+            end_col: self.col,
+        }
+    }
+
+    /// Reads the rest of the source into a buffer of tokens up front, which the parser
+    /// then walks instead of lexing as it goes. This makes [`peek_nth`](Self::peek_nth)
+    /// and going back to a [`Checkpoint`] much cheaper, at the cost of memory for every
+    /// token including whitespace and comments. When the tokens are only read once,
+    /// this is slower than lexing as the parser goes.
+    ///
+    /// Nothing is read if the source is longer than [`Limits::max_input_len`], and at
+    /// most one more than [`Limits::max_tokens`] tokens are buffered. The rest is lexed
+    /// as usual.
+    pub fn tokenize(&mut self) {
+        if matches!(self.limits.max_input_len, Some(max) if self.cursor.src().len() > max) {
+            return;
+        }
+        let max = self.limits.max_tokens.map_or(u64::MAX, |max| max + 1);
+        let (line, col) = (self.line, self.col);
+        // keep the tokens that have not been read yet
+        let mut tokens = self.tokenized[self.tokenized_pos..].to_vec();
+        while (tokens.len() as u64) < max {
+            let span = self.read_span();
+            if span.token.kind == TokenKind::Eof {
+                break;
+            }
+            tokens.push(span);
+        }
+        self.tokenized = tokens.into();
+        self.tokenized_pos = 0;
+        // the position is advanced again as the buffered tokens are read
+        self.line = line;
+        self.col = col;
+    }
+
+    /// Saves the current position, for reading ahead and then going back with
+    /// [`restore`](Self::restore). This does not allocate.
+    pub fn checkpoint(&self) -> Checkpoint<'a> {
+        Checkpoint {
+            cursor: self.cursor.clone(),
+            tokenized_pos: self.tokenized_pos,
+            line: self.line,
+            col: self.col,
+            lookahead: self.lookahead,
+            depth: self.depth,
+            tokens: self.tokens,
+            context: self.context.len(),
+            comments: self.comments.as_ref().map_or(0, Vec::len),
+        }
+    }
+
+    /// Goes back to a position saved by [`checkpoint`](Self::checkpoint).
+    ///
+    /// Context frames and comments added since the checkpoint are dropped, but
+    /// frames popped since then are not put back. Options such as the [`Limits`]
+    /// are left as they are.
+    pub fn restore(&mut self, checkpoint: Checkpoint<'a>) {
+        self.cursor = checkpoint.cursor;
+        self.tokenized_pos = checkpoint.tokenized_pos;
+        self.line = checkpoint.line;
+        self.col = checkpoint.col;
+        self.lookahead = checkpoint.lookahead;
+        self.depth = checkpoint.depth;
+        self.tokens = checkpoint.tokens;
+        self.context.truncate(checkpoint.context);
+        if let Some(comments) = &mut self.comments {
+            comments.truncate(checkpoint.comments);
+        }
+    }
+
+    /// Starts recording every comment read from the source, including the ones
+    /// that are skipped.
+    pub fn capture_comments(&mut self) {
+        self.comments.get_or_insert_with(Vec::new);
+    }
+
+    /// Takes the comments recorded since the last call, leaving capturing on.
+    /// This is empty if [`capture_comments`](Self::capture_comments) was never called.
+    pub fn take_comments(&mut self) -> Vec<SpanToken> {
+        self.comments.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    /// Like [`next_token`](Self::next_token), but checks the token against the [`Limits`].
+    pub fn next_checked(&mut self) -> Result<SpanToken, ParseError> {
+        let next = self.next_token();
+        let exceeded = |limit| Err(ParseError::new(next, ParseErrorKind::LimitExceeded(limit)));
+        let limits = &self.limits;
+        match limits.max_input_len {
+            Some(max) if self.cursor.src().len() > max => return exceeded(Limit::InputLen(max)),
+            _ => (),
+        }
+        match limits.max_tokens {
+            Some(max) if self.tokens > max => return exceeded(Limit::Tokens(max)),
+            _ => (),
+        }
+        match limits.max_string_len {
+            Some(max)
+                if matches!(
+                    next.token.kind,
+                    TokenKind::String { .. } | TokenKind::Char { .. }
+                ) && next.token.end - next.token.start > max =>
+            {
+                return exceeded(Limit::StringLen(max))
+            }
+            _ => (),
         }
+        Ok(next)
+    }
+
+    /// Checks that a map or list can have a `count`th entry, the one at `token`.
+    pub fn check_elements(&self, count: usize, token: SpanToken) -> Result<(), ParseError> {
+        match self.limits.max_elements {
+            Some(max) if count > max => Err(ParseError::new(
+                token,
+                ParseErrorKind::LimitExceeded(Limit::Elements(max)),
+            )),
+            _ => Ok(()),
+        }
+    }
 
-        SpanToken { token, line, col }
+    /// Like [`peek_token`](Self::peek_token), but only returns the kind, which is all
+    /// most callers look at. This is inlined and does not copy the whole token when it
+    /// is already in the lookahead.
+    #[inline]
+    pub fn peek_kind(&mut self) -> TokenKind {
+        match self.lookahead {
+            Some(token) if !self.skip(token.token.kind) => token.token.kind,
+            _ => self.peek_token().token.kind,
+        }
     }
 
     pub fn peek_token(&mut self) -> SpanToken {
@@ -76,6 +510,24 @@ impl<'a> Parser<'a> {
         next
     }
 
+    /// Returns the `n`th token after the next one without consuming anything, so
+    /// `peek_nth(0)` is the same as [`peek_token`](Self::peek_token). This lexes the
+    /// tokens in between again unless the source was [`tokenize`](Self::tokenize)d.
+    pub fn peek_nth(&mut self, n: usize) -> SpanToken {
+        if n == 0 {
+            return self.peek_token();
+        }
+        let checkpoint = self.checkpoint();
+        self.peek_token();
+        self.lookahead = None;
+        for _ in 1..n {
+            self.next_token();
+        }
+        let next = self.next_token();
+        self.restore(checkpoint);
+        next
+    }
+
     pub fn next_no_skip(&mut self) -> SpanToken {
         let old = mem::replace(&mut self.skip, false);
         let res = self.next_token();
@@ -90,6 +542,13 @@ impl<'a> Parser<'a> {
         res
     }
 
+    /// Turns the parser into an iterator over every remaining token,
+    /// including whitespace and comments.
+    pub fn into_tokens(mut self) -> Tokens<'a> {
+        self.skip = false;
+        Tokens { parser: self }
+    }
+
     fn skip(&self, kind: TokenKind) -> bool {
         self.skip && matches!(kind, TokenKind::Whitespace | TokenKind::Comment)
     }
@@ -99,15 +558,72 @@ impl<'a> Parser<'a> {
     }
 
     pub fn peek_eof(&mut self) -> bool {
-        self.peek_token().token.kind == TokenKind::Eof
+        self.peek_kind() == TokenKind::Eof
+    }
+
+    /// Whether the value of an entry with its `=` on `line` is missing, since the next
+    /// token is the key of another entry: an identifier on a later line that is
+    /// followed by a `=` or a `.`. That is an error either way, this only reports it
+    /// as a missing value rather than as an unexpected `=` after the identifier.
+    pub(crate) fn value_missing(&mut self, line: u32) -> bool {
+        let next = self.peek_token();
+        next.token.kind == TokenKind::Ident
+            && next.line > line
+            && matches!(
+                self.peek_nth(1).token.kind,
+                TokenKind::Equal | TokenKind::Dot
+            )
+    }
+
+    /// Whether the rest of the input is top level entries rather than a single value,
+    /// which is the case when it starts with `key =` or `key.`, or is empty.
+    pub fn starts_top_level(&mut self) -> bool {
+        match self.peek_kind() {
+            TokenKind::Eof => true,
+            kind if starts_key(kind) => matches!(
+                self.peek_nth(1).token.kind,
+                TokenKind::Equal | TokenKind::Dot
+            ),
+            _ => false,
+        }
+    }
+
+    /// Checks that nothing but whitespace and comments is left.
+    pub fn end_of_input(&mut self) -> Result<(), ParseError> {
+        let next = self.next_checked()?;
+        match next.token.kind {
+            TokenKind::Eof => Ok(()),
+            _ => Err(ParseError::new(
+                next,
+                ParseErrorKind::Expected(ExpectedSet::EOF),
+            )),
+        }
     }
 
     pub fn error(&mut self, kind: ParseErrorKind) -> ParseError {
         ParseError::new(self.peek_token(), kind)
     }
 
+    #[inline]
+    pub fn push_context(&mut self, frame: ContextFrame<'a>) {
+        self.context.push(frame);
+    }
+
+    #[inline]
+    pub fn pop_context(&mut self) -> Option<ContextFrame<'a>> {
+        self.context.pop()
+    }
+
+    /// Attaches the current context to `err`, unless it already has one from
+    /// further inside.
+    pub fn add_context(&self, err: &mut ParseError) {
+        if err.context.is_empty() {
+            err.context = self.context.iter().map(ContextFrame::to_static).collect();
+        }
+    }
+
     pub fn parse_null(&mut self) -> Result<(), ParseError> {
-        let next = self.next_token();
+        let next = self.next_checked()?;
         match next.token.kind {
             TokenKind::Keyword(Keyword::Null) => Ok(()),
             _ => Err(ParseError::new(next, ParseErrorKind::ExpectedBool)),
@@ -115,18 +631,14 @@ impl<'a> Parser<'a> {
     }
 
     pub fn try_parse_null(&mut self) -> Option<Result<(), ParseError>> {
-        let next = self.peek_token();
-        match next.token.kind {
-            TokenKind::Keyword(Keyword::Null) => {
-                self.next_token();
-                Some(Ok(()))
-            }
+        match self.peek_kind() {
+            TokenKind::Keyword(Keyword::Null) => Some(self.parse_null()),
             _ => None,
         }
     }
 
     pub fn parse_bool(&mut self) -> Result<bool, ParseError> {
-        let next = self.next_token();
+        let next = self.next_checked()?;
         match next.token.kind {
             TokenKind::Keyword(Keyword::True) => Ok(true),
             TokenKind::Keyword(Keyword::False) => Ok(false),
@@ -135,102 +647,204 @@ impl<'a> Parser<'a> {
     }
 
     pub fn try_parse_bool(&mut self) -> Option<Result<bool, ParseError>> {
-        let next = self.peek_token();
-        let val = match next.token.kind {
+        let val = match self.peek_kind() {
             TokenKind::Keyword(Keyword::True) => true,
             TokenKind::Keyword(Keyword::False) => false,
             _ => return None,
         };
         // consume peeked token
-        self.next_token();
-        Some(Ok(val))
+        Some(self.next_checked().map(|_| val))
     }
 
     pub fn parse_string(&mut self) -> Result<String, ParseError> {
-        let next = self.next_token();
-        if let TokenKind::String { terminated } = next.token.kind {
+        self.parse_str().map(Cow::into_owned)
+    }
+
+    /// Like [`parse_string`](Self::parse_string), but borrows from the source
+    /// unless the string has escapes.
+    pub fn parse_str(&mut self) -> Result<Cow<'a, str>, ParseError> {
+        Ok(match self.parse_str_scratch()? {
+            Reference::Borrowed(s) => Cow::Borrowed(s),
+            Reference::Copied(s) => Cow::Owned(s.to_owned()),
+        })
+    }
+
+    /// Like [`parse_str`](Self::parse_str), but unescapes into a buffer owned by the
+    /// parser instead of allocating a new string each time.
+    pub fn parse_str_scratch(&mut self) -> Result<Reference<'a, '_>, ParseError> {
+        let next = self.next_checked()?;
+        // the string if it is in the source, rather than unescaped into the scratch buffer
+        let borrowed = if let TokenKind::String {
+            terminated,
+            escaped,
+        } = next.token.kind
+        {
             if !terminated {
                 return Err(ParseError::new(next, ParseErrorKind::StringUnterminated));
             }
-            let mut chars = self.src(next.token).chars().enumerate();
-            // skip the starting '"'
-            chars.next();
-
-            let mut out = String::with_capacity(self.src(next.token).len() - 2);
-            while let Some((pos, c)) = chars.next() {
-                match c {
-                    '\\' => {
-                        let esc = chars.next().expect("string should be terminated").1;
-                        let ive = || {
-                            ParseError::new(next, ParseErrorKind::InvalidEscape { pos: pos as u32 })
-                        };
-                        match esc {
-                            'n' => out.push('\n'),
-                            'r' => out.push('\r'),
-                            't' => out.push('\t'),
-                            '0' => out.push('\0'),
-                            '\\' => out.push('\\'),
-                            '"' => out.push('"'),
-                            'x' => {
-                                let mut val = 0;
-                                val |= chars
-                                    .next()
-                                    .and_then(|(_, c)| c.to_digit(16))
-                                    .ok_or_else(ive)?;
-                                if val > 0x7 {
+            let src = self.src(next.token);
+            if !escaped {
+                Some(&src[1..src.len() - 1])
+            } else {
+                self.unescape_scratch(next)?;
+                None
+            }
+        } else if next.token.kind == TokenKind::Ident && self.bare_strings {
+            Some(self.src(next.token))
+        } else {
+            return Err(ParseError::new(next, ParseErrorKind::ExpectedString));
+        };
+
+        if let Some(env) = &self.env {
+            let substituted = env
+                .substitute(borrowed.unwrap_or(&self.scratch))
+                .map_err(|name| ParseError::new(next, ParseErrorKind::UnsetVariable(name)))?;
+            if let Some(substituted) = substituted {
+                self.scratch = substituted;
+                return Ok(Reference::Copied(&self.scratch));
+            }
+        }
+        Ok(match borrowed {
+            Some(s) => Reference::Borrowed(s),
+            None => Reference::Copied(&self.scratch),
+        })
+    }
+
+    pub fn parse_char(&mut self) -> Result<char, ParseError> {
+        let next = self.next_checked()?;
+        if let TokenKind::Char { terminated } = next.token.kind {
+            if !terminated {
+                return Err(ParseError::new(next, ParseErrorKind::CharUnterminated));
+            }
+            self.unescape_scratch(next)?;
+            let mut chars = self.scratch.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(ParseError::new(next, ParseErrorKind::InvalidChar)),
+            }
+        } else {
+            Err(ParseError::new(next, ParseErrorKind::ExpectedChar))
+        }
+    }
+
+    pub fn try_parse_char(&mut self) -> Option<Result<char, ParseError>> {
+        if let TokenKind::Char { .. } = self.peek_kind() {
+            Some(self.parse_char())
+        } else {
+            None
+        }
+    }
+
+    /// Unescapes the body of a terminated string or char token into the scratch buffer.
+    fn unescape_scratch(&mut self, next: SpanToken) -> Result<(), ParseError> {
+        let mut out = mem::take(&mut self.scratch);
+        out.clear();
+        let res = self.unescape(next, &mut out);
+        self.scratch = out;
+        res
+    }
+
+    /// Unescapes the body of a terminated string or char token into `out`.
+    fn unescape(&self, next: SpanToken, out: &mut String) -> Result<(), ParseError> {
+        let mut chars = self.src(next.token).chars().enumerate();
+        // skip the starting quote
+        let quote = chars.next().expect("quoted token should not be empty").1;
+
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '\\' => {
+                    let esc = chars.next().expect("token should be terminated").1;
+                    let ive = || {
+                        ParseError::new(next, ParseErrorKind::InvalidEscape { pos: pos as u32 })
+                    };
+                    match esc {
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        '0' => out.push('\0'),
+                        '\\' => out.push('\\'),
+                        '"' => out.push('"'),
+                        '\'' => out.push('\''),
+                        'x' => {
+                            let mut val = 0;
+                            val |= chars
+                                .next()
+                                .and_then(|(_, c)| c.to_digit(16))
+                                .ok_or_else(ive)?;
+                            if val > 0x7 {
+                                return Err(ive());
+                            }
+                            val <<= 4;
+                            val |= chars
+                                .next()
+                                .and_then(|(_, c)| c.to_digit(16))
+                                .ok_or_else(ive)?;
+                            out.push(char::from_u32(val).ok_or_else(ive)?);
+                        }
+                        'u' if matches!(chars.clone().next(), Some((_, '{'))) => {
+                            chars.next();
+                            let mut ct = 0;
+                            let mut val = 0;
+                            loop {
+                                let c = chars.next().ok_or_else(ive)?.1;
+                                if c == '}' {
+                                    break;
+                                } else if ct >= 6 {
                                     return Err(ive());
                                 }
+                                ct += 1;
                                 val <<= 4;
-                                val |= chars
-                                    .next()
-                                    .and_then(|(_, c)| c.to_digit(16))
-                                    .ok_or_else(ive)?;
-                                out.push(char::from_u32(val).ok_or_else(ive)?);
+                                val |= c.to_digit(16).ok_or_else(ive)?;
                             }
-                            'u' => {
-                                if !matches!(chars.next(), Some((_, '{'))) {
-                                    return Err(ive());
-                                }
-                                let mut ct = 0;
-                                let mut val = 0;
-                                while let Some((_, c)) = chars.next() {
-                                    if c == '}' {
-                                        break;
-                                    } else if ct >= 6 {
+                            if ct == 0 {
+                                return Err(ive());
+                            }
+                            out.push(char::from_u32(val).ok_or_else(ive)?);
+                        }
+                        'u' => {
+                            // JSON style `\uXXXX`, where a surrogate pair is spelled
+                            // as two consecutive escapes
+                            let high = hex4(&mut chars).ok_or_else(ive)?;
+                            let val = match high {
+                                0xD800..=0xDBFF => {
+                                    if !matches!(
+                                        (chars.next(), chars.next()),
+                                        (Some((_, '\\')), Some((_, 'u')))
+                                    ) {
                                         return Err(ive());
                                     }
-                                    ct += 1;
-                                    val <<= 4;
-                                    val |= chars
-                                        .next()
-                                        .and_then(|(_, c)| c.to_digit(16))
-                                        .ok_or_else(ive)?;
-                                }
-                                if ct == 0 {
-                                    return Err(ive());
+                                    match hex4(&mut chars).ok_or_else(ive)? {
+                                        low @ 0xDC00..=0xDFFF => {
+                                            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                                        }
+                                        _ => return Err(ive()),
+                                    }
                                 }
-                                out.push(char::from_u32(val).ok_or_else(ive)?);
-                            }
-                            _ => return Err(ive()),
+                                _ => high,
+                            };
+                            out.push(char::from_u32(val).ok_or_else(ive)?);
                         }
+                        _ => return Err(ive()),
                     }
-                    '"' => {
-                        break;
-                    }
-                    _ => out.push(c),
                 }
+                c if c == quote => {
+                    break;
+                }
+                _ => out.push(c),
             }
-            Ok(out)
-        } else {
-            Err(ParseError::new(next, ParseErrorKind::ExpectedString))
         }
+        Ok(())
     }
 
     pub fn try_parse_string(&mut self) -> Option<Result<String, ParseError>> {
-        if let TokenKind::String { .. } = self.peek_token().token.kind {
-            Some(self.parse_string())
-        } else {
-            None
+        Some(self.try_parse_str()?.map(Cow::into_owned))
+    }
+
+    pub fn try_parse_str(&mut self) -> Option<Result<Cow<'a, str>, ParseError>> {
+        match self.peek_kind() {
+            TokenKind::String { .. } => Some(self.parse_str()),
+            TokenKind::Ident if self.bare_strings => Some(self.parse_str()),
+            _ => None,
         }
     }
 
@@ -238,15 +852,23 @@ impl<'a> Parser<'a> {
     where
         T: TryFrom<u64>,
     {
-        let next = self.next_token();
+        if let Some(r) =
+            self.parse_numeric_string(ParseErrorKind::InvalidInteger, |p| p.parse_uint())
+        {
+            return r;
+        }
+        if let Some(r) = self.parse_integral_float(0.0..u64::MAX as f64) {
+            let (v, next) = r?;
+            return T::try_from(v as u64)
+                .map_err(|_| ParseError::new(next, ParseErrorKind::InvalidInteger));
+        }
+        let next = self.next_checked()?;
         if let TokenKind::Integer { base, sign: false } = next.token.kind {
             let start = next.token.start + base.digit_offset();
             let end = next.token.end;
             let src = &self.cursor.src()[start..end];
-            let val = match u64::from_str_radix(src, base.radix()) {
-                Ok(v) => v,
-                Err(_) => return Err(ParseError::new(next, ParseErrorKind::InvalidInteger)),
-            };
+            let val = parse_radix_u64(src, base.radix())
+                .map_err(|e| self.radix_error(next, start, base, e))?;
 
             T::try_from(val).map_err(|_| ParseError::new(next, ParseErrorKind::InvalidInteger))
         } else {
@@ -261,16 +883,24 @@ impl<'a> Parser<'a> {
     where
         T: TryFrom<i64>,
     {
-        let next = self.next_token();
+        if let Some(r) =
+            self.parse_numeric_string(ParseErrorKind::InvalidInteger, |p| p.parse_int())
+        {
+            return r;
+        }
+        if let Some(r) = self.parse_integral_float(i64::MIN as f64..-(i64::MIN as f64)) {
+            let (v, next) = r?;
+            return T::try_from(v as i64)
+                .map_err(|_| ParseError::new(next, ParseErrorKind::InvalidInteger));
+        }
+        let next = self.next_checked()?;
         if let TokenKind::Integer { base, sign } = next.token.kind {
             let start = next.token.start + base.digit_offset() + sign as usize;
             let end = next.token.end;
             let src = &self.cursor.src()[start..end];
 
-            let val = match u64::from_str_radix(src, base.radix()) {
-                Ok(v) => v,
-                Err(_) => return Err(ParseError::new(next, ParseErrorKind::InvalidInteger)),
-            };
+            let val = parse_radix_u64(src, base.radix())
+                .map_err(|e| self.radix_error(next, start, base, e))?;
 
             let int = if sign {
                 let val = val.wrapping_neg() as i64;
@@ -296,57 +926,256 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Like [`parse_uint`](Self::parse_uint), but for integers that do not fit in a `u64`.
+    pub fn parse_u128(&mut self) -> Result<u128, ParseError> {
+        if let Some(r) =
+            self.parse_numeric_string(ParseErrorKind::InvalidInteger, |p| p.parse_u128())
+        {
+            return r;
+        }
+        if let Some(r) = self.parse_integral_float(0.0..u128::MAX as f64) {
+            let (v, _) = r?;
+            return Ok(v as u128);
+        }
+        let next = self.next_checked()?;
+        if let TokenKind::Integer { base, sign: false } = next.token.kind {
+            let start = next.token.start + base.digit_offset();
+            let end = next.token.end;
+            let src = &self.cursor.src()[start..end];
+            parse_radix_u128(src, base.radix()).map_err(|e| self.radix_error(next, start, base, e))
+        } else {
+            Err(ParseError::new(
+                next,
+                ParseErrorKind::ExpectedInteger { signed: false },
+            ))
+        }
+    }
+
+    /// Like [`parse_int`](Self::parse_int), but for integers that do not fit in an `i64`.
+    pub fn parse_i128(&mut self) -> Result<i128, ParseError> {
+        if let Some(r) =
+            self.parse_numeric_string(ParseErrorKind::InvalidInteger, |p| p.parse_i128())
+        {
+            return r;
+        }
+        if let Some(r) = self.parse_integral_float(i128::MIN as f64..-(i128::MIN as f64)) {
+            let (v, _) = r?;
+            return Ok(v as i128);
+        }
+        let next = self.next_checked()?;
+        if let TokenKind::Integer { base, sign } = next.token.kind {
+            let start = next.token.start + base.digit_offset() + sign as usize;
+            let end = next.token.end;
+            let src = &self.cursor.src()[start..end];
+
+            let val = parse_radix_u128(src, base.radix())
+                .map_err(|e| self.radix_error(next, start, base, e))?;
+
+            if sign {
+                let val = val.wrapping_neg() as i128;
+                // same as in `parse_int`, a positive result means the value underflowed
+                if val > 0 {
+                    Err(ParseError::new(next, ParseErrorKind::InvalidInteger))
+                } else {
+                    Ok(val)
+                }
+            } else {
+                i128::try_from(val)
+                    .map_err(|_| ParseError::new(next, ParseErrorKind::InvalidInteger))
+            }
+        } else {
+            Err(ParseError::new(
+                next,
+                ParseErrorKind::ExpectedInteger { signed: true },
+            ))
+        }
+    }
+
+    /// Turns a failure to parse the digits of an integer token from `start` on into an
+    /// error, pointing at the offending character if a digit is not valid for `base`.
+    fn radix_error(&self, next: SpanToken, start: usize, base: Base, e: RadixError) -> ParseError {
+        match e {
+            RadixError::Digit(i) => {
+                let digit = self.cursor.src()[start + i..]
+                    .chars()
+                    .next()
+                    .expect("digit should be in the token");
+                let src = self.cursor.src();
+                let offset = self
+                    .column_encoding
+                    .width(&src[next.token.start..start + i]);
+                let width = self
+                    .column_encoding
+                    .width(&src[start + i..][..digit.len_utf8()]);
+                let token = SpanToken {
+                    token: Token {
+                        kind: next.token.kind,
+                        start: start + i,
+                        end: start + i + digit.len_utf8(),
+                    },
+                    line: next.line,
+                    col: next.col + offset,
+                    end_line: next.line,
+                    end_col: next.col + offset + width,
+                };
+                ParseError::new(token, ParseErrorKind::InvalidDigit { digit, base })
+            }
+            RadixError::Invalid => ParseError::new(next, ParseErrorKind::InvalidInteger),
+        }
+    }
+
+    /// Parses a float, or an integer that the float type can represent exactly.
     pub fn parse_float<T>(&mut self) -> Result<T, ParseError>
     where
-        T: FromStr,
+        T: Float,
     {
-        let next = self.next_token();
+        if let Some(r) =
+            self.parse_numeric_string(ParseErrorKind::InvalidFloat, |p| p.parse_float())
+        {
+            return r;
+        }
+        if let TokenKind::Integer { sign, .. } = self.peek_kind() {
+            let next = self.peek_token();
+            let float = if sign {
+                T::from_i128_exact(self.parse_i128()?)
+            } else {
+                T::from_u128_exact(self.parse_u128()?)
+            };
+            return float.ok_or_else(|| ParseError::new(next, ParseErrorKind::InexactFloat));
+        }
+        let next = self.next_checked()?;
         if let TokenKind::Float = next.token.kind {
             let start = next.token.start;
             let end = next.token.end;
             let src = &self.cursor.src()[start..end];
-            src.parse()
-                .map_err(|_| ParseError::new(next, ParseErrorKind::InvalidFloat))
+            T::parse_src(src).ok_or_else(|| ParseError::new(next, ParseErrorKind::InvalidFloat))
         } else {
             Err(ParseError::new(next, ParseErrorKind::ExpectedFloat))
         }
     }
 
-    pub fn try_parse_uint(&mut self) -> Option<Result<u64, ParseError>> {
-        if let TokenKind::Integer { sign: false, .. } = self.peek_token().token.kind {
-            Some(self.parse_uint())
+    pub fn parse_duration(&mut self) -> Result<Duration, ParseError> {
+        let next = self.next_checked()?;
+        if let TokenKind::Duration = next.token.kind {
+            parse_duration_src(self.src(next.token)).map_err(|kind| ParseError::new(next, kind))
         } else {
-            None
+            Err(ParseError::new(next, ParseErrorKind::ExpectedDuration))
         }
     }
 
-    pub fn try_parse_int(&mut self) -> Option<Result<i64, ParseError>> {
-        if let TokenKind::Integer { .. } = self.peek_token().token.kind {
-            Some(self.parse_int())
+    pub fn try_parse_duration(&mut self) -> Option<Result<Duration, ParseError>> {
+        if let TokenKind::Duration = self.peek_kind() {
+            Some(self.parse_duration())
         } else {
             None
         }
     }
 
-    pub fn try_parse_float(&mut self) -> Option<Result<f64, ParseError>> {
-        if let TokenKind::Float = self.peek_token().token.kind {
-            Some(self.parse_float())
-        } else {
-            None
+    /// If [`numeric_strings`](Self::numeric_strings) is set and the next token is a
+    /// string, parses its contents as a whole with `parse`. An error points at the
+    /// string with the `invalid` kind.
+    fn parse_numeric_string<T>(
+        &mut self,
+        invalid: ParseErrorKind,
+        parse: impl FnOnce(&mut Parser<'_>) -> Result<T, ParseError>,
+    ) -> Option<Result<T, ParseError>> {
+        if !self.numeric_strings {
+            return None;
+        }
+        let next = self.peek_token();
+        if !matches!(next.token.kind, TokenKind::String { .. }) {
+            return None;
         }
+        Some(self.parse_str_scratch().and_then(|src| {
+            let mut inner = Parser::new(src.trim());
+            parse(&mut inner)
+                .and_then(|v| inner.end_of_input().map(|()| v))
+                .map_err(|_| ParseError::new(next, invalid))
+        }))
     }
 
-    pub fn start_map(&mut self) -> Result<(), ParseError> {
-        let next = self.next_token();
-        if let TokenKind::StartCurly = next.token.kind {
-            Ok(())
+    /// If [`integral_floats`](Self::integral_floats) is set and the next token is a
+    /// float, parses it and checks that it is a whole number in `range`.
+    fn parse_integral_float(
+        &mut self,
+        range: Range<f64>,
+    ) -> Option<Result<(f64, SpanToken), ParseError>> {
+        if !self.integral_floats || self.peek_kind() != TokenKind::Float {
+            return None;
+        }
+        let next = self.peek_token();
+        Some(self.parse_float::<f64>().and_then(|v| {
+            // `% 1.0` is the fractional part, `f64::fract` needs std
+            if v % 1.0 != 0.0 {
+                Err(ParseError::new(next, ParseErrorKind::FractionalInteger))
+            } else if !range.contains(&v) {
+                Err(ParseError::new(next, ParseErrorKind::InvalidInteger))
+            } else {
+                Ok((v, next))
+            }
+        }))
+    }
+
+    /// Parses a string holding bytes in the parser's [`BytesEncoding`].
+    pub fn parse_bytes(&mut self) -> Result<Vec<u8>, ParseError> {
+        let next = self.peek_token();
+        let encoding = self.bytes_encoding;
+        let src = self.parse_str_scratch()?;
+        encoding
+            .decode(&src)
+            .ok_or_else(|| ParseError::new(next, ParseErrorKind::InvalidBytes(encoding)))
+    }
+
+    pub fn try_parse_uint(&mut self) -> Option<Result<u64, ParseError>> {
+        if let TokenKind::Integer { sign: false, .. } = self.peek_kind() {
+            Some(self.parse_uint())
+        } else {
+            None
+        }
+    }
+
+    pub fn try_parse_int(&mut self) -> Option<Result<i64, ParseError>> {
+        if let TokenKind::Integer { .. } = self.peek_kind() {
+            Some(self.parse_int())
+        } else {
+            None
+        }
+    }
+
+    pub fn try_parse_float(&mut self) -> Option<Result<f64, ParseError>> {
+        if let TokenKind::Float = self.peek_kind() {
+            Some(self.parse_float())
+        } else {
+            None
+        }
+    }
+
+    /// Opens a map or list started by `token`, checking the depth limit.
+    fn enter(&mut self, token: SpanToken) -> Result<(), ParseError> {
+        self.depth += 1;
+        match self.max_depth {
+            Some(max) if self.depth > max => {
+                Err(ParseError::new(token, ParseErrorKind::TooDeep { max }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn exit(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    pub fn start_map(&mut self) -> Result<(), ParseError> {
+        let next = self.next_checked()?;
+        if let TokenKind::StartCurly = next.token.kind {
+            self.enter(next)
         } else {
             Err(ParseError::new(next, ParseErrorKind::ExpectedMapStart))
         }
     }
 
     pub fn map_delimiter(&mut self) -> Result<(), ParseError> {
-        let next = self.next_token();
+        let next = self.next_checked()?;
         if let TokenKind::Equal = next.token.kind {
             Ok(())
         } else {
@@ -355,25 +1184,25 @@ impl<'a> Parser<'a> {
     }
 
     pub fn end_map(&mut self) -> Result<(), ParseError> {
-        let next = self.next_token();
+        let next = self.next_checked()?;
         if let TokenKind::EndCurly = next.token.kind {
+            self.exit();
             Ok(())
         } else {
             Err(ParseError::new(next, ParseErrorKind::ExpectedMapEnd))
         }
     }
 
-    pub fn try_start_map(&mut self) -> Option<()> {
-        if let TokenKind::StartCurly = self.peek_token().token.kind {
-            self.next_token();
-            Some(())
+    pub fn try_start_map(&mut self) -> Option<Result<(), ParseError>> {
+        if let TokenKind::StartCurly = self.peek_kind() {
+            Some(self.start_map())
         } else {
             None
         }
     }
 
     pub fn try_map_delimiter(&mut self) -> Option<()> {
-        if let TokenKind::Equal = self.peek_token().token.kind {
+        if let TokenKind::Equal = self.peek_kind() {
             self.next_token();
             Some(())
         } else {
@@ -381,127 +1210,1148 @@ impl<'a> Parser<'a> {
         }
     }
 
-    // pub fn try_end_map(&mut self) -> Option<()> {
-    //     if let TokenKind::EndCurly = self.peek_token().token.kind {
-    //         self.next_token();
-    //         Some(())
-    //     } else {
-    //         None
-    //     }
-    // }
-    
-    pub fn peek_end_map(&mut self) -> bool {
-        TokenKind::EndCurly == self.peek_token().token.kind
+    // pub fn try_end_map(&mut self) -> Option<()> {
+    //     if let TokenKind::EndCurly = self.peek_token().token.kind {
+    //         self.next_token();
+    //         Some(())
+    //     } else {
+    //         None
+    //     }
+    // }
+    
+    pub fn peek_end_map(&mut self) -> bool {
+        TokenKind::EndCurly == self.peek_kind()
+    }
+
+    pub fn parse_path(&mut self) -> Result<MapPath, ParseError> {
+        self.parse_path_ref().map(MapPath::from)
+    }
+
+    /// Like [`parse_path`](Self::parse_path), but borrows the segments from the source.
+    pub fn parse_path_ref(&mut self) -> Result<MapPathRef<'a>, ParseError> {
+        let next = self.next_checked()?;
+        self.path_from(next)
+    }
+
+    /// Reads the rest of a path that starts with `next`, which was already read.
+    fn path_from(&mut self, next: SpanToken) -> Result<MapPathRef<'a>, ParseError> {
+        let mut segments = SmallVec::new();
+        segments.push((self.key_segment(next)?, next));
+        while let TokenKind::Dot = self.peek_no_skip().token.kind {
+            self.next_no_skip();
+            let next_segment = self.next_no_skip();
+            segments.push((self.key_segment(next_segment)?, next_segment));
+        }
+        Ok(MapPathRef { segments })
+    }
+
+    /// The text of one segment of a path, which is an identifier or a quoted string
+    /// like `"8080"`. Quoted segments are borrowed, so they can not have escapes.
+    pub(crate) fn key_segment(&self, token: SpanToken) -> Result<&'a str, ParseError> {
+        match token.token.kind {
+            TokenKind::Ident => Ok(self.src(token.token)),
+            TokenKind::String {
+                terminated: false, ..
+            } => Err(ParseError::new(token, ParseErrorKind::StringUnterminated)),
+            TokenKind::String { escaped: true, .. } => {
+                Err(ParseError::new(token, ParseErrorKind::EscapedKey))
+            }
+            TokenKind::String { .. } => {
+                let quoted = self.src(token.token);
+                Ok(&quoted[1..quoted.len() - 1])
+            }
+            _ => Err(ParseError::new(token, ParseErrorKind::ExpectedIdent)),
+        }
+    }
+
+    pub fn try_parse_path(&mut self) -> Option<Result<MapPath, ParseError>> {
+        if starts_key(self.peek_kind()) {
+            Some(self.parse_path())
+        } else {
+            None
+        }
+    }
+
+    pub fn start_list(&mut self) -> Result<(), ParseError> {
+        let next = self.next_checked()?;
+        if let TokenKind::StartSquare = next.token.kind {
+            self.enter(next)
+        } else {
+            Err(ParseError::new(next, ParseErrorKind::ExpectedListStart))
+        }
+    }
+
+    pub fn end_list(&mut self) -> Result<(), ParseError> {
+        let next = self.next_checked()?;
+        if let TokenKind::EndSquare = next.token.kind {
+            self.exit();
+            Ok(())
+        } else {
+            Err(ParseError::new(next, ParseErrorKind::ExpectedListEnd))
+        }
+    }
+
+    pub fn try_start_list(&mut self) -> Option<Result<(), ParseError>> {
+        if let TokenKind::StartSquare = self.peek_kind() {
+            Some(self.start_list())
+        } else {
+            None
+        }
+    }
+
+    // pub fn try_end_list(&mut self) -> Option<()> {
+    //     if let TokenKind::EndSquare = self.peek_token().token.kind {
+    //         self.next_token();
+    //         Some(())
+    //     } else {
+    //         None
+    //     }
+    // }
+    
+    pub fn peek_end_list(&mut self) -> bool {
+        TokenKind::EndSquare == self.peek_kind()
+    }
+
+    /// Consumes a whole value, scalar or container, without unescaping strings or
+    /// converting numbers. Containers are checked for the same `key = value` grammar
+    /// as when reading them, but nothing in them is kept.
+    ///
+    /// An identifier is a value as well, since it can name a unit enum variant.
+    pub fn skip_value(&mut self) -> Result<(), ParseError> {
+        self.skip_value_with(|_, _| ())
+    }
+
+    /// Like [`skip_value`](Self::skip_value), calling `skipped` with the opening token
+    /// of every map or list in the value that is the value of a key, and the position
+    /// just past its end, for going past it again with [`skip_to`](Self::skip_to).
+    pub(crate) fn skip_value_with(
+        &mut self,
+        mut skipped: impl FnMut(SpanToken, Checkpoint<'a>),
+    ) -> Result<(), ParseError> {
+        // the open containers, innermost last, kept on the heap so deeply nested
+        // values can not overflow the stack
+        let mut containers = Vec::new();
+        self.skip_in(&mut containers, &mut skipped)
+            .map_err(|mut err| {
+                // the same context as when the containers are read
+                if err.context.is_empty() {
+                    err.context = self.context.iter().map(ContextFrame::to_static).collect();
+                    for skipping in containers.iter().filter(|s| s.in_value) {
+                        match skipping.key {
+                            Some(key) => {
+                                let path = Parser::new(self.src(key)).parse_path_ref();
+                                err.context.extend(path.into_iter().flat_map(|path| {
+                                    path.segments.into_iter().map(|(segment, _)| {
+                                        ContextFrame::Key(Cow::Owned(segment.into()))
+                                    })
+                                }));
+                            }
+                            None => err.context.push(ContextFrame::Element(skipping.count - 1)),
+                        }
+                    }
+                }
+                err
+            })
+    }
+
+    fn skip_in(
+        &mut self,
+        containers: &mut Vec<Skipping>,
+        skipped: &mut impl FnMut(SpanToken, Checkpoint<'a>),
+    ) -> Result<(), ParseError> {
+        // the line of the `=` when the next value is the value of a key
+        let mut key_line = None;
+        let mut next = self.next_skipped()?;
+        loop {
+            // `next` starts a value
+            match next.token.kind {
+                TokenKind::StartCurly | TokenKind::StartSquare => containers.push(Skipping {
+                    closer: if next.token.kind == TokenKind::StartCurly {
+                        TokenKind::EndCurly
+                    } else {
+                        TokenKind::EndSquare
+                    },
+                    open: key_line.map(|_| next),
+                    count: 0,
+                    key: None,
+                    in_value: false,
+                }),
+                // the value is missing and this is the key of the next entry
+                TokenKind::Ident
+                    if key_line.is_some_and(|line| next.line > line)
+                        && matches!(self.peek_kind(), TokenKind::Equal | TokenKind::Dot) =>
+                {
+                    return Err(ParseError::new(next, ParseErrorKind::ExpectedValue));
+                }
+                TokenKind::Keyword(_)
+                | TokenKind::Integer { .. }
+                | TokenKind::Float
+                | TokenKind::Duration
+                | TokenKind::String { .. }
+                | TokenKind::Char { .. }
+                | TokenKind::Ident => (),
+                _ => return Err(ParseError::new(next, ParseErrorKind::ExpectedValue)),
+            }
+
+            // close every container that ends here, up to the start of the next value
+            loop {
+                let Some(top) = containers.last_mut() else {
+                    return Ok(());
+                };
+                top.in_value = false;
+                next = self.next_checked()?;
+                if next.token.kind == top.closer {
+                    if let Some(Skipping {
+                        open: Some(open), ..
+                    }) = containers.pop()
+                    {
+                        skipped(open, self.checkpoint());
+                    }
+                    continue;
+                }
+                let is_map = top.closer == TokenKind::EndCurly;
+                if matches!(
+                    next.token.kind,
+                    TokenKind::EndSquare | TokenKind::EndCurly | TokenKind::Eof
+                ) {
+                    // an unclosed container or a mismatched closer
+                    let expected = if is_map {
+                        ExpectedSet::IDENT | ExpectedSet::MAP_END
+                    } else {
+                        ExpectedSet::VALUE | ExpectedSet::LIST_END
+                    };
+                    return Err(ParseError::new(next, ParseErrorKind::Expected(expected)));
+                }
+                top.count += 1;
+                self.check_elements(top.count, next)?;
+                if !is_map {
+                    top.in_value = true;
+                    key_line = None;
+                    next = check_skipped(next)?;
+                    break;
+                }
+                if !starts_key(next.token.kind) {
+                    let expected = ExpectedSet::IDENT | ExpectedSet::MAP_END;
+                    return Err(ParseError::new(next, ParseErrorKind::Expected(expected)));
+                }
+                let path = self.path_from(next)?;
+                let delimiter = self.next_checked()?;
+                if delimiter.token.kind != TokenKind::Equal {
+                    return Err(ParseError::new(delimiter, ParseErrorKind::ExpectedEqual));
+                }
+                let last = path.segments[path.segments.len() - 1].1;
+                top.key = Some(Token {
+                    end: last.token.end,
+                    ..next.token
+                });
+                top.in_value = true;
+                key_line = Some(delimiter.line);
+                next = self.next_skipped()?;
+                break;
+            }
+        }
+    }
+
+    /// Goes past a value that was skipped before, to the position just after it that
+    /// [`skip_value_with`](Self::skip_value_with) gave. Unlike [`restore`](Self::restore),
+    /// the depth, token count, context and comments are left as they are.
+    pub(crate) fn skip_to(&mut self, end: &Checkpoint<'a>) {
+        self.cursor = end.cursor.clone();
+        self.tokenized_pos = end.tokenized_pos;
+        self.line = end.line;
+        self.col = end.col;
+        self.lookahead = end.lookahead;
+    }
+
+    /// The next token for [`skip_value`](Self::skip_value), which has to be one that
+    /// could be read when not skipping.
+    fn next_skipped(&mut self) -> Result<SpanToken, ParseError> {
+        let next = self.next_checked()?;
+        check_skipped(next)
+    }
+}
+
+// error recovering syntax checks
+impl<'a> Parser<'a> {
+    /// Checks the syntax of a top level document, a list of entries without braces.
+    ///
+    /// Instead of stopping at the first error, the parser skips ahead to the next
+    /// entry, so every error in the document is returned.
+    pub fn check_top_level(&mut self) -> Vec<ParseError> {
+        let mut errors = Vec::new();
+        if let Err(e) = self.check_input_len() {
+            return vec![e];
+        }
+        self.check_entries(TokenKind::Eof, &mut errors);
+        errors
+    }
+
+    /// Checks the syntax of a single value, like [`check_top_level`](Self::check_top_level).
+    pub fn check_value(&mut self) -> Vec<ParseError> {
+        let mut errors = Vec::new();
+        if let Err(e) = self.check_input_len() {
+            return vec![e];
+        }
+        let line = self.peek_token().line;
+        self.check_value_into(line, &mut errors);
+        errors
+    }
+
+    fn check_entries(&mut self, closer: TokenKind, errors: &mut Vec<ParseError>) {
+        for count in 1.. {
+            let next = self.peek_token();
+            if next.token.kind == closer || next.token.kind == TokenKind::Eof {
+                return;
+            }
+            if let Err(e) = self.check_elements(count, next) {
+                self.report(errors, e);
+                self.skip_rest(closer);
+                return;
+            }
+            if !starts_key(next.token.kind) {
+                self.next_token();
+                let expected = ExpectedSet::IDENT | ExpectedSet::closing(closer);
+                self.report(
+                    errors,
+                    ParseError::new(next, ParseErrorKind::Expected(expected)),
+                );
+                self.recover(closer, next.line);
+                continue;
+            }
+            let path = match self.parse_path_ref() {
+                Ok(path) => path,
+                Err(e) => {
+                    let line = e.token.line;
+                    self.report(errors, e);
+                    self.recover(closer, line);
+                    continue;
+                }
+            };
+            let delimiter = self.peek_token();
+            if let Err(e) = self.map_delimiter() {
+                let line = e.token.line;
+                self.report(errors, e);
+                self.recover(closer, line);
+                continue;
+            }
+            for &(segment, _) in &path.segments {
+                self.push_context(ContextFrame::Key(Cow::Borrowed(segment)));
+            }
+            self.check_value_into(delimiter.line, errors);
+            self.context
+                .truncate(self.context.len() - path.segments.len());
+        }
+    }
+
+    /// Checks a value that should start on `line` or after it.
+    fn check_value_into(&mut self, line: u32, errors: &mut Vec<ParseError>) {
+        let next = self.peek_token();
+        let res = match next.token.kind {
+            TokenKind::Keyword(_) => {
+                self.next_token();
+                Ok(())
+            }
+            TokenKind::Integer { sign: false, .. } => self.parse_u128().map(drop),
+            TokenKind::Integer { sign: true, .. } => self.parse_i128().map(drop),
+            TokenKind::Float => self.parse_float::<f64>().map(drop),
+            TokenKind::Duration => self.parse_duration().map(drop),
+            TokenKind::String { .. } => self.parse_str_scratch().map(drop),
+            TokenKind::Char { .. } => self.parse_char().map(drop),
+            TokenKind::StartSquare => return self.check_container(TokenKind::EndSquare, errors),
+            TokenKind::StartCurly => return self.check_container(TokenKind::EndCurly, errors),
+            TokenKind::EndSquare | TokenKind::EndCurly | TokenKind::Eof => {
+                // the value is missing, leave the closer to its container
+                Err(ParseError::new(next, ParseErrorKind::ExpectedValue))
+            }
+            TokenKind::Ident if self.value_missing(line) => {
+                // leave the key of the next entry to be checked
+                Err(ParseError::new(next, ParseErrorKind::ExpectedValue))
+            }
+            TokenKind::Ident => {
+                // a unit enum variant, or a string with `bare_strings`
+                self.next_token();
+                Ok(())
+            }
+            _ => {
+                self.next_token();
+                Err(ParseError::new(next, ParseErrorKind::ExpectedValue))
+            }
+        };
+        if let Err(e) = res {
+            self.report(errors, e);
+        }
+    }
+
+    fn check_container(&mut self, closer: TokenKind, errors: &mut Vec<ParseError>) {
+        let start = if closer == TokenKind::EndCurly {
+            self.start_map()
+        } else {
+            self.start_list()
+        };
+        if let Err(e) = start {
+            // too deep, skip the whole container without descending into it
+            self.report(errors, e);
+            self.exit();
+            self.skip_balanced(1);
+            return;
+        }
+
+        if closer == TokenKind::EndCurly {
+            self.check_entries(closer, errors);
+        } else {
+            for index in 0.. {
+                let next = self.peek_token();
+                if matches!(
+                    next.token.kind,
+                    TokenKind::EndSquare | TokenKind::EndCurly | TokenKind::Eof
+                ) {
+                    break;
+                }
+                if let Err(e) = self.check_elements(index + 1, next) {
+                    self.report(errors, e);
+                    self.skip_rest(closer);
+                    break;
+                }
+                self.push_context(ContextFrame::Element(index));
+                self.check_value_into(next.line, errors);
+                self.pop_context();
+            }
+        }
+
+        if self.peek_kind() == closer {
+            self.next_token();
+        } else {
+            // an unclosed container or a mismatched closer that belongs to a parent
+            let expected = if closer == TokenKind::EndCurly {
+                ExpectedSet::IDENT | ExpectedSet::MAP_END
+            } else {
+                ExpectedSet::VALUE | ExpectedSet::LIST_END
+            };
+            let e = self.error(ParseErrorKind::Expected(expected));
+            self.report(errors, e);
+        }
+        self.exit();
+    }
+
+    fn check_input_len(&mut self) -> Result<(), ParseError> {
+        match self.limits.max_input_len {
+            Some(max) if self.cursor.src().len() > max => {
+                Err(self.error(ParseErrorKind::LimitExceeded(Limit::InputLen(max))))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn report(&self, errors: &mut Vec<ParseError>, mut err: ParseError) {
+        // every token after the limit is over it, so it is only reported the first time
+        if matches!(err.kind, ParseErrorKind::LimitExceeded(Limit::Tokens(_)))
+            && errors.iter().any(|e| e.kind == err.kind)
+        {
+            return;
+        }
+        self.add_context(&mut err);
+        errors.push(err);
+    }
+
+    /// Skips tokens until the next plausible entry boundary after an error on `line`:
+    /// an identifier on a later line, the `closer` of the current container, or the end.
+    fn recover(&mut self, closer: TokenKind, line: u32) {
+        loop {
+            let next = self.peek_token();
+            match next.token.kind {
+                TokenKind::Eof => return,
+                kind if kind == closer => return,
+                TokenKind::Ident if next.line > line => return,
+                TokenKind::StartCurly | TokenKind::StartSquare => {
+                    self.next_token();
+                    self.skip_balanced(1);
+                }
+                _ => {
+                    // including stray closers
+                    self.next_token();
+                }
+            }
+        }
+    }
+
+    /// Skips the rest of the current container, up to its `closer`.
+    fn skip_rest(&mut self, closer: TokenKind) {
+        loop {
+            match self.peek_kind() {
+                TokenKind::Eof => return,
+                kind if kind == closer => return,
+                TokenKind::StartCurly | TokenKind::StartSquare => {
+                    self.next_token();
+                    self.skip_balanced(1);
+                }
+                _ => {
+                    self.next_token();
+                }
+            }
+        }
+    }
+
+    /// Skips tokens until `depth` more containers have been closed.
+    fn skip_balanced(&mut self, mut depth: u32) {
+        while depth > 0 {
+            match self.next_token().token.kind {
+                TokenKind::StartCurly | TokenKind::StartSquare => depth += 1,
+                TokenKind::EndCurly | TokenKind::EndSquare => depth -= 1,
+                TokenKind::Eof => return,
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Why the digits of an integer token could not be parsed.
+enum RadixError {
+    // the byte offset of the first character that is not a digit in the radix
+    Digit(usize),
+    // there are no digits, or the value does not fit
+    Invalid,
+}
+
+macro_rules! impl_parse_radix {
+    ($($name:ident: $ty:ty),*) => {$(
+        /// Parses the digits of an integer token in one pass, skipping `_` separators.
+        /// A character that is not a digit is reported even after an overflow.
+        fn $name(digits: &str, radix: u32) -> Result<$ty, RadixError> {
+            let mut val: $ty = 0;
+            let mut empty = true;
+            let mut overflow = false;
+            for (i, b) in digits.bytes().enumerate() {
+                let digit = match b {
+                    b'_' => continue,
+                    b'0'..=b'9' => b - b'0',
+                    b'a'..=b'z' => b - b'a' + 10,
+                    b'A'..=b'Z' => b - b'A' + 10,
+                    _ => u8::MAX,
+                };
+                if u32::from(digit) >= radix {
+                    return Err(RadixError::Digit(i));
+                }
+                empty = false;
+                match val.checked_mul(radix as $ty).and_then(|v| v.checked_add(digit as $ty)) {
+                    Some(v) => val = v,
+                    None => overflow = true,
+                }
+            }
+            if empty || overflow {
+                return Err(RadixError::Invalid);
+            }
+            Ok(val)
+        }
+    )*};
+}
+
+impl_parse_radix!(parse_radix_u64: u64, parse_radix_u128: u128);
+
+/// Reads the four hex digits of a `\uXXXX` escape.
+fn hex4(chars: &mut impl Iterator<Item = (usize, char)>) -> Option<u32> {
+    let mut val = 0;
+    for _ in 0..4 {
+        val <<= 4;
+        val |= chars.next()?.1.to_digit(16)?;
+    }
+    Some(val)
+}
+
+/// Duration units from largest to smallest, with their length in nanoseconds.
+const DURATION_UNITS: [(&str, u128); 7] = [
+    ("d", 24 * 60 * 60 * 1_000_000_000),
+    ("h", 60 * 60 * 1_000_000_000),
+    ("m", 60 * 1_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("ns", 1),
+];
+
+/// Parses the source of a duration token, such as `250ms`, `2h30m` or `1.5s`.
+///
+/// Fractions are truncated to whole nanoseconds. Units have to go from largest to
+/// smallest, each at most once, and the total has to fit in a `Duration`.
+fn parse_duration_src(src: &str) -> Result<Duration, ParseErrorKind> {
+    let digits = |s: &str| {
+        s.find(|c: char| !matches!(c, '0'..='9' | '_'))
+            .unwrap_or(s.len())
+    };
+
+    let mut total: u128 = 0;
+    let mut next_unit = 0;
+    let mut rest = src;
+    while !rest.is_empty() {
+        let int_end = digits(rest);
+        let (frac, after) = match rest[int_end..].strip_prefix('.') {
+            Some(after) => {
+                let (frac, after) = after.split_at(digits(after));
+                (Some(frac), after)
+            }
+            None => (None, &rest[int_end..]),
+        };
+        if int_end == 0 || frac == Some("") {
+            return Err(ParseErrorKind::InvalidDuration);
+        }
+        let unit_end = after
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(after.len());
+
+        let unit = &after[..unit_end];
+        let index = DURATION_UNITS
+            .iter()
+            .position(|&(name, _)| name == unit)
+            .ok_or(ParseErrorKind::InvalidDuration)?;
+        if index < next_unit {
+            return Err(ParseErrorKind::UnorderedDuration);
+        }
+        next_unit = index + 1;
+        let nanos = DURATION_UNITS[index].1;
+
+        let mut val: u128 = 0;
+        for c in rest[..int_end].chars().filter(|&c| c != '_') {
+            val = val
+                .checked_mul(10)
+                .and_then(|v| v.checked_add(c.to_digit(10)? as u128))
+                .ok_or(ParseErrorKind::InvalidDuration)?;
+        }
+        let mut part = val
+            .checked_mul(nanos)
+            .ok_or(ParseErrorKind::InvalidDuration)?;
+        // digits past the 18th are always below a nanosecond
+        let (mut numer, mut denom) = (0u128, 1u128);
+        for c in frac
+            .unwrap_or_default()
+            .chars()
+            .filter(|&c| c != '_')
+            .take(18)
+        {
+            numer = numer * 10 + c.to_digit(10).unwrap_or(0) as u128;
+            denom *= 10;
+        }
+        part += numer * nanos / denom;
+        total = total
+            .checked_add(part)
+            .ok_or(ParseErrorKind::InvalidDuration)?;
+        rest = &after[unit_end..];
+    }
+
+    let secs = u64::try_from(total / 1_000_000_000).map_err(|_| ParseErrorKind::InvalidDuration)?;
+    Ok(Duration::new(secs, (total % 1_000_000_000) as u32))
+}
+
+fn decode_hex(src: &str) -> Option<Vec<u8>> {
+    if !src.len().is_multiple_of(2) {
+        return None;
+    }
+    src.as_bytes()
+        .chunks_exact(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high << 4 | low) as u8)
+        })
+        .collect()
+}
+
+fn decode_base64(src: &str) -> Option<Vec<u8>> {
+    let sextet = |b: u8| -> Option<u32> {
+        Some(match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        } as u32)
+    };
+
+    let data = src.trim_end_matches('=');
+    // at most 2 padding characters, and only to fill out the last group of 4
+    if src.len() - data.len() > 2 || (src.len() != data.len() && !src.len().is_multiple_of(4)) {
+        return None;
+    }
+    // a single leftover character does not hold a whole byte
+    if data.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    for group in data.as_bytes().chunks(4) {
+        let mut bits = 0;
+        for &b in group {
+            bits = bits << 6 | sextet(b)?;
+        }
+        // left align a short last group as if it were padded
+        bits <<= 6 * (4 - group.len());
+        let bytes = bits.to_be_bytes();
+        out.extend_from_slice(&bytes[1..group.len()]);
+    }
+    Some(out)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapPath {
+    pub key: String,
+    pub path: Vec<String>,
+    /// The span of every segment, starting with the key.
+    pub spans: Vec<SpanToken>,
+}
+
+/// A [`MapPath`] that borrows its segments from the source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapPathRef<'a> {
+    /// Every segment along with its span, starting with the key.
+    pub segments: SmallVec<[(&'a str, SpanToken); 4]>,
+}
+
+impl<'a> MapPathRef<'a> {
+    #[inline]
+    pub fn key(&self) -> &'a str {
+        self.segments[0].0
+    }
+}
+
+impl From<MapPathRef<'_>> for MapPath {
+    fn from(path: MapPathRef<'_>) -> Self {
+        let mut segments = path.segments.iter().map(|&(s, _)| s.to_string());
+        Self {
+            key: segments.next().expect("a path has at least one segment"),
+            path: segments.collect(),
+            spans: path.segments.iter().map(|&(_, span)| span).collect(),
+        }
+    }
+}
+
+impl MapPath {
+    /// Iterates over every segment along with its span, starting with the key.
+    pub fn segments(&self) -> impl Iterator<Item = (&str, SpanToken)> + '_ {
+        core::iter::once(&self.key)
+            .chain(&self.path)
+            .map(String::as_str)
+            .zip(self.spans.iter().copied())
+    }
+}
+
+/// A map or list that [`Parser::skip_value`] is inside of.
+struct Skipping {
+    closer: TokenKind,
+    // the opening token, when the container is the value of a key
+    open: Option<SpanToken>,
+    // the number of entries or elements so far
+    count: usize,
+    // the span of the path of the last entry of a map
+    key: Option<Token>,
+    // whether the value of the last entry or element is being skipped, which puts
+    // its key or index in the context of errors
+    in_value: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpanToken {
+    pub token: Token,
+    pub line: u32,
+    pub col: u32,
+    /// The line and column just past the end of the token. This is on the next
+    /// line for whitespace and comments that end with a newline.
+    pub end_line: u32,
+    pub end_col: u32,
+}
+
+impl SpanToken {
+    /// The byte range of the token in the source.
+    #[inline]
+    pub fn range(&self) -> Range<usize> {
+        self.token.start..self.token.end
+    }
+}
+
+/// The text of `input`, which has to be UTF-8, without a UTF-8 byte order mark at
+/// its start, where positions in errors are counted after the mark. A UTF-16 byte
+/// order mark is an error saying that the encoding is not supported.
+pub fn decode(input: &[u8], column_encoding: ColumnEncoding) -> Result<&str, ParseError> {
+    let error = |start: usize, end: usize, line: u32, col: u32, kind| {
+        let token = SpanToken {
+            token: Token {
+                kind: TokenKind::Unknown,
+                start,
+                end,
+            },
+            line,
+            col,
+            end_line: line,
+            end_col: col + 1,
+        };
+        ParseError::new(token, kind)
+    };
+    for (bom, encoding) in [(b"\xff\xfe", "UTF-16LE"), (b"\xfe\xff", "UTF-16BE")] {
+        if input.starts_with(bom) {
+            return Err(error(
+                0,
+                2,
+                0,
+                0,
+                ParseErrorKind::UnsupportedEncoding(encoding),
+            ));
+        }
+    }
+    let input = input.strip_prefix(b"\xef\xbb\xbf").unwrap_or(input);
+    core::str::from_utf8(input).map_err(|err| {
+        let start = err.valid_up_to();
+        let end = start + err.error_len().unwrap_or(input.len() - start);
+        let valid = core::str::from_utf8(&input[..start]).expect("the bytes before are valid");
+        let mut index = LineIndex::new(valid);
+        index.encoding = column_encoding;
+        let (line, col) = index.position(start).expect("the end is a char boundary");
+        error(start, end, line, col, ParseErrorKind::InvalidUtf8)
+    })
+}
+
+/// Lexes `src` into every one of its tokens, including whitespace and comments.
+pub fn lex(src: &str) -> Tokens<'_> {
+    Parser::new(src).into_tokens()
+}
+
+/// An iterator over the tokens of a source, created by [`lex`] or [`Parser::into_tokens`].
+///
+/// The final [`TokenKind::Eof`] token is not yielded.
+#[derive(Clone, Debug)]
+pub struct Tokens<'a> {
+    parser: Parser<'a>,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn src(&self, token: Token) -> &'a str {
+        self.parser.src(token)
     }
 
-    pub fn parse_path(&mut self) -> Result<MapPath, ParseError> {
-        let next = self.next_token();
-        if let TokenKind::Ident = next.token.kind {
-            let first = self.src(next.token).to_string();
-            let mut path = Vec::new();
-            while let TokenKind::Dot = self.peek_no_skip().token.kind {
-                self.next_no_skip();
-                let next_segment = self.next_no_skip();
-                if let TokenKind::Ident = next_segment.token.kind {
-                    path.push(self.src(next_segment.token).to_string());
-                } else {
-                    return Err(ParseError::new(next_segment, ParseErrorKind::ExpectedIdent));
-                }
-            }
-            Ok(MapPath { key: first, path })
-        } else {
-            Err(ParseError::new(next, ParseErrorKind::ExpectedIdent))
+    /// Groups the whitespace and comments of the remaining tokens with the
+    /// significant tokens around them.
+    pub fn with_trivia(self) -> TriviaTokens<'a> {
+        TriviaTokens {
+            parser: self.parser,
+            done: false,
         }
     }
+}
 
-    pub fn try_parse_path(&mut self) -> Option<Result<MapPath, ParseError>> {
-        if let TokenKind::Ident = self.peek_token().token.kind {
-            Some(self.parse_path())
-        } else {
+impl Iterator for Tokens<'_> {
+    type Item = SpanToken;
+
+    fn next(&mut self) -> Option<SpanToken> {
+        let next = self.parser.next_token();
+        if next.token.kind == TokenKind::Eof {
             None
+        } else {
+            Some(next)
         }
     }
+}
 
-    pub fn start_list(&mut self) -> Result<(), ParseError> {
-        let next = self.next_token();
-        if let TokenKind::StartSquare = next.token.kind {
-            Ok(())
-        } else {
-            Err(ParseError::new(next, ParseErrorKind::ExpectedListStart))
-        }
+impl FusedIterator for Tokens<'_> {}
+
+/// A significant token along with the trivia (whitespace and comments) around it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TriviaToken {
+    /// The trivia since the end of the previous token's trailing trivia.
+    pub leading: Vec<SpanToken>,
+    pub token: SpanToken,
+    /// The trivia after the token up to the end of its line,
+    /// so a comment like the one in `a = 1 // one` trails the `1`.
+    pub trailing: Vec<SpanToken>,
+}
+
+/// An iterator over significant tokens with their trivia, created by [`Tokens::with_trivia`].
+///
+/// Unlike [`Tokens`], the final [`TokenKind::Eof`] token is yielded so that
+/// the trivia at the end of the source is not lost.
+#[derive(Clone, Debug)]
+pub struct TriviaTokens<'a> {
+    parser: Parser<'a>,
+    done: bool,
+}
+
+impl<'a> TriviaTokens<'a> {
+    pub fn src(&self, token: Token) -> &'a str {
+        self.parser.src(token)
     }
+}
 
-    pub fn end_list(&mut self) -> Result<(), ParseError> {
-        let next = self.next_token();
-        if let TokenKind::EndSquare = next.token.kind {
-            Ok(())
-        } else {
-            Err(ParseError::new(next, ParseErrorKind::ExpectedListEnd))
+/// Whether tokens of `kind` can only be made of ASCII, so their width in every
+/// [`ColumnEncoding`] is their length.
+fn is_ascii_token(kind: TokenKind) -> bool {
+    !matches!(
+        kind,
+        TokenKind::Ident
+            | TokenKind::String { .. }
+            | TokenKind::Char { .. }
+            | TokenKind::Unknown
+            | TokenKind::Whitespace
+            | TokenKind::Comment
+    )
+}
+
+/// Errors for a token that [`Parser::skip_value`] read when it is not valid anywhere,
+/// like an unterminated string.
+fn check_skipped(next: SpanToken) -> Result<SpanToken, ParseError> {
+    let kind = match next.token.kind {
+        TokenKind::String {
+            terminated: false, ..
+        } => ParseErrorKind::StringUnterminated,
+        TokenKind::Char { terminated: false } => ParseErrorKind::CharUnterminated,
+        TokenKind::Unknown => ParseErrorKind::UnknownToken,
+        _ => return Ok(next),
+    };
+    Err(ParseError::new(next, kind))
+}
+
+fn is_trivia(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::Whitespace | TokenKind::Comment)
+}
+
+impl Iterator for TriviaTokens<'_> {
+    type Item = TriviaToken;
+
+    fn next(&mut self) -> Option<TriviaToken> {
+        if self.done {
+            return None;
         }
-    }
+        let mut leading = Vec::new();
+        let token = loop {
+            let next = self.parser.next_no_skip();
+            if !is_trivia(next.token.kind) {
+                break next;
+            }
+            leading.push(next);
+        };
 
-    pub fn try_start_list(&mut self) -> Option<()> {
-        if let TokenKind::StartSquare = self.peek_token().token.kind {
-            self.next_token();
-            Some(())
+        let mut trailing = Vec::new();
+        if token.token.kind == TokenKind::Eof {
+            self.done = true;
         } else {
-            None
+            loop {
+                let next = self.parser.peek_no_skip();
+                match next.token.kind {
+                    TokenKind::Whitespace if !self.parser.src(next.token).contains('\n') => {
+                        trailing.push(self.parser.next_no_skip());
+                    }
+                    TokenKind::Comment => {
+                        // line comments end with their newline
+                        trailing.push(self.parser.next_no_skip());
+                        break;
+                    }
+                    _ => break,
+                }
+            }
         }
-    }
 
-    // pub fn try_end_list(&mut self) -> Option<()> {
-    //     if let TokenKind::EndSquare = self.peek_token().token.kind {
-    //         self.next_token();
-    //         Some(())
-    //     } else {
-    //         None
-    //     }
-    // }
-    
-    pub fn peek_end_list(&mut self) -> bool {
-        TokenKind::EndSquare == self.peek_token().token.kind
+        Some(TriviaToken {
+            leading,
+            token,
+            trailing,
+        })
     }
 }
 
+impl FusedIterator for TriviaTokens<'_> {}
+
+/// Something that was being parsed when an error occurred.
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct MapPath {
-    pub key: String,
-    pub path: Vec<String>,
+pub enum ContextFrame<'a> {
+    /// The value of a key, or of one segment of a dotted key.
+    Key(Cow<'a, str>),
+    /// The element of a list at an index.
+    Element(usize),
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub struct SpanToken {
-    pub token: Token,
-    pub line: u32,
-    pub col: u32,
+impl ContextFrame<'_> {
+    pub fn to_static(&self) -> ContextFrame<'static> {
+        match self {
+            Self::Key(key) => ContextFrame::Key(Cow::Owned(key.to_string())),
+            Self::Element(i) => ContextFrame::Element(*i),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ParseError {
     pub token: SpanToken,
     pub kind: ParseErrorKind,
+    /// What was being parsed, outermost first.
+    pub context: Vec<ContextFrame<'static>>,
 }
 
 impl ParseError {
     pub fn new(token: SpanToken, kind: ParseErrorKind) -> Self {
-        Self { token, kind }
+        Self {
+            token,
+            kind,
+            context: Vec::new(),
+        }
+    }
+}
+
+impl ParseError {
+    /// Writes the error like its `Display` impl does, with `file` before the position.
+    pub(crate) fn write_in(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        file: Option<impl fmt::Display>,
+    ) -> fmt::Result {
+        write!(f, "{} at ", self.kind.display(self.token))?;
+        if let Some(file) = file {
+            write!(f, "{file}:")?;
+        }
+        // 1-based, like editors and `Error::render`
+        write!(f, "{}:{}", self.token.line + 1, self.token.col + 1)?;
+        if let Some((note, token)) = self.kind.note() {
+            write!(f, ", {note} at {}:{}", token.line + 1, token.col + 1)?;
+        }
+        write_context(f, &self.context)
     }
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{} at {}:{}",
-            self.kind.display(self.token),
-            self.token.line,
-            self.token.col,
-        )
+        self.write_in(f, None::<&str>)
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// Writes a line for every frame of `context`, innermost first, with the segments
+/// of dotted keys joined back together.
+pub(crate) fn write_context(f: &mut fmt::Formatter<'_>, context: &[ContextFrame]) -> fmt::Result {
+    let mut frames = context.iter().rev().peekable();
+    while let Some(frame) = frames.next() {
+        match frame {
+            ContextFrame::Key(key) => {
+                let mut path = vec![&**key];
+                while let Some(ContextFrame::Key(key)) = frames.peek() {
+                    path.push(key);
+                    frames.next();
+                }
+                path.reverse();
+                write!(
+                    f,
+                    "\n    while parsing the value for key `{}`",
+                    path.join(".")
+                )?;
+            }
+            ContextFrame::Element(i) => {
+                write!(f, "\n    while parsing list element {i}")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Formats `context` as a path like `servers[2].tls.port`.
+pub fn context_path(context: &[ContextFrame]) -> String {
+    Path::from(context).to_string()
+}
+
+/// Whether a token of `kind` can start a key, which is an identifier or a quoted string.
+pub(crate) fn starts_key(kind: TokenKind) -> bool {
+    matches!(kind, TokenKind::Ident | TokenKind::String { .. })
+}
+
+/// Whether a token of `kind` can start a value.
+pub(crate) fn starts_value(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Ident
+            | TokenKind::Keyword(_)
+            | TokenKind::Integer { .. }
+            | TokenKind::Float
+            | TokenKind::Duration
+            | TokenKind::String { .. }
+            | TokenKind::Char { .. }
+            | TokenKind::StartSquare
+            | TokenKind::StartCurly
+    )
+}
+
+/// A set of the classes of tokens that would have been valid where an error occurred.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ExpectedSet(u8);
+
+impl ExpectedSet {
+    pub const MAP_END: Self = Self(1 << 0);
+    pub const LIST_END: Self = Self(1 << 1);
+    pub const IDENT: Self = Self(1 << 2);
+    pub const VALUE: Self = Self(1 << 3);
+    pub const EQUAL: Self = Self(1 << 4);
+    pub const DOT: Self = Self(1 << 5);
+    pub const EOF: Self = Self(1 << 6);
+
+    const NAMES: [&'static str; 7] = [
+        "'}'",
+        "']'",
+        "an identifier",
+        "a value",
+        "'='",
+        "'.'",
+        "the end of the file",
+    ];
+
+    /// The class of the token that ends a map or list.
+    pub(crate) fn closing(closer: TokenKind) -> Self {
+        match closer {
+            TokenKind::EndCurly => Self::MAP_END,
+            TokenKind::EndSquare => Self::LIST_END,
+            TokenKind::Eof => Self::EOF,
+            _ => Self::default(),
+        }
+    }
+
+    #[inline]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The descriptions of every class in the set, like `"an identifier"`.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        Self::NAMES
+            .into_iter()
+            .enumerate()
+            .filter(move |&(i, _)| self.0 & (1 << i) != 0)
+            .map(|(_, name)| name)
+    }
+}
+
+impl core::ops::BitOr for ExpectedSet {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Display for ExpectedSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.names().count();
+        for (i, name) in self.names().enumerate() {
+            if i > 0 {
+                f.write_str(if i + 1 == count { " or " } else { ", " })?;
+            }
+            f.write_str(name)?;
+        }
+        Ok(())
     }
 }
 
+/// What went wrong in a [`ParseError`]. Kinds are added as the parser learns to
+/// report more errors, so matching on them needs a wildcard arm.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ParseErrorKind {
+    Expected(ExpectedSet),
     ExpectedNull,
     ExpectedBool,
     ExpectedInteger { signed: bool },
@@ -512,12 +2362,49 @@ pub enum ParseErrorKind {
     ExpectedListEnd,
     ExpectedEqual,
     InvalidInteger,
+    InvalidDigit { digit: char, base: Base },
     InvalidFloat,
+    InexactFloat,
+    FractionalInteger,
+    ExpectedDuration,
+    InvalidDuration,
+    UnorderedDuration,
+    InvalidBytes(BytesEncoding),
     ExpectedString,
     InvalidEscape { pos: u32 },
     StringUnterminated,
+    ExpectedChar,
+    ExpectedEnum,
+    InvalidChar,
+    CharUnterminated,
     ExpectedIdent,
+    EscapedKey,
+    ExpectedValue,
     UnknownToken,
+    TooDeep { max: u32 },
+    DuplicateKey { key: String, first: Box<SpanToken> },
+    // the key, and the field it is most likely a typo of
+    UnknownField(String, Option<&'static str>),
+    MergeConflict { key: String, other: Box<SpanToken> },
+    DottedAfterMap { key: String, map: Box<SpanToken> },
+    LimitExceeded(Limit),
+    // why the document an include refers to could not be read
+    Include(String),
+    UnsetVariable(String),
+    // what in the document its schema does not allow
+    Schema(String),
+    InvalidUtf8,
+    // the encoding the byte order mark at the start of the input is for
+    UnsupportedEncoding(&'static str),
+}
+
+/// A limit from [`Limits`] along with its value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    InputLen(usize),
+    Tokens(u64),
+    StringLen(usize),
+    Elements(usize),
 }
 
 fn display_token_kind(kind: TokenKind) -> impl fmt::Display {
@@ -537,7 +2424,9 @@ fn display_token_kind(kind: TokenKind) -> impl fmt::Display {
                 TokenKind::Integer { sign: true, .. } => write!(f, "a signed Integer"),
                 TokenKind::Integer { sign: false, .. } => write!(f, "an unsigned Integer"),
                 TokenKind::Float => write!(f, "a floating point number"),
+                TokenKind::Duration => write!(f, "a duration"),
                 TokenKind::String { .. } => write!(f, "a string"),
+                TokenKind::Char { .. } => write!(f, "a character"),
                 TokenKind::Unknown => write!(f, "an unknown token"),
                 TokenKind::Eof => write!(f, "the end of the file"),
                 TokenKind::Equal => write!(f, "'='"),
@@ -548,7 +2437,19 @@ fn display_token_kind(kind: TokenKind) -> impl fmt::Display {
 }
 
 impl ParseErrorKind {
-    fn display(&self, token: SpanToken) -> impl fmt::Display + '_ {
+    /// Another token the error is about, like where a duplicate key was first defined,
+    /// and what happened there.
+    pub fn note(&self) -> Option<(&'static str, SpanToken)> {
+        match self {
+            Self::DuplicateKey { first, .. } => Some(("first defined", **first)),
+            Self::MergeConflict { other, .. } => Some(("also set with a dotted key", **other)),
+            Self::DottedAfterMap { map, .. } => Some(("already read", **map)),
+            _ => None,
+        }
+    }
+
+    /// The message for an error at `token`, without its position or context.
+    pub fn display(&self, token: SpanToken) -> impl fmt::Display + '_ {
         struct Proxy<'s>(&'s ParseErrorKind, SpanToken);
 
         impl<'a> fmt::Display for Proxy<'a> {
@@ -564,6 +2465,7 @@ impl ParseErrorKind {
 
                 use ParseErrorKind::*;
                 match self.0 {
+                    Expected(set) => expected(&set.to_string()),
                     ExpectedNull => expected("null"),
                     ExpectedBool => expected("a bool"),
                     ExpectedInteger { signed: false } => expected("an unsigned integer"),
@@ -574,11 +2476,31 @@ impl ParseErrorKind {
                     ExpectedListStart => expected("a list"),
                     ExpectedListEnd => expected("']'"),
                     ExpectedEqual => expected("'='"),
+                    ExpectedDuration => expected("a duration"),
                     ExpectedString => expected("a string"),
                     ExpectedIdent => expected("an identifier"),
+                    EscapedKey => write!(f, "Quoted keys can not contain escapes"),
+                    ExpectedValue => expected("a value"),
+                    ExpectedChar => expected("a character"),
+                    ExpectedEnum => expected("an enum variant"),
                     InvalidInteger => write!(f, "Invalid integer"),
+                    InvalidDigit { digit, base } => {
+                        write!(f, "Invalid {} digit '{digit}'", base.name())
+                    }
                     InvalidFloat => write!(f, "Invalid float"),
+                    FractionalInteger => write!(f, "Expected an integer, found a fraction"),
+                    InexactFloat => {
+                        write!(f, "The integer can not be represented exactly as a float")
+                    }
+                    InvalidDuration => write!(f, "Invalid duration"),
+                    UnorderedDuration => write!(
+                        f,
+                        "Duration units have to go from largest to smallest, each at most once"
+                    ),
+                    InvalidBytes(encoding) => write!(f, "Invalid {} bytes", encoding.name()),
                     StringUnterminated => write!(f, "Expected a closing '\"'"),
+                    CharUnterminated => write!(f, "Expected a closing \"'\""),
+                    InvalidChar => write!(f, "Expected exactly one character"),
                     InvalidEscape { pos } => write!(
                         f,
                         "Invalid escape sequence at character {} of string",
@@ -587,6 +2509,37 @@ impl ParseErrorKind {
                     UnknownToken => {
                         write!(f, "Unknown token {}", display_token_kind(self.1.token.kind))
                     }
+                    TooDeep { max } => write!(f, "Exceeded the maximum nesting depth of {max}"),
+                    DuplicateKey { key, .. } => write!(f, "Duplicate key '{key}'"),
+                    UnknownField(field, Some(closest)) => {
+                        write!(f, "Unknown field '{field}', did you mean '{closest}'?")
+                    }
+                    UnknownField(field, None) => write!(f, "Unknown field '{field}'"),
+                    LimitExceeded(limit) => match limit {
+                        Limit::InputLen(max) => write!(f, "The input is longer than {max} bytes"),
+                        Limit::Tokens(max) => write!(f, "The input has more than {max} tokens"),
+                        Limit::StringLen(max) => write!(f, "The string is longer than {max} bytes"),
+                        Limit::Elements(max) => {
+                            write!(f, "The container has more than {max} elements")
+                        }
+                    },
+                    Include(reason) => write!(f, "Could not include the document: {reason}"),
+                    UnsetVariable(name) => write!(f, "The variable '{name}' is not set"),
+                    Schema(message) => write!(f, "{message}"),
+                    InvalidUtf8 => write!(f, "Invalid UTF-8"),
+                    UnsupportedEncoding(encoding) => write!(
+                        f,
+                        "The document is encoded as {encoding}, but only UTF-8 is supported"
+                    ),
+                    MergeConflict { key, .. } => write!(
+                        f,
+                        "Expected a map for '{key}' to merge with its dotted keys, found {}",
+                        display_token_kind(self.1.token.kind)
+                    ),
+                    DottedAfterMap { key, .. } => write!(
+                        f,
+                        "Expected the dotted keys of '{key}' before its braced map, which was already read"
+                    ),
                 }
             }
         }
@@ -628,7 +2581,8 @@ mod tests {
                 Ok(Self::F64(r?))
             } else if let Some(r) = p.try_parse_string() {
                 Ok(Self::String(r?))
-            } else if p.try_start_list().is_some() {
+            } else if let Some(r) = p.try_start_list() {
+                r?;
                 let mut vec = Vec::new();
                 loop {
                     if p.peek_end_list() {
@@ -641,7 +2595,8 @@ mod tests {
                     vec.push(Value::parse(p)?);
                 }
                 Ok(Value::List(vec))
-            } else if p.try_start_map().is_some() {
+            } else if let Some(r) = p.try_start_map() {
+                r?;
                 let mut map = HashMap::new();
                 loop {
                     if p.peek_end_map() {
@@ -686,4 +2641,690 @@ mod tests {
             Ok(Value::Map(map))
         }
     }
+
+    #[test]
+    fn skip_value() {
+        fn skip(src: &str) -> Result<(), ParseErrorKind> {
+            let mut p = Parser::new(src);
+            p.skip_value()
+                .and_then(|()| p.end_of_input())
+                .map_err(|err| err.kind)
+        }
+
+        assert_eq!(
+            skip(r#"{ a = [1 -2 3.5 10s "x\n" 'c' null] b.c = { d = e } }"#),
+            Ok(())
+        );
+        assert_eq!(skip("\"abc"), Err(ParseErrorKind::StringUnterminated));
+        assert_eq!(
+            skip("[1 \"abc\n 2]"),
+            Err(ParseErrorKind::StringUnterminated)
+        );
+        assert_eq!(skip("{ a = 'b }"), Err(ParseErrorKind::CharUnterminated));
+        assert_eq!(skip("[ @ ; ]"), Err(ParseErrorKind::UnknownToken));
+        assert_eq!(
+            skip("[1 }"),
+            Err(ParseErrorKind::Expected(
+                ExpectedSet::VALUE | ExpectedSet::LIST_END
+            ))
+        );
+        assert_eq!(skip("{ x y z }"), Err(ParseErrorKind::ExpectedEqual));
+        assert_eq!(skip("[ = = ]"), Err(ParseErrorKind::ExpectedValue));
+        assert_eq!(
+            skip("{ = }"),
+            Err(ParseErrorKind::Expected(
+                ExpectedSet::IDENT | ExpectedSet::MAP_END
+            ))
+        );
+        assert_eq!(skip("{ a = }"), Err(ParseErrorKind::ExpectedValue));
+        assert_eq!(skip("{ a =\nb = 1 }"), Err(ParseErrorKind::ExpectedValue));
+        assert_eq!(skip("{ a =\nb }"), Ok(()));
+    }
+
+    #[test]
+    fn skipped_values_are_checked() {
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            a: u32,
+            c: u32,
+        }
+
+        let err = crate::from_str::<Config>("a = 1\nb = \"abc\nc = 2").unwrap_err();
+        assert!(
+            err.to_string().starts_with("Expected a closing '\"'"),
+            "{err}"
+        );
+        assert!(crate::from_str::<Config>("a = 1 b = [ @ ; ] c = 2").is_err());
+
+        let cases = [
+            ("b = { x y z }", ParseErrorKind::ExpectedEqual, 8),
+            ("b = [ = = ]", ParseErrorKind::ExpectedValue, 6),
+            (
+                "b = { = }",
+                ParseErrorKind::Expected(ExpectedSet::IDENT | ExpectedSet::MAP_END),
+                6,
+            ),
+            ("b = {c = 1 c}", ParseErrorKind::ExpectedEqual, 12),
+        ];
+        for (ignored, kind, col) in cases {
+            let src = format!("a = 1\n{ignored}\nc = 2");
+            match crate::from_str::<Config>(&src).unwrap_err().kind() {
+                crate::error::ErrorKind::Parse(err) => {
+                    assert_eq!((&err.kind, err.token.line, err.token.col), (&kind, 1, col))
+                }
+                other => panic!("expected a parse error for {src:?}, found {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn check_value_on_next_line() {
+        assert_eq!(Parser::new("mode =\n    fast\n").check_top_level(), []);
+        assert_eq!(Parser::new("{ mode =\n    fast }").check_value(), []);
+
+        let errors = Parser::new("a =\nb = 1\nc =").check_top_level();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|err| (err.kind.clone(), err.token.line))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (ParseErrorKind::ExpectedValue, 1),
+                (ParseErrorKind::ExpectedValue, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn radix_digits() {
+        let mut p = Parser::new("0xff 0o17 -0b1_01 0xaB");
+        assert_eq!(p.parse_uint::<u64>(), Ok(255));
+        assert_eq!(p.parse_uint::<u64>(), Ok(15));
+        assert_eq!(p.parse_int::<i64>(), Ok(-5));
+        assert_eq!(p.parse_uint::<u64>(), Ok(171));
+
+        for (src, digit, base, col) in [
+            ("0b1021", '2', Base::Bin, 4),
+            ("0o178", '8', Base::Oct, 4),
+            ("-0b12", '2', Base::Bin, 4),
+            ("0xfg", 'g', Base::Hex, 3),
+        ] {
+            let err = Parser::new(src).parse_int::<i64>().unwrap_err();
+            assert_eq!(
+                err.kind,
+                ParseErrorKind::InvalidDigit { digit, base },
+                "{src}"
+            );
+            assert_eq!((err.token.col, err.token.end_col), (col, col + 1), "{src}");
+            assert_eq!(err.token.token.start, col as usize, "{src}");
+        }
+        let err = Parser::new("a = 0o9").check_top_level();
+        assert_eq!(
+            err[0].kind,
+            ParseErrorKind::InvalidDigit {
+                digit: '9',
+                base: Base::Oct
+            }
+        );
+        assert_eq!(err[0].token.col, 6);
+        assert_eq!(
+            Parser::new("0o9").parse_u128().unwrap_err().kind,
+            err[0].kind
+        );
+    }
+
+    #[test]
+    fn pretokenize() {
+        let src = "// servers\na.b = { c = [1 2.5 \"x\"] }\na.d = 'q'\ne = 3s";
+        let mut lexed = Parser::new(src);
+        let mut tokenized = Parser::new(src);
+        tokenized.tokenize();
+        loop {
+            let token = lexed.next_token();
+            assert_eq!(tokenized.next_token(), token);
+            if token.token.kind == TokenKind::Eof {
+                break;
+            }
+        }
+
+        // errors point at the same tokens either way
+        let src = "a = 1\nb = [x =]\nc = 0xg";
+        let mut tokenized = Parser::new(src);
+        tokenized.tokenize();
+        assert_eq!(
+            tokenized.check_top_level(),
+            Parser::new(src).check_top_level()
+        );
+    }
+
+    #[test]
+    fn integer_separators() {
+        let mut p = Parser::new("1_000_000 0xff_ff -0b1010_1010 0o_7_");
+        assert_eq!(p.parse_uint::<u64>(), Ok(1_000_000));
+        assert_eq!(p.parse_uint::<u64>(), Ok(0xffff));
+        assert_eq!(p.parse_int::<i64>(), Ok(-0b1010_1010));
+        assert_eq!(p.parse_uint::<u64>(), Ok(7));
+        assert_eq!(
+            Parser::new("0xffff_ffff_ffff_ffff_ffff_ffff_ffff_ffff").parse_u128(),
+            Ok(u128::MAX)
+        );
+
+        let kind = |src| Parser::new(src).parse_uint::<u8>().unwrap_err().kind;
+        assert_eq!(kind("0x_"), ParseErrorKind::InvalidInteger);
+        assert_eq!(kind("1_000"), ParseErrorKind::InvalidInteger);
+        // the bad digit wins over the overflow before it
+        assert_eq!(
+            kind("0b1111_1111_1111_2"),
+            ParseErrorKind::InvalidDigit {
+                digit: '2',
+                base: Base::Bin
+            }
+        );
+    }
+
+    #[test]
+    fn trivia_tokens() {
+        let src = "// head\na = 1 // one\n\nb = [2]  \n// tail";
+        let text = |token: &SpanToken| &src[token.token.start..token.token.end];
+        let tokens: Vec<_> = lex(src).with_trivia().collect();
+
+        // every token comes back once and in order, trivia included
+        let all: Vec<_> = tokens
+            .iter()
+            .flat_map(|t| t.leading.iter().chain([&t.token]).chain(&t.trailing))
+            .copied()
+            .filter(|t| t.token.kind != TokenKind::Eof)
+            .collect();
+        assert_eq!(all, lex(src).collect::<Vec<_>>());
+        // and the significant tokens are the ones the parser reads
+        let mut p = Parser::new(src);
+        for t in &tokens {
+            assert_eq!(t.token, p.next_token());
+        }
+
+        let trivia = |tokens: &[SpanToken]| tokens.iter().map(text).collect::<Vec<_>>();
+        let [a, _, one, b, _, _, _, _, eof] = &tokens[..] else {
+            panic!("{tokens:?}");
+        };
+        assert_eq!(trivia(&a.leading), ["// head\n"]);
+        assert_eq!(
+            (text(&one.token), trivia(&one.trailing)),
+            ("1", vec![" ", "// one\n"])
+        );
+        // a blank line is leading trivia of the next token, not trailing of the last
+        assert_eq!(trivia(&b.leading), ["\n"]);
+        assert_eq!(
+            (eof.token.token.kind, trivia(&eof.leading)),
+            (TokenKind::Eof, vec!["  \n", "// tail"])
+        );
+    }
+
+    #[test]
+    fn depth_limit() {
+        fn limited(src: &str, max_depth: Option<u32>) -> Parser<'_> {
+            let mut p = Parser::new(src);
+            p.max_depth = max_depth;
+            p
+        }
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+
+        let src = nested(3);
+        let mut p = limited(&src, Some(3));
+        assert!(Value::parse(&mut p).is_ok());
+        assert_eq!(p.depth, 0);
+        let src = nested(4);
+        let err = Value::parse(&mut limited(&src, Some(3))).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::TooDeep { max: 3 });
+        assert_eq!(err.token.col, 3);
+        assert_eq!(
+            err.to_string(),
+            "Exceeded the maximum nesting depth of 3 at 1:4"
+        );
+
+        // maps count as well, and so do values that are checked
+        let src = "a = { b = [{}] }";
+        let too_deep = ParseErrorKind::TooDeep { max: 2 };
+        let err = Value::parse_file(&mut limited(src, Some(2))).unwrap_err();
+        assert_eq!(err.kind, too_deep);
+        let errors = limited(src, Some(2)).check_top_level();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, too_deep);
+
+        let src = nested(DEFAULT_MAX_DEPTH as usize + 1);
+        assert!(Value::parse(&mut Parser::new(&src)).is_err());
+        assert!(Value::parse(&mut limited(&src, None)).is_ok());
+    }
+
+    #[test]
+    fn error_recovery() {
+        use ParseErrorKind::*;
+
+        fn check(src: &str) -> Vec<(ParseErrorKind, u32, u32)> {
+            let errors = Parser::new(src).check_top_level();
+            errors
+                .into_iter()
+                .map(|err| (err.kind, err.token.line, err.token.col))
+                .collect()
+        }
+
+        assert_eq!(check("a = 1\nb = { c = [1 2] d = 'x' }"), []);
+        // every entry after an error is still checked, with the lines counted right
+        // after an unterminated string
+        assert_eq!(
+            check("a = 0xz\nb = \"x\nc 1\nd = [1 = 2]\ne = 3 f"),
+            [
+                (
+                    InvalidDigit {
+                        digit: 'z',
+                        base: Base::Hex
+                    },
+                    0,
+                    6
+                ),
+                (StringUnterminated, 1, 4),
+                (ExpectedEqual, 2, 2),
+                (ExpectedValue, 3, 7),
+                (ExpectedEqual, 4, 7),
+            ]
+        );
+        // an entry without a key is skipped up to an identifier on a later line
+        assert_eq!(
+            check("a = { b = @ c = 1 }\n= 2 x = 3\nd = [1 2"),
+            [
+                (ExpectedValue, 0, 10),
+                (Expected(ExpectedSet::IDENT | ExpectedSet::EOF), 1, 0),
+                (Expected(ExpectedSet::VALUE | ExpectedSet::LIST_END), 2, 8),
+            ]
+        );
+        // a mismatched closer does not end the map, which is left open at the end
+        assert_eq!(
+            check("a = { b = 1 ]\nc = 2"),
+            [
+                (Expected(ExpectedSet::IDENT | ExpectedSet::MAP_END), 0, 12),
+                (Expected(ExpectedSet::IDENT | ExpectedSet::MAP_END), 1, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn expected_set() {
+        let set = ExpectedSet::IDENT | ExpectedSet::MAP_END;
+        assert!(set.contains(ExpectedSet::IDENT));
+        assert!(set.contains(ExpectedSet::MAP_END | ExpectedSet::IDENT));
+        assert!(!set.contains(ExpectedSet::VALUE));
+        assert!(ExpectedSet::default().is_empty());
+        assert!(ExpectedSet::closing(TokenKind::Ident).is_empty());
+        assert_eq!(
+            ExpectedSet::closing(TokenKind::EndSquare),
+            ExpectedSet::LIST_END
+        );
+        assert_eq!(set.names().collect::<Vec<_>>(), ["'}'", "an identifier"]);
+        assert_eq!(ExpectedSet::EOF.to_string(), "the end of the file");
+        assert_eq!(set.to_string(), "'}' or an identifier");
+        assert_eq!(
+            (ExpectedSet::VALUE | ExpectedSet::EQUAL | ExpectedSet::DOT).to_string(),
+            "a value, '=' or '.'"
+        );
+
+        // what the parser reports for map entries and list elements
+        let e = Parser::new("a = 1 ]").check_top_level().remove(0);
+        assert_eq!(
+            e.kind,
+            ParseErrorKind::Expected(ExpectedSet::IDENT | ExpectedSet::EOF)
+        );
+        assert_eq!(
+            e.to_string(),
+            "Expected an identifier or the end of the file, found ']' at 1:7"
+        );
+        let e = Parser::new("[1 }").skip_value().unwrap_err();
+        assert_eq!(e.to_string(), "Expected ']' or a value, found '}' at 1:4");
+        let e = Parser::new("{ a = 1 2 }").check_value().remove(0);
+        assert_eq!(
+            e.kind,
+            ParseErrorKind::Expected(ExpectedSet::IDENT | ExpectedSet::MAP_END)
+        );
+        let mut p = Parser::new("1 2");
+        p.parse_uint::<u64>().unwrap();
+        let e = p.end_of_input().unwrap_err();
+        assert_eq!(e.kind, ParseErrorKind::Expected(ExpectedSet::EOF));
+    }
+
+    #[test]
+    fn context_frames() {
+        let mut p = Parser::new("x");
+        p.push_context(ContextFrame::Key(Cow::Borrowed("servers")));
+        p.push_context(ContextFrame::Element(2));
+        p.push_context(ContextFrame::Key(Cow::Borrowed("tls")));
+        p.push_context(ContextFrame::Key(Cow::Borrowed("port")));
+        let mut err = p.parse_uint::<u16>().unwrap_err();
+        assert!(err.context.is_empty());
+        p.add_context(&mut err);
+        assert_eq!(
+            err.context,
+            [
+                ContextFrame::Key("servers".into()),
+                ContextFrame::Element(2),
+                ContextFrame::Key("tls".into()),
+                ContextFrame::Key("port".into()),
+            ]
+        );
+        // innermost first, with the segments of a dotted key joined
+        assert_eq!(
+            err.to_string(),
+            "Expected an unsigned integer, found an identifier at 1:1\n    \
+             while parsing the value for key `tls.port`\n    \
+             while parsing list element 2\n    \
+             while parsing the value for key `servers`"
+        );
+
+        // the context of an error from further inside is kept
+        assert_eq!(p.pop_context(), Some(ContextFrame::Key("port".into())));
+        p.add_context(&mut err);
+        assert_eq!(err.context.len(), 4);
+
+        let errors = Parser::new("a.b = [1 { c = @ }]\nd = @").check_top_level();
+        let context: Vec<_> = errors.iter().map(|err| err.context.clone()).collect();
+        assert_eq!(
+            context,
+            [
+                vec![
+                    ContextFrame::Key("a".into()),
+                    ContextFrame::Key("b".into()),
+                    ContextFrame::Element(1),
+                    ContextFrame::Key("c".into()),
+                ],
+                vec![ContextFrame::Key("d".into())],
+            ]
+        );
+    }
+
+    #[test]
+    fn limits() {
+        fn check(src: &str, limits: Limits) -> Vec<(ParseErrorKind, u32)> {
+            let mut p = Parser::new(src);
+            p.limits = limits;
+            let errors = p.check_top_level();
+            errors
+                .into_iter()
+                .map(|err| (err.kind, err.token.col))
+                .collect()
+        }
+        let exceeded = ParseErrorKind::LimitExceeded;
+        let src = r#"a = "abcd" b = [1 2 3] c = 'x'"#;
+        assert_eq!(check(src, Limits::default()), []);
+
+        let limits = Limits {
+            max_input_len: Some(src.len()),
+            max_tokens: Some(23),
+            max_string_len: Some(6),
+            max_elements: Some(3),
+        };
+        assert_eq!(check(src, limits), []);
+        assert_eq!(
+            check(
+                src,
+                Limits {
+                    max_input_len: Some(10),
+                    ..limits
+                }
+            ),
+            [(exceeded(Limit::InputLen(10)), 0)]
+        );
+        assert_eq!(
+            check(
+                src,
+                Limits {
+                    max_tokens: Some(10),
+                    ..limits
+                }
+            ),
+            [(exceeded(Limit::Tokens(10)), 15)]
+        );
+        // the quotes count toward the length of a string
+        assert_eq!(
+            check(
+                src,
+                Limits {
+                    max_string_len: Some(5),
+                    ..limits
+                }
+            ),
+            [(exceeded(Limit::StringLen(5)), 4)]
+        );
+        // entries of the top level count as well as elements of lists, and the
+        // rest of a list that is too long is skipped
+        assert_eq!(
+            check(
+                src,
+                Limits {
+                    max_elements: Some(2),
+                    ..limits
+                }
+            ),
+            [
+                (exceeded(Limit::Elements(2)), 20),
+                (exceeded(Limit::Elements(2)), 23),
+            ]
+        );
+
+        let mut p = Parser::new("{ a = [1 2 3] }");
+        p.limits.max_elements = Some(2);
+        let errors = p.check_value();
+        assert_eq!(
+            errors[0].to_string(),
+            "The container has more than 2 elements at 1:12\n    \
+             while parsing the value for key `a`"
+        );
+        let mut p = Parser::new(r#""abcdef""#);
+        p.limits.max_string_len = Some(4);
+        assert_eq!(
+            p.parse_string().unwrap_err().to_string(),
+            "The string is longer than 4 bytes at 1:1"
+        );
+    }
+
+    #[test]
+    fn end_positions() {
+        let src = "a = \"xy\" // c\n\n  bb";
+        let spans: Vec<_> = lex(src)
+            .map(|t| (t.token.kind, (t.line, t.col), (t.end_line, t.end_col)))
+            .collect();
+        let string = TokenKind::String {
+            terminated: true,
+            escaped: false,
+        };
+        assert_eq!(
+            spans,
+            [
+                (TokenKind::Ident, (0, 0), (0, 1)),
+                (TokenKind::Whitespace, (0, 1), (0, 2)),
+                (TokenKind::Equal, (0, 2), (0, 3)),
+                (TokenKind::Whitespace, (0, 3), (0, 4)),
+                (string, (0, 4), (0, 8)),
+                (TokenKind::Whitespace, (0, 8), (0, 9)),
+                (TokenKind::Comment, (0, 9), (1, 0)),
+                (TokenKind::Whitespace, (1, 0), (2, 2)),
+                (TokenKind::Ident, (2, 2), (2, 4)),
+            ]
+        );
+
+        // the same positions come out of a tokenized parser and errors
+        let mut p = Parser::new(src);
+        p.tokenize();
+        let ident = p.peek_nth(3);
+        assert_eq!(
+            (ident.line, ident.col, ident.end_line, ident.end_col),
+            (2, 2, 2, 4)
+        );
+        let err = Parser::new("{ a = 1\n  'k' = 2 }").check_value().remove(0);
+        assert_eq!(
+            (
+                err.token.line,
+                err.token.col,
+                err.token.end_line,
+                err.token.end_col
+            ),
+            (1, 2, 1, 5)
+        );
+    }
+
+    #[test]
+    fn column_encoding() {
+        assert_eq!(ColumnEncoding::Bytes.width("é😀"), 6);
+        assert_eq!(ColumnEncoding::Chars.width("é😀"), 2);
+        assert_eq!(ColumnEncoding::Utf16.width("é😀"), 3);
+        assert_eq!(ColumnEncoding::Utf16.width("ascii"), 5);
+
+        let src = "a = \"é😀\" @\nb = '😀' c = 0b2";
+        for (encoding, cols) in [
+            (ColumnEncoding::Bytes, [(13, 14), (17, 18)]),
+            (ColumnEncoding::Chars, [(9, 10), (14, 15)]),
+            (ColumnEncoding::Utf16, [(10, 11), (15, 16)]),
+        ] {
+            let mut p = Parser::new(src);
+            p.column_encoding = encoding;
+            let errors: Vec<_> = p
+                .check_top_level()
+                .into_iter()
+                .map(|err| (err.token.col, err.token.end_col))
+                .collect();
+            assert_eq!(errors, cols, "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn comment_capture() {
+        let src = "// head\na = 1 // one\nb = [ // list\n  2\n] # hash\n";
+        let texts = |p: &Parser, comments: Vec<SpanToken>| {
+            comments
+                .into_iter()
+                .map(|c| (p.src(c.token).trim_end().to_owned(), c.line))
+                .collect::<Vec<_>>()
+        };
+
+        let mut p = Parser::new("// head\na = 1 // one");
+        Value::parse_file(&mut p).unwrap();
+        assert_eq!(p.take_comments(), []);
+
+        let mut p = Parser::new(src);
+        p.cursor.set_hash_comments(true);
+        p.capture_comments();
+        p.parse_path().unwrap();
+        p.map_delimiter().unwrap();
+        p.parse_uint::<u64>().unwrap();
+        // peeking reads the comments before the next token only once
+        p.peek_token();
+        p.peek_token();
+        let comments = p.take_comments();
+        assert_eq!(
+            texts(&p, comments),
+            [("// head".to_owned(), 0), ("// one".to_owned(), 1)]
+        );
+        p.parse_path().unwrap();
+        p.map_delimiter().unwrap();
+        Value::parse(&mut p).unwrap();
+        p.end_of_input().unwrap();
+        let comments = p.take_comments();
+        assert_eq!(
+            texts(&p, comments),
+            [("// list".to_owned(), 2), ("# hash".to_owned(), 4)]
+        );
+        assert_eq!(p.take_comments(), []);
+    }
+
+    #[test]
+    fn wide_integers() {
+        let src = format!(
+            "{} {} {} 0x{:x} -0b1{} 0o7",
+            u128::MAX,
+            i128::MIN,
+            u64::MAX as u128 + 1,
+            u128::MAX,
+            "0".repeat(127),
+        );
+        let mut p = Parser::new(&src);
+        assert_eq!(p.parse_u128(), Ok(u128::MAX));
+        assert_eq!(p.parse_i128(), Ok(i128::MIN));
+        assert_eq!(p.parse_i128(), Ok(u64::MAX as i128 + 1));
+        assert_eq!(p.parse_u128(), Ok(u128::MAX));
+        assert_eq!(p.parse_i128(), Ok(i128::MIN));
+        assert_eq!(p.parse_i128(), Ok(7));
+
+        for (src, signed) in [
+            (format!("{}0", u128::MAX), false),
+            (format!("{}", i128::MAX as u128 + 1), true),
+            (format!("-{}", i128::MAX as u128 + 2), true),
+        ] {
+            let mut p = Parser::new(&src);
+            let err = if signed {
+                p.parse_i128().map(drop)
+            } else {
+                p.parse_u128().map(drop)
+            };
+            assert_eq!(
+                err.unwrap_err().kind,
+                ParseErrorKind::InvalidInteger,
+                "{src}"
+            );
+        }
+        assert_eq!(
+            Parser::new("-1").parse_u128().unwrap_err().kind,
+            ParseErrorKind::ExpectedInteger { signed: false }
+        );
+        assert_eq!(
+            Parser::new("1.5").parse_i128().unwrap_err().kind,
+            ParseErrorKind::ExpectedInteger { signed: true }
+        );
+
+        let mut p = Parser::new("1e20 \"123\"");
+        p.integral_floats = true;
+        p.numeric_strings = true;
+        assert_eq!(p.parse_u128(), Ok(100_000_000_000_000_000_000));
+        assert_eq!(p.parse_i128(), Ok(123));
+    }
+
+    #[test]
+    fn checkpoint_restore() {
+        for tokenized in [false, true] {
+            let mut p = Parser::new("a = [1 2] // c\nb = { c = 3 }");
+            if tokenized {
+                p.tokenize();
+            }
+            p.capture_comments();
+            p.parse_path().unwrap();
+            p.map_delimiter().unwrap();
+            let start = p.checkpoint();
+
+            p.push_context(ContextFrame::Key("a".into()));
+            p.start_list().unwrap();
+            assert_eq!(p.parse_uint::<u64>(), Ok(1));
+            assert_eq!(p.depth, 1);
+            p.restore(start.clone());
+            assert_eq!(p.depth, 0);
+            assert!(p.context.is_empty());
+
+            // reading again from the checkpoint gives the same tokens and positions
+            let list = p.peek_token();
+            assert_eq!(
+                (list.token.kind, list.line, list.col),
+                (TokenKind::StartSquare, 0, 4)
+            );
+            assert!(matches!(Value::parse(&mut p), Ok(Value::List(list)) if list.len() == 2));
+            let b = p.checkpoint();
+            assert_eq!(p.parse_path().unwrap().key, "b");
+            assert_eq!(p.comments.as_ref().map(Vec::len), Some(1));
+
+            // comments read after a checkpoint are dropped when going back to it
+            p.restore(start);
+            assert_eq!(p.take_comments(), []);
+            p.skip_value().unwrap();
+            p.restore(b);
+            let next = p.peek_token();
+            assert_eq!((next.line, next.col), (1, 0), "tokenized: {tokenized}");
+            assert!(Value::parse_file(&mut p).is_ok());
+            p.end_of_input().unwrap();
+        }
+    }
 }
@@ -0,0 +1,437 @@
+//! Paths to values inside others, like `servers[2].tls.port`.
+//!
+//! A path is a list of [`Segment`]s, the keys of maps and the indices of lists. It
+//! is written with a `.` before every key but the first and `[n]` for indices. Keys
+//! that are not identifiers are quoted, like `hosts."example.com".port`, where `\"`
+//! and `\\` are a quote and a backslash.
+//!
+//! ```
+//! # use ycf::Path;
+//! let path: Path = r#"hosts."example.com".ports[0]"#.parse()?;
+//! assert_eq!(path.len(), 4);
+//! assert_eq!(path.to_string(), r#"hosts."example.com".ports[0]"#);
+//! # Ok::<(), ycf::Error>(())
+//! ```
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{fmt, slice, str::FromStr};
+
+use crate::cursor::{is_ident_continue, is_ident_start};
+use crate::error::{Error, Result};
+use crate::parse::ContextFrame;
+
+/// The path to a value inside another. The empty path is the value itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+/// One step along a [`Path`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Segment {
+    /// The value of a key in a map.
+    Key(String),
+    /// The element of a list at an index.
+    Index(usize),
+}
+
+impl Path {
+    /// The empty path.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Segment> {
+        self.segments.iter()
+    }
+
+    pub fn push(&mut self, segment: Segment) {
+        self.segments.push(segment);
+    }
+
+    pub fn push_key(&mut self, key: impl Into<String>) {
+        self.push(Segment::Key(key.into()));
+    }
+
+    pub fn push_index(&mut self, index: usize) {
+        self.push(Segment::Index(index));
+    }
+
+    pub fn pop(&mut self) -> Option<Segment> {
+        self.segments.pop()
+    }
+
+    /// Keeps the first `len` segments of the path, removing the rest.
+    pub fn truncate(&mut self, len: usize) {
+        self.segments.truncate(len);
+    }
+
+    pub fn last(&self) -> Option<&Segment> {
+        self.segments.last()
+    }
+
+    /// The path without its last segment, or `None` if it is empty.
+    pub fn parent(&self) -> Option<Path> {
+        let (_, parent) = self.segments.split_last()?;
+        Some(parent.iter().cloned().collect())
+    }
+
+    /// Whether `prefix` is the start of this path, or all of it.
+    pub fn starts_with(&self, prefix: &Path) -> bool {
+        self.segments.starts_with(&prefix.segments)
+    }
+}
+
+impl FromStr for Path {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Self> {
+        Ok(parse(src)?
+            .into_iter()
+            .map(|part| match part {
+                Part::Bare(key) => Segment::Key(key.to_owned()),
+                Part::Quoted(key) => Segment::Key(key),
+                Part::Index(index) => Segment::Index(index),
+            })
+            .collect())
+    }
+}
+
+/// A segment of a pattern for [`Value::query`](crate::Value::query).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Pattern {
+    Segment(Segment),
+    // `*`, any one key or element
+    Any,
+    // `**`, any number of keys and elements, or none at all
+    AnyDepth,
+}
+
+/// Parses a pattern for [`Value::query`](crate::Value::query), which is written like a
+/// path, but where the bare keys `*` and `**` are wildcards.
+pub(crate) fn parse_pattern(src: &str) -> Result<Vec<Pattern>> {
+    Ok(parse(src)?
+        .into_iter()
+        .map(|part| match part {
+            Part::Bare("*") => Pattern::Any,
+            Part::Bare("**") => Pattern::AnyDepth,
+            Part::Bare(key) => Pattern::Segment(Segment::Key(key.to_owned())),
+            Part::Quoted(key) => Pattern::Segment(Segment::Key(key)),
+            Part::Index(index) => Pattern::Segment(Segment::Index(index)),
+        })
+        .collect())
+}
+
+/// A segment of a path as it is written.
+enum Part<'a> {
+    Bare(&'a str),
+    Quoted(String),
+    Index(usize),
+}
+
+fn parse(src: &str) -> Result<Vec<Part<'_>>> {
+    let error = |reason: &str| Error::custom(format_args!("invalid path `{src}`: {reason}"));
+    let mut parts = Vec::new();
+    let mut rest = src;
+    while !rest.is_empty() {
+        if let Some(index) = rest.strip_prefix('[') {
+            let (index, after) = index
+                .split_once(']')
+                .ok_or_else(|| error("expected a `]`"))?;
+            let index = index
+                .parse()
+                .map_err(|_| error("expected an index between `[` and `]`"))?;
+            parts.push(Part::Index(index));
+            rest = after;
+            continue;
+        }
+        if !parts.is_empty() {
+            rest = rest
+                .strip_prefix('.')
+                .ok_or_else(|| error("expected a `.` or `[` after a key or index"))?;
+        }
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut key = String::new();
+            let mut chars = quoted.char_indices();
+            loop {
+                match chars.next() {
+                    Some((i, '"')) => {
+                        rest = &quoted[i + 1..];
+                        break;
+                    }
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c @ ('"' | '\\'))) => key.push(c),
+                        _ => return Err(error("expected `\"` or `\\` after a `\\`")),
+                    },
+                    Some((_, c)) => key.push(c),
+                    None => return Err(error("expected a closing `\"`")),
+                }
+            }
+            parts.push(Part::Quoted(key));
+        } else {
+            let end = rest.find(['.', '[', ']', '"']).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(error("expected a key"));
+            }
+            parts.push(Part::Bare(&rest[..end]));
+            rest = &rest[end..];
+        }
+    }
+    Ok(parts)
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Key(key) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    write_key(f, key)?;
+                }
+                Segment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `key` bare if it could be written that way in a document, and quoted if not.
+fn write_key(f: &mut fmt::Formatter<'_>, key: &str) -> fmt::Result {
+    let mut chars = key.chars();
+    let bare = chars.next().is_some_and(is_ident_start)
+        && chars.all(is_ident_continue)
+        && !matches!(key, "true" | "false" | "null");
+    if bare {
+        return f.write_str(key);
+    }
+    f.write_str("\"")?;
+    for c in key.chars() {
+        if matches!(c, '"' | '\\') {
+            f.write_str("\\")?;
+        }
+        write!(f, "{c}")?;
+    }
+    f.write_str("\"")
+}
+
+impl FromIterator<Segment> for Path {
+    fn from_iter<I: IntoIterator<Item = Segment>>(iter: I) -> Self {
+        Self {
+            segments: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<Segment> for Path {
+    fn extend<I: IntoIterator<Item = Segment>>(&mut self, iter: I) {
+        self.segments.extend(iter);
+    }
+}
+
+impl IntoIterator for Path {
+    type Item = Segment;
+    type IntoIter = vec::IntoIter<Segment>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Path {
+    type Item = &'a Segment;
+    type IntoIter = slice::Iter<'a, Segment>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.iter()
+    }
+}
+
+impl From<&[ContextFrame<'_>]> for Path {
+    fn from(context: &[ContextFrame<'_>]) -> Self {
+        context
+            .iter()
+            .map(|frame| match frame {
+                ContextFrame::Key(key) => Segment::Key((**key).to_owned()),
+                ContextFrame::Element(i) => Segment::Index(*i),
+            })
+            .collect()
+    }
+}
+
+/// Something that can be used as a [`Path`]: a path itself, or a string that is
+/// parsed as one.
+pub trait AsPath {
+    /// The path, or `None` if this is a string that is not a valid path.
+    fn as_path(&self) -> Option<Cow<'_, Path>>;
+}
+
+impl AsPath for Path {
+    fn as_path(&self) -> Option<Cow<'_, Path>> {
+        Some(Cow::Borrowed(self))
+    }
+}
+
+impl AsPath for str {
+    fn as_path(&self) -> Option<Cow<'_, Path>> {
+        self.parse().ok().map(Cow::Owned)
+    }
+}
+
+impl AsPath for String {
+    fn as_path(&self) -> Option<Cow<'_, Path>> {
+        self.as_str().as_path()
+    }
+}
+
+impl<T: AsPath + ?Sized> AsPath for &T {
+    fn as_path(&self) -> Option<Cow<'_, Path>> {
+        (**self).as_path()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    #[test]
+    fn paths() {
+        let path: Path = r#"hosts."example.com".ports[0][1]"#.parse().unwrap();
+        assert_eq!(
+            path.segments(),
+            [
+                Segment::Key("hosts".into()),
+                Segment::Key("example.com".into()),
+                Segment::Key("ports".into()),
+                Segment::Index(0),
+                Segment::Index(1),
+            ]
+        );
+        for (src, shown) in [
+            ("", ""),
+            ("[0].a", "[0].a"),
+            ("limits.80", r#"limits."80""#),
+            (r#"a."say \"hi\"".b"#, r#"a."say \"hi\"".b"#),
+            (r#""true".x"#, r#""true".x"#),
+            (r#""""#, r#""""#),
+        ] {
+            let path: Path = src.parse().unwrap();
+            assert_eq!(path.to_string(), shown);
+            assert_eq!(shown.parse::<Path>().unwrap(), path);
+        }
+        for invalid in ["a..b", "a[", "a[x]", "a[0]b", r#"a."b"#, r#"a."\n""#, ".a"] {
+            let err = invalid.parse::<Path>().unwrap_err();
+            assert!(err.to_string().starts_with("invalid path"), "{err}");
+        }
+
+        let value: Value = r#"hosts = { "example.com" = { ports = [80 443] } }"#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            value.get_path(r#"hosts."example.com".ports[1]"#),
+            Some(&Value::U64(443))
+        );
+        let mut path = Path::new();
+        path.extend([
+            Segment::Key("hosts".into()),
+            Segment::Key("example.com".into()),
+        ]);
+        path.push_key("ports");
+        assert!(matches!(value.get_path(&path), Some(Value::List(_))));
+        assert_eq!(path.parent().unwrap().len(), 2);
+        assert!(path.starts_with(&path.parent().unwrap()));
+    }
+
+    #[test]
+    fn query() {
+        let value: Value = r#"
+            servers = [
+                { host = "a" port = 1 }
+                { host = "b" port = 2 tls = { port = 3 } }
+            ]
+            db = { port = 4 password = "x" replica.password = "y" }
+            "*" = 5
+            "#
+        .parse()
+        .unwrap();
+        let query = |pattern| {
+            value
+                .query(pattern)
+                .map(|(path, value)| format!("{path} = {value:?}"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            query("servers.*.port"),
+            ["servers[0].port = U64(1)", "servers[1].port = U64(2)"]
+        );
+        assert_eq!(
+            query("**.port"),
+            [
+                "db.port = U64(4)",
+                "servers[0].port = U64(1)",
+                "servers[1].port = U64(2)",
+                "servers[1].tls.port = U64(3)",
+            ]
+        );
+        assert_eq!(
+            query("**.password"),
+            [
+                r#"db.password = String("x")"#,
+                r#"db.replica.password = String("y")"#
+            ]
+        );
+        assert_eq!(
+            query("servers[1].**.port"),
+            ["servers[1].port = U64(2)", "servers[1].tls.port = U64(3)"]
+        );
+        assert_eq!(query(r#""*""#), [r#""*" = U64(5)"#]);
+        assert_eq!(query("*").len(), 3);
+        // every value, the root included
+        assert_eq!(query("**").len(), 16);
+        assert!(query("servers.*.missing").is_empty());
+        assert!(query("a..b").is_empty());
+    }
+
+    #[test]
+    fn get_path() {
+        let value: Value = "server.port = 8080 matrix = [[1 2] [3 4]] list = [{ a = true }]"
+            .parse()
+            .unwrap();
+        assert_eq!(value.get_path("server.port"), Some(&Value::U64(8080)));
+        assert_eq!(value.get_path("matrix[1][0]"), Some(&Value::U64(3)));
+        assert_eq!(value.get_path("list[0].a"), Some(&Value::Bool(true)));
+        assert_eq!(value.get_path(""), Some(&value));
+        for missing in [
+            "server.host",
+            "server.port.x",
+            "matrix[2]",
+            "matrix[x]",
+            "matrix[0",
+            "a..b",
+        ] {
+            assert_eq!(value.get_path(missing), None, "{missing}");
+        }
+
+        let list: Value = "[{ a = 1 }]".parse().unwrap();
+        assert_eq!(list.get_path("[0].a"), Some(&Value::U64(1)));
+    }
+}
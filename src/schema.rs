@@ -0,0 +1,1117 @@
+//! Schemas, which describe what a document should have in it and are written in ycf
+//! themselves. A schema checks documents and reports every [`Violation`] with where
+//! it is in the document, and fills in defaults for keys that are not set.
+//!
+//! The schema describes the root of the document, and every value in it is described
+//! by a map with any of these keys:
+//!
+//! - `type`: what the value is, one of `"null"`, `"bool"`, `"integer"`, `"float"`,
+//!   `"string"`, `"duration"`, `"list"` and `"map"`, or a list of them. A float can
+//!   also be written as an integer.
+//! - `required`: whether the key has to be set, `false` if not given.
+//! - `default`: the value for the key when it is not set.
+//! - `min` and `max`: the bounds of a number or duration, inclusive.
+//! - `enum`: a list of the values that are allowed.
+//! - `fields`: the keys of a map, each with its own description.
+//! - `additional`: whether a map can have keys that are not in its `fields`, `true`
+//!   if not given, or the description of the values of those keys.
+//! - `items`: the description of the elements of a list.
+//! - `description`: what the value is for, which is only for people reading it.
+//! - `deprecated`: whether the key should no longer be used, or why it should not.
+//! - `renamed`: the path from the root that the key has moved to, which makes it
+//!   deprecated. [`Schema::migrate`] moves the value there.
+//!
+//! Deprecated keys that are set are [`Severity::Warning`] violations, and everything
+//! else is a [`Severity::Error`].
+//!
+//! ```
+//! # use ycf::schema::Schema;
+//! let schema: Schema = r#"
+//! fields = {
+//!     host = { type = "string" default = "localhost" }
+//!     port = { type = "integer" min = 1 max = 65535 required = true }
+//!     mode = { enum = ["dev" "prod"] }
+//! }
+//! additional = false
+//! "#
+//! .parse()?;
+//!
+//! let violations = schema.validate_str("port = 0")?;
+//! assert_eq!(violations[0].message, "Expected at least 1 for 'port', found 0");
+//! assert_eq!(violations[0].span.unwrap().col, 7);
+//! # Ok::<(), ycf::Error>(())
+//! ```
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cmp::Ordering, fmt, str::FromStr};
+
+use serde::de::DeserializeOwned;
+
+use crate::cursor::TokenKind;
+use crate::error::{self, Error, Result};
+use crate::parse::{ParseError, ParseErrorKind, Parser, SpanToken};
+use crate::path::{Path, Segment};
+use crate::value::{Key, Map, Value};
+
+/// A compiled schema, read from a document with [`FromStr`] or from a [`Value`] with
+/// [`Schema::new`].
+#[derive(Clone, Debug)]
+pub struct Schema {
+    root: Node,
+    // the keys that have been renamed, by their old path, with their new one
+    renames: Vec<(Path, Path)>,
+}
+
+/// Something in a document that its schema does not allow.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub path: Path,
+    pub message: String,
+    pub severity: Severity,
+    /// Where the value is in the document, or for a key that is missing, the map it
+    /// is missing from. This is `None` for values that were not read from a document.
+    pub span: Option<SpanToken>,
+}
+
+/// How bad a [`Violation`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// The document does not match the schema.
+    Error,
+    /// The document matches, but uses keys that are deprecated.
+    Warning,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<Violation> for Error {
+    fn from(violation: Violation) -> Self {
+        match violation.span {
+            Some(span) => ParseError::new(span, ParseErrorKind::Schema(violation.message)).into(),
+            None => Error::custom(violation.message),
+        }
+    }
+}
+
+/// The description of one value.
+#[derive(Clone, Debug, Default)]
+struct Node {
+    // no types at all is any type
+    types: Vec<Type>,
+    required: bool,
+    default: Option<Value>,
+    min: Option<Value>,
+    max: Option<Value>,
+    one_of: Option<Vec<Value>>,
+    fields: BTreeMap<Key, Node>,
+    additional: Additional,
+    items: Option<Box<Node>>,
+    // why the key is deprecated, if it is, which can be empty
+    deprecated: Option<String>,
+    renamed: Option<Path>,
+}
+
+#[derive(Clone, Debug, Default)]
+enum Additional {
+    #[default]
+    Allow,
+    Deny,
+    Schema(Box<Node>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    Null,
+    Bool,
+    Integer,
+    Float,
+    String,
+    Duration,
+    List,
+    Map,
+}
+
+const TYPES: &[&str] = &[
+    "null", "bool", "integer", "float", "string", "duration", "list", "map",
+];
+
+const SCHEMA_KEYS: &[&str] = &[
+    "type",
+    "required",
+    "default",
+    "min",
+    "max",
+    "enum",
+    "fields",
+    "additional",
+    "items",
+    "description",
+    "deprecated",
+    "renamed",
+];
+
+impl Type {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "null" => Type::Null,
+            "bool" => Type::Bool,
+            "integer" => Type::Integer,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "duration" => Type::Duration,
+            "list" => Type::List,
+            "map" => Type::Map,
+            _ => return None,
+        })
+    }
+
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Type::Null,
+            Value::Bool(_) => Type::Bool,
+            Value::U64(_) | Value::I64(_) | Value::U128(_) | Value::I128(_) => Type::Integer,
+            Value::F64(_) => Type::Float,
+            Value::String(_) => Type::String,
+            Value::Duration(_) => Type::Duration,
+            Value::List(_) => Type::List,
+            Value::Map(_) => Type::Map,
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        let found = Type::of(value);
+        found == self || (self == Type::Float && found == Type::Integer)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Type::Null => "null",
+            Type::Bool => "a bool",
+            Type::Integer => "an integer",
+            Type::Float => "a float",
+            Type::String => "a string",
+            Type::Duration => "a duration",
+            Type::List => "a list",
+            Type::Map => "a map",
+        }
+    }
+}
+
+impl Schema {
+    /// Compiles the schema in `schema`. Errors do not say where they are, unlike
+    /// reading the schema from a document, which does.
+    pub fn new(schema: &Value) -> Result<Self> {
+        Self::compile(schema, &Locations::default())
+    }
+
+    fn compile(schema: &Value, locations: &Locations) -> Result<Self> {
+        let root = Compiler { locations }.node(schema, &mut Path::new())?;
+        let mut renames = Vec::new();
+        find_renames(&root, &mut Path::new(), &mut renames);
+        Ok(Self { root, renames })
+    }
+
+    /// Checks `value`, returning every violation in it in order.
+    pub fn validate(&self, value: &Value) -> Vec<Violation> {
+        self.check(value, &Locations::default())
+    }
+
+    fn check(&self, value: &Value, locations: &Locations) -> Vec<Violation> {
+        let mut checker = Checker::new(value, &self.renames, locations);
+        checker.check(&self.root, value, &mut Path::new());
+        checker.violations
+    }
+
+    /// Reads the document `src` and checks it like [`validate`](Self::validate),
+    /// with the span of every violation. Syntax errors are returned as errors.
+    pub fn validate_str(&self, src: &str) -> Result<Vec<Violation>> {
+        Ok(self.check_str(src)?.1)
+    }
+
+    fn check_str(&self, src: &str) -> Result<(Value, Vec<Violation>)> {
+        let value: Value = src.parse()?;
+        let violations = self.check(&value, &Locations::read(src));
+        Ok((value, violations))
+    }
+
+    /// Sets the keys that are not set in `value` to their defaults. A map that is not
+    /// set, but has fields with defaults, is set to a map of them.
+    pub fn apply_defaults(&self, value: &mut Value) {
+        apply_defaults(&self.root, value);
+    }
+
+    /// Moves the values of keys that have been renamed to their new paths, unless
+    /// those are set as well. Only keys in the `fields` of maps can be renamed, not
+    /// ones in the elements of lists or the `additional` keys of maps.
+    pub fn migrate(&self, value: &mut Value) {
+        for (old, new) in &self.renames {
+            if value.get_path(new).is_some() {
+                continue;
+            }
+            let (Some(parent), Some(Segment::Key(key))) = (old.parent(), old.last()) else {
+                continue;
+            };
+            let Some(Value::Map(map)) = value.get_path_mut(&parent) else {
+                continue;
+            };
+            let Some(moved) = map.remove(key.as_str()) else {
+                continue;
+            };
+            insert_path(value, new, moved);
+        }
+    }
+
+    /// Reads the document `src`, checks it, moves its renamed keys, fills in its
+    /// defaults and deserializes a `T` from it. The first error is returned, and
+    /// warnings are left out.
+    pub fn deserialize_str<T: DeserializeOwned>(&self, src: &str) -> Result<T> {
+        let (mut value, violations) = self.check_str(src)?;
+        let mut errors = violations
+            .into_iter()
+            .filter(|violation| violation.severity == Severity::Error);
+        if let Some(violation) = errors.next() {
+            return Err(violation.into());
+        }
+        self.migrate(&mut value);
+        self.apply_defaults(&mut value);
+        value.deserialize_into()
+    }
+}
+
+impl FromStr for Schema {
+    type Err = Error;
+
+    /// Reads and compiles a schema, where errors in it have a span.
+    fn from_str(src: &str) -> Result<Self> {
+        let schema: Value = src.parse()?;
+        Self::compile(&schema, &Locations::read(src))
+    }
+}
+
+struct Compiler<'l> {
+    locations: &'l Locations,
+}
+
+impl Compiler<'_> {
+    /// Compiles the description at `path` in the schema.
+    fn node(&self, schema: &Value, path: &mut Path) -> Result<Node> {
+        let Value::Map(map) = schema else {
+            return Err(self.error(path, format!("Expected a map for '{path}'")));
+        };
+        let mut node = Node::default();
+        for (key, value) in map {
+            path.push_key(&**key);
+            match &**key {
+                "type" => {
+                    let names = match value {
+                        Value::List(names) => names.iter().collect(),
+                        value => Vec::from([value]),
+                    };
+                    for name in names {
+                        let ty = match name {
+                            Value::String(name) => Type::from_name(name),
+                            _ => None,
+                        };
+                        node.types.push(ty.ok_or_else(|| {
+                            self.error(
+                                path,
+                                format!("Expected one of {} for '{path}'", display_names(TYPES)),
+                            )
+                        })?);
+                    }
+                }
+                "required" => {
+                    node.required = self.bool(value, path)?;
+                }
+                "default" => node.default = Some(value.clone()),
+                "min" | "max" => {
+                    if !matches!(
+                        value,
+                        Value::U64(_)
+                            | Value::I64(_)
+                            | Value::U128(_)
+                            | Value::I128(_)
+                            | Value::F64(_)
+                            | Value::Duration(_)
+                    ) {
+                        return Err(
+                            self.error(path, format!("Expected a number or duration for '{path}'"))
+                        );
+                    }
+                    match &**key {
+                        "min" => node.min = Some(value.clone()),
+                        _ => node.max = Some(value.clone()),
+                    }
+                }
+                "enum" => match value {
+                    Value::List(values) => node.one_of = Some(values.clone()),
+                    _ => return Err(self.error(path, format!("Expected a list for '{path}'"))),
+                },
+                "fields" => {
+                    let Value::Map(fields) = value else {
+                        return Err(self.error(path, format!("Expected a map for '{path}'")));
+                    };
+                    for (name, field) in fields {
+                        path.push_key(&**name);
+                        node.fields.insert(name.clone(), self.node(field, path)?);
+                        path.pop();
+                    }
+                }
+                "additional" => {
+                    node.additional = match value {
+                        Value::Bool(true) => Additional::Allow,
+                        Value::Bool(false) => Additional::Deny,
+                        value => Additional::Schema(Box::new(self.node(value, path)?)),
+                    }
+                }
+                "items" => node.items = Some(Box::new(self.node(value, path)?)),
+                "description" => {
+                    self.string(value, path)?;
+                }
+                "deprecated" => {
+                    node.deprecated = match value {
+                        Value::Bool(true) => Some(String::new()),
+                        Value::Bool(false) => None,
+                        Value::String(reason) => Some(reason.clone()),
+                        _ => {
+                            return Err(
+                                self.error(path, format!("Expected a bool or string for '{path}'"))
+                            )
+                        }
+                    }
+                }
+                "renamed" => {
+                    let new = self.string(value, path)?.parse().ok().filter(|new: &Path| {
+                        !new.is_empty()
+                            && new.iter().all(|segment| matches!(segment, Segment::Key(_)))
+                    });
+                    let Some(new) = new else {
+                        return Err(self.error(
+                            path,
+                            format!("Expected a path of keys for '{path}', like 'server.port'"),
+                        ));
+                    };
+                    node.renamed = Some(new);
+                }
+                key => {
+                    let message = match error::closest(key, SCHEMA_KEYS) {
+                        Some(closest) => {
+                            format!("Unknown schema key '{path}', did you mean '{closest}'?")
+                        }
+                        None => format!("Unknown schema key '{path}'"),
+                    };
+                    return Err(self.error_at_key(path, message));
+                }
+            }
+            path.pop();
+        }
+
+        if let Some(default) = &node.default {
+            path.push_key("default");
+            let locations = Locations::default();
+            let mut checker = Checker::new(default, &[], &locations);
+            checker.check(&node, default, &mut Path::new());
+            let mut errors = checker.violations.iter();
+            if let Some(violation) = errors.find(|violation| violation.severity == Severity::Error)
+            {
+                let message = format!("The default does not match its schema: {violation}");
+                return Err(self.error(path, message));
+            }
+            path.pop();
+        }
+        Ok(node)
+    }
+
+    fn bool(&self, value: &Value, path: &Path) -> Result<bool> {
+        match value {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(self.error(path, format!("Expected a bool for '{path}'"))),
+        }
+    }
+
+    fn string<'v>(&self, value: &'v Value, path: &Path) -> Result<&'v str> {
+        match value {
+            Value::String(value) => Ok(value),
+            _ => Err(self.error(path, format!("Expected a string for '{path}'"))),
+        }
+    }
+
+    /// An error in the value at `path` in the schema.
+    fn error(&self, path: &Path, message: String) -> Error {
+        Violation {
+            path: path.clone(),
+            message,
+            severity: Severity::Error,
+            span: self.locations.value(path),
+        }
+        .into()
+    }
+
+    /// An error in the key at `path` in the schema.
+    fn error_at_key(&self, path: &Path, message: String) -> Error {
+        Violation {
+            path: path.clone(),
+            message,
+            severity: Severity::Error,
+            span: self.locations.key(path),
+        }
+        .into()
+    }
+}
+
+struct Checker<'a> {
+    // the value being checked, for finding the old keys of ones that were renamed
+    root: &'a Value,
+    renames: &'a [(Path, Path)],
+    locations: &'a Locations,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(root: &'a Value, renames: &'a [(Path, Path)], locations: &'a Locations) -> Self {
+        Self {
+            root,
+            renames,
+            locations,
+            violations: Vec::new(),
+        }
+    }
+
+    /// Checks `value`, which is at `path`, against `node`.
+    fn check(&mut self, node: &Node, value: &Value, path: &mut Path) {
+        if !node.types.is_empty() && !node.types.iter().any(|ty| ty.matches(value)) {
+            let mut expected = String::new();
+            for (i, ty) in node.types.iter().enumerate() {
+                if i > 0 {
+                    expected.push_str(" or ");
+                }
+                expected.push_str(ty.name());
+            }
+            let found = Type::of(value).name();
+            self.violation(
+                path,
+                format!("Expected {expected}{}, found {found}", at(path)),
+            );
+            return;
+        }
+        if let Some(one_of) = &node.one_of {
+            if !one_of.contains(value) {
+                let mut expected = String::new();
+                for (i, allowed) in one_of.iter().enumerate() {
+                    if i > 0 {
+                        expected.push_str(", ");
+                    }
+                    expected.push_str(&describe(allowed));
+                }
+                let found = describe(value);
+                let message = format!("Expected one of {expected}{}, found {found}", at(path));
+                self.violation(path, message);
+            }
+        }
+        if let Some(min) = &node.min {
+            if compare(value, min) == Some(Ordering::Less) {
+                let message = format!(
+                    "Expected at least {}{}, found {}",
+                    describe(min),
+                    at(path),
+                    describe(value)
+                );
+                self.violation(path, message);
+            }
+        }
+        if let Some(max) = &node.max {
+            if compare(value, max) == Some(Ordering::Greater) {
+                let message = format!(
+                    "Expected at most {}{}, found {}",
+                    describe(max),
+                    at(path),
+                    describe(value)
+                );
+                self.violation(path, message);
+            }
+        }
+
+        match value {
+            Value::Map(map) => self.check_map(node, map, path),
+            Value::List(list) => {
+                if let Some(items) = &node.items {
+                    for (i, item) in list.iter().enumerate() {
+                        path.push_index(i);
+                        self.check(items, item, path);
+                        path.pop();
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn check_map(&mut self, node: &Node, map: &Map, path: &mut Path) {
+        // where keys that are missing are reported
+        let map_span = self.locations.key(path);
+        for (name, field) in &node.fields {
+            path.push_key(&**name);
+            match map.get(name) {
+                Some(value) => {
+                    self.deprecation(field, path);
+                    self.check(field, value, path);
+                }
+                // a key that is set under its old name is warned about there instead
+                None if field.required && !self.set_before_rename(path) => {
+                    self.violations.push(Violation {
+                        path: path.clone(),
+                        message: format!("Missing required key '{path}'"),
+                        severity: Severity::Error,
+                        span: map_span,
+                    });
+                }
+                None => (),
+            }
+            path.pop();
+        }
+        for (key, value) in map {
+            if node.fields.contains_key(key) {
+                continue;
+            }
+            path.push_key(&**key);
+            match &node.additional {
+                Additional::Allow => (),
+                Additional::Deny => {
+                    let fields: Vec<&str> = node.fields.keys().map(|key| &**key).collect();
+                    let message = match error::closest(key, &fields) {
+                        Some(closest) => {
+                            format!("Unknown key '{path}', did you mean '{closest}'?")
+                        }
+                        None => format!("Unknown key '{path}'"),
+                    };
+                    let span = self.locations.key(path);
+                    self.violations.push(Violation {
+                        path: path.clone(),
+                        message,
+                        severity: Severity::Error,
+                        span,
+                    });
+                }
+                Additional::Schema(schema) => self.check(schema, value, path),
+            }
+            path.pop();
+        }
+    }
+
+    fn violation(&mut self, path: &Path, message: String) {
+        self.violations.push(Violation {
+            path: path.clone(),
+            message,
+            severity: Severity::Error,
+            span: self.locations.value(path),
+        });
+    }
+
+    /// Warns about the key at `path`, which is set, if `node` says it is deprecated.
+    fn deprecation(&mut self, node: &Node, path: &Path) {
+        let mut message = match (&node.renamed, &node.deprecated) {
+            (Some(new), _) => format!("'{path}' is deprecated, use '{new}' instead"),
+            (None, Some(_)) => format!("'{path}' is deprecated"),
+            (None, None) => return,
+        };
+        if let Some(reason) = node.deprecated.as_ref().filter(|reason| !reason.is_empty()) {
+            message.push_str(": ");
+            message.push_str(reason);
+        }
+        self.violations.push(Violation {
+            path: path.clone(),
+            message,
+            severity: Severity::Warning,
+            span: self.locations.key(path),
+        });
+    }
+
+    /// Whether a key that was renamed to `path` is set under its old path.
+    fn set_before_rename(&self, path: &Path) -> bool {
+        self.renames
+            .iter()
+            .any(|(old, new)| new == path && self.root.get_path(old).is_some())
+    }
+}
+
+fn apply_defaults(node: &Node, value: &mut Value) {
+    match value {
+        Value::Map(map) => {
+            for (name, field) in &node.fields {
+                match map.get_mut(name) {
+                    Some(value) => apply_defaults(field, value),
+                    None => {
+                        if let Some(default) = defaults(field) {
+                            map.insert(name.clone(), default);
+                        }
+                    }
+                }
+            }
+            if let Additional::Schema(schema) = &node.additional {
+                for (key, value) in map.iter_mut() {
+                    if !node.fields.contains_key(key) {
+                        apply_defaults(schema, value);
+                    }
+                }
+            }
+        }
+        Value::List(list) => {
+            if let Some(items) = &node.items {
+                for item in list {
+                    apply_defaults(items, item);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Finds the keys in `node`, which is at `path`, that have been renamed.
+fn find_renames(node: &Node, path: &mut Path, out: &mut Vec<(Path, Path)>) {
+    for (name, field) in &node.fields {
+        path.push_key(&**name);
+        if let Some(new) = &field.renamed {
+            out.push((path.clone(), new.clone()));
+        }
+        find_renames(field, path, out);
+        path.pop();
+    }
+}
+
+/// Sets `path`, which only has keys, in `value` to `new`, making maps for the keys
+/// along it that are not set. Values along it that are not maps are left as they are.
+fn insert_path(value: &mut Value, path: &Path, new: Value) {
+    let Some((Segment::Key(last), parents)) = path.segments().split_last() else {
+        return;
+    };
+    let mut value = value;
+    for segment in parents {
+        let (Value::Map(map), Segment::Key(key)) = (value, segment) else {
+            return;
+        };
+        value = map
+            .entry(Key::from(key.as_str()))
+            .or_insert_with(|| Value::Map(Map::new()));
+    }
+    if let Value::Map(map) = value {
+        map.insert(Key::from(last.as_str()), new);
+    }
+}
+
+/// The value for a key described by `node` that is not set, if it has one.
+fn defaults(node: &Node) -> Option<Value> {
+    if let Some(default) = &node.default {
+        return Some(default.clone());
+    }
+    let map: Map = node
+        .fields
+        .iter()
+        .filter_map(|(name, field)| Some((name.clone(), defaults(field)?)))
+        .collect();
+    (!map.is_empty()).then_some(Value::Map(map))
+}
+
+/// How `value` compares to the bound `bound`, if they are both numbers or durations.
+fn compare(value: &Value, bound: &Value) -> Option<Ordering> {
+    match (value, bound) {
+        (Value::Duration(value), Value::Duration(bound)) => Some(value.cmp(bound)),
+        _ => match (integer(value), integer(bound)) {
+            (Some(value), Some(bound)) => Some(value.cmp(&bound)),
+            _ => float(value)?.partial_cmp(&float(bound)?),
+        },
+    }
+}
+
+/// An integer as whether it is not negative and its bits, which sort like the
+/// integers do.
+fn integer(value: &Value) -> Option<(bool, u128)> {
+    let v: i128 = match *value {
+        Value::U64(v) => return Some((true, v.into())),
+        Value::U128(v) => return Some((true, v)),
+        Value::I64(v) => v.into(),
+        Value::I128(v) => v,
+        _ => return None,
+    };
+    Some((v >= 0, v as u128))
+}
+
+fn float(value: &Value) -> Option<f64> {
+    match *value {
+        Value::U64(v) => Some(v as f64),
+        Value::I64(v) => Some(v as f64),
+        Value::U128(v) => Some(v as f64),
+        Value::I128(v) => Some(v as f64),
+        Value::F64(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Writes `value` for a message.
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::U64(v) => v.to_string(),
+        Value::I64(v) => v.to_string(),
+        Value::U128(v) => v.to_string(),
+        Value::I128(v) => v.to_string(),
+        Value::F64(v) => v.to_string(),
+        Value::String(v) => format!("{v:?}"),
+        Value::Duration(v) => format!("{v:?}"),
+        Value::List(_) | Value::Map(_) => Type::of(value).name().to_string(),
+    }
+}
+
+fn display_names(names: &[&str]) -> String {
+    let names: Vec<String> = names.iter().map(|name| format!("'{name}'")).collect();
+    names.join(", ")
+}
+
+/// Where a message is about, if it is not the root.
+fn at(path: &Path) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" for '{path}'")
+    }
+}
+
+/// Where the keys and values of a document are, by their path.
+#[derive(Default)]
+struct Locations {
+    keys: BTreeMap<Path, SpanToken>,
+    values: BTreeMap<Path, SpanToken>,
+}
+
+impl Locations {
+    /// Finds the keys and values in `src`, which has to be a valid document.
+    fn read(src: &str) -> Self {
+        let mut locations = Self::default();
+        let mut parser = Parser::new(src);
+        if parser.starts_top_level() {
+            locations.read_entries(&mut parser, TokenKind::Eof, &mut Path::new());
+        } else {
+            locations.read_value(&mut parser, &mut Path::new());
+        }
+        locations
+    }
+
+    /// The key at `path`, or its value if it is not in a map.
+    fn key(&self, path: &Path) -> Option<SpanToken> {
+        self.keys.get(path).or(self.values.get(path)).copied()
+    }
+
+    /// The value at `path`, or the key that sets it if it is a map made by dotted keys.
+    fn value(&self, path: &Path) -> Option<SpanToken> {
+        self.values.get(path).or(self.keys.get(path)).copied()
+    }
+
+    /// Reads the entries of a map up to and including `closer`.
+    fn read_entries(&mut self, parser: &mut Parser<'_>, closer: TokenKind, path: &mut Path) {
+        loop {
+            let next = parser.next_token();
+            if next.token.kind == closer || next.token.kind == TokenKind::Eof {
+                return;
+            }
+            let prefix = path.len();
+            let mut key = next;
+            loop {
+                let text = parser.src(key.token);
+                // quoted keys can not have escapes, so they only need their quotes taken off
+                let text = text
+                    .strip_prefix('"')
+                    .and_then(|text| text.strip_suffix('"'))
+                    .unwrap_or(text);
+                path.push_key(text);
+                if parser.peek_kind() != TokenKind::Dot {
+                    self.keys.insert(path.clone(), key);
+                    break;
+                }
+                // a map made by a dotted key is where it is first set
+                self.keys.entry(path.clone()).or_insert(key);
+                parser.next_token();
+                key = parser.next_token();
+            }
+            // the `=`
+            parser.next_token();
+            self.read_value(parser, path);
+            path.truncate(prefix);
+        }
+    }
+
+    fn read_value(&mut self, parser: &mut Parser<'_>, path: &mut Path) {
+        let next = parser.next_token();
+        self.values.insert(path.clone(), next);
+        match next.token.kind {
+            TokenKind::StartCurly => self.read_entries(parser, TokenKind::EndCurly, path),
+            TokenKind::StartSquare => {
+                let mut i = 0;
+                while !matches!(parser.peek_kind(), TokenKind::EndSquare | TokenKind::Eof) {
+                    path.push_index(i);
+                    self.read_value(parser, path);
+                    path.pop();
+                    i += 1;
+                }
+                parser.next_token();
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[test]
+    fn schema() {
+        let schema: Schema = r#"
+            fields = {
+                name = { type = "string" required = true }
+                server = {
+                    fields = {
+                        host = { type = "string" default = "localhost" }
+                        port = { type = "integer" min = 1 max = 65535 default = 8080 }
+                        timeout = { type = "duration" max = 1m }
+                    }
+                    additional = false
+                }
+                mode = { enum = ["dev" "prod"] default = "dev" }
+                ratio = { type = ["float" "null"] min = 0 max = 1 }
+                tags = { type = "list" items = { type = "string" } }
+                env = { type = "map" additional = { type = "string" } }
+            }
+            "#
+        .parse()
+        .unwrap();
+
+        let src = r#"
+            server = { prot = 80 port = 0 timeout = 2m }
+            mode = "test"
+            ratio = 0.5
+            tags = ["a" 1]
+            env.HOME = true
+        "#;
+        let violations = schema.validate_str(src).unwrap();
+        let found: Vec<_> = violations
+            .iter()
+            .map(|violation| {
+                let span = violation.span.map(|span| (span.line, span.col));
+                (violation.path.to_string(), violation.message.as_str(), span)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "env.HOME",
+                    "Expected a string for 'env.HOME', found a bool",
+                    Some((5, 23))
+                ),
+                (
+                    "mode",
+                    r#"Expected one of "dev", "prod" for 'mode', found "test""#,
+                    Some((2, 19))
+                ),
+                // a key missing from the root is not anywhere in the document
+                ("name", "Missing required key 'name'", None),
+                (
+                    "server.port",
+                    "Expected at least 1 for 'server.port', found 0",
+                    Some((1, 40))
+                ),
+                (
+                    "server.timeout",
+                    "Expected at most 60s for 'server.timeout', found 120s",
+                    Some((1, 52))
+                ),
+                (
+                    "server.prot",
+                    "Unknown key 'server.prot', did you mean 'port'?",
+                    Some((1, 23))
+                ),
+                (
+                    "tags[1]",
+                    "Expected a string for 'tags[1]', found an integer",
+                    Some((4, 24))
+                ),
+            ]
+            .map(|(path, message, span)| (path.to_string(), message, span))
+        );
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Server {
+            host: String,
+            port: u16,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct App {
+            name: String,
+            server: Server,
+            mode: String,
+            ratio: Option<f64>,
+        }
+
+        let app: App = schema.deserialize_str(r#"name = "app" ratio = 1"#).unwrap();
+        assert_eq!(
+            app,
+            App {
+                name: "app".into(),
+                server: Server {
+                    host: "localhost".into(),
+                    port: 8080,
+                },
+                mode: "dev".into(),
+                ratio: Some(1.0),
+            }
+        );
+        let err = schema
+            .deserialize_str::<App>("name = \"app\"\nserver.port = 70000")
+            .unwrap_err();
+        assert_eq!(err.classify(), error::Category::Data);
+        assert_eq!((err.line(), err.column()), (Some(1), Some(14)));
+        assert!(err.to_string().contains("at most 65535"), "{err}");
+
+        let value: Value = "mode = \"prod\"".parse().unwrap();
+        let violations = schema.validate(&value);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].span, None);
+
+        // errors in the schema itself
+        for (src, message, col) in [
+            (
+                "tpye = \"map\"",
+                "Unknown schema key 'tpye', did you mean 'type'?",
+                0,
+            ),
+            ("type = \"text\"", "Expected one of 'null', 'bool'", 7),
+            (
+                "fields.a.min = \"x\"",
+                "Expected a number or duration for 'fields.a.min'",
+                15,
+            ),
+            (
+                "fields.a = { type = \"integer\" default = \"x\" }",
+                "The default does not match its schema: Expected an integer, found a string",
+                40,
+            ),
+        ] {
+            let err = src.parse::<Schema>().unwrap_err();
+            assert!(err.to_string().starts_with(message), "{err}");
+            assert_eq!(err.column(), Some(col), "{err}");
+        }
+    }
+
+    #[test]
+    fn schema_deprecations() {
+        let schema: Schema = r#"
+            fields = {
+                listen = {
+                    fields.port = { type = "integer" required = true }
+                }
+                port = { renamed = "listen.port" }
+                verbose = { type = "bool" deprecated = "set `log` instead" }
+                debug = { deprecated = true }
+            }
+            additional = false
+            "#
+        .parse()
+        .unwrap();
+
+        let src = "port = 80\nverbose = true";
+        let violations = schema.validate_str(src).unwrap();
+        let found: Vec<_> = violations
+            .iter()
+            .map(|violation| {
+                let span = violation.span.unwrap();
+                (violation.message.as_str(), violation.severity, span.line)
+            })
+            .collect();
+        // `listen.port` is not missing, as it is set under its old name
+        assert_eq!(
+            found,
+            [
+                (
+                    "'port' is deprecated, use 'listen.port' instead",
+                    Severity::Warning,
+                    0
+                ),
+                (
+                    "'verbose' is deprecated: set `log` instead",
+                    Severity::Warning,
+                    1
+                ),
+            ]
+        );
+
+        let mut value: Value = "port = 80 debug = 1".parse().unwrap();
+        schema.migrate(&mut value);
+        let expected: Value = "listen.port = 80 debug = 1".parse().unwrap();
+        assert_eq!(value, expected);
+        // the new key wins if both are set
+        let mut value: Value = "port = 80 listen.port = 90".parse().unwrap();
+        schema.migrate(&mut value);
+        assert_eq!(value, "port = 80 listen.port = 90".parse().unwrap());
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Listen {
+            port: u16,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct App {
+            listen: Listen,
+        }
+
+        let app: App = schema.deserialize_str("port = 80").unwrap();
+        assert_eq!(app.listen, Listen { port: 80 });
+
+        let err = r#"fields.a.renamed = "b[0]""#.parse::<Schema>().unwrap_err();
+        assert!(
+            err.to_string().starts_with("Expected a path of keys"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn wide_integer_bounds() {
+        let schema: Schema = r#"
+            fields.id = { type = "integer" min = -9223372036854775809 max = 18446744073709551616 }
+            "#
+        .parse()
+        .unwrap();
+        let messages = |src| {
+            let violations = schema.validate_str(src).unwrap();
+            violations
+                .into_iter()
+                .map(|violation| violation.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(messages("id = 18446744073709551616").is_empty());
+        assert!(messages("id = -9223372036854775809").is_empty());
+        assert!(messages("id = 0").is_empty());
+        assert!(messages("id = -1").is_empty());
+        assert_eq!(
+            messages("id = 18446744073709551617"),
+            ["Expected at most 18446744073709551616 for 'id', found 18446744073709551617"]
+        );
+        assert_eq!(
+            messages("id = -9223372036854775810"),
+            ["Expected at least -9223372036854775809 for 'id', found -9223372036854775810"]
+        );
+    }
+}
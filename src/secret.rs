@@ -0,0 +1,108 @@
+//! Values that are kept out of logs, like passwords and tokens in a config.
+
+use core::fmt;
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+/// What secrets are written as.
+pub const REDACTED: &str = "<redacted>";
+
+/// A value that is read like a `T`, but is written as `"<redacted>"` when serialized
+/// or debug printed, so that dumping a config does not show it.
+///
+/// ```
+/// # use serde::Deserialize;
+/// # use ycf::Secret;
+/// #[derive(Debug, Deserialize)]
+/// struct Database {
+///     user: String,
+///     password: Secret<String>,
+/// }
+///
+/// let db: Database = ycf::from_str(r#"user = "app" password = "hunter2""#)?;
+/// assert_eq!(db.password.expose(), "hunter2");
+/// assert!(!format!("{db:?}").contains("hunter2"));
+/// # Ok::<(), ycf::Error>(())
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// The value itself, for the code that actually needs it.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(REDACTED, f)
+    }
+}
+
+impl<T> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::de::from_str;
+    use crate::value::Value;
+
+    #[test]
+    fn secrets() {
+        #[derive(Debug, Deserialize, Serialize)]
+        struct Database {
+            user: String,
+            password: Secret<String>,
+            port: Secret<u16>,
+        }
+
+        let db: Database = from_str(r#"user = "app" password = "hunter2" port = 5432"#).unwrap();
+        assert_eq!(db.password.expose(), "hunter2");
+        assert_eq!(*db.port.expose(), 5432);
+        assert_eq!(
+            format!("{db:?}"),
+            r#"Database { user: "app", password: "<redacted>", port: "<redacted>" }"#
+        );
+        let written = crate::to_string(&db).unwrap();
+        assert!(!written.contains("hunter2"), "{written}");
+        assert!(written.contains("password = \"<redacted>\""), "{written}");
+
+        let mut value: Value = r#"db = { user = "app" password = "hunter2" } tokens = ["a" "b"]"#
+            .parse()
+            .unwrap();
+        value.redact(["db.password", "tokens[1]", "missing.key", "db.user[0]"]);
+        let expected: Value =
+            r#"db = { user = "app" password = "<redacted>" } tokens = ["a" "<redacted>"]"#
+                .parse()
+                .unwrap();
+        assert_eq!(value, expected);
+    }
+}
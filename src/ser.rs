@@ -1,7 +1,1143 @@
-// use serde::{ser, Serialize};
+use std::{fmt::Display, io, time::Duration};
 
-// use super::error::{Error, Result};
+use super::cursor::{is_ident_continue, is_ident_start};
+use super::error::{Error, Result};
+use serde::ser::{self, Impossible, Serialize};
 
-// pub struct Serializer {
-    
-// }
\ No newline at end of file
+/// The spelling used for `\u` escapes in strings and characters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnicodeEscape {
+    /// `\u{1F600}`
+    #[default]
+    Braced,
+    /// `\uD83D\uDE00`, compatible with JSON
+    Json,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub unicode_escape: UnicodeEscape,
+    // escape every non-ascii character instead of writing it as is
+    pub ascii_only: bool,
+}
+
+pub struct Serializer<W> {
+    writer: W,
+    options: Options,
+    depth: usize,
+    // whether the root map is written without braces
+    top_level: bool,
+}
+
+impl<W: io::Write> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, Options::default())
+    }
+
+    pub fn with_options(writer: W, options: Options) -> Self {
+        Self {
+            writer,
+            options,
+            depth: 0,
+            top_level: false,
+        }
+    }
+
+    /// Writes the root value as a top level document, where a map is a list of entries
+    /// without braces, the form read by [`TopDeserializer`](crate::de::TopDeserializer).
+    /// Any other value, like a list, is written as it is.
+    pub fn top_level(mut self) -> Self {
+        self.top_level = true;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        self.writer.write_all(s.as_bytes()).map_err(Error::io)
+    }
+
+    fn write_display(&mut self, v: impl Display) -> Result<()> {
+        write!(self.writer, "{v}").map_err(Error::io)
+    }
+
+    fn write_indent(&mut self, depth: usize) -> Result<()> {
+        for _ in 0..depth {
+            self.write_str("    ")?;
+        }
+        Ok(())
+    }
+
+    fn write_quoted(&mut self, s: &str, quote: char) -> Result<()> {
+        let mut out = String::with_capacity(s.len() + 2);
+        out.push(quote);
+        for c in s.chars() {
+            match c {
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '\0' => out.push_str("\\0"),
+                '\\' => out.push_str("\\\\"),
+                c if c == quote => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                c if c.is_control() || (self.options.ascii_only && !c.is_ascii()) => {
+                    match self.options.unicode_escape {
+                        UnicodeEscape::Braced => {
+                            out.push_str(&format!("\\u{{{:x}}}", c as u32));
+                        }
+                        UnicodeEscape::Json => {
+                            for unit in c.encode_utf16(&mut [0; 2]) {
+                                out.push_str(&format!("\\u{unit:04x}"));
+                            }
+                        }
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+        out.push(quote);
+        self.write_str(&out)
+    }
+
+    fn write_float(&mut self, v: f64, src: impl std::fmt::Debug) -> Result<()> {
+        if !v.is_finite() {
+            return Err(Error::custom(format!(
+                "cannot serialize non-finite float {v}"
+            )));
+        }
+        // debug formatting always includes a '.' or an exponent
+        self.write_str(&format!("{src:?}"))
+    }
+
+    fn write_duration(&mut self, duration: Duration) -> Result<()> {
+        const UNITS: [(&str, u128); 7] = [
+            ("d", 24 * 60 * 60 * 1_000_000_000),
+            ("h", 60 * 60 * 1_000_000_000),
+            ("m", 60 * 1_000_000_000),
+            ("s", 1_000_000_000),
+            ("ms", 1_000_000),
+            ("us", 1_000),
+            ("ns", 1),
+        ];
+        let mut nanos = duration.as_nanos();
+        if nanos == 0 {
+            return self.write_str("0s");
+        }
+        for (unit, size) in UNITS {
+            if nanos >= size {
+                self.write_display(nanos / size)?;
+                self.write_str(unit)?;
+                nanos %= size;
+            }
+        }
+        Ok(())
+    }
+
+    fn start_compound(
+        &mut self,
+        open: &'static str,
+        close: &'static str,
+    ) -> Result<Compound<'_, W>> {
+        let braceless = self.open(open)?;
+        Ok(Compound {
+            ser: self,
+            close,
+            braceless,
+            variant: false,
+            empty: true,
+            duration: None,
+        })
+    }
+
+    /// Writes `open` and goes a level deeper, returning whether the compound is
+    /// braceless instead, which only the root map of a top level document is.
+    fn open(&mut self, open: &'static str) -> Result<bool> {
+        let braceless = self.top_level && self.depth == 0 && open == "{";
+        if braceless {
+            self.top_level = false;
+        } else {
+            self.write_str(open)?;
+            self.depth += 1;
+        }
+        Ok(braceless)
+    }
+
+    fn start_variant(&mut self, variant: &'static str) -> Result<()> {
+        self.write_str("{\n")?;
+        self.write_indent(self.depth + 1)?;
+        self.write_key(variant)?;
+        self.write_str(" = ")?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn end_variant(&mut self) -> Result<()> {
+        self.depth -= 1;
+        self.write_str("\n")?;
+        self.write_indent(self.depth)?;
+        self.write_str("}")
+    }
+
+    fn write_key(&mut self, key: &str) -> Result<()> {
+        let mut chars = key.chars();
+        let valid = chars.next().map(is_ident_start).unwrap_or(false)
+            && chars.all(is_ident_continue)
+            && !matches!(key, "true" | "false" | "null");
+        if valid {
+            return self.write_str(key);
+        }
+        // quoted keys are read without unescaping
+        if key
+            .chars()
+            .any(|c| matches!(c, '"' | '\\') || c.is_control())
+        {
+            return Err(Error::custom(format!(
+                "key `{key}` can not be written without escapes"
+            )));
+        }
+        self.write_str("\"")?;
+        self.write_str(key)?;
+        self.write_str("\"")
+    }
+}
+
+pub fn to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: ?Sized + Serialize,
+{
+    to_writer_with_options(writer, value, Options::default())
+}
+
+pub fn to_writer_with_options<W, T>(writer: W, value: &T, options: Options) -> Result<()>
+where
+    W: io::Write,
+    T: ?Sized + Serialize,
+{
+    let mut ser = Serializer::with_options(writer, options).top_level();
+    value.serialize(&mut ser)?;
+    ser.write_str("\n")
+}
+
+/// Serializes `value` as a top level document, which is entries without braces for
+/// a map, or any other value as it is.
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    to_string_with_options(value, Options::default())
+}
+
+pub fn to_string_with_options<T>(value: &T, options: Options) -> Result<String>
+where
+    T: ?Sized + Serialize,
+{
+    let mut out = Vec::new();
+    to_writer_with_options(&mut out, value, options)?;
+    Ok(String::from_utf8(out).expect("serializer should only write utf-8"))
+}
+
+impl<'a, W: io::Write> ser::Serializer for &'a mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Compound<'a, W>;
+    type SerializeTuple = Compound<'a, W>;
+    type SerializeTupleStruct = Compound<'a, W>;
+    type SerializeTupleVariant = Compound<'a, W>;
+    type SerializeMap = Compound<'a, W>;
+    type SerializeStruct = Compound<'a, W>;
+    type SerializeStructVariant = Compound<'a, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_str(if v { "true" } else { "false" })
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_display(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.write_display(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.serialize_u64(v as u64)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_display(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.write_display(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_float(v as f64, v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_float(v, v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.write_quoted(v.encode_utf8(&mut [0; 4]), '\'')
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.write_quoted(v, '"')
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        use ser::SerializeSeq;
+        let mut seq = self.serialize_seq(Some(v.len()))?;
+        for b in v {
+            seq.serialize_element(b)?;
+        }
+        seq.end()
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.write_str("null")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.start_variant(variant)?;
+        value.serialize(&mut *self)?;
+        self.end_variant()
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a, W>> {
+        self.start_compound("[", "]")
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a, W>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a, W>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a, W>> {
+        self.start_variant(variant)?;
+        let mut compound = self.start_compound("[", "]")?;
+        compound.variant = true;
+        Ok(compound)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a, W>> {
+        self.start_compound("{", "}")
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Compound<'a, W>> {
+        if name == "Duration" && len == 2 {
+            // this is `std::time::Duration` if its fields are `secs` and `nanos`,
+            // so nothing is written until they have been seen
+            return Ok(Compound {
+                ser: self,
+                close: "}",
+                braceless: false,
+                variant: false,
+                empty: true,
+                duration: Some(DurationFields::default()),
+            });
+        }
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a, W>> {
+        self.start_variant(variant)?;
+        let mut compound = self.start_compound("{", "}")?;
+        compound.variant = true;
+        Ok(compound)
+    }
+}
+
+pub struct Compound<'a, W> {
+    ser: &'a mut Serializer<W>,
+    close: &'static str,
+    braceless: bool,
+    // whether this is the body of a tuple or struct variant
+    variant: bool,
+    empty: bool,
+    // the fields read so far of a struct that may be a `std::time::Duration`, before
+    // anything of it is written
+    duration: Option<DurationFields>,
+}
+
+#[derive(Default)]
+struct DurationFields {
+    secs: Option<u64>,
+    nanos: Option<u32>,
+}
+
+impl<W: io::Write> Compound<'_, W> {
+    fn start_entry(&mut self) -> Result<()> {
+        if !self.braceless || !self.empty {
+            self.ser.write_str("\n")?;
+        }
+        self.empty = false;
+        self.ser.write_indent(self.ser.depth)
+    }
+
+    /// Writes the struct that was taken to be a `Duration` as a map after all, with
+    /// the fields read so far.
+    fn duration_to_map(&mut self) -> Result<()> {
+        if let Some(fields) = self.duration.take() {
+            self.braceless = self.ser.open("{")?;
+            if let Some(secs) = fields.secs {
+                ser::SerializeMap::serialize_entry(self, "secs", &secs)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if let Some(DurationFields {
+            secs: Some(secs),
+            nanos: Some(nanos),
+        }) = self.duration
+        {
+            return self.ser.write_duration(Duration::new(secs, nanos));
+        }
+        self.duration_to_map()?;
+        if self.braceless {
+            return Ok(());
+        }
+        self.ser.depth -= 1;
+        if !self.empty {
+            self.ser.write_str("\n")?;
+            self.ser.write_indent(self.ser.depth)?;
+        }
+        self.ser.write_str(self.close)?;
+        if self.variant {
+            self.ser.end_variant()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: io::Write> ser::SerializeSeq for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.start_entry()?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeTuple for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeTupleStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeTupleVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeMap for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.start_entry()?;
+        key.serialize(KeySerializer {
+            ser: &mut *self.ser,
+        })
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.ser.write_str(" = ")?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeStruct for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        if let Some(fields) = &mut self.duration {
+            let integer = || value.serialize(IntegerSerializer).ok();
+            let taken = match (key, fields.secs, fields.nanos) {
+                ("secs", None, None) => integer().map(|secs| fields.secs = Some(secs)),
+                ("nanos", Some(_), None) => integer()
+                    .and_then(|nanos| u32::try_from(nanos).ok())
+                    .map(|nanos| fields.nanos = Some(nanos)),
+                _ => None,
+            };
+            if taken.is_some() {
+                return Ok(());
+            }
+            self.duration_to_map()?;
+        }
+        ser::SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<W: io::Write> ser::SerializeStructVariant for Compound<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        ser::SerializeMap::serialize_entry(self, key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+struct KeySerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+}
+
+fn key_must_be_a_string() -> Error {
+    Error::custom("map keys must be strings")
+}
+
+impl<W: io::Write> ser::Serializer for KeySerializer<'_, W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Impossible<(), Error>;
+    type SerializeTuple = Impossible<(), Error>;
+    type SerializeTupleStruct = Impossible<(), Error>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Impossible<(), Error>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.ser.write_key(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.ser.write_key(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.ser.write_key(variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.ser.write_key(&v.to_string())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<()> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<()> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(key_must_be_a_string())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(key_must_be_a_string())
+    }
+}
+
+/// Reads an integer that fits in a `u64`, for the fields of a `Duration`.
+struct IntegerSerializer;
+
+fn not_an_integer() -> Error {
+    Error::custom("expected an integer")
+}
+
+impl ser::Serializer for IntegerSerializer {
+    type Ok = u64;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<u64, Error>;
+    type SerializeTuple = Impossible<u64, Error>;
+    type SerializeTupleStruct = Impossible<u64, Error>;
+    type SerializeTupleVariant = Impossible<u64, Error>;
+    type SerializeMap = Impossible<u64, Error>;
+    type SerializeStruct = Impossible<u64, Error>;
+    type SerializeStructVariant = Impossible<u64, Error>;
+
+    fn serialize_u8(self, v: u8) -> Result<u64> {
+        Ok(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<u64> {
+        Ok(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<u64> {
+        Ok(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<u64> {
+        Ok(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<u64> {
+        u64::try_from(v).map_err(|_| not_an_integer())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<u64> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<u64> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<u64> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<u64> {
+        u64::try_from(v).map_err(|_| not_an_integer())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<u64> {
+        u64::try_from(v).map_err(|_| not_an_integer())
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<u64>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_none(self) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_some<T>(self, _value: &T) -> Result<u64>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_an_integer())
+    }
+
+    fn serialize_unit(self) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<u64> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<u64>
+    where
+        T: ?Sized + Serialize,
+    {
+        Err(not_an_integer())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(not_an_integer())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(not_an_integer())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+
+    use super::*;
+
+    #[test]
+    fn flatten() {
+        #[derive(Serialize)]
+        struct Server {
+            host: &'static str,
+            port: u16,
+        }
+
+        #[derive(Serialize)]
+        struct Config {
+            name: &'static str,
+            #[serde(flatten)]
+            server: Server,
+            #[serde(flatten)]
+            rest: BTreeMap<&'static str, u32>,
+        }
+
+        let config = Config {
+            name: "app",
+            server: Server {
+                host: "localhost",
+                port: 8080,
+            },
+            rest: BTreeMap::from([("workers", 4)]),
+        };
+        assert_eq!(
+            to_string(&config).unwrap(),
+            "name = \"app\"\nhost = \"localhost\"\nport = 8080\nworkers = 4\n"
+        );
+    }
+
+    #[test]
+    fn durations() {
+        #[derive(Serialize)]
+        struct Timeouts {
+            read: Duration,
+            idle: Duration,
+            never: Duration,
+        }
+
+        let timeouts = Timeouts {
+            read: Duration::from_millis(1500),
+            idle: Duration::from_secs(90 * 60),
+            never: Duration::ZERO,
+        };
+        assert_eq!(
+            to_string(&timeouts).unwrap(),
+            "read = 1s500ms\nidle = 1h30m\nnever = 0s\n"
+        );
+    }
+
+    #[test]
+    fn structs_named_duration() {
+        #[derive(Serialize)]
+        struct Duration {
+            secs: u64,
+            label: &'static str,
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename = "Duration")]
+        struct Signed {
+            secs: i64,
+            nanos: u32,
+        }
+
+        let other = Duration {
+            secs: 3,
+            label: "three",
+        };
+        assert_eq!(to_string(&other).unwrap(), "secs = 3\nlabel = \"three\"\n");
+        let signed = Signed { secs: -1, nanos: 0 };
+        assert_eq!(to_string(&signed).unwrap(), "secs = -1\nnanos = 0\n");
+    }
+
+    #[test]
+    fn nesting() {
+        #[derive(Serialize)]
+        enum Shape {
+            Point,
+            Circle(f64),
+            Rect { w: u32, h: u32 },
+        }
+
+        let shapes = BTreeMap::from([
+            ("a", vec![Shape::Point, Shape::Circle(0.5)]),
+            ("b", vec![Shape::Rect { w: 1, h: 2 }]),
+        ]);
+        let top = to_string(&shapes).unwrap();
+        let mut out = Vec::new();
+        shapes.serialize(&mut Serializer::new(&mut out)).unwrap();
+        let nested = String::from_utf8(out).unwrap();
+        assert_eq!(
+            top,
+            "a = [\n    \"Point\"\n    {\n        Circle = 0.5\n    }\n]\n\
+             b = [\n    {\n        Rect = {\n            w = 1\n            h = 2\n        }\n    }\n]\n"
+        );
+        // the same entries, braced and indented a level
+        let indented = top.trim_end().replace('\n', "\n    ");
+        assert_eq!(nested, format!("{{\n    {indented}\n}}"));
+    }
+
+    #[test]
+    fn escapes() {
+        let text = "tab\t \"quote\" é 😀 \u{7f}";
+        assert_eq!(
+            to_string(text).unwrap(),
+            "\"tab\\t \\\"quote\\\" é 😀 \\u{7f}\"\n"
+        );
+        let json = Options {
+            unicode_escape: UnicodeEscape::Json,
+            ascii_only: true,
+        };
+        assert_eq!(
+            to_string_with_options(text, json).unwrap(),
+            "\"tab\\t \\\"quote\\\" \\u00e9 \\ud83d\\ude00 \\u007f\"\n"
+        );
+        let braced = Options {
+            ascii_only: true,
+            ..Options::default()
+        };
+        assert_eq!(to_string_with_options(&'é', braced).unwrap(), "'\\u{e9}'\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(to_string(&f64::NAN)
+            .unwrap_err()
+            .to_string()
+            .contains("non-finite"));
+        assert!(to_string(&BTreeMap::from([((), 2)]))
+            .unwrap_err()
+            .to_string()
+            .contains("strings"));
+        let key = to_string(&BTreeMap::from([("a\"b", 1)])).unwrap_err();
+        assert!(key.to_string().contains("without escapes"));
+    }
+
+    #[test]
+    fn quoted_keys() {
+        let map = BTreeMap::from([("two words", 1), ("true", 2), ("plain", 3)]);
+        assert_eq!(
+            to_string(&map).unwrap(),
+            "plain = 3\n\"true\" = 2\n\"two words\" = 1\n"
+        );
+    }
+}
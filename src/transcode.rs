@@ -0,0 +1,90 @@
+//! Converting documents between ycf and other serde formats without a Rust type in
+//! between, with [`serde_transcode`]. Entries keep their order, and durations are
+//! given to other formats as `{ secs, nanos }` maps.
+
+use serde::{de, ser, Deserializer};
+use serde_transcode::Transcoder;
+
+use crate::de::{from_str_seed_with_options, Options};
+use crate::error::{Error, Result};
+
+/// Transcodes the ycf document `input` into `serializer`.
+pub fn transcode<S>(input: &str, serializer: S) -> Result<S::Ok>
+where
+    S: ser::Serializer,
+{
+    transcode_with_options(input, serializer, Options::default())
+}
+
+pub fn transcode_with_options<S>(input: &str, serializer: S, options: Options) -> Result<S::Ok>
+where
+    S: ser::Serializer,
+{
+    from_str_seed_with_options(input, TranscodeSeed(serializer), options)
+}
+
+/// Transcodes everything `deserializer` gives into a top level ycf document, where a
+/// map at the root is written as entries without braces.
+pub fn from_deserializer<'de, D>(deserializer: D) -> Result<String>
+where
+    D: Deserializer<'de>,
+{
+    crate::to_string(&Transcoder::new(deserializer))
+}
+
+/// Converts a JSON document to ycf.
+pub fn json_str_to_ycf(json: &str) -> Result<String> {
+    let mut de = serde_json::Deserializer::from_str(json);
+    let ycf = from_deserializer(&mut de)?;
+    de.end().map_err(Error::custom)?;
+    Ok(ycf)
+}
+
+/// Converts a ycf document to a JSON string.
+pub fn ycf_to_json_string(input: &str) -> Result<String> {
+    let mut out = Vec::new();
+    transcode(input, &mut serde_json::Serializer::new(&mut out))?;
+    Ok(String::from_utf8(out).expect("serde_json should only write utf-8"))
+}
+
+/// Converts a ycf document to a [`serde_json::Value`].
+pub fn ycf_to_json_value(input: &str) -> Result<serde_json::Value> {
+    transcode(input, serde_json::value::Serializer)
+}
+
+struct TranscodeSeed<S>(S);
+
+impl<'de, S> de::DeserializeSeed<'de> for TranscodeSeed<S>
+where
+    S: ser::Serializer,
+{
+    type Value = S::Ok;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<S::Ok, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        serde_transcode::transcode(deserializer, self.0).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcode_json() {
+        let ycf = json_str_to_ycf(r#"{"z": [1, -2, 0.5], "a": {"b": "x", "c": null}}"#).unwrap();
+        assert_eq!(
+            ycf_to_json_string(&ycf).unwrap(),
+            r#"{"z":[1,-2,0.5],"a":{"b":"x","c":null}}"#
+        );
+        assert_eq!(
+            ycf_to_json_value("t = 1500ms").unwrap(),
+            serde_json::json!({ "t": { "secs": 1, "nanos": 500_000_000 } })
+        );
+        assert_eq!(json_str_to_ycf("[1]").unwrap(), "[\n    1\n]\n");
+        assert!(json_str_to_ycf("{} x").is_err());
+        assert!(ycf_to_json_string("a = ").is_err());
+    }
+}
@@ -0,0 +1,1269 @@
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{fmt, mem, ops::Range, str::FromStr, time::Duration};
+
+use serde::de::value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, Deserialize, DeserializeOwned, Error as _, IntoDeserializer, MapAccess, SeqAccess,
+    Unexpected, Visitor,
+};
+use serde::ser::{Serialize, Serializer};
+use smallvec::SmallVec;
+
+use crate::cursor::{Keyword, TokenKind};
+use crate::de::{DurationDeserializer, DurationValueMap, DURATION_KEY, VALUE_NAME};
+use crate::error::Error;
+use crate::parse::{
+    starts_key, starts_value, ContextFrame, ExpectedSet, MapPathRef, ParseError, ParseErrorKind,
+    Parser, SpanToken,
+};
+use crate::path::{self, AsPath, Path, Pattern, Segment};
+use crate::secret::REDACTED;
+
+/// A key of a [`Map`]. Keys are reference counted so that maps read by the same
+/// [`Interner`] share one allocation for every distinct key.
+pub type Key = Arc<str>;
+
+pub type Map = BTreeMap<Key, Value>;
+
+/// Hands out one shared [`Key`] for every distinct string, for documents that repeat
+/// the same keys many times, like lists of maps. See [`Value::parse_interned`].
+#[derive(Clone, Debug, Default)]
+pub struct Interner {
+    keys: BTreeSet<Key>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared key for `key`, allocating it the first time it is seen.
+    pub fn intern(&mut self, key: &str) -> Key {
+        if let Some(key) = self.keys.get(key) {
+            return key.clone();
+        }
+        let key = Key::from(key);
+        self.keys.insert(key.clone());
+        key
+    }
+
+    /// The number of distinct keys interned so far.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Forgets every key, which values that still hold them keep alive on their own.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    /// An unsigned integer that does not fit in a `u64`.
+    U128(u128),
+    /// A signed integer that does not fit in an `i64`.
+    I128(i128),
+    F64(f64),
+    String(String),
+    Duration(Duration),
+    List(Vec<Value>),
+    Map(Map),
+}
+
+impl Value {
+    /// Parses the next value from `parser`, without recursing for nested maps and
+    /// lists. Unlike deserializing, this can read documents of any depth when the
+    /// parser's `max_depth` is `None`, since the containers are kept on the heap.
+    ///
+    /// Entries are merged like the deserializer does with the default options: dotted
+    /// keys extend maps, and a key that is set again replaces the earlier value.
+    /// Dropping a very deep value still recurses, so it may have to be leaked instead.
+    ///
+    /// Keys that appear more than once in the value share one allocation.
+    pub fn parse(parser: &mut Parser<'_>) -> Result<Value, ParseError> {
+        Self::parse_interned(parser, &mut Interner::new())
+    }
+
+    /// Like [`parse`](Self::parse), but reads top level entries up to the end of the
+    /// input into a map.
+    pub fn parse_document(parser: &mut Parser<'_>) -> Result<Value, ParseError> {
+        Self::parse_document_interned(parser, &mut Interner::new())
+    }
+
+    /// Like [`parse`](Self::parse), but with keys from `interner`, so they are shared
+    /// with every other value read with it.
+    pub fn parse_interned(
+        parser: &mut Parser<'_>,
+        interner: &mut Interner,
+    ) -> Result<Value, ParseError> {
+        Self::parse_from(parser, Vec::new(), interner, None)
+    }
+
+    /// Like [`parse_document`](Self::parse_document), but with keys from `interner`.
+    pub fn parse_document_interned(
+        parser: &mut Parser<'_>,
+        interner: &mut Interner,
+    ) -> Result<Value, ParseError> {
+        let top = Open {
+            container: Container::Map(MapBuilder::default()),
+            slot: Slot::Document,
+        };
+        Self::parse_from(parser, vec![top], interner, None)
+    }
+
+    /// Parses a document or a single value like [`FromStr`], where an entry like
+    /// `include "path"` in a map is replaced with the entries of the map `include`
+    /// gives for the path.
+    pub(crate) fn parse_including(
+        parser: &mut Parser<'_>,
+        include: &mut Include<'_>,
+    ) -> Result<Value, ParseError> {
+        let next = parser.peek_token();
+        // a document can start with an include, which is not an entry
+        let stack = if parser.starts_top_level() || is_include(parser, next) {
+            vec![Open {
+                container: Container::Map(MapBuilder::default()),
+                slot: Slot::Document,
+            }]
+        } else {
+            Vec::new()
+        };
+        let value = Self::parse_from(parser, stack, &mut Interner::new(), Some(include))?;
+        parser.end_of_input()?;
+        Ok(value)
+    }
+
+    fn parse_from<'a>(
+        parser: &mut Parser<'a>,
+        stack: Vec<Open<'a>>,
+        interner: &mut Interner,
+        include: Option<&mut Include<'_>>,
+    ) -> Result<Value, ParseError> {
+        let context = parser.context.len();
+        let res = parse_iterative(parser, stack, interner, include).map_err(|mut err| {
+            parser.add_context(&mut err);
+            err
+        });
+        parser.context.truncate(context);
+        res
+    }
+
+    /// The value of `key`, if this is a map that has it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// The value at `path`, a [`Path`] or a string like `server.ports[0]` that is
+    /// parsed as one. This is `None` if nothing is there, or if the string is not a
+    /// valid path. An empty path is the value itself.
+    pub fn get_path<P: AsPath + ?Sized>(&self, path: &P) -> Option<&Value> {
+        path.as_path()?
+            .iter()
+            .try_fold(self, |value, segment| match (value, segment) {
+                (Value::Map(map), Segment::Key(key)) => map.get(key.as_str()),
+                (Value::List(list), Segment::Index(i)) => list.get(*i),
+                _ => None,
+            })
+    }
+
+    /// Like [`get_path`](Self::get_path), but for changing the value.
+    pub fn get_path_mut<P: AsPath + ?Sized>(&mut self, path: &P) -> Option<&mut Value> {
+        path.as_path()?
+            .iter()
+            .try_fold(self, |value, segment| match (value, segment) {
+                (Value::Map(map), Segment::Key(key)) => map.get_mut(key.as_str()),
+                (Value::List(list), Segment::Index(i)) => list.get_mut(*i),
+                _ => None,
+            })
+    }
+
+    /// Replaces the values at `paths`, given like for [`get_path`](Self::get_path),
+    /// with the string `"<redacted>"`, so that the value can be logged or shown without
+    /// the credentials in it. Paths that are not set are left out.
+    ///
+    /// For typed configs, fields can be made a [`Secret`](crate::Secret) instead.
+    pub fn redact<P: AsPath>(&mut self, paths: impl IntoIterator<Item = P>) {
+        for path in paths {
+            if let Some(value) = self.get_path_mut(&path) {
+                *value = Value::String(REDACTED.to_string());
+            }
+        }
+    }
+
+    /// The values that match `pattern`, which is written like a path, but where a `*`
+    /// matches any one key or element and a `**` matches any number of them, none
+    /// included, like `servers.*.port` or `**.password`. The matches are in order of
+    /// their paths, and a pattern that is not valid matches nothing.
+    pub fn query(&self, pattern: &str) -> impl Iterator<Item = (Path, &Value)> {
+        let mut matches = BTreeMap::new();
+        if let Ok(pattern) = path::parse_pattern(pattern) {
+            query(self, &pattern, &mut Path::new(), &mut matches);
+        }
+        matches.into_iter()
+    }
+
+    /// Merges `other` into this value. Maps are merged key by key, recursively, and
+    /// anything else, lists included, is replaced by the value from `other`.
+    pub fn merge(&mut self, other: Value) {
+        match (self, other) {
+            (Value::Map(map), Value::Map(other)) => {
+                for (key, value) in other {
+                    match map.get_mut(&key) {
+                        Some(existing) => existing.merge(value),
+                        None => {
+                            map.insert(key, value);
+                        }
+                    }
+                }
+            }
+            (this, other) => *this = other,
+        }
+    }
+
+    /// Replaces references to other values in the document, like `"${base}/data"`,
+    /// where the path is written like for [`get_path`](Self::get_path) from the root
+    /// of this value. A string that is only a reference, like `"${defaults.ports}"`,
+    /// becomes a copy of the value it refers to, whatever that is, and otherwise the
+    /// value has to be a string, number or bool to go in the string. `$${` is a `${`
+    /// that is not a reference.
+    ///
+    /// References can refer to values with references of their own, but not to
+    /// themselves, directly or through others. References use the same syntax as the
+    /// variables of [`de::Options::env`](crate::de::Options::env), which are
+    /// substituted while parsing, so the two can not be used in the same document.
+    pub fn resolve_references(&mut self) -> Result<(), Error> {
+        let mut pending = Vec::new();
+        find_references(self, &mut Path::new(), &mut pending);
+        while !pending.is_empty() {
+            let before = pending.len();
+            let mut i = 0;
+            while i < pending.len() {
+                match self.substitute(&pending[i])? {
+                    Some(value) => {
+                        *self
+                            .get_path_mut(&pending[i])
+                            .expect("paths are found in the value") = value;
+                        pending.swap_remove(i);
+                    }
+                    None => i += 1,
+                }
+            }
+            if pending.len() == before {
+                // nothing left can be resolved, so every one of them is in a cycle
+                return Err(Error::custom(format_args!(
+                    "the references in `{}` refer back to it",
+                    pending[0]
+                )));
+            }
+        }
+        unescape_references(self);
+        Ok(())
+    }
+
+    /// The value for the string at `path` with its references replaced, or `None` if
+    /// one of them refers to a value with references that are not resolved yet.
+    fn substitute(&self, path: &Path) -> Result<Option<Value>, Error> {
+        let Value::String(text) = self.get_path(path).expect("paths are found in the value") else {
+            unreachable!("only strings have references")
+        };
+        let mut out = String::new();
+        let mut end = 0;
+        for (range, target) in references(text) {
+            let value = self.get_path(target).ok_or_else(|| {
+                Error::custom(format_args!(
+                    "`{}` refers to `{target}`, which is not set",
+                    path
+                ))
+            })?;
+            if has_references(value) {
+                return Ok(None);
+            }
+            if range == (0..text.len()) {
+                return Ok(Some(value.clone()));
+            }
+            out.push_str(&text[end..range.start]);
+            match value {
+                Value::String(value) => out.push_str(value),
+                Value::Bool(v) => out.push_str(&v.to_string()),
+                Value::U64(v) => out.push_str(&v.to_string()),
+                Value::I64(v) => out.push_str(&v.to_string()),
+                Value::U128(v) => out.push_str(&v.to_string()),
+                Value::I128(v) => out.push_str(&v.to_string()),
+                Value::F64(v) => out.push_str(&v.to_string()),
+                _ => {
+                    return Err(Error::custom(format_args!(
+                    "`{}` refers to `{target}` in a string, which is not a string, number or bool",
+                    path
+                )))
+                }
+            }
+            end = range.end;
+        }
+        out.push_str(&text[end..]);
+        Ok(Some(Value::String(out)))
+    }
+}
+
+/// Adds the values in `value`, which is at `path`, that match `pattern` to `out`.
+fn query<'v>(
+    value: &'v Value,
+    pattern: &[Pattern],
+    path: &mut Path,
+    out: &mut BTreeMap<Path, &'v Value>,
+) {
+    let Some((first, rest)) = pattern.split_first() else {
+        out.insert(path.clone(), value);
+        return;
+    };
+    let mut children = |pattern: &[Pattern], path: &mut Path| match value {
+        Value::Map(map) => {
+            for (key, value) in map {
+                path.push_key(&**key);
+                query(value, pattern, path, out);
+                path.pop();
+            }
+        }
+        Value::List(list) => {
+            for (i, value) in list.iter().enumerate() {
+                path.push_index(i);
+                query(value, pattern, path, out);
+                path.pop();
+            }
+        }
+        _ => (),
+    };
+    match first {
+        Pattern::Segment(segment) => {
+            let child = match (value, segment) {
+                (Value::Map(map), Segment::Key(key)) => map.get(key.as_str()),
+                (Value::List(list), Segment::Index(i)) => list.get(*i),
+                _ => None,
+            };
+            if let Some(child) = child {
+                path.push(segment.clone());
+                query(child, rest, path, out);
+                path.pop();
+            }
+        }
+        Pattern::Any => children(rest, path),
+        Pattern::AnyDepth => {
+            children(pattern, path);
+            query(value, rest, path, out);
+        }
+    }
+}
+
+/// The references in a string, with their ranges and the paths they refer to.
+fn references(text: &str) -> Vec<(Range<usize>, &str)> {
+    let mut out = Vec::new();
+    let mut from = 0;
+    while let Some(start) = text[from..].find("${").map(|i| i + from) {
+        if text[..start].ends_with('$') {
+            from = start + 2;
+            continue;
+        }
+        // a `${` that is never closed is not a reference
+        let Some(len) = text[start..].find('}') else {
+            break;
+        };
+        out.push((start..start + len + 1, &text[start + 2..start + len]));
+        from = start + len + 1;
+    }
+    out
+}
+
+fn has_references(value: &Value) -> bool {
+    match value {
+        Value::String(text) => !references(text).is_empty(),
+        Value::List(list) => list.iter().any(has_references),
+        Value::Map(map) => map.values().any(has_references),
+        _ => false,
+    }
+}
+
+/// Adds the paths of the strings with references in `value`, which is at `path`, to `out`.
+fn find_references(value: &Value, path: &mut Path, out: &mut Vec<Path>) {
+    match value {
+        Value::String(_) if has_references(value) => out.push(path.clone()),
+        Value::List(list) => {
+            for (i, value) in list.iter().enumerate() {
+                path.push_index(i);
+                find_references(value, path, out);
+                path.pop();
+            }
+        }
+        Value::Map(map) => {
+            for (key, value) in map {
+                path.push_key(&**key);
+                find_references(value, path, out);
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Turns every `$${` into `${`, once there are no references left.
+fn unescape_references(value: &mut Value) {
+    match value {
+        Value::String(text) if text.contains("$${") => *text = text.replace("$${", "${"),
+        Value::List(list) => list.iter_mut().for_each(unescape_references),
+        Value::Map(map) => map.values_mut().for_each(unescape_references),
+        _ => (),
+    }
+}
+
+/// Parses a document or a single value, like [`from_str`](crate::from_str), but with
+/// [`Value::parse`] and without a limit on the depth.
+impl FromStr for Value {
+    type Err = Error;
+
+    fn from_str(src: &str) -> Result<Value, Error> {
+        let mut parser = Parser::new(src);
+        parser.max_depth = None;
+        if parser.starts_top_level() {
+            Ok(Value::parse_document(&mut parser)?)
+        } else {
+            let value = Value::parse(&mut parser)?;
+            parser.end_of_input()?;
+            Ok(value)
+        }
+    }
+}
+
+/// Reads the document a path in an include refers to, given the path and its token.
+pub(crate) type Include<'f> = dyn FnMut(&str, SpanToken) -> Result<Value, ParseError> + 'f;
+
+/// A map or list that [`Value::parse`] has started but not finished.
+struct Open<'a> {
+    container: Container<'a>,
+    // where the container goes once it is closed
+    slot: Slot<'a>,
+}
+
+enum Container<'a> {
+    List(Vec<Value>),
+    Map(MapBuilder<'a>),
+}
+
+/// Where a value goes once it has been read.
+enum Slot<'a> {
+    Root,
+    // the top level entries of a document, which end at the end of the input
+    Document,
+    Element,
+    // the key of an entry, and the first token of its value
+    Entry(Box<MapPathRef<'a>>, SpanToken),
+}
+
+#[derive(Default)]
+struct MapBuilder<'a> {
+    map: Map,
+    // the number of entries read, for `Limits::max_elements`
+    entries: usize,
+    // for every path set in this map, where it was set and whether that was by a
+    // dotted key, which makes it a map that later entries can add to
+    origins: BTreeMap<SmallVec<[&'a str; 4]>, (SpanToken, bool)>,
+    // the path of this map in the map it was merged from, for errors
+    path: SmallVec<[&'a str; 4]>,
+}
+
+impl<'a> MapBuilder<'a> {
+    /// Takes out the map at `path` if dotted keys made it, so a map written for the
+    /// same key is read into it instead of replacing it.
+    fn take_dotted(&mut self, path: &MapPathRef<'a>) -> MapBuilder<'a> {
+        let prefix: SmallVec<[&str; 4]> = path.segments.iter().map(|&(s, _)| s).collect();
+        if !matches!(self.origins.get(&prefix), Some(&(_, true))) {
+            return MapBuilder::default();
+        }
+        let (&(last, _), parents) = path.segments.split_last().expect("path is not empty");
+        let mut map = &mut self.map;
+        for &(segment, _) in parents {
+            match map.get_mut(segment) {
+                Some(Value::Map(inner)) => map = inner,
+                _ => return MapBuilder::default(),
+            }
+        }
+        let Some(Value::Map(map)) = map.remove(last) else {
+            return MapBuilder::default();
+        };
+        let origins = self
+            .origins
+            .iter()
+            .filter(|(key, _)| key.len() > prefix.len() && key.starts_with(&prefix))
+            .map(|(key, &origin)| (key[prefix.len()..].into(), origin))
+            .collect();
+        MapBuilder {
+            map,
+            entries: 0,
+            origins,
+            path: self.path.iter().chain(&prefix).copied().collect(),
+        }
+    }
+
+    /// The error for a value at `prefix` that can not be merged with the dotted key
+    /// at `other`.
+    fn conflict(&self, prefix: &[&str], token: SpanToken, other: SpanToken) -> ParseError {
+        let key = self.path.iter().chain(prefix).copied().collect::<Vec<_>>();
+        ParseError::new(
+            token,
+            ParseErrorKind::MergeConflict {
+                key: key.join("."),
+                other: Box::new(other),
+            },
+        )
+    }
+
+    /// Sets `path` to `value`, whose first token is `token`. A conflict with an earlier
+    /// entry is returned along with the index of the segment it is at.
+    fn insert(
+        &mut self,
+        path: &MapPathRef<'a>,
+        value: Value,
+        token: SpanToken,
+        interner: &mut Interner,
+    ) -> Result<(), (ParseError, usize)> {
+        let (&(last, _), parents) = path.segments.split_last().expect("path is not empty");
+        let mut prefix = SmallVec::<[&str; 4]>::new();
+        let mut map = &mut self.map;
+        // the most recent place something along the path was set, for conflicts
+        // with values from inside a map that was written out
+        let mut set_at = token;
+        for (i, &(segment, span)) in parents.iter().enumerate() {
+            prefix.push(segment);
+            let origin = *self.origins.entry(prefix.clone()).or_insert((span, true));
+            let inner = map
+                .entry(interner.intern(segment))
+                .or_insert_with(|| Value::Map(Map::new()));
+            match inner {
+                Value::Map(inner) => map = inner,
+                _ if origin.1 => return Err((self.conflict(&prefix, span, set_at), i)),
+                _ => return Err((self.conflict(&prefix, origin.0, span), i)),
+            }
+            set_at = origin.0;
+        }
+        prefix.push(last);
+        match (map.get(last), self.origins.get(&prefix)) {
+            (Some(_), Some(&(span, true))) => {
+                Err((self.conflict(&prefix, token, span), parents.len()))
+            }
+            (Some(_), _) => {
+                // the old value is replaced, along with everything dotted keys set in it
+                self.origins
+                    .retain(|key, _| key.len() <= prefix.len() || !key.starts_with(&prefix));
+                self.origins.insert(prefix, (token, false));
+                map.insert(interner.intern(last), value);
+                Ok(())
+            }
+            (None, _) => {
+                self.origins.entry(prefix).or_insert((token, false));
+                map.insert(interner.intern(last), value);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The loop behind [`Value::parse`], with the containers that are open in `stack`.
+fn parse_iterative<'a>(
+    parser: &mut Parser<'a>,
+    mut stack: Vec<Open<'a>>,
+    interner: &mut Interner,
+    mut include: Option<&mut Include<'_>>,
+) -> Result<Value, ParseError> {
+    // the slot of the value to read next, if there is one
+    let mut pending = stack.is_empty().then_some(Slot::Root);
+    loop {
+        let mut complete = None;
+        if let Some(slot) = pending.take() {
+            match parser.peek_kind() {
+                TokenKind::StartSquare => {
+                    parser.start_list()?;
+                    let container = Container::List(Vec::new());
+                    stack.push(Open { container, slot });
+                }
+                TokenKind::StartCurly => {
+                    parser.start_map()?;
+                    let builder = match (&slot, stack.last_mut()) {
+                        (
+                            Slot::Entry(path, _),
+                            Some(Open {
+                                container: Container::Map(parent),
+                                ..
+                            }),
+                        ) => parent.take_dotted(path),
+                        _ => MapBuilder::default(),
+                    };
+                    let container = Container::Map(builder);
+                    stack.push(Open { container, slot });
+                }
+                _ => complete = Some((parse_scalar(parser)?, slot)),
+            }
+        }
+
+        // put finished values in their containers, closing the ones that end,
+        // until there is a new value to read
+        loop {
+            if let Some((value, slot)) = complete.take() {
+                let Some(open) = stack.last_mut() else {
+                    return Ok(value);
+                };
+                match (slot, &mut open.container) {
+                    (Slot::Element, Container::List(list)) => {
+                        list.push(value);
+                        parser.pop_context();
+                    }
+                    (Slot::Entry(path, token), Container::Map(builder)) => {
+                        let len = parser.context.len() - path.segments.len();
+                        if let Err((err, segment)) = builder.insert(&path, value, token, interner) {
+                            // the error is about the value of that segment
+                            parser.context.truncate(len + segment + 1);
+                            return Err(err);
+                        }
+                        parser.context.truncate(len);
+                    }
+                    _ => unreachable!("values are read for the container on top"),
+                }
+            }
+
+            let open = stack.last_mut().expect("a container is open");
+            let next = parser.peek_token();
+            match &mut open.container {
+                Container::List(list) => {
+                    if next.token.kind == TokenKind::EndSquare {
+                        parser.end_list()?;
+                    } else if starts_value(next.token.kind) {
+                        parser.check_elements(list.len() + 1, next)?;
+                        parser.push_context(ContextFrame::Element(list.len()));
+                        pending = Some(Slot::Element);
+                        break;
+                    } else {
+                        let expected = ExpectedSet::VALUE | ExpectedSet::LIST_END;
+                        return Err(ParseError::new(next, ParseErrorKind::Expected(expected)));
+                    }
+                }
+                Container::Map(builder) => {
+                    let closer = match open.slot {
+                        Slot::Document => TokenKind::Eof,
+                        _ => TokenKind::EndCurly,
+                    };
+                    if next.token.kind == closer {
+                        if closer == TokenKind::EndCurly {
+                            parser.end_map()?;
+                        }
+                    } else if let Some(include) =
+                        include.as_deref_mut().filter(|_| is_include(parser, next))
+                    {
+                        parser.next_token();
+                        let token = parser.peek_token();
+                        let path = parser.parse_str()?;
+                        // the entries are merged in as if they were written here
+                        let mut map = Value::Map(mem::take(&mut builder.map));
+                        map.merge(include(&path, token)?);
+                        if let Value::Map(map) = map {
+                            builder.map = map;
+                        }
+                        continue;
+                    } else {
+                        builder.entries += 1;
+                        parser.check_elements(builder.entries, next)?;
+                        if !starts_key(next.token.kind) {
+                            let expected = ExpectedSet::IDENT | ExpectedSet::closing(closer);
+                            return Err(ParseError::new(next, ParseErrorKind::Expected(expected)));
+                        }
+                        let path = parser.parse_path_ref()?;
+                        let delimiter = parser.peek_token();
+                        parser.map_delimiter()?;
+                        let value = parser.peek_token();
+                        if parser.value_missing(delimiter.line) {
+                            return Err(ParseError::new(value, ParseErrorKind::ExpectedValue));
+                        }
+                        for &(segment, _) in &path.segments {
+                            parser.push_context(ContextFrame::Key(segment.into()));
+                        }
+                        pending = Some(Slot::Entry(Box::new(path), value));
+                        break;
+                    }
+                }
+            }
+
+            let open = stack.pop().expect("a container is open");
+            let value = match open.container {
+                Container::List(list) => Value::List(list),
+                Container::Map(builder) => Value::Map(builder.map),
+            };
+            complete = Some((value, open.slot));
+        }
+    }
+}
+
+/// Whether the entry starting at `next` is an include, `include` followed by a string
+/// rather than `=`.
+fn is_include(parser: &mut Parser<'_>, next: SpanToken) -> bool {
+    next.token.kind == TokenKind::Ident
+        && parser.src(next.token) == "include"
+        && matches!(parser.peek_nth(1).token.kind, TokenKind::String { .. })
+}
+
+/// Reads a value that is not a map or list, like `deserialize_any` does.
+fn parse_scalar(parser: &mut Parser<'_>) -> Result<Value, ParseError> {
+    Ok(match parser.peek_kind() {
+        TokenKind::Keyword(Keyword::Null) => {
+            parser.parse_null()?;
+            Value::Null
+        }
+        TokenKind::Keyword(_) => Value::Bool(parser.parse_bool()?),
+        // integers that do not fit in 64 bits are kept as 128 bit ones
+        TokenKind::Integer { sign: false, .. } => Value::uint(parser.parse_u128()?),
+        TokenKind::Integer { sign: true, .. } => Value::int(parser.parse_i128()?),
+        TokenKind::Float => Value::F64(parser.parse_float()?),
+        TokenKind::Duration => Value::Duration(parser.parse_duration()?),
+        TokenKind::String { .. } => Value::String(parser.parse_string()?),
+        TokenKind::Ident if parser.bare_strings => Value::String(parser.parse_string()?),
+        TokenKind::Char { .. } => Value::String(parser.parse_char()?.to_string()),
+        _ => return Err(parser.error(ParseErrorKind::ExpectedValue)),
+    })
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Self::Null => serializer.serialize_unit(),
+            Self::Bool(v) => serializer.serialize_bool(*v),
+            Self::U64(v) => serializer.serialize_u64(*v),
+            Self::I64(v) => serializer.serialize_i64(*v),
+            Self::U128(v) => serializer.serialize_u128(*v),
+            Self::I128(v) => serializer.serialize_i128(*v),
+            Self::F64(v) => serializer.serialize_f64(*v),
+            Self::String(v) => serializer.serialize_str(v),
+            Self::Duration(v) => v.serialize(serializer),
+            Self::List(v) => v.serialize(serializer),
+            Self::Map(v) => serializer.collect_map(v.iter().map(|(k, v)| (&**k, v))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(VALUE_NAME, ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "any value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::I64(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::U64(v))
+    }
+
+    fn visit_i128<E>(self, v: i128) -> Result<Value, E> {
+        Ok(Value::int(v))
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<Value, E> {
+        Ok(Value::uint(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::F64(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(v) = seq.next_element()? {
+            list.push(v);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut out = Map::new();
+        let Some(first) = map.next_key::<String>()? else {
+            return Ok(Value::Map(out));
+        };
+        if first == DURATION_KEY {
+            return Ok(Value::Duration(map.next_value()?));
+        }
+        out.insert(first.into(), map.next_value()?);
+        while let Some((k, v)) = map.next_entry::<String, Value>()? {
+            out.insert(k.into(), v);
+        }
+        Ok(Value::Map(out))
+    }
+}
+
+impl Value {
+    /// Deserializes a `T` from this value, like [`from_str`](crate::from_str) does from
+    /// a document. Errors have no position, as values do not keep where they were read.
+    pub fn deserialize_into<T: DeserializeOwned>(self) -> Result<T, Error> {
+        T::deserialize(self)
+    }
+
+    /// A `U64`, or a `U128` if the integer does not fit.
+    pub(crate) fn uint(v: u128) -> Value {
+        u64::try_from(v).map_or(Value::U128(v), Value::U64)
+    }
+
+    /// An `I64`, or an `I128` if the integer does not fit.
+    pub(crate) fn int(v: i128) -> Value {
+        i64::try_from(v).map_or(Value::I128(v), Value::I64)
+    }
+
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Value::Null => Unexpected::Unit,
+            Value::Bool(v) => Unexpected::Bool(*v),
+            Value::U64(v) => Unexpected::Unsigned(*v),
+            Value::I64(v) => Unexpected::Signed(*v),
+            Value::U128(_) | Value::I128(_) => Unexpected::Other("a 128 bit integer"),
+            Value::F64(v) => Unexpected::Float(*v),
+            Value::String(v) => Unexpected::Str(v),
+            Value::Duration(_) => Unexpected::Other("a duration"),
+            Value::List(_) => Unexpected::Seq,
+            Value::Map(_) => Unexpected::Map,
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+/// Deserializes other types from a value, the way the document it was read from
+/// would deserialize them.
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::U64(v) => visitor.visit_u64(v),
+            Value::I64(v) => visitor.visit_i64(v),
+            Value::U128(v) => visitor.visit_u128(v),
+            Value::I128(v) => visitor.visit_i128(v),
+            Value::F64(v) => visitor.visit_f64(v),
+            Value::String(v) => visitor.visit_string(v),
+            Value::Duration(v) => visitor.visit_map(DurationDeserializer::new(v)),
+            Value::List(list) => {
+                let mut seq = SeqDeserializer::new(list.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Value::Map(map) => {
+                let mut map =
+                    MapDeserializer::new(map.into_iter().map(|(k, v)| (KeyDeserializer(k), v)));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::Null => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            // a `Value` is read back with its durations marked, like from a document
+            Value::Duration(duration) if name == VALUE_NAME => {
+                visitor.visit_map(DurationValueMap {
+                    duration: Some(duration),
+                })
+            }
+            value if name == VALUE_NAME => value.deserialize_any(visitor),
+            value => visitor.visit_newtype_struct(value),
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Value::String(variant) => visitor.visit_enum(variant.into_deserializer()),
+            // a variant with data is a map with a single entry, like `{ Tcp = 80 }`
+            Value::Map(map) if map.len() == 1 => {
+                let map =
+                    MapDeserializer::new(map.into_iter().map(|(k, v)| (KeyDeserializer(k), v)));
+                visitor.visit_enum(MapAccessDeserializer::new(map))
+            }
+            value => Err(Error::invalid_type(value.unexpected(), &"an enum")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// A key of a map, which is parsed for types like integers, like the keys of a
+/// document are.
+struct KeyDeserializer(Key);
+
+impl KeyDeserializer {
+    fn parse<T: FromStr>(&self, expected: &str) -> Result<T, Error> {
+        self.0
+            .parse()
+            .map_err(|_| Error::invalid_value(Unexpected::Str(&self.0), &expected))
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for KeyDeserializer {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed_key {
+    ($($method:ident => $visit:ident($expected:literal),)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Error>
+            where
+                V: Visitor<'de>,
+            {
+                visitor.$visit(self.parse($expected)?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_str(&self.0)
+    }
+
+    deserialize_parsed_key! {
+        deserialize_bool => visit_bool("a bool"),
+        deserialize_i8 => visit_i8("an integer"),
+        deserialize_i16 => visit_i16("an integer"),
+        deserialize_i32 => visit_i32("an integer"),
+        deserialize_i64 => visit_i64("an integer"),
+        deserialize_i128 => visit_i128("an integer"),
+        deserialize_u8 => visit_u8("an integer"),
+        deserialize_u16 => visit_u16("an integer"),
+        deserialize_u32 => visit_u32("an integer"),
+        deserialize_u64 => visit_u64("an integer"),
+        deserialize_u128 => visit_u128("an integer"),
+        deserialize_f32 => visit_f32("a float"),
+        deserialize_f64 => visit_f64("a float"),
+        deserialize_char => visit_char("a char"),
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_enum(String::from(&*self.0).into_deserializer())
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::from_str;
+
+    #[test]
+    fn parse_value_iteratively() {
+        let depth = 5000;
+        let src = format!("a = {}1{}", "[".repeat(depth), "]".repeat(depth));
+        let mut value: Value = src.parse().unwrap();
+        for _ in 0..depth {
+            value = match value {
+                Value::Map(mut map) => map.remove("a").unwrap(),
+                Value::List(mut list) => list.pop().unwrap(),
+                value => panic!("{value:?}"),
+            };
+        }
+        assert_eq!(value, Value::List(vec![Value::U64(1)]));
+
+        let src = "a.b.c = 1\na = { b = { d = 'x' } }\nl = [1 -2 null 3s]";
+        assert_eq!(
+            src.parse::<Value>().unwrap(),
+            from_str::<Value>(src).unwrap()
+        );
+        let err = "a.b = 1\na = 2".parse::<Value>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            from_str::<Value>("a.b = 1\na = 2").unwrap_err().to_string()
+        );
+
+        let mut parser = Parser::new("mode =\n    fast");
+        parser.bare_strings = true;
+        let value = Value::parse_document(&mut parser).unwrap();
+        assert_eq!(value.get_path("mode"), Some(&Value::String("fast".into())));
+        let err = "a =\nb = 1".parse::<Value>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected a value, found an identifier at 2:1"
+        );
+    }
+
+    #[test]
+    fn wide_integers() {
+        let src = "small = 1 signed = -1 big = 18446744073709551616 \
+                   low = -9223372036854775809 max = 0xffff_ffff_ffff_ffff_ffff_ffff_ffff_ffff";
+        let value: Value = from_str(src).unwrap();
+        assert_eq!(value.get("small"), Some(&Value::U64(1)));
+        assert_eq!(value.get("signed"), Some(&Value::I64(-1)));
+        assert_eq!(value.get("big"), Some(&Value::U128(1 << 64)));
+        assert_eq!(value.get("low"), Some(&Value::I128(i64::MIN as i128 - 1)));
+        assert_eq!(value.get("max"), Some(&Value::U128(u128::MAX)));
+        // read the same without the deserializer
+        let parsed = Value::parse_document(&mut Parser::new(src)).unwrap();
+        assert_eq!(parsed, value);
+
+        // written back and deserialized out of the value as they were read
+        #[cfg(feature = "std")]
+        {
+            let written = crate::to_string(&value).unwrap();
+            assert_eq!(from_str::<Value>(&written).unwrap(), value);
+        }
+        let big: u128 = value
+            .get("big")
+            .unwrap()
+            .clone()
+            .deserialize_into()
+            .unwrap();
+        assert_eq!(big, 1 << 64);
+        let low: i128 = value
+            .get("low")
+            .unwrap()
+            .clone()
+            .deserialize_into()
+            .unwrap();
+        assert_eq!(low, i64::MIN as i128 - 1);
+        assert!(value
+            .get("big")
+            .unwrap()
+            .clone()
+            .deserialize_into::<u64>()
+            .is_err());
+
+        // past 128 bits is still an error
+        assert!(from_str::<Value>("a = 340282366920938463463374607431768211456").is_err());
+    }
+
+    #[test]
+    fn interned_keys() {
+        let value: Value = "servers = [{ host = 'a' port = 1 } { host = 'b' port = 2 }]"
+            .parse()
+            .unwrap();
+        let Value::Map(mut map) = value else { panic!() };
+        let Some(Value::List(servers)) = map.remove("servers") else {
+            panic!()
+        };
+        let keys: Vec<_> = servers
+            .iter()
+            .map(|server| match server {
+                Value::Map(map) => map.keys().cloned().collect::<Vec<_>>(),
+                value => panic!("{value:?}"),
+            })
+            .collect();
+        assert!(std::sync::Arc::ptr_eq(&keys[0][0], &keys[1][0]));
+        assert!(std::sync::Arc::ptr_eq(&keys[0][1], &keys[1][1]));
+
+        let mut interner = Interner::new();
+        let mut parser = Parser::new("x = 1");
+        let a = Value::parse_document_interned(&mut parser, &mut interner).unwrap();
+        let mut parser = Parser::new("x = 2");
+        let b = Value::parse_document_interned(&mut parser, &mut interner).unwrap();
+        let (Value::Map(a), Value::Map(b)) = (a, b) else {
+            panic!()
+        };
+        assert!(std::sync::Arc::ptr_eq(
+            a.keys().next().unwrap(),
+            b.keys().next().unwrap()
+        ));
+        assert_eq!(interner.len(), 1);
+    }
+
+    #[test]
+    fn merge_values() {
+        let mut value: Value = "server = { host = 1 port = 1 } tags = [1 2] debug = true"
+            .parse()
+            .unwrap();
+        let other: Value = "server.port = 2 server.tls = true tags = [3] debug = null"
+            .parse()
+            .unwrap();
+        value.merge(other);
+        let expected: Value = "server = { host = 1 port = 2 tls = true } tags = [3] debug = null"
+            .parse()
+            .unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn references() {
+        let mut value: Value = r#"
+            base = "/srv"
+            dirs = { data = "${base}/data" logs = "${dirs.data}/logs" }
+            ports = [80 443]
+            copy = "${ports}"
+            first = "port ${ports[0]} $${base}"
+        "#
+        .parse()
+        .unwrap();
+        value.resolve_references().unwrap();
+        let expected: Value = r#"
+            base = "/srv"
+            dirs = { data = "/srv/data" logs = "/srv/data/logs" }
+            ports = [80 443]
+            copy = [80 443]
+            first = "port 80 ${base}"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(value, expected);
+
+        for (src, message) in [
+            (r#"a = "${b}" b = "${a}""#, "refer back to it"),
+            (r#"a = { b = "${a}" }"#, "refer back to it"),
+            (r#"a = "${b}""#, "not set"),
+            (r#"a = "x ${b}" b = [1]"#, "not a string"),
+        ] {
+            let mut value: Value = src.parse().unwrap();
+            let err = value.resolve_references().unwrap_err();
+            assert!(err.to_string().contains(message), "{err}");
+        }
+    }
+}
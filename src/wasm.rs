@@ -0,0 +1,89 @@
+//! Bindings for JavaScript with [`wasm_bindgen`], so editors and playgrounds on the
+//! web can use this parser. Positions are 0 indexed, with columns in UTF-16 code
+//! units like JavaScript strings.
+
+use serde::de::IgnoredAny;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use wasm_bindgen::prelude::*;
+
+use crate::de::{from_str_with_options, Options};
+use crate::error::{Error, ErrorKind};
+use crate::fmt::Config;
+use crate::parse::ColumnEncoding;
+use crate::transcode::transcode_with_options;
+
+fn options() -> Options {
+    Options {
+        column_encoding: ColumnEncoding::Utf16,
+        ..Options::default()
+    }
+}
+
+/// Parses a document into plain JavaScript objects, arrays and primitives. Durations
+/// are given as `{ secs, nanos }` objects.
+#[wasm_bindgen]
+pub fn parse(src: &str) -> Result<JsValue, JsError> {
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    transcode_with_options(src, &serializer, options())
+        .map_err(|err| JsError::new(&err.to_string()))
+}
+
+/// Checks that a document is valid, giving `null` if it is, or the error as an object
+/// with `message`, `path` and, for errors in the document, the `line`, `column`,
+/// `endLine` and `endColumn` it spans.
+#[wasm_bindgen]
+pub fn validate(src: &str) -> JsValue {
+    match from_str_with_options::<IgnoredAny>(src, options()) {
+        Ok(_) => JsValue::NULL,
+        Err(err) => {
+            let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+            ValidationError(&err)
+                .serialize(&serializer)
+                .unwrap_or_else(|err| err.into())
+        }
+    }
+}
+
+/// Rewrites a document in the layout the serializer writes, keeping its comments and
+/// the order of its entries.
+#[wasm_bindgen]
+pub fn format(src: &str) -> Result<String, JsError> {
+    crate::fmt::format(src, &Config::default()).map_err(|err| JsError::new(&err.to_string()))
+}
+
+struct ValidationError<'a>(&'a Error);
+
+impl Serialize for ValidationError<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let err = self.0;
+        let mut s = serializer.serialize_struct("ValidationError", 6)?;
+        // without the position and context, which have fields of their own
+        let message = match err.kind() {
+            ErrorKind::Message(msg, _) => msg.clone(),
+            ErrorKind::Parse(err) => err.kind.display(err.token).to_string(),
+            ErrorKind::Io(err) => err.to_string(),
+        };
+        s.serialize_field("message", &message)?;
+        s.serialize_field("path", &err.path().map(|path| path.to_string()))?;
+        let span = err.span();
+        s.serialize_field("line", &span.map(|span| span.line))?;
+        s.serialize_field("column", &span.map(|span| span.col))?;
+        s.serialize_field("endLine", &span.map(|span| span.end_line))?;
+        s.serialize_field("endColumn", &span.map(|span| span.end_col))?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wasm_format() {
+        let formatted = format("b = { d = 1 c = [1 2] }  a = 'x' // x").unwrap();
+        assert_eq!(
+            formatted,
+            "b = {\n    d = 1\n    c = [\n        1\n        2\n    ]\n}\na = 'x' // x\n"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! Runs the `ycf` binary on files in a scratch directory, checking its output and
+//! its exit codes: 0 when all is well, 1 when problems were found, and 2 when it
+//! could not run.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+struct Output {
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+/// Runs `ycf` with `args` in the directory of the test, writing `stdin` to it.
+fn ycf(dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ycf"))
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    Output {
+        code: output.status.code().unwrap(),
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+    }
+}
+
+/// An empty directory for a test, with `files` written in it.
+fn dir(test: &str, files: &[(&str, &[u8])]) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(test);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (name, contents) in files {
+        fs::write(dir.join(name), contents).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn fmt() {
+    let dir = dir(
+        "fmt",
+        &[
+            ("messy.ycf", b"a=1   b={c=[1 2]}"),
+            ("tidy.ycf", b"a = 1\n"),
+            ("broken.ycf", b"a = [1"),
+            ("bom.ycf", b"\xef\xbb\xbfa=1"),
+        ],
+    );
+    let formatted = "a = 1\nb = {\n    c = [\n        1\n        2\n    ]\n}\n";
+
+    let out = ycf(&dir, &["fmt", "--check", "messy.ycf", "tidy.ycf"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (1, "messy.ycf\n"));
+    let out = ycf(&dir, &["fmt", "--stdout", "messy.ycf"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (0, formatted));
+    // files are formatted in place, and formatted files are left as they are
+    let out = ycf(&dir, &["fmt", "messy.ycf", "tidy.ycf"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (0, ""));
+    assert_eq!(
+        fs::read_to_string(dir.join("messy.ycf")).unwrap(),
+        formatted
+    );
+    let out = ycf(&dir, &["fmt", "--check", "messy.ycf", "bom.ycf"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (1, "bom.ycf\n"));
+
+    let out = ycf(&dir, &["fmt"], "b = [ ]\n");
+    assert_eq!((out.code, out.stdout.as_str()), (0, "b = []\n"));
+
+    let out = ycf(&dir, &["fmt", "broken.ycf"], "");
+    assert_eq!(out.code, 2);
+    assert!(out.stderr.contains("broken.ycf"), "{}", out.stderr);
+    assert_eq!(
+        fs::read_to_string(dir.join("broken.ycf")).unwrap(),
+        "a = [1"
+    );
+    let out = ycf(&dir, &["fmt", "missing.ycf"], "");
+    assert_eq!(out.code, 2);
+    assert!(
+        out.stderr.starts_with("error: cannot read `missing.ycf`"),
+        "{}",
+        out.stderr
+    );
+}
+
+#[test]
+fn check() {
+    let dir = dir(
+        "check",
+        &[
+            ("ok.ycf", b"a = 1\nb = [1 2]\n"),
+            ("errors.ycf", b"a = 0xz\nb = @\nc = 3\n"),
+            ("twice.ycf", b"a = 1\na = 2\n"),
+            ("bom.ycf", b"\xef\xbb\xbfa = 1\n"),
+            ("utf16.ycf", b"\xff\xfea\x00"),
+            (
+                "schema.ycf",
+                b"fields.port = { type = \"integer\" max = 10 }\nadditional = false",
+            ),
+        ],
+    );
+
+    let out = ycf(&dir, &["check", "ok.ycf", "bom.ycf"], "");
+    assert_eq!((out.code, out.stderr.as_str()), (0, ""));
+    let out = ycf(&dir, &["check"], "a = [1 2]");
+    assert_eq!((out.code, out.stderr.as_str()), (0, ""));
+
+    // every syntax error is reported, not just the first
+    let out = ycf(&dir, &["check", "errors.ycf"], "");
+    assert_eq!(out.code, 1);
+    assert!(out.stderr.contains("errors.ycf:1:7"), "{}", out.stderr);
+    assert!(out.stderr.contains("errors.ycf:2:5"), "{}", out.stderr);
+
+    // warnings only fail with `--deny-warnings`
+    // and are shown with a snippet of the source like errors
+    let out = ycf(&dir, &["check", "twice.ycf"], "");
+    assert_eq!(out.code, 0);
+    assert_eq!(
+        out.stderr,
+        "warning: `a` is set more than once, which most types do not allow\n \
+         --> twice.ycf:2:1\n  \
+         |\n\
+         2 | a = 2\n  \
+         | ^\n\
+         note: first set here\n \
+         --> twice.ycf:1:1\n  \
+         |\n\
+         1 | a = 1\n  \
+         | ^\n\n"
+    );
+    let out = ycf(&dir, &["check", "--deny-warnings", "twice.ycf"], "");
+    assert_eq!(out.code, 1);
+
+    let out = ycf(
+        &dir,
+        &["check", "--format", "json", "errors.ycf", "twice.ycf"],
+        "",
+    );
+    assert_eq!(out.code, 1);
+    let problems: serde_json::Value = serde_json::from_str(&out.stdout).unwrap();
+    let problems: Vec<_> = problems
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|problem| {
+            (
+                problem["file"].as_str().unwrap(),
+                problem["severity"].as_str().unwrap(),
+                problem["line"].as_u64().unwrap(),
+                problem["column"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        problems,
+        [
+            ("errors.ycf", "error", 1, 7),
+            ("errors.ycf", "error", 2, 5),
+            ("twice.ycf", "warning", 2, 1),
+        ]
+    );
+
+    let out = ycf(
+        &dir,
+        &["check", "--schema", "schema.ycf"],
+        "port = 11 host = \"a\"",
+    );
+    assert_eq!(out.code, 1);
+    assert!(out.stderr.contains("at most 10"), "{}", out.stderr);
+    assert!(out.stderr.contains("Unknown key 'host'"), "{}", out.stderr);
+
+    // files that can not be read are errors of their own, and the rest are checked
+    let out = ycf(&dir, &["check", "missing.ycf", "utf16.ycf", "ok.ycf"], "");
+    assert_eq!(out.code, 2);
+    assert!(
+        out.stderr.contains("cannot read `missing.ycf`"),
+        "{}",
+        out.stderr
+    );
+    assert!(out.stderr.contains("UTF-16LE"), "{}", out.stderr);
+}
+
+#[test]
+fn get() {
+    let dir = dir(
+        "get",
+        &[
+            (
+                "app.ycf",
+                b"server = { host = \"a\\tb\" ports = [80 443] timeout = 1500ms }\n",
+            ),
+            ("bom.ycf", b"\xef\xbb\xbfa = 1\n"),
+            ("broken.ycf", b"a = 1\nb = [\n"),
+            (
+                "wide.ycf",
+                b"id = 18446744073709551616\nlow = -9223372036854775809\n",
+            ),
+        ],
+    );
+
+    let out = ycf(&dir, &["get", "app.ycf", "server.ports[1]"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (0, "443\n"));
+    let out = ycf(&dir, &["get", "app.ycf", "server.host"], "");
+    assert_eq!(out.stdout, "\"a\\tb\"\n");
+    let out = ycf(&dir, &["get", "--raw", "app.ycf", "server.host"], "");
+    assert_eq!(out.stdout, "a\tb\n");
+    let out = ycf(&dir, &["get", "--json", "app.ycf", "server.timeout"], "");
+    let json: serde_json::Value = serde_json::from_str(&out.stdout).unwrap();
+    assert_eq!(json, serde_json::json!({ "secs": 1, "nanos": 500_000_000 }));
+    let out = ycf(&dir, &["get", "app.ycf", "server.ports"], "");
+    assert_eq!(out.stdout, "[\n    80\n    443\n]\n");
+    let out = ycf(&dir, &["get", "bom.ycf", "a"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (0, "1\n"));
+    let out = ycf(&dir, &["get", "-", "x.y"], "x.y = true");
+    assert_eq!((out.code, out.stdout.as_str()), (0, "true\n"));
+    // integers that do not fit in 64 bits are kept exactly
+    let out = ycf(&dir, &["get", "wide.ycf", "id"], "");
+    assert_eq!(
+        (out.code, out.stdout.as_str()),
+        (0, "18446744073709551616\n")
+    );
+    let out = ycf(&dir, &["get", "--json", "wide.ycf", "low"], "");
+    assert_eq!(out.stdout, "-9223372036854775809\n");
+    let out = ycf(&dir, &["check", "wide.ycf"], "");
+    assert_eq!((out.code, out.stderr.as_str()), (0, ""));
+
+    let out = ycf(&dir, &["get", "app.ycf", "server.missing"], "");
+    assert_eq!(out.code, 1);
+    assert_eq!(
+        out.stderr,
+        "error: `app.ycf` has no value at `server.missing`\n"
+    );
+    // syntax errors after the value are still errors
+    let out = ycf(&dir, &["get", "broken.ycf", "a"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (2, ""));
+    assert!(out.stderr.contains("broken.ycf:"), "{}", out.stderr);
+    let out = ycf(&dir, &["get", "app.ycf", "a..b"], "");
+    assert_eq!(out.code, 2);
+}
+
+#[test]
+fn convert() {
+    let dir = dir(
+        "convert",
+        &[
+            (
+                "app.ycf",
+                b"name = \"app\"\nports = [80 443]\nserver.tls = true\n",
+            ),
+            ("app.json", b"{\"a\": [1, {\"b\": null}]}"),
+            ("app.toml", b"a = 1\n[b]\nc = \"x\"\n"),
+            ("app.yaml", b"a:\n- 1\n- two\n"),
+            ("bad.json", b"{\"a\": }"),
+            ("bom.ycf", b"\xef\xbb\xbfa = 1\n"),
+        ],
+    );
+
+    let out = ycf(&dir, &["convert", "app.ycf", "--to", "json"], "");
+    assert_eq!(out.code, 0);
+    let json: serde_json::Value = serde_json::from_str(&out.stdout).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({ "name": "app", "ports": [80, 443], "server": { "tls": true } })
+    );
+    let out = ycf(&dir, &["convert", "app.ycf", "--to", "toml"], "");
+    assert_eq!(
+        out.stdout,
+        "name = \"app\"\nports = [\n    80,\n    443,\n]\n\n[server]\ntls = true\n"
+    );
+    let out = ycf(&dir, &["convert", "app.ycf", "--to", "yaml"], "");
+    assert_eq!(
+        out.stdout,
+        "name: app\nports:\n- 80\n- 443\nserver:\n  tls: true\n"
+    );
+
+    // other formats become ycf by default
+    let out = ycf(&dir, &["convert", "app.json"], "");
+    assert_eq!(
+        (out.code, out.stdout.as_str()),
+        (0, "a = [\n    1\n    {\n        b = null\n    }\n]\n")
+    );
+    let out = ycf(&dir, &["convert", "app.toml"], "");
+    assert_eq!(out.stdout, "a = 1\nb = {\n    c = \"x\"\n}\n");
+    let out = ycf(&dir, &["convert", "app.yaml"], "");
+    assert_eq!(out.stdout, "a = [\n    1\n    \"two\"\n]\n");
+    let out = ycf(&dir, &["convert", "--from", "json", "-"], "[true]");
+    assert_eq!(out.stdout, "[\n    true\n]\n");
+
+    // the format of the output can come from its extension
+    let out = ycf(&dir, &["convert", "bom.ycf", "-o", "out.json"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (0, ""));
+    let json = fs::read_to_string(dir.join("out.json")).unwrap();
+    assert_eq!(json, "{\n  \"a\": 1\n}\n");
+
+    let out = ycf(&dir, &["convert", "app.ycf"], "");
+    assert_eq!(out.code, 2);
+    assert_eq!(
+        out.stderr,
+        "error: give the format to convert to with `--to`\n"
+    );
+    let out = ycf(&dir, &["convert", "app.ycf", "-o", "out.txt"], "");
+    assert_eq!(out.code, 2);
+    let out = ycf(&dir, &["convert", "bad.json"], "");
+    assert_eq!(out.code, 2);
+    assert!(
+        out.stderr.starts_with("error: bad.json: "),
+        "{}",
+        out.stderr
+    );
+}
+
+#[test]
+fn merge_and_diff() {
+    let dir = dir(
+        "merge",
+        &[
+            (
+                "base.ycf",
+                b"server = { host = \"a\" port = 80 }\nlist = [1 2]\n",
+            ),
+            (
+                "local.ycf",
+                b"\xef\xbb\xbfserver.port = 90\nlist = [3]\nnew.\"x y\" = 1\n",
+            ),
+            ("broken.ycf", b"a = {"),
+        ],
+    );
+
+    // maps are merged key by key, and everything else is replaced
+    let out = ycf(
+        &dir,
+        &["merge", "base.ycf", "local.ycf", "--set", "server.host=b"],
+        "",
+    );
+    assert_eq!(out.code, 0);
+    assert_eq!(
+        out.stdout,
+        "list = [\n    3\n]\nnew = {\n    \"x y\" = 1\n}\nserver = {\n    host = \"b\"\n    port = 90\n}\n"
+    );
+    let out = ycf(
+        &dir,
+        &["merge", "local.ycf", "base.ycf", "-o", "out.ycf"],
+        "",
+    );
+    assert_eq!((out.code, out.stdout.as_str()), (0, ""));
+    let merged = fs::read_to_string(dir.join("out.ycf")).unwrap();
+    assert!(
+        merged.starts_with("list = [\n    1\n    2\n]\n"),
+        "{merged}"
+    );
+
+    let out = ycf(&dir, &["merge", "base.ycf", "--set", "port"], "");
+    assert_eq!(out.code, 2);
+    assert!(
+        out.stderr.contains("invalid override `port`"),
+        "{}",
+        out.stderr
+    );
+    let out = ycf(&dir, &["merge", "base.ycf", "broken.ycf"], "");
+    assert_eq!(out.code, 2);
+    assert!(out.stderr.contains("broken.ycf:"), "{}", out.stderr);
+
+    // like `diff`, this fails when the files differ
+    let out = ycf(&dir, &["diff", "base.ycf", "local.ycf"], "");
+    assert_eq!(out.code, 1);
+    assert_eq!(
+        out.stdout,
+        "- list[0] = 1\n\
+         + list[0] = 3\n\
+         - list[1] = 2\n\
+         + new.\"x y\" = 1\n\
+         - server.host = \"a\"\n\
+         - server.port = 80\n\
+         + server.port = 90\n"
+    );
+    let out = ycf(&dir, &["diff", "base.ycf", "out.ycf"], "");
+    assert_eq!(out.code, 1);
+    assert_eq!(out.stdout, "+ new.\"x y\" = 1\n");
+    let out = ycf(&dir, &["diff", "base.ycf", "base.ycf"], "");
+    assert_eq!((out.code, out.stdout.as_str()), (0, ""));
+    let out = ycf(&dir, &["diff", "base.ycf", "missing.ycf"], "");
+    assert_eq!(out.code, 2);
+}